@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use argh::FromArgs;
+
+use holyrig::capability_matrix::{Format, generate};
+use holyrig::resources::Resources;
+use holyrig::rig_settings::DeviceClass;
+
+#[derive(FromArgs)]
+/// Generates the capability matrix of the rig files for the book
+struct Args {
+    #[argh(option, default = "PathBuf::from(\"schema\")")]
+    /// directory of the schema files
+    schemas: PathBuf,
+    #[argh(option)]
+    /// directory of rig files, repeatable, defaults to the directories of every device class
+    rigs: Vec<PathBuf>,
+    #[argh(option, default = "Format::Markdown")]
+    /// output format, markdown or html
+    format: Format,
+    #[argh(option)]
+    /// file to write, printed to stdout if omitted
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args: Args = argh::from_env();
+
+    let schemas = Resources::load_schemas_from(&args.schemas)
+        .map_err(|err| anyhow!("loading schemas from {:?}: {err}", args.schemas))?;
+    let rig_dirs = if args.rigs.is_empty() {
+        let parent = args.schemas.parent().unwrap_or(Path::new(""));
+        DeviceClass::iter_classes()
+            .map(|class| parent.join(class.rigs_dir()))
+            .filter(|dir| dir.is_dir())
+            .collect()
+    } else {
+        args.rigs
+    };
+    let mut rigs = Vec::new();
+    for dir in &rig_dirs {
+        let rig_files = Resources::load_rig_files_from(dir, &schemas)
+            .map_err(|err| anyhow!("loading rigs from {dir:?}: {err}"))?;
+        rigs.extend(rig_files.into_values());
+    }
+
+    let matrix = generate(&schemas, &rigs, args.format);
+    match &args.output {
+        Some(output) => std::fs::write(output, matrix)?,
+        None => print!("{matrix}"),
+    }
+    Ok(())
+}
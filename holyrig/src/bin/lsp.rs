@@ -0,0 +1,8 @@
+use anyhow::Result;
+
+use holyrig::lsp::LspServer;
+
+/// Language server for rig, rig module and schema files, talking LSP over stdin and stdout.
+fn main() -> Result<()> {
+    LspServer::new(std::io::stdout().lock()).run(std::io::stdin().lock())
+}
@@ -0,0 +1,62 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+use argh::FromArgs;
+
+use holyrig::omnirig_import::convert;
+use holyrig::runtime::{parse_and_validate_with_schema, parse_schema};
+
+#[derive(FromArgs)]
+/// Converts an OmniRig rig definition into a rig file for the Transceiver schema
+struct Args {
+    #[argh(positional)]
+    /// ini file of the OmniRig rig definition to convert
+    ini: PathBuf,
+    #[argh(option)]
+    /// rig file to write, printed to stdout if omitted
+    output: Option<PathBuf>,
+    #[argh(option)]
+    /// name of the rig, defaults to the name of the ini file
+    name: Option<String>,
+    #[argh(option)]
+    /// schema file the converted rig file is validated against
+    schema: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args: Args = argh::from_env();
+
+    // OmniRig definitions are often Windows-1252 encoded, non-UTF-8 bytes only appear in comments
+    let bytes = std::fs::read(&args.ini).with_context(|| format!("reading {:?}", args.ini))?;
+    let ini = String::from_utf8_lossy(&bytes);
+    let name = match args.name {
+        Some(name) => name,
+        None => args
+            .ini
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+
+    let conversion = convert(&ini, &name)?;
+    for unsupported in &conversion.unsupported {
+        eprintln!("Not converted: {unsupported}");
+    }
+
+    if let Some(schema) = &args.schema {
+        let schema = parse_schema(&std::fs::read_to_string(schema)?)?;
+        let schemas = HashMap::from([(schema.name.clone(), schema)]);
+        if let Err(errors) = parse_and_validate_with_schema(&conversion.rig, &schemas) {
+            for err in errors {
+                eprintln!("{err}");
+            }
+            bail!("Converted rig file does not match the schema");
+        }
+    }
+
+    match &args.output {
+        Some(output) => std::fs::write(output, &conversion.rig)?,
+        None => print!("{}", conversion.rig),
+    }
+    Ok(())
+}
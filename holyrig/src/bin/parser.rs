@@ -1,75 +1,603 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, bail};
 use argh::FromArgs;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{Mutex, mpsc},
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
 
-use holyrig::runtime::{parse_and_validate_with_schema, parse_rig_file, parse_schema};
+use holyrig::resources::Resources;
+use holyrig::runtime::{
+    ChannelTracer, Debugger, Diagnostic, DryRunApi, ExternalApi, Interpreter, ParseError,
+    Recovered, RigFile, SchemaFile, SemanticWarning, Severity, TraceEvent, Value,
+    diagnostics_to_json, diagnostics_to_sarif, format_rig_file, format_rig_module, format_schema,
+    module_loader, parse_and_check_with_imports, parse_hex, parse_rig_module_with_recovery,
+    parse_schema_with_recovery, read_module,
+};
+
+/// Exit code when checked files have errors or tests fail.
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the tool itself fails, e.g. on invalid arguments or unreadable files.
+const EXIT_ERROR: u8 = 2;
 
 #[derive(FromArgs)]
 /// Command line tool for validating rig files and schema files
+#[argh(
+    error_code(1, "checked files have errors or tests failed"),
+    error_code(2, "invalid arguments, unreadable files or a failed command")
+)]
 struct Args {
+    #[argh(positional)]
+    /// rig, rig module and schema files or directories with them, checked together
+    paths: Vec<PathBuf>,
     #[argh(option)]
     /// rig file to validate
     rig: Option<PathBuf>,
     #[argh(option)]
     /// schema file to validate
     schema: Option<PathBuf>,
+    #[argh(option)]
+    /// command to run on a rig, e.g. "set_freq freq=14074000 target=A", "init" or "status"
+    command: Option<String>,
+    #[argh(option)]
+    /// serial port of the rig the command is run on
+    port: Option<String>,
+    #[argh(option, default = "9600")]
+    /// baud rate of the serial port
+    baud_rate: u32,
+    #[argh(switch)]
+    /// print every executed statement, evaluated value and transferred bytes
+    trace: bool,
+    #[argh(switch)]
+    /// pause before every statement
+    step: bool,
+    #[argh(option, long = "break")]
+    /// pause before statements on this line of the rig file
+    breakpoints: Vec<usize>,
+    #[argh(option)]
+    /// print the bytes a command would exchange with the rig without opening a port
+    dry_run: Option<String>,
+    #[argh(option, long = "reply")]
+    /// hex reply of the rig for the dry run, used by the reads in order
+    replies: Vec<String>,
+    #[argh(switch)]
+    /// run the test blocks of the rig file
+    test: bool,
+    #[argh(switch)]
+    /// rewrite the rig or rig module and schema file in canonical formatting
+    fmt: bool,
+    #[argh(switch)]
+    /// with --fmt, only report files that are not formatted and fail if there are any
+    check: bool,
+    #[argh(option, default = "OutputFormat::Text")]
+    /// format of the reported errors and warnings: text, json or sarif
+    format: OutputFormat,
 }
 
-fn main() -> Result<()> {
-    let args: Args = argh::from_env();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Sarif,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            _ => Err(format!(
+                "unknown format '{format}', expected text, json or sarif"
+            )),
+        }
+    }
+}
+
+/// Collects the errors and warnings of the checked files. Text output is printed right away, the other
+/// formats are printed as a whole by `finish`.
+struct Checker {
+    format: OutputFormat,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn info(&self, message: impl std::fmt::Display) {
+        if self.format == OutputFormat::Text {
+            println!("{message}");
+        }
+    }
+
+    fn error(&mut self, path: &Path, error: &ParseError) {
+        if self.format == OutputFormat::Text {
+            eprintln!("{}:\n{error}", path.display());
+        }
+        self.diagnostics
+            .push(Diagnostic::from_parse_error(path, error));
+    }
 
-    let rig = if let Some(rig) = args.rig {
-        Some(std::fs::read_to_string(rig)?)
-    } else {
-        None
+    fn warning(&mut self, path: &Path, warning: &SemanticWarning) {
+        if self.format == OutputFormat::Text {
+            match &warning.position {
+                Some(position) => eprintln!(
+                    "{}:{}:{}: warning: {warning}",
+                    path.display(),
+                    position.line,
+                    position.column
+                ),
+                None => eprintln!("{}: warning: {warning}", path.display()),
+            }
+        }
+        self.diagnostics
+            .push(Diagnostic::from_warning(path, warning));
+    }
+
+    /// Number of reported errors, warnings do not make the check fail.
+    fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    /// Reports the errors of parsing with recovery and returns the parts that parsed.
+    fn recovered<T>(&mut self, path: &Path, recovered: Recovered<T>) -> Option<T> {
+        for err in &recovered.errors {
+            self.error(path, err);
+        }
+        recovered.value
+    }
+
+    fn finish(&self) -> Result<()> {
+        let output = match self.format {
+            OutputFormat::Text => return Ok(()),
+            OutputFormat::Json => diagnostics_to_json(&self.diagnostics),
+            OutputFormat::Sarif => diagnostics_to_sarif(&self.diagnostics),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        Ok(())
+    }
+}
+
+struct SerialApi {
+    port: Mutex<SerialStream>,
+}
+
+impl ExternalApi for SerialApi {
+    async fn write(&self, data: &[u8]) -> Result<()> {
+        self.port.lock().await.write_all(data).await?;
+        Ok(())
+    }
+
+    async fn read(&self, size: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; size];
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            self.port.lock().await.read_exact(&mut buf),
+        )
+        .await
+        .context("Timed out waiting for the rig")??;
+        Ok(buf)
+    }
+
+    fn set_var(&self, var: &str, value: Value) -> Result<()> {
+        println!("{var} = {value}");
+        Ok(())
+    }
+}
+
+fn parse_invocation(invocation: &str) -> Result<(String, HashMap<String, Value>)> {
+    let mut parts = invocation.split_whitespace();
+    let command = parts.next().ok_or_else(|| anyhow!("Empty command"))?;
+    let params = parts
+        .map(|part| {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected name=value, got '{part}'"))?;
+            Ok((name.to_string(), Value::String(value.to_string())))
+        })
+        .collect::<Result<_>>()?;
+    Ok((command.to_string(), params))
+}
+
+/// Lists argument errors by parameter name.
+fn list(errors: &BTreeMap<String, impl std::fmt::Display>) -> String {
+    errors
+        .iter()
+        .map(|(name, err)| format!("{name}: {err}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses a command invocation. The arguments of schema commands are checked like the JSON-RPC
+/// server checks them: converted to the declared types, within the declared ranges and supported
+/// by the capabilities of the rig.
+fn prepare_invocation(
+    rig_file: &RigFile,
+    schema: &SchemaFile,
+    invocation: &str,
+) -> Result<(String, HashMap<String, Value>)> {
+    let (command, params) = parse_invocation(invocation)?;
+    let Some(schema_command) = schema.commands.get(&command) else {
+        return Ok((command, params));
     };
+    let params = schema_command
+        .prepare_args(params, &rig_file.enum_mappings())
+        .map_err(|errors| anyhow!("Invalid params for command {command}: {}", list(&errors)))?;
+    rig_file
+        .capabilities()
+        .cloned()
+        .unwrap_or_default()
+        .check_args(schema_command, &params, None)
+        .map_err(|errors| anyhow!("Command {command} rejected: {}", list(&errors)))?;
+    Ok((command, params))
+}
+
+async fn execute(
+    interpreter: &Interpreter,
+    command: &str,
+    params: HashMap<String, Value>,
+    api: &impl ExternalApi,
+) -> Result<()> {
+    match command {
+        "init" => interpreter.execute_init(api).await,
+        "status" => interpreter.execute_status(api).await,
+        _ => interpreter
+            .execute_command(command, params, api)
+            .await
+            .map(|_| ()),
+    }
+}
+
+async fn dry_run(
+    rig_file: RigFile,
+    schema: &SchemaFile,
+    args: &Args,
+    invocation: &str,
+) -> Result<()> {
+    let (command, params) = prepare_invocation(&rig_file, schema, invocation)?;
+    let replies = args
+        .replies
+        .iter()
+        .map(|reply| parse_hex(reply))
+        .collect::<Result<_>>()?;
 
-    let schema = if let Some(schema) = args.schema {
-        Some(std::fs::read_to_string(schema)?)
-    } else {
-        None
+    let interpreter = Interpreter::new(rig_file);
+    let api = DryRunApi::new(replies);
+    let result = execute(&interpreter, &command, params, &api).await;
+
+    let transcript = api.into_transcript(result);
+    for entry in &transcript.entries {
+        println!("{entry}");
+    }
+    match transcript.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Runs the test blocks of the rig file, `false` if any of them failed.
+async fn run_tests(rig_file: RigFile) -> Result<bool> {
+    let tests = rig_file.impl_block.tests.clone();
+    let interpreter = Interpreter::new(rig_file);
+
+    let mut failed = 0;
+    for test in &tests {
+        match interpreter.run_test(test).await {
+            Ok(()) => println!("test {} ... ok", test.name),
+            Err(err) => {
+                println!("test {} ... FAILED\n{err:#}", test.name);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", tests.len() - failed);
+    Ok(failed == 0)
+}
+
+async fn run_command(
+    rig_file: RigFile,
+    schema: &SchemaFile,
+    args: &Args,
+    invocation: &str,
+) -> Result<()> {
+    // Checked before opening the port, the rig never sees rejected arguments
+    let (command, params) = prepare_invocation(&rig_file, schema, invocation)?;
+    let port = args
+        .port
+        .as_ref()
+        .ok_or_else(|| anyhow!("--port is required to run a command"))?;
+    let api = SerialApi {
+        port: Mutex::new(
+            tokio_serial::new(port, args.baud_rate)
+                .open_native_async()
+                .with_context(|| format!("Failed to open serial port {port}"))?,
+        ),
     };
 
-    match (rig, schema) {
-        (Some(rig), Some(schema)) => {
-            let schema = parse_schema(&schema)?;
-            let schemas = HashMap::from([(schema.name.clone(), schema)]);
-            match parse_and_validate_with_schema(&rig, &schemas) {
-                Ok(rig_file) => {
-                    println!("Successfully parsed schema and rig!");
-                    println!(" - Schema: {}", rig_file.impl_block.schema);
-                    println!(" - Name: {}", rig_file.impl_block.name);
-                }
-                Err(errors) => {
-                    for err in errors {
-                        eprintln!("{err}");
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let debugger = Arc::new(Debugger::new(ChannelTracer::new(event_tx)));
+    debugger.set_stepping(args.step);
+    for line in &args.breakpoints {
+        debugger.add_breakpoint(*line);
+    }
+
+    let mut interpreter = Interpreter::new(rig_file);
+    if args.trace || args.step || !args.breakpoints.is_empty() {
+        interpreter = interpreter.with_tracer(debugger.clone());
+    }
+
+    let mut execution =
+        tokio::spawn(async move { execute(&interpreter, &command, params, &api).await });
+
+    loop {
+        tokio::select! {
+            Some(event) = event_rx.recv() => {
+                println!("{event}");
+                if matches!(event, TraceEvent::Paused { .. }) {
+                    eprint!("[Enter] step, [c] continue: ");
+                    let line = tokio::task::spawn_blocking(|| {
+                        let mut line = String::new();
+                        std::io::stdin().read_line(&mut line).map(|_| line)
+                    })
+                    .await??;
+                    if line.trim() == "c" {
+                        debugger.set_stepping(false);
                     }
+                    debugger.resume();
                 }
             }
-        }
-        (None, Some(schema)) => match parse_schema(&schema) {
-            Ok(schema) => {
-                println!("Successfully parsed schema \"{}\"!", schema.name);
+            result = &mut execution => {
+                while let Ok(event) = event_rx.try_recv() {
+                    println!("{event}");
+                }
+                return result?;
             }
-            Err(err) => {
-                eprintln!("{err}");
+        }
+    }
+}
+
+/// Formats the given files in place, with `check` only reports the ones that would change.
+fn format_files(args: &Args) -> Result<()> {
+    let mut files = collect_files(&args.paths)?;
+    files.extend(args.rig.iter().chain(args.schema.iter()).cloned());
+    let mut unformatted = 0;
+    for path in &files {
+        let source = std::fs::read_to_string(path)?;
+        let formatted = match extension(path) {
+            Some("schema") => format_schema(&source),
+            Some("rigmod") => format_rig_module(&source),
+            _ => format_rig_file(&source),
+        }
+        .map_err(|err| anyhow!("{}:\n{err}", path.display()))?;
+
+        if formatted == source {
+            continue;
+        }
+        if args.check {
+            println!("would reformat {}", path.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(path, formatted)?;
+            println!("formatted {}", path.display());
+        }
+    }
+
+    if unformatted > 0 {
+        bail!("{unformatted} files are not formatted");
+    }
+    Ok(())
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+/// Rig, rig module and schema files given directly or found in the given directories.
+fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        for entry in path
+            .read_dir()
+            .with_context(|| format!("Failed to read {}", path.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() || matches!(extension(&path), Some("rig" | "rigmod" | "schema")) {
+                pending.push(path);
             }
-        },
-        (Some(rig), None) => match parse_rig_file(&rig) {
-            Ok(rig) => {
-                println!("Successfully parsed rig!");
-                println!(" - Schema: {}", rig.impl_block.schema);
-                println!(" - Name: {}", rig.impl_block.name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Checks the files like `Resources::load` loads them: every schema first, then the rig files
+/// against them.
+fn check_files(checker: &mut Checker, paths: &[PathBuf]) -> Result<()> {
+    let files = collect_files(paths)?;
+
+    let mut schemas = HashMap::new();
+    for path in files
+        .iter()
+        .filter(|path| extension(path) == Some("schema"))
+    {
+        if let Some(schema) = checker.recovered(path, parse_schema_with_recovery(&read(path)?)) {
+            schemas.insert(schema.name.clone(), schema);
+        }
+    }
+
+    for path in files
+        .iter()
+        .filter(|path| extension(path) != Some("schema"))
+    {
+        let source = read(path)?;
+        if extension(path) == Some("rigmod") {
+            checker.recovered(path, parse_rig_module_with_recovery(&source));
+        } else {
+            check_rig(checker, path, &source, &schemas);
+        }
+    }
+
+    checker.info(format!(
+        "Checked {} files, {} errors, {} warnings",
+        files.len(),
+        checker.errors(),
+        checker.diagnostics.len() - checker.errors()
+    ));
+    Ok(())
+}
+
+fn check_rig(
+    checker: &mut Checker,
+    path: &Path,
+    source: &str,
+    schemas: &HashMap<String, SchemaFile>,
+) -> Option<RigFile> {
+    let rigs_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+    let (result, warnings) =
+        parse_and_check_with_imports(source, schemas, module_loader(&rigs_dir, read_module));
+    for warning in &warnings {
+        checker.warning(path, warning);
+    }
+    match result {
+        Ok(rig_file) => Some(rig_file),
+        Err(errors) => {
+            for err in errors {
+                checker.error(path, &err);
             }
-            Err(err) => {
-                eprintln!("{err}");
+            None
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Checks the rig file against the schema it implements, returns both if the rig file is valid.
+fn check_rig_with_schema(
+    checker: &mut Checker,
+    path: &Path,
+    source: &str,
+    mut schemas: HashMap<String, SchemaFile>,
+) -> Option<(RigFile, SchemaFile)> {
+    let rig_file = check_rig(checker, path, source, &schemas)?;
+    checker.info("Successfully parsed schema and rig!");
+    checker.info(format!(" - Schema: {}", rig_file.impl_block.schema));
+    checker.info(format!(" - Name: {}", rig_file.impl_block.name));
+    let schema = schemas.remove(&rig_file.impl_block.schema)?;
+    Some((rig_file, schema))
+}
+
+/// Checks the files given with `--rig` and `--schema`, returns the rig file if it is valid, with
+/// its schema. Without `--schema`, the schema is taken from the schema directory next to the
+/// directory of the rig file, as `Resources` loads them.
+fn check_args(checker: &mut Checker, args: &Args) -> Result<Option<(RigFile, SchemaFile)>> {
+    let rig = args.rig.as_ref().map(|path| read(path)).transpose()?;
+    let schema = args.schema.as_ref().map(|path| read(path)).transpose()?;
+
+    let rig_file = match (&args.rig, rig, &args.schema, schema) {
+        (Some(rig_path), Some(rig), Some(schema_path), Some(schema)) => {
+            let Some(schema) = checker.recovered(schema_path, parse_schema_with_recovery(&schema))
+            else {
+                return Ok(None);
+            };
+            let schemas = HashMap::from([(schema.name.clone(), schema)]);
+            check_rig_with_schema(checker, rig_path, &rig, schemas)
+        }
+        (None, _, Some(schema_path), Some(schema)) => {
+            let errors = checker.errors();
+            let schema = checker.recovered(schema_path, parse_schema_with_recovery(&schema));
+            if let Some(schema) = schema.filter(|_| checker.errors() == errors) {
+                checker.info(format!("Successfully parsed schema \"{}\"!", schema.name));
             }
-        },
-        (None, None) => {
-            eprintln!("You must provide rig file, schema or both!");
+            None
         }
+        (Some(rig_path), Some(rig), ..) => {
+            let rigs_dir = rig_path.parent().unwrap_or(Path::new(""));
+            let schema_dir = rigs_dir.join("..").join("schema");
+            let schemas = Resources::load_schemas_from(&schema_dir).with_context(|| {
+                format!(
+                    "Failed to load the schemas from {}, pass the schema of the rig with --schema",
+                    schema_dir.display()
+                )
+            })?;
+            check_rig_with_schema(checker, rig_path, &rig, schemas)
+        }
+        _ => None,
+    };
+    Ok(rig_file)
+}
+
+fn run(args: &Args) -> Result<ExitCode> {
+    if args.fmt {
+        format_files(args)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.paths.is_empty() && args.rig.is_none() && args.schema.is_none() {
+        bail!("You must provide rig file, schema or both!");
     }
 
-    Ok(())
+    let mut checker = Checker {
+        format: args.format,
+        diagnostics: Vec::new(),
+    };
+    if !args.paths.is_empty() {
+        check_files(&mut checker, &args.paths)?;
+    }
+    let checked = check_args(&mut checker, args)?;
+    checker.finish()?;
+    if checker.errors() > 0 {
+        return Ok(ExitCode::from(EXIT_FAILURE));
+    }
+
+    if args.test {
+        let Some((rig_file, _)) = checked else {
+            bail!("A valid rig file is required to run its tests");
+        };
+        if !tokio::runtime::Runtime::new()?.block_on(run_tests(rig_file))? {
+            return Ok(ExitCode::from(EXIT_FAILURE));
+        }
+    } else if let Some(invocation) = &args.dry_run {
+        let Some((rig_file, schema)) = checked else {
+            bail!("A valid rig file is required for a dry run");
+        };
+        tokio::runtime::Runtime::new()?.block_on(dry_run(rig_file, &schema, args, invocation))?;
+    } else if let Some(invocation) = &args.command {
+        let Some((rig_file, schema)) = checked else {
+            bail!("A valid rig file is required to run a command");
+        };
+        tokio::runtime::Runtime::new()?
+            .block_on(run_command(rig_file, &schema, args, invocation))?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Args = argh::from_env();
+    match run(&args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
 }
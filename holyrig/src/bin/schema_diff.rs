@@ -0,0 +1,123 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{Context, Result};
+use argh::FromArgs;
+
+use holyrig::resources::Resources;
+use holyrig::rig_settings::DeviceClass;
+use holyrig::runtime::{SchemaFile, SemanticAnalyzer, parse_schema};
+use holyrig::schema_diff::{Compatibility, diff};
+
+/// Exit code when the new schema has breaking changes.
+const EXIT_BREAKING: u8 = 1;
+/// Exit code when the tool itself fails, e.g. on unreadable or invalid files.
+const EXIT_ERROR: u8 = 2;
+
+#[derive(FromArgs)]
+/// Compares two versions of a schema and reports the changes breaking clients and rig files
+struct Args {
+    #[argh(positional)]
+    /// schema file of the current version
+    old: PathBuf,
+    #[argh(positional)]
+    /// schema file of the new version
+    new: PathBuf,
+    #[argh(option, default = "PathBuf::from(\"schema\")")]
+    /// directory of the other schemas the rig files may implement
+    schemas: PathBuf,
+    #[argh(option)]
+    /// directory of the rig files to check against the new version, defaults to the directory
+    /// of the device class of the schema
+    rigs: Option<PathBuf>,
+}
+
+fn load_schema(path: &PathBuf) -> Result<SchemaFile> {
+    let source = std::fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+    parse_schema(&source).with_context(|| format!("parsing {path:?}"))
+}
+
+fn run(args: &Args) -> Result<ExitCode> {
+    let old = load_schema(&args.old)?;
+    let new = load_schema(&args.new)?;
+
+    // The rig files are written against the old version, which replaces the installed one
+    let mut schemas = Resources::load_schemas_from(&args.schemas)
+        .with_context(|| format!("loading schemas from {:?}", args.schemas))?;
+    schemas.insert(old.name.clone(), old.clone());
+    let rigs_dir = args.rigs.clone().unwrap_or_else(|| {
+        let class = DeviceClass::from_schema(&old.name).unwrap_or_default();
+        let parent = args.schemas.parent().unwrap_or(Path::new(""));
+        parent.join(class.rigs_dir())
+    });
+    let rigs = Resources::load_rig_files_from(&rigs_dir, &schemas)
+        .with_context(|| format!("loading rigs from {rigs_dir:?}"))?;
+    let mut rigs: Vec<_> = rigs
+        .into_values()
+        .filter(|rig| rig.impl_block.schema == old.name)
+        .collect();
+    rigs.sort_by(|a, b| a.impl_block.name.cmp(&b.impl_block.name));
+
+    println!(
+        "Comparing {} version {} with {} version {}",
+        old.name, old.version, new.name, new.version
+    );
+    let changes = diff(&old, &new);
+    for change in &changes {
+        let broken: Vec<_> = rigs
+            .iter()
+            .filter(|rig| change.breaks(rig))
+            .map(|rig| rig.impl_block.name.as_str())
+            .collect();
+        let mut line = format!("{:<10}  {change}", change.compatibility().to_string());
+        if !broken.is_empty() {
+            line.push_str(&format!(" (breaks {})", broken.join(", ")));
+        }
+        println!("{line}");
+    }
+
+    let breaking = changes
+        .iter()
+        .filter(|change| change.compatibility() == Compatibility::Breaking)
+        .count();
+    println!(
+        "{} changes, {breaking} breaking, {} compatible",
+        changes.len(),
+        changes.len() - breaking
+    );
+    if breaking > 0 && old.version == new.version {
+        println!("The version should be increased for the breaking changes");
+    }
+
+    let analyzer = SemanticAnalyzer::new(new.clone());
+    for rig in &rigs {
+        if let Err(errors) = analyzer.analyze(rig) {
+            println!(
+                "{} does not validate against the new version:",
+                rig.impl_block.name
+            );
+            for err in errors {
+                println!("  {err}");
+            }
+        }
+    }
+
+    if breaking > 0 {
+        Ok(ExitCode::from(EXIT_BREAKING))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Args = argh::from_env();
+    match run(&args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
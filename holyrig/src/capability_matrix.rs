@@ -0,0 +1,496 @@
+//! Tables comparing what the rig files implement of their schema, rendered as Markdown or HTML
+//! for the book.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::runtime::{FrequencyRange, RigFile, SchemaFile, SchemaParameter};
+
+const SUPPORTED: &str = "✓";
+const UNSUPPORTED: &str = "—";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(format!(
+                "unknown format '{format}', expected markdown or html"
+            )),
+        }
+    }
+}
+
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+/// Capability matrix of the rigs, one section per schema with the commands, status fields,
+/// enum mappings, declared ranges and settings of every rig implementing it.
+pub fn generate(schemas: &HashMap<String, SchemaFile>, rigs: &[RigFile], format: Format) -> String {
+    let blocks = blocks(schemas, rigs);
+    match format {
+        Format::Markdown => render_markdown(&blocks),
+        Format::Html => render_html(&blocks),
+    }
+}
+
+fn blocks(schemas: &HashMap<String, SchemaFile>, rigs: &[RigFile]) -> Vec<Block> {
+    let mut blocks = vec![
+        Block::Heading(1, "Capability matrix".to_string()),
+        Block::Paragraph(format!(
+            "Generated from the schema and rig files by the `capability-matrix` tool. \
+             {SUPPORTED} marks the commands and status fields a rig implements."
+        )),
+    ];
+
+    let mut schemas: Vec<_> = schemas.values().collect();
+    schemas.sort_by(|a, b| a.name.cmp(&b.name));
+    for schema in schemas {
+        let mut rigs: Vec<_> = rigs
+            .iter()
+            .filter(|rig| rig.impl_block.schema == schema.name)
+            .collect();
+        rigs.sort_by(|a, b| a.impl_block.name.cmp(&b.impl_block.name));
+
+        blocks.push(Block::Heading(2, schema.name.clone()));
+        if rigs.is_empty() {
+            blocks.push(Block::Paragraph(
+                "No rig implements this schema.".to_string(),
+            ));
+            continue;
+        }
+        schema_blocks(schema, &rigs, &mut blocks);
+    }
+    blocks
+}
+
+fn schema_blocks(schema: &SchemaFile, rigs: &[&RigFile], blocks: &mut Vec<Block>) {
+    let header = |columns: &[&str]| -> Vec<String> {
+        columns
+            .iter()
+            .map(|column| column.to_string())
+            .chain(rigs.iter().map(|rig| rig.impl_block.name.clone()))
+            .collect()
+    };
+    let mark = |supported: bool| if supported { SUPPORTED } else { UNSUPPORTED }.to_string();
+
+    blocks.push(Block::Heading(3, "Commands".to_string()));
+    blocks.push(Block::Table {
+        header: header(&["Command", "Parameters", "Description"]),
+        rows: schema
+            .commands
+            .iter()
+            .map(|(name, command)| {
+                let parameters: Vec<_> = command.parameters.iter().map(parameter).collect();
+                [
+                    name.clone(),
+                    parameters.join(", "),
+                    command.doc.clone().unwrap_or_default(),
+                ]
+                .into_iter()
+                .chain(
+                    rigs.iter()
+                        .map(|rig| mark(rig.impl_block.commands.contains_key(name))),
+                )
+                .collect()
+            })
+            .collect(),
+    });
+
+    let status_fields: Vec<_> = rigs
+        .iter()
+        .map(|rig| rig.get_supported_status_fields())
+        .collect();
+    blocks.push(Block::Heading(3, "Status fields".to_string()));
+    blocks.push(Block::Table {
+        header: header(&["Field", "Type", "Description"]),
+        rows: schema
+            .status
+            .iter()
+            .map(|(name, field)| {
+                [
+                    name.clone(),
+                    field_type(field),
+                    field.doc.clone().unwrap_or_default(),
+                ]
+                .into_iter()
+                .chain(
+                    status_fields
+                        .iter()
+                        .map(|fields| mark(fields.contains(name))),
+                )
+                .collect()
+            })
+            .collect(),
+    });
+
+    if !schema.enums.is_empty() {
+        blocks.push(Block::Heading(3, "Enum mappings".to_string()));
+        blocks.push(Block::Paragraph(
+            "Values the rigs send for each variant.".to_string(),
+        ));
+    }
+    for (enum_name, variants) in &schema.enums {
+        let mappings: Vec<_> = rigs.iter().map(|rig| rig.enum_mappings()).collect();
+        blocks.push(Block::Heading(4, enum_name.clone()));
+        blocks.push(Block::Table {
+            header: header(&["Variant"]),
+            rows: variants
+                .iter()
+                .map(|variant| {
+                    std::iter::once(variant.clone())
+                        .chain(mappings.iter().map(|mapping| {
+                            mapping
+                                .get(enum_name)
+                                .and_then(|values| values.get(variant))
+                                .map_or(UNSUPPORTED.to_string(), |value| value.to_string())
+                        }))
+                        .collect()
+                })
+                .collect(),
+        });
+    }
+
+    let capabilities: Vec<_> = rigs.iter().map(|rig| rig.capabilities()).collect();
+    if capabilities.iter().any(Option::is_some) {
+        let row = |name: &str, cell: &dyn Fn(&crate::runtime::Capabilities) -> String| {
+            std::iter::once(name.to_string())
+                .chain(capabilities.iter().map(|capabilities| {
+                    capabilities.map_or(UNSUPPORTED.to_string(), |capabilities| {
+                        let cell = cell(capabilities);
+                        if cell.is_empty() {
+                            UNSUPPORTED.to_string()
+                        } else {
+                            cell
+                        }
+                    })
+                }))
+                .collect()
+        };
+        let join = |values: Vec<String>| values.join(", ");
+        blocks.push(Block::Heading(3, "Declared ranges".to_string()));
+        blocks.push(Block::Table {
+            header: header(&["Capability"]),
+            rows: vec![
+                row("Receive", &|capabilities| {
+                    join(capabilities.rx_ranges.iter().map(frequency_range).collect())
+                }),
+                row("Transmit", &|capabilities| {
+                    join(capabilities.tx_ranges.iter().map(frequency_range).collect())
+                }),
+                row("Modes", &|capabilities| join(capabilities.modes.clone())),
+                row("Tuning steps", &|capabilities| {
+                    join(
+                        capabilities
+                            .tuning_steps
+                            .iter()
+                            .map(|step| frequency(*step))
+                            .collect(),
+                    )
+                }),
+                row("Hamlib model", &|capabilities| {
+                    capabilities
+                        .hamlib_model
+                        .map(|model| model.to_string())
+                        .unwrap_or_default()
+                }),
+                row("Hamlib modes", &|capabilities| {
+                    join(
+                        capabilities
+                            .hamlib_modes
+                            .iter()
+                            .map(|mode| format!("{} as {}", mode.mode, mode.hamlib))
+                            .collect(),
+                    )
+                }),
+                row("Passbands", &|capabilities| {
+                    join(
+                        capabilities
+                            .passbands
+                            .iter()
+                            .map(|passbands| {
+                                let widths: Vec<_> =
+                                    passbands.widths.iter().map(|w| frequency(*w)).collect();
+                                format!("{} ({})", passbands.mode, widths.join(" / "))
+                            })
+                            .collect(),
+                    )
+                }),
+            ],
+        });
+    }
+
+    let settings: BTreeSet<_> = rigs
+        .iter()
+        .flat_map(|rig| rig.settings.settings.keys())
+        .filter(|name| name.as_str() != "version")
+        .collect();
+    if !settings.is_empty() {
+        blocks.push(Block::Heading(3, "Settings".to_string()));
+        blocks.push(Block::Table {
+            header: header(&["Setting"]),
+            rows: settings
+                .into_iter()
+                .map(|name| {
+                    std::iter::once(name.to_string())
+                        .chain(rigs.iter().map(|rig| {
+                            rig.settings
+                                .settings
+                                .get(name)
+                                .map_or(UNSUPPORTED.to_string(), |value| value.to_string())
+                        }))
+                        .collect()
+                })
+                .collect(),
+        });
+    }
+}
+
+/// Type of a parameter with its declared range, e.g. `int<300..900>`.
+fn field_type(field: &SchemaParameter) -> String {
+    match &field.range {
+        Some(range) => format!("{}<{}..{}>", field.param_type, range.start(), range.end()),
+        None => field.param_type.to_string(),
+    }
+}
+
+fn parameter(parameter: &SchemaParameter) -> String {
+    let mut description = format!("{} {}", field_type(parameter), parameter.name);
+    let attributes: Vec<_> = [
+        parameter.unit.clone(),
+        parameter.step.map(|step| format!("step {step}")),
+        parameter
+            .default
+            .as_ref()
+            .map(|default| format!("default {default}")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !attributes.is_empty() {
+        let _ = write!(description, " ({})", attributes.join(", "));
+    }
+    description
+}
+
+/// Frequency in the largest unit keeping it readable, e.g. `7.1 MHz`.
+fn frequency(hz: i64) -> String {
+    let (value, unit) = match hz.abs() {
+        1_000_000.. => (hz as f64 / 1_000_000.0, "MHz"),
+        1_000.. => (hz as f64 / 1_000.0, "kHz"),
+        _ => (hz as f64, "Hz"),
+    };
+    format!("{value} {unit}")
+}
+
+fn frequency_range(range: &FrequencyRange) -> String {
+    let mut description = format!("{}–{}", frequency(range.min), frequency(range.max));
+    if !range.modes.is_empty() {
+        let _ = write!(description, " ({})", range.modes.join(", "));
+    }
+    description
+}
+
+fn render_markdown(blocks: &[Block]) -> String {
+    let escape = |text: &str| text.replace('|', "\\|");
+    let mut output = String::new();
+    for block in blocks {
+        match block {
+            Block::Heading(level, text) => {
+                let _ = writeln!(output, "{} {text}\n", "#".repeat(*level));
+            }
+            Block::Paragraph(text) => {
+                let _ = writeln!(output, "{text}\n");
+            }
+            Block::Table { header, rows } => {
+                let line = |cells: &[String]| {
+                    let cells: Vec<_> = cells.iter().map(|cell| escape(cell)).collect();
+                    format!("| {} |", cells.join(" | "))
+                };
+                let _ = writeln!(output, "{}", line(header));
+                let _ = writeln!(output, "|{}", "---|".repeat(header.len()));
+                for row in rows {
+                    let _ = writeln!(output, "{}", line(row));
+                }
+                output.push('\n');
+            }
+        }
+    }
+    output.truncate(output.trim_end().len());
+    output.push('\n');
+    output
+}
+
+fn render_html(blocks: &[Block]) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+    let mut output = String::new();
+    for block in blocks {
+        match block {
+            Block::Heading(level, text) => {
+                let _ = writeln!(output, "<h{level}>{}</h{level}>", escape(text));
+            }
+            Block::Paragraph(text) => {
+                let _ = writeln!(output, "<p>{}</p>", escape(text));
+            }
+            Block::Table { header, rows } => {
+                output.push_str("<table>\n<thead>\n<tr>");
+                for cell in header {
+                    let _ = write!(output, "<th>{}</th>", escape(cell));
+                }
+                output.push_str("</tr>\n</thead>\n<tbody>\n");
+                for row in rows {
+                    output.push_str("<tr>");
+                    for cell in row {
+                        let _ = write!(output, "<td>{}</td>", escape(cell));
+                    }
+                    output.push_str("</tr>\n");
+                }
+                output.push_str("</tbody>\n</table>\n");
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{parse_and_validate_with_schema, parse_schema};
+
+    const SCHEMA: &str = r#"
+        version = 1;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                B,
+            }
+
+            /// Tune a VFO to the given frequency.
+            fn set_freq(int<30000..470000000> freq [unit = s"Hz"], Vfo target [default = Vfo::A]);
+            /// Enable or disable | split.
+            fn set_split(bool split);
+
+            status {
+                int freq_a [unit = s"Hz"];
+                bool transmit;
+            }
+        }
+    "#;
+
+    const FULL_RIG: &str = r#"
+        version = 1;
+        baudrate = 19200;
+
+        impl Transceiver for Full {
+            enum Vfo {
+                A = 0,
+                B = 1,
+            }
+
+            capabilities {
+                rx_ranges = [30000..60000000];
+                tx_ranges = [7000000..7300000];
+                tuning_steps = [10];
+            }
+
+            fn set_freq(int freq, Vfo target) {
+                write("00.{target:1}.{freq:bcd_lu:5}");
+                read("00");
+            }
+
+            fn set_split(bool split) {
+                write("01.{split:1}");
+                read("01");
+            }
+
+            status {
+                write("02");
+                read("02.{freq_a:bcd_lu:5}");
+                set_var(s"freq_a", freq_a);
+            }
+        }
+    "#;
+
+    const BASIC_RIG: &str = r#"
+        impl Transceiver for Basic {
+            enum Vfo {
+                A = 3,
+            }
+
+            fn set_freq(int freq, Vfo target) {
+                write("00.{freq:bcd_lu:5}");
+                read("00");
+            }
+        }
+    "#;
+
+    fn rigs() -> (HashMap<String, SchemaFile>, Vec<RigFile>) {
+        let schema = parse_schema(SCHEMA).unwrap();
+        let schemas = HashMap::from([(schema.name.clone(), schema)]);
+        let rigs = [BASIC_RIG, FULL_RIG]
+            .map(|rig| parse_and_validate_with_schema(rig, &schemas).unwrap())
+            .to_vec();
+        (schemas, rigs)
+    }
+
+    #[test]
+    fn test_markdown_matrix() {
+        let (schemas, rigs) = rigs();
+        let markdown = generate(&schemas, &rigs, Format::Markdown);
+        let lines: Vec<_> = markdown.lines().collect();
+
+        let contains = |line: &str| lines.contains(&line);
+        assert!(contains("## Transceiver"));
+        assert!(contains(
+            "| Command | Parameters | Description | Basic | Full |"
+        ));
+        assert!(contains(
+            "| set_freq | int<30000..470000000> freq (Hz), Vfo target (default A) \
+             | Tune a VFO to the given frequency. | ✓ | ✓ |"
+        ));
+        assert!(contains(
+            "| set_split | bool split | Enable or disable \\| split. | — | ✓ |"
+        ));
+        assert!(contains("| freq_a | int |  | — | ✓ |"));
+        assert!(contains("| A | 3 | 0 |"));
+        assert!(contains("| B | — | 1 |"));
+        assert!(contains("| Receive | — | 30 kHz–60 MHz |"));
+        assert!(contains("| Transmit | — | 7 MHz–7.3 MHz |"));
+        assert!(contains("| baudrate | — | 19200 |"));
+        assert!(!markdown.contains("version"));
+    }
+
+    #[test]
+    fn test_html_matrix() {
+        let (schemas, rigs) = rigs();
+        let html = generate(&schemas, &rigs, Format::Html);
+        assert!(html.contains("<h2>Transceiver</h2>"));
+        assert!(html.contains("<th>Basic</th><th>Full</th>"));
+        assert!(html.contains("<td>int&lt;30000..470000000&gt; freq (Hz), "));
+        assert_eq!(
+            html.matches("<table>").count(),
+            html.matches("</table>").count()
+        );
+    }
+}
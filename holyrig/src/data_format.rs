@@ -283,6 +283,39 @@ impl DataFormat {
         }
     }
 
+    /// Smallest and largest value that fits in `length` bytes of this format. Signed BCD
+    /// values keep the first byte for the sign when negative.
+    pub fn capacity(&self, length: usize) -> (i64, i64) {
+        let length = u32::try_from(length).unwrap_or(u32::MAX);
+        let bits = length.saturating_mul(8).max(1);
+        // `None` once the value no longer fits below `i64::MAX`.
+        let bound = |bits: u32| 1i64.checked_shl(bits).filter(|bound| *bound > 0);
+        let (min, max) = match self {
+            DataFormat::BcdBu | DataFormat::BcdLu => {
+                (0, 10i64.saturating_pow(length.saturating_mul(2)) - 1)
+            }
+            DataFormat::BcdBs | DataFormat::BcdLs => (
+                1 - 10i64.saturating_pow(length.saturating_sub(1).saturating_mul(2)),
+                10i64.saturating_pow(length.saturating_mul(2)) - 1,
+            ),
+            DataFormat::IntBu | DataFormat::IntLu => {
+                (0, bound(bits).map_or(i64::MAX, |bound| bound - 1))
+            }
+            DataFormat::IntBs | DataFormat::IntLs => {
+                bound(bits - 1).map_or((i64::MIN, i64::MAX), |bound| (-bound, bound - 1))
+            }
+            DataFormat::Text => (
+                1 - 10i64.saturating_pow(length.saturating_sub(1)),
+                10i64.saturating_pow(length) - 1,
+            ),
+        };
+        // Values are encoded as `i32`, unsigned integers reinterpret its bits.
+        match self {
+            DataFormat::IntBu | DataFormat::IntLu => (min, max.min(u32::MAX.into())),
+            _ => (min.max(i32::MIN.into()), max.min(i32::MAX.into())),
+        }
+    }
+
     fn decode_bcd_bs(data: &[u8]) -> Result<i32, DataFormatError> {
         let is_negative = data[0] == 0xFF;
         if is_negative && data.len() == 1 {
@@ -544,6 +577,48 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_capacity() -> Result<(), DataFormatError> {
+        assert_eq!(DataFormat::BcdLu.capacity(5), (0, i32::MAX.into()));
+        assert_eq!(DataFormat::BcdBu.capacity(2), (0, 9999));
+        assert_eq!(DataFormat::BcdBs.capacity(2), (-99, 9999));
+        assert_eq!(DataFormat::IntLu.capacity(1), (0, 255));
+        assert_eq!(DataFormat::IntLu.capacity(4), (0, u32::MAX.into()));
+        assert_eq!(DataFormat::IntBs.capacity(2), (-32768, 32767));
+        assert_eq!(DataFormat::Text.capacity(3), (-99, 999));
+
+        for length in [8, 9, 15, 16, usize::MAX] {
+            assert_eq!(DataFormat::IntLu.capacity(length), (0, u32::MAX.into()));
+            assert_eq!(DataFormat::IntBu.capacity(length), (0, u32::MAX.into()));
+            assert_eq!(
+                DataFormat::IntLs.capacity(length),
+                (i32::MIN.into(), i32::MAX.into())
+            );
+            assert_eq!(
+                DataFormat::BcdBs.capacity(length),
+                (i32::MIN.into(), i32::MAX.into())
+            );
+        }
+        assert_eq!(DataFormat::Text.capacity(8), (-9_999_999, 99_999_999));
+        assert_eq!(
+            DataFormat::Text.capacity(16),
+            (i32::MIN.into(), i32::MAX.into())
+        );
+
+        for (format, length) in [
+            (DataFormat::BcdBu, 2),
+            (DataFormat::BcdBs, 2),
+            (DataFormat::IntLu, 1),
+            (DataFormat::Text, 3),
+        ] {
+            let (min, max) = format.capacity(length);
+            format.encode(min as i32, length)?;
+            format.encode(max as i32, length)?;
+            assert!(format.encode(max as i32 + 1, length).is_err());
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_decode_bcd() -> Result<(), DataFormatError> {
         assert_eq!(DataFormat::BcdBs.decode(&[0x00, 0x00, 0x04, 0x18])?, 418);
@@ -1,5 +1,5 @@
 use crate::{
-    rig_settings::{BaudRate, DataBits, RigSettings, StopBits},
+    rig_settings::{BaudRate, DataBits, DeviceClass, RigSettings, StopBits},
     serial::ManagerCommand,
 };
 use eframe::egui;
@@ -8,6 +8,7 @@ use egui_dock::{
     AllowedSplits, DockArea, DockState, NodeIndex, SurfaceIndex, TabViewer,
     tab_viewer::OnCloseResponse,
 };
+use std::collections::HashMap;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 pub enum GuiMessage {
@@ -17,17 +18,20 @@ pub enum GuiMessage {
 struct AppTabViewer {
     current_index: usize,
     add_tab_request: bool,
-    rig_types: Vec<String>,
+    rig_types: HashMap<DeviceClass, Vec<String>>,
     sender: Sender<ManagerCommand>,
     error_message: Option<String>,
     active_tab_id: Option<usize>,
+    /// Settings of every tab, checked for conflicting listeners
+    rigs: Vec<RigSettings>,
 }
 
 impl AppTabViewer {
     fn new(
         sender: Sender<ManagerCommand>,
-        rig_types: Vec<String>,
+        rig_types: HashMap<DeviceClass, Vec<String>>,
         active_tab_id: Option<usize>,
+        rigs: Vec<RigSettings>,
     ) -> Self {
         AppTabViewer {
             current_index: 0,
@@ -36,6 +40,7 @@ impl AppTabViewer {
             sender,
             error_message: None,
             active_tab_id,
+            rigs,
         }
     }
 }
@@ -58,11 +63,30 @@ impl TabViewer for AppTabViewer {
             ui.style_mut().spacing.combo_width *= 0.75;
 
             Grid::new("rig_settings").num_columns(2).show(ui, |ui| {
+                ui.label("Device class:");
+                ComboBox::from_id_salt("device_class")
+                    .selected_text(rig.device_class.to_string())
+                    .show_ui(ui, |ui| {
+                        for class in DeviceClass::iter_classes() {
+                            ui.selectable_value(&mut rig.device_class, class, class.to_string());
+                        }
+                    });
+                ui.end_row();
+
+                let rig_types = self
+                    .rig_types
+                    .get(&rig.device_class)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                if !rig_types.contains(&rig.rig_type) {
+                    rig.rig_type = RigSettings::default().rig_type;
+                }
+
                 ui.label("Rig type:");
                 ComboBox::from_id_salt("rig_type")
                     .selected_text(rig.rig_type.to_string())
                     .show_ui(ui, |ui| {
-                        for rig_type in &self.rig_types {
+                        for rig_type in rig_types {
                             ui.selectable_value(
                                 &mut rig.rig_type,
                                 rig_type.clone(),
@@ -125,6 +149,30 @@ impl TabViewer for AppTabViewer {
                 ui.label("Timeout (ms):");
                 ui.add(egui::DragValue::new(&mut rig.timeout).range(10..=5000));
                 ui.end_row();
+
+                if rig.device_class == DeviceClass::Transceiver {
+                    ui.label("Rigctld:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut rig.rigctld.enabled, "");
+                        ui.add_enabled(
+                            rig.rigctld.enabled,
+                            egui::TextEdit::singleline(&mut rig.rigctld.address),
+                        );
+                    });
+                    ui.end_row();
+                }
+
+                if rig.device_class == DeviceClass::Rotator {
+                    ui.label("Rotctld:");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut rig.rotctld.enabled, "");
+                        ui.add_enabled(
+                            rig.rotctld.enabled,
+                            egui::TextEdit::singleline(&mut rig.rotctld.address),
+                        );
+                    });
+                    ui.end_row();
+                }
             });
 
             ui.separator();
@@ -132,7 +180,7 @@ impl TabViewer for AppTabViewer {
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                 if ui.button("OK").clicked() {
                     let sender = self.sender.clone();
-                    match rig.validate() {
+                    match rig.validate(&self.rigs) {
                         Ok(_) => {
                             let tab = rig.clone();
                             tokio::task::spawn(async move {
@@ -181,13 +229,13 @@ impl TabViewer for AppTabViewer {
 
 struct AppTabs {
     dock_state: DockState<RigSettings>,
-    rig_types: Vec<String>,
+    rig_types: HashMap<DeviceClass, Vec<String>>,
     sender: Sender<ManagerCommand>,
     current_device_id: usize,
 }
 
 impl AppTabs {
-    fn new(sender: Sender<ManagerCommand>, rig_types: Vec<String>) -> Self {
+    fn new(sender: Sender<ManagerCommand>, rig_types: HashMap<DeviceClass, Vec<String>>) -> Self {
         let dock_state = DockState::new(vec![RigSettings::default()]);
         Self {
             dock_state,
@@ -220,8 +268,17 @@ impl AppTabs {
                     .map(|(_, rig)| rig.tabs[0].id)
             });
 
-        let mut tab_viewer =
-            AppTabViewer::new(self.sender.clone(), self.rig_types.clone(), active_tab_id);
+        let rigs: Vec<_> = self
+            .dock_state
+            .iter_all_tabs()
+            .map(|(_, rig)| rig.clone())
+            .collect();
+        let mut tab_viewer = AppTabViewer::new(
+            self.sender.clone(),
+            self.rig_types.clone(),
+            active_tab_id,
+            rigs.clone(),
+        );
 
         DockArea::new(&mut self.dock_state)
             .show_add_buttons(true)
@@ -235,9 +292,11 @@ impl AppTabs {
 
         if tab_viewer.add_tab_request {
             self.current_device_id += 1;
-            self.dock_state
-                .main_surface_mut()
-                .push_to_first_leaf(RigSettings::default().with_id(self.current_device_id));
+            self.dock_state.main_surface_mut().push_to_first_leaf(
+                RigSettings::default()
+                    .with_id(self.current_device_id)
+                    .with_free_ports(&rigs),
+            );
             tab_viewer.add_tab_request = false;
         }
     }
@@ -252,7 +311,7 @@ impl App {
     pub fn new(
         gui_receiver: Receiver<GuiMessage>,
         serial_sender: Sender<ManagerCommand>,
-        rig_types: Vec<String>,
+        rig_types: HashMap<DeviceClass, Vec<String>>,
     ) -> Self {
         App {
             gui_receiver,
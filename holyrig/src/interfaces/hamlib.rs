@@ -0,0 +1,318 @@
+//! Definitions shared by the hamlib network protocols, rigctld and rotctld.
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::rig_settings::{DeviceClass, RigSettings};
+use crate::runtime::Value;
+use crate::serial::ManagerCommand;
+use crate::serial::manager::{CommandErrorKind, ManagerMessage};
+
+/// Hamlib error codes, answered negated in `RPRT` lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RigError {
+    /// Invalid parameter
+    Invalid = 1,
+    /// Invalid configuration
+    Config = 2,
+    /// Memory shortage
+    NoMemory = 3,
+    /// Function not implemented
+    NotImplemented = 4,
+    /// Communication timed out
+    Timeout = 5,
+    /// IO error
+    Io = 6,
+    /// Internal error
+    Internal = 7,
+    /// Protocol error
+    Protocol = 8,
+    /// Command rejected by the rig
+    Rejected = 9,
+    /// Argument truncated
+    Truncated = 10,
+    /// Function not available
+    NotAvailable = 11,
+    /// VFO not targetable
+    NotTargetable = 12,
+    /// Error talking on the bus
+    Bus = 13,
+    /// Collision on the bus
+    BusBusy = 14,
+    /// Invalid argument
+    Argument = 15,
+    /// Invalid VFO
+    Vfo = 16,
+    /// Argument out of domain
+    Domain = 17,
+}
+
+impl RigError {
+    /// Status code sent in `RPRT` lines.
+    pub fn code(self) -> i32 {
+        -(self as i32)
+    }
+}
+
+impl fmt::Display for RigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPRT {}", self.code())
+    }
+}
+
+impl From<CommandErrorKind> for RigError {
+    fn from(kind: CommandErrorKind) -> Self {
+        match kind {
+            CommandErrorKind::DeviceNotFound | CommandErrorKind::Io => RigError::Io,
+            CommandErrorKind::UnknownCommand => RigError::NotImplemented,
+            CommandErrorKind::InvalidParams => RigError::Invalid,
+            CommandErrorKind::Timeout => RigError::Timeout,
+            CommandErrorKind::Protocol => RigError::Protocol,
+            CommandErrorKind::Internal => RigError::Internal,
+        }
+    }
+}
+
+/// `RPRT` line reporting the outcome of a command.
+pub fn report(result: Result<(), RigError>) -> String {
+    match result {
+        Ok(()) => "RPRT 0\n".to_string(),
+        Err(err) => format!("{err}\n"),
+    }
+}
+
+/// Delay before accepting again after a failed accept, e.g. once out of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// State of a device shared by the clients of its listener.
+pub trait DeviceState: Send + Sync + 'static {
+    fn new(settings: &RigSettings) -> Self;
+
+    /// Applies settings that changed while the listener kept its address.
+    fn configure(&mut self, settings: &RigSettings);
+
+    fn set_connected(&mut self, connected: bool);
+
+    fn update(&mut self, values: HashMap<String, Value>);
+}
+
+/// Serves a client connection on the state of the device it connected to.
+pub type SessionFactory<S> = Arc<
+    dyn Fn(
+            TcpStream,
+            SocketAddr,
+            Arc<RwLock<S>>,
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Hamlib instance serving one device.
+struct Listener<S> {
+    /// Configured address, the listener restarts when it changes
+    address: String,
+    local_addr: SocketAddr,
+    state: Arc<RwLock<S>>,
+    task: JoinHandle<()>,
+}
+
+/// Hamlib listeners of the devices of a class, by device id, following the manager messages.
+pub struct Listeners<S> {
+    /// Protocol name used in the logs
+    name: &'static str,
+    class: DeviceClass,
+    command_sender: Sender<ManagerCommand>,
+    session: SessionFactory<S>,
+    listeners: HashMap<usize, Listener<S>>,
+}
+
+impl<S: DeviceState> Listeners<S> {
+    pub fn new(
+        name: &'static str,
+        class: DeviceClass,
+        command_sender: Sender<ManagerCommand>,
+        session: SessionFactory<S>,
+    ) -> Self {
+        Self {
+            name,
+            class,
+            command_sender,
+            session,
+            listeners: HashMap::new(),
+        }
+    }
+
+    /// Ids of the devices with a running listener, in order.
+    pub fn device_ids(&self) -> Vec<usize> {
+        let mut device_ids: Vec<_> = self.listeners.keys().copied().collect();
+        device_ids.sort();
+        device_ids
+    }
+
+    pub fn local_addr(&self, device_id: usize) -> Option<SocketAddr> {
+        self.listeners
+            .get(&device_id)
+            .map(|listener| listener.local_addr)
+    }
+
+    pub fn state(&self, device_id: usize) -> Option<Arc<RwLock<S>>> {
+        self.listeners
+            .get(&device_id)
+            .map(|listener| listener.state.clone())
+    }
+
+    async fn start(&self, settings: &RigSettings, address: String) -> Result<Listener<S>> {
+        let (name, device_id) = (self.name, settings.id);
+        let listener = TcpListener::bind(&address).await?;
+        let local_addr = listener.local_addr()?;
+        println!("{name} server of device {device_id} listening on {local_addr}");
+
+        let state = Arc::new(RwLock::new(S::new(settings)));
+        let session = self.session.clone();
+        let client_state = state.clone();
+        let task = tokio::spawn(async move {
+            // Aborting the task drops the clients, which disconnects them
+            let mut clients = JoinSet::new();
+            loop {
+                let (socket, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        eprintln!("{name} server of device {device_id} failed to accept: {err}");
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                let client = session(socket, addr, client_state.clone());
+                clients.spawn(async move {
+                    if let Err(err) = client.await {
+                        eprintln!("{name} client {addr} failed: {err}");
+                    }
+                });
+                while clients.try_join_next().is_some() {}
+            }
+        });
+
+        Ok(Listener {
+            address,
+            local_addr,
+            state,
+            task,
+        })
+    }
+
+    async fn stop(&mut self, device_id: usize) {
+        if let Some(listener) = self.listeners.remove(&device_id) {
+            listener.task.abort();
+            let _ = listener.task.await;
+            println!("{} server on {} stopped", self.name, listener.local_addr);
+        }
+    }
+
+    /// Starts, restarts or stops the listener of a device to match its settings.
+    async fn configure(&mut self, settings: &RigSettings) {
+        let device_id = settings.id;
+        let address = (settings.device_class == self.class)
+            .then(|| settings.hamlib_address())
+            .flatten()
+            .map(str::to_string);
+        if let Some(listener) = self.listeners.get(&device_id)
+            && Some(&listener.address) == address.as_ref()
+        {
+            listener.state.write().configure(settings);
+            return;
+        }
+
+        self.stop(device_id).await;
+        let Some(address) = address else {
+            return;
+        };
+        match self.start(settings, address.clone()).await {
+            Ok(listener) => {
+                self.listeners.insert(device_id, listener);
+            }
+            Err(err) => eprintln!(
+                "{} server of device {device_id} can't listen on {address}: {err}",
+                self.name
+            ),
+        }
+    }
+
+    pub async fn handle_message(&mut self, message: ManagerMessage) {
+        match message {
+            ManagerMessage::DeviceAdded { settings } => self.configure(&settings).await,
+            ManagerMessage::DeviceRemoved { device_id } => self.stop(device_id).await,
+            ManagerMessage::DeviceConnected { device_id, .. } => {
+                if let Some(listener) = self.listeners.get(&device_id) {
+                    listener.state.write().set_connected(true);
+                }
+            }
+            ManagerMessage::DeviceDisconnected { device_id } => {
+                if let Some(listener) = self.listeners.get(&device_id) {
+                    listener.state.write().set_connected(false);
+                }
+            }
+            ManagerMessage::StatusUpdate { device_id, values } => {
+                if let Some(listener) = self.listeners.get(&device_id) {
+                    listener.state.write().update(values);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Matches the listeners to the devices of the manager, after missing some of its messages.
+    pub async fn resync(&mut self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.command_sender
+            .send(ManagerCommand::GetDevices {
+                response_channel: tx,
+            })
+            .await?;
+        let devices = rx.await?;
+
+        for device_id in self.device_ids() {
+            if !devices.iter().any(|(settings, _)| settings.id == device_id) {
+                self.stop(device_id).await;
+            }
+        }
+        for (settings, connected) in devices {
+            self.configure(&settings).await;
+            if let Some(listener) = self.listeners.get(&settings.id) {
+                listener.state.write().set_connected(connected);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the next manager message, resyncing after missed ones. `false` once the manager
+    /// is gone.
+    pub async fn receive(&mut self, receiver: &mut Receiver<ManagerMessage>) -> Result<bool> {
+        match receiver.recv().await {
+            Ok(message) => self.handle_message(message).await,
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!("{} servers missed {skipped} manager messages", self.name);
+                self.resync().await?;
+            }
+            Err(RecvError::Closed) => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub async fn run(mut self, mut receiver: Receiver<ManagerMessage>) -> Result<()> {
+        while self.receive(&mut receiver).await? {}
+        Ok(())
+    }
+}
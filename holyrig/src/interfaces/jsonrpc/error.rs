@@ -92,6 +92,17 @@ impl RpcError {
         Self::new(Self::INVALID_COMMAND_PARAMS, msg)
     }
 
+    pub fn invalid_command_params_with_errors(
+        command: &str,
+        errors: serde_json::Map<String, Value>,
+    ) -> Self {
+        Self::with_data(
+            Self::INVALID_COMMAND_PARAMS,
+            format!("Invalid params for command: {command}"),
+            Value::Object(errors),
+        )
+    }
+
     pub fn subscription_error(msg: impl Into<String>) -> Self {
         Self::new(Self::SUBSCRIPTION_ERROR, msg)
     }
@@ -5,13 +5,19 @@ use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
 use super::{Request, Response, RpcError};
-use crate::runtime::{RigFile, SchemaFile};
+use crate::rig_settings::DeviceClass;
+use crate::runtime::{self, Capabilities, Interpreter, RigFile, SchemaFile, SemanticAnalyzer};
 use crate::serial::manager::ManagerCommand;
 
 pub struct RigRpcHandler {
     schema: SchemaFile,
     implemented_commands: HashSet<String>,
     implemented_status: HashSet<String>,
+    enums: HashMap<String, HashMap<String, u32>>,
+    rig_capabilities: Option<Capabilities>,
+    /// Coverage and lint warnings of the rig file against its schema.
+    warnings: Vec<Value>,
+    interpreter: Interpreter,
     command_sender: Sender<ManagerCommand>,
 }
 
@@ -23,11 +29,27 @@ impl RigRpcHandler {
     ) -> Self {
         let implemented_commands = rig_file.impl_block.commands.keys().cloned().collect();
         let implemented_status = rig_file.get_supported_status_fields();
+        let enums = rig_file.enum_mappings();
+        let rig_capabilities = rig_file.capabilities().cloned();
+        let warnings = SemanticAnalyzer::new(schema.clone())
+            .warnings(rig_file)
+            .iter()
+            .map(|warning| {
+                serde_json::json!({
+                    "code": warning.warning_type.code(),
+                    "message": warning.to_string(),
+                })
+            })
+            .collect();
 
         Self {
             schema: schema.clone(),
             implemented_commands,
             implemented_status,
+            enums,
+            rig_capabilities,
+            warnings,
+            interpreter: Interpreter::new(rig_file.clone()),
             command_sender,
         }
     }
@@ -48,9 +70,14 @@ impl RigRpcHandler {
     fn get_capabilities(&self) -> Result<Value> {
         let mut capabilities = serde_json::Map::new();
 
+        capabilities.insert("schema".to_string(), self.schema.name.clone().into());
+        if let Some(class) = DeviceClass::from_schema(&self.schema.name) {
+            capabilities.insert("class".to_string(), class.to_string().to_lowercase().into());
+        }
+
         let mut commands = serde_json::Map::new();
         for cmd_name in &self.implemented_commands {
-            let params = self
+            let command = self
                 .schema
                 .commands
                 .get(cmd_name)
@@ -59,75 +86,114 @@ impl RigRpcHandler {
             let mut cmd_info = serde_json::Map::new();
             let mut parameters = serde_json::Map::new();
 
-            for param in params {
-                parameters.insert(
-                    param.name.clone(),
-                    Value::String(param.param_type.to_string()),
-                );
+            for param in &command.parameters {
+                parameters.insert(param.name.clone(), parameter_info(param));
             }
 
             cmd_info.insert("parameters".to_string(), Value::Object(parameters));
+            if let Some(doc) = &command.doc {
+                cmd_info.insert("doc".to_string(), Value::String(doc.clone()));
+            }
             commands.insert(cmd_name.clone(), Value::Object(cmd_info));
         }
         capabilities.insert("commands".to_string(), Value::Object(commands));
 
         let mut status_fields = serde_json::Map::new();
         for field_name in &self.implemented_status {
-            let field_type = self
+            let field = self
                 .schema
                 .status
                 .get(field_name)
                 .expect("Implemented status field should be in the schema");
-            status_fields.insert(field_name.clone(), Value::String(field_type.to_string()));
+            status_fields.insert(field_name.clone(), parameter_info(field));
         }
         capabilities.insert("status_fields".to_string(), Value::Object(status_fields));
 
+        if let Some(rig_capabilities) = &self.rig_capabilities {
+            capabilities.insert("rig".to_string(), serde_json::to_value(rig_capabilities)?);
+        }
+
+        capabilities.insert("warnings".to_string(), Value::Array(self.warnings.clone()));
+
         Ok(Value::Object(capabilities))
     }
 
-    async fn execute_command(
+    /// Validates the parameters of a command against the schema and the rig capabilities.
+    fn prepare_params(
         &self,
-        rig_id: usize,
-        command: String,
+        command: &str,
         params: HashMap<String, Value>,
-    ) -> Result<Value> {
+    ) -> Result<HashMap<String, runtime::Value>> {
         let command_params = self
             .schema
             .commands
-            .get(&command)
-            .ok_or_else(|| anyhow!(RpcError::unknown_command(&command)))?;
+            .get(command)
+            .ok_or_else(|| anyhow!(RpcError::unknown_command(command)))?;
 
-        if !self.implemented_commands.contains(&command) {
-            return Err(anyhow!(RpcError::new(
-                -32001,
-                format!("Command '{}' is not implemented by this rig", command)
-            )));
+        if !self.implemented_commands.contains(command) {
+            return Err(anyhow!(RpcError::invalid_command_params(format!(
+                "Command '{}' is not implemented by this rig",
+                command
+            ))));
         }
 
-        let mut string_params = HashMap::new();
-        for param in command_params {
-            let value = params.get(&param.name).ok_or_else(|| {
-                anyhow!(RpcError::invalid_command_params(format!(
-                    "Missing parameter: {}",
-                    param.name
-                )))
-            })?;
-
-            let value = match value {
-                Value::Bool(boolean) => boolean.to_string(),
-                Value::Number(number) => number.to_string(),
-                Value::String(string) => string.clone(),
-                value => todo!("{value:?}"),
-            };
-            string_params.insert(param.name.clone(), value.to_string());
+        let mut values = HashMap::new();
+        let mut errors = serde_json::Map::new();
+        for (name, value) in &params {
+            match runtime::Value::try_from(value) {
+                Ok(value) => {
+                    values.insert(name.clone(), value);
+                }
+                Err(err) => {
+                    errors.insert(name.clone(), err.to_string().into());
+                }
+            }
+        }
+
+        let typed_params = match command_params.prepare_args(values, &self.enums) {
+            Ok(typed_params) if errors.is_empty() => {
+                if let Some(rig_capabilities) = &self.rig_capabilities
+                    && let Err(arg_errors) =
+                        rig_capabilities.check_args(command_params, &typed_params, None)
+                {
+                    for (name, err) in arg_errors {
+                        errors.insert(name, err.to_string().into());
+                    }
+                }
+                typed_params
+            }
+            Ok(_) => HashMap::new(),
+            Err(arg_errors) => {
+                for (name, err) in arg_errors {
+                    errors.entry(name).or_insert_with(|| err.to_string().into());
+                }
+                HashMap::new()
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(anyhow!(RpcError::invalid_command_params_with_errors(
+                command, errors
+            )));
         }
 
+        Ok(typed_params)
+    }
+
+    async fn execute_command(
+        &self,
+        rig_id: usize,
+        command: String,
+        params: HashMap<String, Value>,
+    ) -> Result<Value> {
+        let typed_params = self.prepare_params(&command, params)?;
+
         let (tx, rx) = oneshot::channel();
         self.command_sender
             .send(ManagerCommand::ExecuteCommand {
                 device_id: rig_id,
                 command_name: command,
-                params: string_params,
+                params: typed_params,
                 response_channel: Some(tx),
             })
             .await?;
@@ -137,13 +203,37 @@ impl RigRpcHandler {
         Ok(response.into())
     }
 
+    /// Runs the command without touching the rig and returns the bytes it would exchange.
+    async fn explain_command(
+        &self,
+        command: String,
+        params: HashMap<String, Value>,
+        replies: Vec<Vec<u8>>,
+    ) -> Result<Value> {
+        let typed_params = self.prepare_params(&command, params)?;
+        let transcript = self
+            .interpreter
+            .dry_run(&command, typed_params, replies)
+            .await;
+
+        let entries: Vec<Value> = transcript.entries.iter().map(Value::from).collect();
+        match transcript.error {
+            None => Ok(serde_json::json!({ "transcript": entries })),
+            Some(err) => Err(anyhow!(RpcError::with_data(
+                RpcError::INTERNAL_ERROR,
+                format!("Command '{command}' failed: {err}"),
+                serde_json::json!({ "transcript": entries }),
+            ))),
+        }
+    }
+
     pub async fn handle_request(&self, request: &Request, rig_id: usize) -> Result<Response> {
         let response = match request.method.as_str() {
             "get_capabilities" => {
                 let result = self.get_capabilities()?;
                 Response::build_result(request.id.clone(), result)
             }
-            "execute_command" => {
+            "execute_command" | "explain_command" => {
                 let params = request
                     .params
                     .as_ref()
@@ -164,7 +254,29 @@ impl RigRpcHandler {
                     .map(|obj| obj.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
                     .unwrap_or_default();
 
-                match self.execute_command(rig_id, command, parameters).await {
+                let result = if request.method == "execute_command" {
+                    self.execute_command(rig_id, command, parameters).await
+                } else {
+                    let replies = params_map
+                        .get("replies")
+                        .and_then(|v| v.as_array())
+                        .map(|replies| {
+                            replies
+                                .iter()
+                                .map(|reply| {
+                                    reply
+                                        .as_str()
+                                        .and_then(|reply| runtime::parse_hex(reply).ok())
+                                        .ok_or_else(|| anyhow!(RpcError::invalid_params()))
+                                })
+                                .collect::<Result<Vec<_>>>()
+                        })
+                        .transpose()?
+                        .unwrap_or_default();
+                    self.explain_command(command, parameters, replies).await
+                };
+
+                match result {
                     Ok(result) => Response::build_result(request.id.clone(), result),
                     Err(err) => {
                         if let Some(rpc_err) = err.downcast_ref::<RpcError>() {
@@ -186,3 +298,84 @@ impl RigRpcHandler {
         Ok(response)
     }
 }
+
+fn parameter_info(param: &runtime::SchemaParameter) -> Value {
+    let mut info = serde_json::Map::new();
+    info.insert(
+        "type".to_string(),
+        Value::String(param.param_type.to_string()),
+    );
+    if let Some(range) = &param.range {
+        info.insert("min".to_string(), (*range.start()).into());
+        info.insert("max".to_string(), (*range.end()).into());
+    }
+    if let Some(unit) = &param.unit {
+        info.insert("unit".to_string(), Value::String(unit.clone()));
+    }
+    if let Some(step) = param.step {
+        info.insert("step".to_string(), step.into());
+    }
+    if param.rf {
+        info.insert("rf".to_string(), Value::Bool(true));
+    }
+    if param.tx {
+        info.insert("tx".to_string(), Value::Bool(true));
+    }
+    if let Some(default) = &param.default {
+        info.insert(
+            "default".to_string(),
+            default_value(&param.param_type, default),
+        );
+    }
+    if let Some(doc) = &param.doc {
+        info.insert("doc".to_string(), Value::String(doc.clone()));
+    }
+    Value::Object(info)
+}
+
+/// Declared defaults are kept as written in the schema, they are published with the JSON type
+/// of their parameter, or as written when they don't parse as that type.
+fn default_value(param_type: &runtime::DataType, default: &str) -> Value {
+    match param_type {
+        runtime::DataType::Int => default
+            .parse::<i64>()
+            .map_or_else(|_| Value::String(default.to_string()), Value::from),
+        runtime::DataType::Float => default
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or_else(|| Value::String(default.to_string()), Value::Number),
+        runtime::DataType::Bool => default
+            .parse::<bool>()
+            .map_or_else(|_| Value::String(default.to_string()), Value::Bool),
+        _ => Value::String(default.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::runtime::{DataType, SchemaParameter};
+
+    #[test]
+    fn test_parameter_defaults() {
+        let defaults = [
+            (DataType::Int, "-50", json!(-50)),
+            (DataType::Bool, "true", json!(true)),
+            (DataType::Bool, "false", json!(false)),
+            (DataType::Bool, "yes", json!("yes")),
+            (DataType::Float, "2.5", json!(2.5)),
+            (DataType::Float, "-1", json!(-1.0)),
+            (DataType::Enum("Vfo".to_string()), "A", json!("A")),
+        ];
+        for (param_type, default, expected) in defaults {
+            let param = SchemaParameter {
+                default: Some(default.to_string()),
+                ..SchemaParameter::new(param_type, "param")
+            };
+            assert_eq!(parameter_info(&param)["default"], expected);
+        }
+    }
+}
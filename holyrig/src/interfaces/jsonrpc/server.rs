@@ -1,7 +1,7 @@
 use anyhow::{Result, anyhow};
 use parking_lot::RwLock;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
@@ -10,7 +10,7 @@ use tokio::sync::{broadcast, mpsc};
 use super::{Notification, RigRpcHandler};
 use crate::interfaces::jsonrpc::{Request, Response, RpcError};
 use crate::resources::Resources;
-use crate::serial::manager::{ManagerCommand, ManagerMessage};
+use crate::serial::manager::{Debuggers, ManagerCommand, ManagerMessage};
 
 type Subscriptions = HashMap<(usize, SocketAddr), Vec<String>>;
 
@@ -20,6 +20,9 @@ pub struct JsonRpcServer {
     handlers: Arc<HashMap<String, RigRpcHandler>>,
     rigs_state: Arc<RwLock<HashMap<usize, (String, bool)>>>,
     registered_status: Arc<RwLock<Subscriptions>>,
+    registered_traces: Arc<RwLock<HashSet<(usize, SocketAddr)>>>,
+    debuggers: Debuggers,
+    command_tx: mpsc::Sender<ManagerCommand>,
     manager_rx: broadcast::Receiver<ManagerMessage>,
 }
 
@@ -30,6 +33,7 @@ impl JsonRpcServer {
         resources: Arc<Resources>,
         command_tx: mpsc::Sender<ManagerCommand>,
         manager_rx: broadcast::Receiver<ManagerMessage>,
+        debuggers: Debuggers,
     ) -> Result<Self> {
         let handlers = resources
             .rigs
@@ -48,6 +52,9 @@ impl JsonRpcServer {
             handlers: Arc::new(handlers),
             rigs_state: Arc::new(RwLock::new(HashMap::new())),
             registered_status: Arc::new(RwLock::new(HashMap::new())),
+            registered_traces: Arc::new(RwLock::new(HashSet::new())),
+            debuggers,
+            command_tx,
             manager_rx,
         })
     }
@@ -85,7 +92,14 @@ impl JsonRpcServer {
                         }
                     }
                     message = self.manager_rx.recv() => {
-                        let message = message.unwrap();
+                        let message = match message {
+                            Ok(message) => message,
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                eprintln!("JSON-RPC server skipped {skipped} manager messages");
+                                continue;
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        };
                         if let Err(err) = self.handle_manager_message(message, &socket).await {
                             eprintln!("Error handling manager message: {err}");
                         }
@@ -147,6 +161,82 @@ impl JsonRpcServer {
 
                 Response::build_success(request.id)
             }
+            "subscribe_trace" | "unsubscribe_trace" => {
+                let id = request
+                    .get_rig_id()
+                    .ok_or_else(|| anyhow!(RpcError::missing_rig_id()))?;
+                // Rigs whose device failed to open are listed but can't be traced
+                if !self.debuggers.read().contains_key(&id) {
+                    return Err(anyhow!(RpcError::unknown_rig_id(id).with_id(&request.id)));
+                }
+
+                let enabled = {
+                    let mut registered_traces = self.registered_traces.write();
+                    if request.method == "subscribe_trace" {
+                        registered_traces.insert((id, src_addr));
+                    } else {
+                        registered_traces.remove(&(id, src_addr));
+                    }
+                    registered_traces.iter().any(|(rig_id, _)| *rig_id == id)
+                };
+                if !enabled && let Some(debugger) = self.debuggers.read().get(&id) {
+                    // Nobody is left to resume the paused commands
+                    debugger.clear();
+                }
+                self.command_tx
+                    .send(ManagerCommand::SetTracing {
+                        device_id: id,
+                        enabled,
+                    })
+                    .await?;
+
+                Response::build_success(request.id)
+            }
+            "set_breakpoint" | "step" | "resume" => {
+                let id = request
+                    .get_rig_id()
+                    .ok_or_else(|| anyhow!(RpcError::missing_rig_id()))?;
+                let debugger =
+                    self.debuggers.read().get(&id).cloned().ok_or_else(|| {
+                        anyhow!(RpcError::unknown_rig_id(id).with_id(&request.id))
+                    })?;
+
+                match request.method.as_str() {
+                    "set_breakpoint" => {
+                        let params = request
+                            .params
+                            .as_ref()
+                            .and_then(|params| params.as_object());
+                        let line = params
+                            .and_then(|params| params.get("line"))
+                            .and_then(|line| line.as_u64())
+                            .ok_or_else(|| {
+                                anyhow!(RpcError::invalid_params().with_id(&request.id))
+                            })?;
+                        let enabled = params
+                            .and_then(|params| params.get("enabled"))
+                            .map_or(Some(true), |enabled| enabled.as_bool())
+                            .ok_or_else(|| {
+                                anyhow!(RpcError::invalid_params().with_id(&request.id))
+                            })?;
+                        if enabled {
+                            debugger.add_breakpoint(line as usize);
+                        } else {
+                            debugger.remove_breakpoint(line as usize);
+                        }
+                    }
+                    "step" => {
+                        debugger.set_stepping(true);
+                        debugger.resume();
+                    }
+                    _ => {
+                        debugger.set_stepping(false);
+                        debugger.resume();
+                    }
+                }
+
+                Response::build_success(request.id)
+            }
             _ => {
                 let id = request
                     .get_rig_id()
@@ -177,9 +267,18 @@ impl JsonRpcServer {
                     .map(|(device_id, rig_model)| (*device_id, (rig_model.clone(), false)))
                     .collect();
             }
+            ManagerMessage::DeviceAdded { settings } => {
+                let mut rigs_state = self.rigs_state.write();
+                let (rig_model, _) = rigs_state.entry(settings.id).or_default();
+                *rig_model = settings.rig_type;
+            }
+            ManagerMessage::DeviceRemoved { device_id } => {
+                self.rigs_state.write().remove(&device_id);
+            }
             ManagerMessage::DeviceConnected {
                 device_id,
                 rig_model,
+                ..
             } => {
                 self.rigs_state.write().insert(device_id, (rig_model, true));
             }
@@ -232,7 +331,105 @@ impl JsonRpcServer {
                     }
                 }
             }
+            ManagerMessage::Trace { device_id, event } => {
+                let clients: Vec<_> = self
+                    .registered_traces
+                    .read()
+                    .iter()
+                    .filter(|(id, _)| *id == device_id)
+                    .map(|(_, addr)| *addr)
+                    .collect();
+
+                let notification = Notification {
+                    jsonrpc: super::VERSION.into(),
+                    method: "trace".to_string(),
+                    params: json!({
+                        "rig_id": device_id,
+                        "event": serde_json::Value::from(&event),
+                    }),
+                };
+                let packet = serde_json::to_vec(&notification).unwrap();
+                for addr in clients {
+                    if let Err(err) = socket.send_to(&packet, addr).await {
+                        eprintln!("Failed to send trace to {addr}: {err}");
+                        self.registered_traces.write().remove(&(device_id, addr));
+                    }
+                }
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_trace_without_device() -> Result<()> {
+        let (command_tx, mut command_rx) = mpsc::channel(10);
+        let (_, manager_rx) = broadcast::channel(10);
+        let server = JsonRpcServer::new(
+            "127.0.0.1",
+            0,
+            Resources::load()?,
+            command_tx,
+            manager_rx,
+            Debuggers::default(),
+        )?;
+        server
+            .rigs_state
+            .write()
+            .insert(1, ("IC7300".to_string(), false));
+
+        let addr: SocketAddr = "127.0.0.1:4000".parse()?;
+        for method in ["subscribe_trace", "unsubscribe_trace"] {
+            let packet =
+                json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": {"rig_id": 1}});
+            let err = server
+                .handle_packet(packet.to_string().as_bytes(), addr)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<RpcError>().map(|err| err.code),
+                Some(RpcError::UNKNOWN_RIG_ID)
+            );
+        }
+        assert!(command_rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_command_params() -> Result<()> {
+        let (command_tx, _command_rx) = mpsc::channel(10);
+        let (_, manager_rx) = broadcast::channel(10);
+        let server = JsonRpcServer::new(
+            "127.0.0.1",
+            0,
+            Resources::load()?,
+            command_tx,
+            manager_rx,
+            Debuggers::default(),
+        )?;
+        server
+            .rigs_state
+            .write()
+            .insert(1, ("IC7300".to_string(), false));
+
+        let addr: SocketAddr = "127.0.0.1:4000".parse()?;
+        let packet = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "explain_command",
+            "params": {"rig_id": 1, "command": "set_freq", "parameters": {"freq": "fast"}},
+        });
+        let response = server
+            .handle_packet(packet.to_string().as_bytes(), addr)
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&response)?["error"]["code"],
+            RpcError::INVALID_COMMAND_PARAMS
+        );
+        Ok(())
+    }
+}
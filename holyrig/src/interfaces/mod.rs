@@ -1,3 +1,5 @@
+pub mod hamlib;
 pub mod jsonrpc;
 pub mod rigctld;
+pub mod rotctld;
 pub mod udp_server;
@@ -0,0 +1,776 @@
+//! The rigctld command set and its translation to the transceiver schema.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::modes::ModeMap;
+use super::{DeviceStatus, state};
+use crate::interfaces::hamlib::RigError;
+use crate::runtime::{RigFile, SchemaFile, Value};
+
+/// Levels supported by `l`/`L`.
+const LEVELS: &[&str] = &["CWPITCH"];
+/// Functions supported by `u`/`U`.
+const FUNCTIONS: &[&str] = &["RIT", "XIT"];
+
+/// Schema command with its parameters.
+pub(super) type SchemaCall = (&'static str, HashMap<String, Value>);
+
+/// Answer of a command handler.
+#[derive(Debug, PartialEq)]
+pub(super) enum Reply {
+    /// Values of the command outputs, in order
+    Values(Vec<String>),
+    /// Text answered as is, e.g. the state dump
+    Text(String),
+    /// Schema commands to run on the transceiver
+    Execute(Vec<SchemaCall>),
+    /// Switches the VFO mode of the session
+    VfoMode(bool),
+    /// Closes the connection
+    Quit,
+}
+
+/// State a command is answered from.
+pub(super) struct Context<'a> {
+    pub status: &'a DeviceStatus,
+    pub vfo_mode: bool,
+    /// Rig file of the transceiver, if its rig type is loaded
+    pub rig: Option<&'a RigFile>,
+    /// Transceiver schema
+    pub schema: Option<&'a SchemaFile>,
+}
+
+impl<'a> Context<'a> {
+    /// Modes of the transceiver.
+    pub fn modes(&self) -> ModeMap<'a> {
+        ModeMap::new(self.rig, self.schema)
+    }
+}
+
+type Handler = fn(&Invocation, &Context) -> Result<Reply, RigError>;
+
+/// Entry of the command table.
+pub(super) struct Command {
+    /// Single-character name, if any
+    pub short: Option<char>,
+    /// Long name, sent after a backslash
+    pub name: &'static str,
+    /// Takes a VFO before the arguments
+    pub vfo: bool,
+    /// Number of arguments
+    pub args: usize,
+    /// Last argument extends to the end of the line
+    pub text: bool,
+    /// Labels of the values in extended responses
+    pub outputs: &'static [&'static str],
+    handler: Handler,
+}
+
+const fn command(
+    short: Option<char>,
+    name: &'static str,
+    vfo: bool,
+    args: usize,
+    outputs: &'static [&'static str],
+    handler: Handler,
+) -> Command {
+    Command {
+        short,
+        name,
+        vfo,
+        args,
+        text: false,
+        outputs,
+        handler,
+    }
+}
+
+pub(super) static COMMANDS: &[Command] = &[
+    command(Some('F'), "set_freq", true, 1, &[], set_freq),
+    command(Some('f'), "get_freq", true, 0, &["Frequency"], get_freq),
+    command(Some('M'), "set_mode", true, 2, &[], set_mode),
+    command(
+        Some('m'),
+        "get_mode",
+        true,
+        0,
+        &["Mode", "Passband"],
+        get_mode,
+    ),
+    command(Some('V'), "set_vfo", false, 1, &[], set_vfo),
+    command(Some('v'), "get_vfo", false, 0, &["VFO"], get_vfo),
+    command(Some('T'), "set_ptt", true, 1, &[], set_ptt),
+    command(Some('t'), "get_ptt", true, 0, &["PTT"], get_ptt),
+    command(Some('S'), "set_split_vfo", true, 2, &[], set_split_vfo),
+    command(
+        Some('s'),
+        "get_split_vfo",
+        true,
+        0,
+        &["Split", "TX VFO"],
+        get_split_vfo,
+    ),
+    command(Some('I'), "set_split_freq", true, 1, &[], set_split_freq),
+    command(
+        Some('i'),
+        "get_split_freq",
+        true,
+        0,
+        &["TX Frequency"],
+        get_split_freq,
+    ),
+    command(Some('X'), "set_split_mode", true, 2, &[], not_implemented),
+    command(
+        Some('x'),
+        "get_split_mode",
+        true,
+        0,
+        &["TX Mode", "TX Passband"],
+        not_implemented,
+    ),
+    command(Some('J'), "set_rit", true, 1, &[], set_offset),
+    command(Some('j'), "get_rit", true, 0, &["RIT"], get_rit),
+    command(Some('Z'), "set_xit", true, 1, &[], set_offset),
+    command(Some('z'), "get_xit", true, 0, &["XIT"], get_xit),
+    command(Some('G'), "vfo_op", true, 1, &[], vfo_op),
+    command(Some('L'), "set_level", true, 2, &[], set_level),
+    command(Some('l'), "get_level", true, 1, &["Level Value"], get_level),
+    command(Some('U'), "set_func", true, 2, &[], set_func),
+    command(Some('u'), "get_func", true, 1, &["Func Status"], get_func),
+    Command {
+        text: true,
+        ..command(Some('b'), "send_morse", true, 1, &[], not_implemented)
+    },
+    command(Some('_'), "get_info", false, 0, &["Info"], get_info),
+    command(
+        None,
+        "get_powerstat",
+        false,
+        0,
+        &["Power Status"],
+        get_powerstat,
+    ),
+    command(None, "set_powerstat", false, 1, &[], set_powerstat),
+    command(None, "chk_vfo", false, 0, &["ChkVFO"], chk_vfo),
+    command(None, "set_vfo_opt", false, 1, &[], set_vfo_opt),
+    command(
+        None,
+        "get_vfo_info",
+        false,
+        1,
+        &["Freq", "Mode", "Width", "Split", "SatMode"],
+        get_vfo_info,
+    ),
+    command(None, "get_rig_info", false, 0, &[], get_rig_info),
+    command(None, "dump_state", false, 0, &[], dump_state),
+    command(Some('q'), "quit", false, 0, &[], quit),
+    command(Some('Q'), "quit", false, 0, &[], quit),
+];
+
+/// Command parsed from a client line.
+#[derive(Debug)]
+pub(super) struct Invocation {
+    pub command: &'static Command,
+    /// Separator of the extended response, if requested
+    pub separator: Option<char>,
+    /// Target VFO, when given
+    pub vfo: Option<String>,
+    pub args: Vec<String>,
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl Invocation {
+    pub fn handle(&self, context: &Context) -> Result<Reply, RigError> {
+        (self.command.handler)(self, context)
+    }
+
+    fn vfo(&self) -> &str {
+        self.vfo.as_deref().unwrap_or("currVFO")
+    }
+
+    /// Formats the response to the command, in the extended format if it was requested.
+    pub fn respond(&self, result: Result<Reply, RigError>) -> String {
+        let Some(separator) = self.separator else {
+            return match result {
+                Ok(Reply::Values(values)) if !values.is_empty() => {
+                    values.iter().map(|value| format!("{value}\n")).collect()
+                }
+                Ok(Reply::Text(text)) => text,
+                Ok(_) => "RPRT 0\n".to_string(),
+                Err(err) => format!("{err}\n"),
+            };
+        };
+
+        let mut response = format!("{}:", self.command.name);
+        for arg in self.vfo.iter().chain(&self.args) {
+            response.push(' ');
+            response.push_str(arg);
+        }
+        response.push(separator);
+        let code = match result {
+            Ok(reply) => {
+                match reply {
+                    Reply::Values(values) => {
+                        for (label, value) in self.command.outputs.iter().zip(values) {
+                            response.push_str(&format!("{label}: {value}{separator}"));
+                        }
+                    }
+                    Reply::Text(text) => response.push_str(&text),
+                    _ => {}
+                }
+                0
+            }
+            Err(err) => err.code(),
+        };
+        response.push_str(&format!("RPRT {code}\n"));
+        response
+    }
+}
+
+/// Extended response separator selected by a command prefix.
+fn separator(prefix: char) -> Option<char> {
+    match prefix {
+        '+' => Some('\n'),
+        ';' | '|' | ',' => Some(prefix),
+        _ => None,
+    }
+}
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    match name.strip_prefix('\\') {
+        Some(name) => COMMANDS.iter().find(|command| command.name == name),
+        None => {
+            let mut chars = name.chars();
+            let short = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            COMMANDS.iter().find(|command| command.short == Some(short))
+        }
+    }
+}
+
+/// Parses the commands of a line, several commands may follow each other. Parsing stops at the
+/// first invalid command.
+pub(super) fn parse_line(line: &str) -> Vec<Result<Invocation, RigError>> {
+    let mut tokens = line.split_whitespace().peekable();
+    let mut invocations = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        let mut name = token;
+        let mut separator_char = None;
+        if let Some(prefix) = token.chars().next()
+            && token.len() > 1
+            && let Some(sep) = separator(prefix)
+        {
+            separator_char = Some(sep);
+            name = &token[1..];
+        }
+        let Some(command) = find_command(name) else {
+            invocations.push(Err(RigError::Invalid));
+            break;
+        };
+
+        // The VFO is optional, clients outside of the VFO mode leave it out
+        let vfo = if command.vfo {
+            tokens
+                .next_if(|token| VFO_NAMES.contains(token))
+                .map(str::to_string)
+        } else {
+            None
+        };
+
+        let mut args = Vec::new();
+        while args.len() < command.args {
+            // `?` lists the supported values instead
+            if args.first().is_some_and(|arg| arg == "?") {
+                break;
+            }
+            let Some(arg) = tokens.next() else {
+                break;
+            };
+            if command.text && args.len() + 1 == command.args {
+                let rest: Vec<_> = std::iter::once(arg).chain(tokens.by_ref()).collect();
+                args.push(rest.join(" "));
+            } else {
+                args.push(arg.to_string());
+            }
+        }
+        let complete = args.len() == command.args || args.first().is_some_and(|arg| arg == "?");
+        if !complete {
+            invocations.push(Err(RigError::Invalid));
+            break;
+        }
+
+        invocations.push(Ok(Invocation {
+            command,
+            separator: separator_char,
+            vfo,
+            args,
+        }));
+    }
+    invocations
+}
+
+/// Hamlib VFO names, not all of them exist in the schema.
+const VFO_NAMES: &[&str] = &[
+    "VFOA", "VFOB", "VFOC", "currVFO", "VFO", "MEM", "Main", "Sub", "TX", "RX", "MainA", "MainB",
+    "MainC", "SubA", "SubB", "SubC", "None",
+];
+
+/// Schema VFO addressed by a hamlib VFO name. The current VFO is addressed as `Current` only
+/// when the rig gives it a value, otherwise as the active one.
+fn schema_vfo(vfo: &str, context: &Context) -> Result<&'static str, RigError> {
+    match vfo {
+        "VFOA" | "Main" | "MainA" => Ok("A"),
+        "VFOB" | "Sub" | "MainB" | "SubA" => Ok("B"),
+        "currVFO" | "VFO" | "RX" | "TX" => {
+            let has_current = context.rig.is_some_and(|rig| {
+                rig.impl_block.enums.iter().any(|enum_def| {
+                    enum_def.name == "Vfo" && enum_def.variants.contains_key("Current")
+                })
+            });
+            Ok(if has_current {
+                "Current"
+            } else {
+                active_vfo(context.status)
+            })
+        }
+        _ => Err(RigError::Vfo),
+    }
+}
+
+/// Hamlib name of a schema VFO.
+fn hamlib_vfo(vfo: &str) -> &'static str {
+    match vfo {
+        "B" => "VFOB",
+        _ => "VFOA",
+    }
+}
+
+fn number<T: FromStr>(arg: &str) -> Result<T, RigError> {
+    arg.parse().map_err(|_| RigError::Invalid)
+}
+
+fn flag(value: bool) -> String {
+    (value as i32).to_string()
+}
+
+fn params<const N: usize>(params: [(&str, Value); N]) -> HashMap<String, Value> {
+    params
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+/// Schema VFO currently active, `A` or `B`.
+fn active_vfo(status: &DeviceStatus) -> &'static str {
+    match status.vfo.as_str() {
+        "B" => "B",
+        _ => "A",
+    }
+}
+
+fn other_vfo(status: &DeviceStatus) -> &'static str {
+    match active_vfo(status) {
+        "A" => "B",
+        _ => "A",
+    }
+}
+
+fn frequency(status: &DeviceStatus, vfo: &str) -> i64 {
+    let vfo = match vfo {
+        "Current" => active_vfo(status),
+        vfo => vfo,
+    };
+    match vfo {
+        "B" => status.freq_b,
+        _ => status.freq_a,
+    }
+}
+
+fn set_freq(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let freq: f64 = number(&invocation.args[0])?;
+    let target = schema_vfo(invocation.vfo(), context)?;
+    Ok(Reply::Execute(vec![(
+        "set_freq",
+        params([
+            ("freq", Value::Float(freq)),
+            ("target", Value::String(target.to_string())),
+        ]),
+    )]))
+}
+
+fn get_freq(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let vfo = schema_vfo(invocation.vfo(), context)?;
+    Ok(Reply::Values(vec![
+        frequency(context.status, vfo).to_string(),
+    ]))
+}
+
+fn set_mode(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let modes = context.modes();
+    let [mode, passband] = invocation.args.as_slice() else {
+        return match invocation.args.as_slice() {
+            [query] if query == "?" => Ok(list(&modes.hamlib_modes())),
+            _ => Err(RigError::Invalid),
+        };
+    };
+    let passband = number::<i64>(passband)?;
+    if passband < -1 {
+        return Err(RigError::Invalid);
+    }
+    let mode = modes.schema(mode).ok_or(RigError::Invalid)?;
+    // -1 keeps the passband and 0 asks for the normal one. The schema has no filter
+    // selection, so other widths can't be set
+    if ![-1, 0, modes.passband(mode)].contains(&passband) {
+        return Err(RigError::NotImplemented);
+    }
+    Ok(Reply::Execute(vec![(
+        "set_mode",
+        params([("mode", Value::String(mode.to_string()))]),
+    )]))
+}
+
+/// Hamlib name and normal passband of the current mode.
+fn mode(context: &Context) -> (String, String) {
+    let modes = context.modes();
+    let mode = context.status.mode.as_str();
+    (
+        modes.hamlib(mode).unwrap_or(mode).to_string(),
+        modes.passband(mode).to_string(),
+    )
+}
+
+fn get_mode(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let (mode, passband) = mode(context);
+    Ok(Reply::Values(vec![mode, passband]))
+}
+
+fn set_vfo(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let vfo = schema_vfo(&invocation.args[0], context)?;
+    Ok(Reply::Execute(vec![(
+        "set_vfo",
+        params([
+            ("rx", Value::String(vfo.to_string())),
+            ("tx", Value::String(vfo.to_string())),
+        ]),
+    )]))
+}
+
+fn get_vfo(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Values(vec![
+        hamlib_vfo(active_vfo(context.status)).to_string(),
+    ]))
+}
+
+fn set_ptt(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    let ptt: i32 = number(&invocation.args[0])?;
+    Ok(Reply::Execute(vec![(
+        "transmit",
+        params([("tx", Value::Boolean(ptt != 0))]),
+    )]))
+}
+
+fn get_ptt(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Values(vec![flag(context.status.transmit)]))
+}
+
+fn set_split_vfo(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let split: i32 = number(&invocation.args[0])?;
+    let tx_vfo = &invocation.args[1];
+    let tx = schema_vfo(tx_vfo, context)?;
+    // The rig transmits on the other VFO while split, which is where the split frequency goes
+    if split != 0 && tx_vfo != "TX" && tx != other_vfo(context.status) {
+        return Err(RigError::Invalid);
+    }
+    Ok(Reply::Execute(vec![(
+        "set_split",
+        params([("split", Value::Boolean(split != 0))]),
+    )]))
+}
+
+fn get_split_vfo(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    // The split state is not reported by the schema
+    Ok(Reply::Values(vec![
+        "0".to_string(),
+        hamlib_vfo(active_vfo(context.status)).to_string(),
+    ]))
+}
+
+fn set_split_freq(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let freq: f64 = number(&invocation.args[0])?;
+    Ok(Reply::Execute(vec![(
+        "set_freq",
+        params([
+            ("freq", Value::Float(freq)),
+            (
+                "target",
+                Value::String(other_vfo(context.status).to_string()),
+            ),
+        ]),
+    )]))
+}
+
+fn get_split_freq(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let status = context.status;
+    Ok(Reply::Values(vec![
+        frequency(status, other_vfo(status)).to_string(),
+    ]))
+}
+
+/// `J`/`Z`, only clearing the offset is supported.
+fn set_offset(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    let offset: i64 = number(&invocation.args[0])?;
+    if offset != 0 {
+        return Err(RigError::NotImplemented);
+    }
+    Ok(Reply::Execute(vec![("clear_rit", HashMap::new())]))
+}
+
+/// Offset is not reported by the schema, it is only known to be zero while switched off.
+fn offset(enabled: bool) -> Result<Reply, RigError> {
+    if enabled {
+        return Err(RigError::NotImplemented);
+    }
+    Ok(Reply::Values(vec!["0".to_string()]))
+}
+
+fn get_rit(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    offset(context.status.rit)
+}
+
+fn get_xit(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    offset(context.status.xit)
+}
+
+fn vfo_op(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let call = match invocation.args[0].as_str() {
+        "CPY" => ("vfo_equal", HashMap::new()),
+        "XCHG" => ("vfo_swap", HashMap::new()),
+        "TOGGLE" => {
+            let vfo = other_vfo(context.status);
+            (
+                "set_vfo",
+                params([
+                    ("rx", Value::String(vfo.to_string())),
+                    ("tx", Value::String(vfo.to_string())),
+                ]),
+            )
+        }
+        _ => return Err(RigError::NotImplemented),
+    };
+    Ok(Reply::Execute(vec![call]))
+}
+
+/// Space separated list of the supported values, answered to `?`.
+fn list(values: &[&str]) -> Reply {
+    Reply::Text(values.join(" ") + "\n")
+}
+
+fn set_level(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    let [level, value] = invocation.args.as_slice() else {
+        return Ok(list(LEVELS));
+    };
+    let value: f64 = number(value)?;
+    match level.as_str() {
+        "CWPITCH" => Ok(Reply::Execute(vec![(
+            "cw_pitch",
+            params([("pitch", Value::Integer(value as i64))]),
+        )])),
+        _ => Err(RigError::NotImplemented),
+    }
+}
+
+fn get_level(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    match invocation.args[0].as_str() {
+        "?" => Ok(list(LEVELS)),
+        "CWPITCH" => Ok(Reply::Values(vec![context.status.cw_pitch.to_string()])),
+        _ => Err(RigError::NotImplemented),
+    }
+}
+
+fn set_func(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    let [func, status] = invocation.args.as_slice() else {
+        return Ok(list(FUNCTIONS));
+    };
+    let enabled = number::<i32>(status)? != 0;
+    let (command_name, param) = match func.as_str() {
+        "RIT" => ("set_rit", "rit"),
+        "XIT" => ("set_xit", "xit"),
+        _ => return Err(RigError::NotImplemented),
+    };
+    Ok(Reply::Execute(vec![(
+        command_name,
+        params([(param, Value::Boolean(enabled))]),
+    )]))
+}
+
+fn get_func(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let enabled = match invocation.args[0].as_str() {
+        "?" => return Ok(list(FUNCTIONS)),
+        "RIT" => context.status.rit,
+        "XIT" => context.status.xit,
+        _ => return Err(RigError::NotImplemented),
+    };
+    Ok(Reply::Values(vec![flag(enabled)]))
+}
+
+fn not_implemented(_: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    Err(RigError::NotImplemented)
+}
+
+fn get_info(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let rig_model = context.status.rig_model().unwrap_or_default();
+    Ok(Reply::Values(vec![rig_model.to_string()]))
+}
+
+fn get_powerstat(_: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Values(vec!["1".to_string()]))
+}
+
+/// The rigs can't be switched off, powering them on has nothing to do.
+fn set_powerstat(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    match number::<i32>(&invocation.args[0])? {
+        1 => Ok(Reply::Execute(Vec::new())),
+        _ => Err(RigError::NotImplemented),
+    }
+}
+
+fn chk_vfo(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Values(vec![flag(context.vfo_mode)]))
+}
+
+fn set_vfo_opt(invocation: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::VfoMode(number::<i32>(&invocation.args[0])? != 0))
+}
+
+fn get_vfo_info(invocation: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let vfo = schema_vfo(&invocation.args[0], context)?;
+    let (mode, passband) = mode(context);
+    Ok(Reply::Values(vec![
+        frequency(context.status, vfo).to_string(),
+        mode,
+        passband,
+        "0".to_string(),
+        "0".to_string(),
+    ]))
+}
+
+fn get_rig_info(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    let status = context.status;
+    let active = active_vfo(status);
+    let mut info = String::new();
+    let (mode, passband) = mode(context);
+    for vfo in ["A", "B"] {
+        let selected = flag(vfo == active);
+        info.push_str(&format!(
+            "VFO={} Freq={} Mode={mode} Width={passband} RX={selected} TX={selected}\n",
+            hamlib_vfo(vfo),
+            frequency(status, vfo),
+        ));
+    }
+    info.push_str("Split=0 SatMode=0\n");
+    info.push_str(&format!("Rig={}\n", status.rig_type));
+    info.push_str(&format!("Model={}\n", state::hamlib_model(context)));
+    info.push_str("App=Holyrig\n");
+    info.push_str(&format!("Version={}\n", env!("CARGO_PKG_VERSION")));
+    Ok(Reply::Text(info))
+}
+
+fn dump_state(_: &Invocation, context: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Text(state::dump_state(context)))
+}
+
+fn quit(_: &Invocation, _: &Context) -> Result<Reply, RigError> {
+    Ok(Reply::Quit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Parsed = (&'static str, Option<String>, Vec<String>);
+
+    fn names(line: &str) -> Vec<Result<Parsed, RigError>> {
+        parse_line(line)
+            .into_iter()
+            .map(|invocation| {
+                invocation
+                    .map(|invocation| (invocation.command.name, invocation.vfo, invocation.args))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let strings = |values: &[&str]| values.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            names("F 14074000\n"),
+            vec![Ok(("set_freq", None, strings(&["14074000"])))]
+        );
+        assert_eq!(
+            names("\\set_freq VFOB 7074000.000000\n"),
+            vec![Ok((
+                "set_freq",
+                Some("VFOB".to_string()),
+                strings(&["7074000.000000"])
+            ))]
+        );
+        assert_eq!(
+            names("f m t"),
+            vec![
+                Ok(("get_freq", None, vec![])),
+                Ok(("get_mode", None, vec![])),
+                Ok(("get_ptt", None, vec![])),
+            ]
+        );
+        assert_eq!(
+            names("b VFOA CQ CQ DE F4ABC"),
+            vec![Ok((
+                "send_morse",
+                Some("VFOA".to_string()),
+                strings(&["CQ CQ DE F4ABC"])
+            ))]
+        );
+        assert_eq!(names("l ?"), vec![Ok(("get_level", None, strings(&["?"])))]);
+        assert_eq!(names("L ?"), vec![Ok(("set_level", None, strings(&["?"])))]);
+        assert_eq!(names("Q"), vec![Ok(("quit", None, vec![]))]);
+
+        assert_eq!(
+            names("f \\nonsense f"),
+            vec![Ok(("get_freq", None, vec![])), Err(RigError::Invalid)]
+        );
+        assert_eq!(names("M USB"), vec![Err(RigError::Invalid)]);
+        assert_eq!(names("freq"), vec![Err(RigError::Invalid)]);
+
+        let invocations = parse_line("+f ;m |\\chk_vfo ,v");
+        let separators: Vec<_> = invocations
+            .iter()
+            .map(|invocation| invocation.as_ref().unwrap().separator)
+            .collect();
+        assert_eq!(separators, [Some('\n'), Some(';'), Some('|'), Some(',')]);
+    }
+
+    #[test]
+    fn test_command_table() {
+        for (index, command) in COMMANDS.iter().enumerate() {
+            assert!(
+                command.outputs.is_empty()
+                    || command.name.starts_with("get_")
+                    || command.name == "chk_vfo"
+            );
+            let duplicate = COMMANDS[..index]
+                .iter()
+                .any(|other| command.short.is_some() && other.short == command.short);
+            assert!(!duplicate, "{} is declared twice", command.name);
+        }
+    }
+}
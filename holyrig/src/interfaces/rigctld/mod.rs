@@ -0,0 +1,789 @@
+use crate::runtime::Value;
+use anyhow::Result;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use super::hamlib::{DeviceState, Listeners, RigError, SessionFactory, report};
+use crate::resources::Resources;
+use crate::rig_settings::{DeviceClass, RigSettings};
+use crate::serial::ManagerCommand;
+use crate::serial::manager::{CommandResponse, ManagerMessage};
+
+mod commands;
+mod modes;
+mod state;
+
+use commands::{Context, Reply, SchemaCall};
+
+struct DeviceStatus {
+    device_id: usize,
+    /// Configured rig type of the transceiver
+    rig_type: String,
+    /// Serial timeout of the transceiver, in milliseconds
+    timeout: u16,
+    connected: bool,
+    freq_a: i64,
+    freq_b: i64,
+    vfo: String,
+    mode: String,
+    cw_pitch: i64,
+    transmit: bool,
+    rit: bool,
+    xit: bool,
+}
+
+impl DeviceStatus {
+    /// Id of the transceiver, while it is connected.
+    fn device_id(&self) -> Option<usize> {
+        self.connected.then_some(self.device_id)
+    }
+
+    /// Rig type of the transceiver, while it is connected.
+    fn rig_model(&self) -> Option<&str> {
+        self.connected.then_some(self.rig_type.as_str())
+    }
+}
+
+impl DeviceState for DeviceStatus {
+    fn new(settings: &RigSettings) -> Self {
+        Self {
+            device_id: settings.id,
+            rig_type: settings.rig_type.clone(),
+            timeout: settings.timeout,
+            connected: false,
+            freq_a: 0,
+            freq_b: 0,
+            vfo: "A".to_string(),
+            mode: "USB".to_string(),
+            cw_pitch: 0,
+            transmit: false,
+            rit: false,
+            xit: false,
+        }
+    }
+
+    fn configure(&mut self, settings: &RigSettings) {
+        self.rig_type = settings.rig_type.clone();
+        self.timeout = settings.timeout;
+    }
+
+    fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    fn update(&mut self, values: HashMap<String, Value>) {
+        for (name, value) in values {
+            let value = match value {
+                Value::EnumVariant { variant_name, .. } => Value::String(variant_name),
+                value => value,
+            };
+            match (name.as_str(), value) {
+                ("freq_a", Value::Integer(f)) => self.freq_a = f,
+                ("freq_b", Value::Integer(f)) => self.freq_b = f,
+                ("vfo", Value::String(v)) => self.vfo = v,
+                ("mode", Value::String(m)) => self.mode = m,
+                ("cw_pitch", Value::Integer(p)) => self.cw_pitch = p,
+                ("transmit", Value::Boolean(t)) => self.transmit = t,
+                ("rit", Value::Boolean(r)) => self.rit = r,
+                ("xit", Value::Boolean(x)) => self.xit = x,
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Session {
+    resources: Arc<Resources>,
+    device_status: Arc<RwLock<DeviceStatus>>,
+    command_sender: Sender<ManagerCommand>,
+    /// Whether the client was told to send a VFO with the commands.
+    vfo_mode: bool,
+}
+
+impl Session {
+    fn new(
+        resources: Arc<Resources>,
+        device_status: Arc<RwLock<DeviceStatus>>,
+        command_sender: Sender<ManagerCommand>,
+    ) -> Self {
+        Self {
+            resources,
+            device_status,
+            command_sender,
+            vfo_mode: false,
+        }
+    }
+
+    /// Runs the schema commands in order on the transceiver, up to the first failure.
+    async fn execute(&self, calls: Vec<SchemaCall>) -> Result<(), RigError> {
+        if calls.is_empty() {
+            return Ok(());
+        }
+        let Some(device_id) = self.device_status.read().device_id() else {
+            return Err(RigError::Io);
+        };
+        for (command_name, params) in calls {
+            let (tx, rx) = oneshot::channel();
+            self.command_sender
+                .send(ManagerCommand::ExecuteCommand {
+                    device_id,
+                    command_name: command_name.to_string(),
+                    params,
+                    response_channel: Some(tx),
+                })
+                .await
+                .map_err(|_| RigError::Internal)?;
+            match rx.await {
+                Ok(CommandResponse::Success(_)) => {}
+                Ok(CommandResponse::Error(err)) => {
+                    eprintln!("Rigctld command {command_name} failed: {err}");
+                    return Err(err.kind.into());
+                }
+                Err(_) => return Err(RigError::Internal),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers the commands of a line, the flag is set once the client quits.
+    async fn handle_line(&mut self, line: &str) -> (String, bool) {
+        let mut response = String::new();
+        for invocation in commands::parse_line(line) {
+            let invocation = match invocation {
+                Ok(invocation) => invocation,
+                Err(err) => {
+                    eprintln!("Invalid rigctld command: {}", line.trim());
+                    response.push_str(&report(Err(err)));
+                    break;
+                }
+            };
+            let reply = {
+                let status = self.device_status.read();
+                invocation.handle(&Context {
+                    status: &status,
+                    vfo_mode: self.vfo_mode,
+                    rig: self
+                        .resources
+                        .rigs
+                        .get(&status.rig_type)
+                        .map(|rig| rig.rig_file()),
+                    schema: self
+                        .resources
+                        .schemas
+                        .get(DeviceClass::Transceiver.schema()),
+                })
+            };
+            let reply = match reply {
+                Ok(Reply::Quit) => return (response, true),
+                Ok(Reply::VfoMode(vfo_mode)) => {
+                    self.vfo_mode = vfo_mode;
+                    Ok(Reply::Values(Vec::new()))
+                }
+                Ok(Reply::Execute(calls)) => self
+                    .execute(calls)
+                    .await
+                    .map(|()| Reply::Values(Vec::new())),
+                reply => reply,
+            };
+            response.push_str(&invocation.respond(reply));
+        }
+        (response, false)
+    }
+}
+
+async fn handle_client(mut socket: TcpStream, mut session: Session) -> Result<()> {
+    let (reader, mut writer) = socket.split();
+
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, quit) = session.handle_line(&line).await;
+        writer.write_all(response.as_bytes()).await?;
+        if quit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Rigctld listeners of the transceivers.
+fn listeners(
+    resources: Arc<Resources>,
+    command_sender: Sender<ManagerCommand>,
+) -> Listeners<DeviceStatus> {
+    let session_sender = command_sender.clone();
+    let session: SessionFactory<DeviceStatus> = Arc::new(move |socket, _, device_status| {
+        let session = Session::new(resources.clone(), device_status, session_sender.clone());
+        Box::pin(handle_client(socket, session))
+    });
+    Listeners::new("Rigctld", DeviceClass::Transceiver, command_sender, session)
+}
+
+pub async fn run_server(
+    resources: Arc<Resources>,
+    command_sender: Sender<ManagerCommand>,
+    message_receiver: Receiver<ManagerMessage>,
+) -> Result<()> {
+    listeners(resources, command_sender)
+        .run(message_receiver)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rig_settings::RigctldSettings;
+    use crate::serial::manager::{CommandError, CommandErrorKind};
+    use parking_lot::Mutex;
+    use tokio::sync::{broadcast, mpsc};
+
+    type Executed = Arc<Mutex<Vec<(String, Vec<(String, Value)>)>>>;
+
+    fn settings(id: usize) -> RigSettings {
+        RigSettings {
+            id,
+            rig_type: "IC7300".to_string(),
+            ..RigSettings::default()
+        }
+    }
+
+    /// Session on an IC-7300 whose commands are answered by a fake manager, failing the
+    /// commands listed in `failures` and those whose arguments the rig can't take.
+    fn session(failures: &[(&'static str, CommandErrorKind)]) -> (Session, Executed) {
+        let resources = Resources::load().unwrap();
+        let rig_file = resources.rigs["IC7300"].rig_file().clone();
+        let schema = resources.schemas[&rig_file.impl_block.schema].clone();
+        let status = DeviceStatus {
+            connected: true,
+            freq_a: 14074000,
+            freq_b: 7074000,
+            cw_pitch: 600,
+            ..DeviceStatus::new(&settings(1))
+        };
+        let (sender, mut receiver) = mpsc::channel(32);
+        let executed = Executed::default();
+        let failures: HashMap<_, _> = failures.iter().copied().collect();
+
+        let recorded = executed.clone();
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                let ManagerCommand::ExecuteCommand {
+                    device_id: 1,
+                    command_name,
+                    params,
+                    response_channel: Some(response_channel),
+                } = command
+                else {
+                    continue;
+                };
+                let prepared = schema.commands[&command_name]
+                    .prepare_args(params.clone(), &rig_file.enum_mappings());
+                let response = match (failures.get(command_name.as_str()), prepared) {
+                    (Some(kind), _) => CommandResponse::Error(CommandError {
+                        kind: *kind,
+                        message: format!("{command_name} failed"),
+                    }),
+                    (None, Err(errors)) => CommandResponse::Error(CommandError {
+                        kind: CommandErrorKind::InvalidParams,
+                        message: format!("Invalid arguments of {command_name}: {errors:?}"),
+                    }),
+                    (None, Ok(_)) => CommandResponse::Success(HashMap::new()),
+                };
+                let mut params: Vec<_> = params.into_iter().collect();
+                params.sort_by(|a, b| a.0.cmp(&b.0));
+                recorded.lock().push((command_name, params));
+                response_channel.send(response).unwrap();
+            }
+        });
+        let session = Session::new(resources, Arc::new(RwLock::new(status)), sender);
+        (session, executed)
+    }
+
+    /// Replays a client transcript, the lines starting with `> ` are sent by the client and
+    /// followed by the expected answer.
+    async fn replay(session: &mut Session, transcript: &str) {
+        let mut exchanges: Vec<(&str, String)> = Vec::new();
+        for line in transcript
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            match line.strip_prefix("> ") {
+                Some(request) => exchanges.push((request, String::new())),
+                None => {
+                    let (_, answer) = exchanges.last_mut().unwrap();
+                    answer.push_str(line);
+                    answer.push('\n');
+                }
+            }
+        }
+        for (request, expected) in exchanges {
+            let (answer, _) = session.handle_line(&format!("{request}\n")).await;
+            assert_eq!(answer, expected, "answer to {request}");
+        }
+    }
+
+    fn call(command_name: &str, params: &[(&str, Value)]) -> (String, Vec<(String, Value)>) {
+        (
+            command_name.to_string(),
+            params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wsjtx_session() -> Result<()> {
+        let (mut session, executed) = session(&[]);
+        replay(
+            &mut session,
+            r"
+            > \chk_vfo
+            0
+            > \set_vfo_opt 1
+            RPRT 0
+            > \chk_vfo
+            1
+            > \get_powerstat
+            1
+            > f VFOA
+            14074000
+            > m VFOA
+            USB
+            2400
+            > s VFOA
+            0
+            VFOA
+            > t VFOA
+            0
+            > F VFOA 14076000
+            RPRT 0
+            > M VFOA PKTUSB 2400
+            RPRT 0
+            > T VFOA 1
+            RPRT 0
+            > T VFOA 0
+            RPRT 0
+            ",
+        )
+        .await;
+        assert_eq!(
+            *executed.lock(),
+            [
+                call(
+                    "set_freq",
+                    &[
+                        ("freq", Value::Float(14076000.0)),
+                        ("target", Value::String("A".to_string())),
+                    ]
+                ),
+                call("set_mode", &[("mode", Value::String("DIGIU".to_string()))]),
+                call("transmit", &[("tx", Value::Boolean(true))]),
+                call("transmit", &[("tx", Value::Boolean(false))]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jtdx_split_session() -> Result<()> {
+        let (mut session, executed) = session(&[]);
+        replay(
+            &mut session,
+            r"
+            > \set_vfo_opt 1
+            RPRT 0
+            > i VFOA
+            7074000
+            > S VFOA 1 VFOB
+            RPRT 0
+            > I VFOA 14075500
+            RPRT 0
+            > S VFOA 0 VFOA
+            RPRT 0
+            ",
+        )
+        .await;
+        assert_eq!(
+            *executed.lock(),
+            [
+                call("set_split", &[("split", Value::Boolean(true))]),
+                call(
+                    "set_freq",
+                    &[
+                        ("freq", Value::Float(14075500.0)),
+                        ("target", Value::String("B".to_string())),
+                    ]
+                ),
+                call("set_split", &[("split", Value::Boolean(false))]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_split_tx_vfo() {
+        let (mut session, executed) = session(&[]);
+        replay(
+            &mut session,
+            r"
+            > S 1 VFOA
+            RPRT -1
+            > S 1 currVFO
+            RPRT -1
+            > S 1 VFOB
+            RPRT 0
+            > S 1 TX
+            RPRT 0
+            > S 0 VFOA
+            RPRT 0
+            ",
+        )
+        .await;
+        assert_eq!(
+            *executed.lock(),
+            [
+                call("set_split", &[("split", Value::Boolean(true))]),
+                call("set_split", &[("split", Value::Boolean(true))]),
+                call("set_split", &[("split", Value::Boolean(false))]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fldigi_session() -> Result<()> {
+        let (mut session, executed) = session(&[]);
+        replay(
+            &mut session,
+            r"
+            > \set_vfo_opt 0
+            RPRT 0
+            > \chk_vfo
+            0
+            > f
+            14074000
+            > m
+            USB
+            2400
+            > F 7040000
+            RPRT 0
+            > l CWPITCH
+            600
+            > L CWPITCH 700
+            RPRT 0
+            > l ?
+            CWPITCH
+            > u RIT
+            0
+            > U RIT 1
+            RPRT 0
+            > b CQ CQ DE F4ABC
+            RPRT -4
+            > L STRENGTH 0
+            RPRT -4
+            ",
+        )
+        .await;
+        assert_eq!(
+            *executed.lock(),
+            [
+                call(
+                    "set_freq",
+                    &[
+                        ("freq", Value::Float(7040000.0)),
+                        ("target", Value::String("A".to_string())),
+                    ]
+                ),
+                call("cw_pitch", &[("pitch", Value::Integer(700))]),
+                call("set_rit", &[("rit", Value::Boolean(true))]),
+            ]
+        );
+        assert_eq!(session.handle_line("q\n").await, (String::new(), true));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_log4om_session() -> Result<()> {
+        let (mut session, executed) = session(&[]);
+        replay(
+            &mut session,
+            r"
+            > +\get_vfo_info VFOA
+            get_vfo_info: VFOA
+            Freq: 14074000
+            Mode: USB
+            Width: 2400
+            Split: 0
+            SatMode: 0
+            RPRT 0
+            > ;f ;m ;t
+            get_freq:;Frequency: 14074000;RPRT 0
+            get_mode:;Mode: USB;Passband: 2400;RPRT 0
+            get_ptt:;PTT: 0;RPRT 0
+            > |\get_level VFOA CWPITCH
+            get_level: VFOA CWPITCH|Level Value: 600|RPRT 0
+            > +\set_freq 14074500
+            set_freq: 14074500
+            RPRT 0
+            > +j
+            get_rit:
+            RIT: 0
+            RPRT 0
+            > +\set_split_mode VFOA USB 3000
+            set_split_mode: VFOA USB 3000
+            RPRT -4
+            > +\get_status
+            RPRT -1
+            ",
+        )
+        .await;
+        assert_eq!(executed.lock().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rig_info() -> Result<()> {
+        let (mut session, _) = session(&[]);
+        let (answer, _) = session.handle_line("\\get_rig_info\n").await;
+        assert!(answer.starts_with(
+            "VFO=VFOA Freq=14074000 Mode=USB Width=2400 RX=1 TX=1\n\
+             VFO=VFOB Freq=7074000 Mode=USB Width=2400 RX=0 TX=0\n\
+             Split=0 SatMode=0\n\
+             Rig=IC7300\n\
+             Model=3073\n"
+        ));
+
+        let (answer, _) = session.handle_line("\\dump_state\n").await;
+        assert!(answer.starts_with("1\n3073\n0\n30000 74800000 0xcaf -1 -1 0x3 0x0\n"));
+        assert!(answer.ends_with(&format!(
+            "rig_model=3073\nrigctld_version=Holyrig {}\ndone\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_without_transceiver() -> Result<()> {
+        let (mut session, executed) = session(&[]);
+        session.device_status.write().connected = false;
+        replay(
+            &mut session,
+            r"
+            > F 14074000
+            RPRT -6
+            > \set_powerstat 1
+            RPRT 0
+            ",
+        )
+        .await;
+        assert!(executed.lock().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_failures() {
+        let (mut session, executed) = session(&[
+            ("transmit", CommandErrorKind::Timeout),
+            ("set_mode", CommandErrorKind::InvalidParams),
+            ("vfo_swap", CommandErrorKind::UnknownCommand),
+            ("set_vfo", CommandErrorKind::Protocol),
+        ]);
+        replay(
+            &mut session,
+            r"
+            > T 1
+            RPRT -5
+            > M USB 0 f
+            RPRT -1
+            14074000
+            > G XCHG
+            RPRT -4
+            > +V VFOB
+            set_vfo: VFOB
+            RPRT -8
+            > f VFOC
+            RPRT -16
+            > F 14074000
+            RPRT 0
+            ",
+        )
+        .await;
+        assert_eq!(executed.lock().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_modes() {
+        let (mut session, executed) = session(&[]);
+        session.device_status.write().mode = "DIGIL".to_string();
+        replay(
+            &mut session,
+            r"
+            > m
+            PKTLSB
+            2400
+            > M ?
+            LSB USB AM CWR CW PKTLSB PKTUSB FM
+            > M CWR 500
+            RPRT 0
+            > M RTTY -1
+            RPRT 0
+            > M WFM 0
+            RPRT -1
+            > M USB -2
+            RPRT -1
+            > M USB 3000
+            RPRT -4
+            > M FM 10000
+            RPRT 0
+            ",
+        )
+        .await;
+        assert_eq!(
+            *executed.lock(),
+            [
+                call("set_mode", &[("mode", Value::String("CWL".to_string()))]),
+                call("set_mode", &[("mode", Value::String("DIGIL".to_string()))]),
+                call("set_mode", &[("mode", Value::String("FM".to_string()))]),
+            ]
+        );
+
+        let (answer, _) = session.handle_line("\\dump_state\n").await;
+        assert!(answer.contains("\n0 0\n0x8 2400\n0x8 1800\n0x8 3000\n0x4 2400\n"));
+    }
+
+    #[tokio::test]
+    async fn test_listeners() -> Result<()> {
+        let (sender, _receiver) = mpsc::channel(32);
+        let mut listeners = listeners(Resources::load()?, sender);
+        let settings = RigSettings {
+            rigctld: RigctldSettings {
+                enabled: true,
+                address: "127.0.0.1:0".to_string(),
+            },
+            ..settings(3)
+        };
+        listeners
+            .handle_message(ManagerMessage::DeviceAdded {
+                settings: settings.clone(),
+            })
+            .await;
+        listeners
+            .handle_message(ManagerMessage::DeviceAdded {
+                settings: RigSettings {
+                    id: 4,
+                    device_class: DeviceClass::Rotator,
+                    ..settings.clone()
+                },
+            })
+            .await;
+        assert_eq!(listeners.device_ids(), [3]);
+        let addr = listeners.local_addr(3).unwrap();
+
+        for device_id in [3, 4] {
+            listeners
+                .handle_message(ManagerMessage::StatusUpdate {
+                    device_id,
+                    values: HashMap::from([(
+                        "freq_a".to_string(),
+                        Value::Integer(7074000 + device_id as i64),
+                    )]),
+                })
+                .await;
+        }
+        let mut client = BufReader::new(TcpStream::connect(addr).await?);
+        client.write_all(b"f\n").await?;
+        let mut line = String::new();
+        client.read_line(&mut line).await?;
+        assert_eq!(line, "7074003\n");
+
+        listeners
+            .handle_message(ManagerMessage::DeviceRemoved { device_id: 3 })
+            .await;
+        assert!(listeners.device_ids().is_empty());
+        line.clear();
+        assert_eq!(client.read_line(&mut line).await?, 0);
+        assert!(TcpStream::connect(addr).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lagged_messages() -> Result<()> {
+        let listening = |id: usize| RigSettings {
+            rigctld: RigctldSettings {
+                enabled: true,
+                address: "127.0.0.1:0".to_string(),
+            },
+            ..settings(id)
+        };
+        let (sender, mut commands) = mpsc::channel(32);
+        tokio::spawn(async move {
+            while let Some(command) = commands.recv().await {
+                if let ManagerCommand::GetDevices { response_channel } = command {
+                    let _ = response_channel.send(vec![(listening(5), true)]);
+                }
+            }
+        });
+        let mut listeners = listeners(Resources::load()?, sender);
+        listeners
+            .handle_message(ManagerMessage::DeviceAdded {
+                settings: listening(3),
+            })
+            .await;
+
+        // Transceiver 3 is replaced by 5 while the listeners don't read the messages
+        let (message_sender, mut receiver) = broadcast::channel(1);
+        message_sender.send(ManagerMessage::DeviceRemoved { device_id: 3 })?;
+        message_sender.send(ManagerMessage::DeviceAdded {
+            settings: listening(5),
+        })?;
+        message_sender.send(ManagerMessage::StatusUpdate {
+            device_id: 5,
+            values: HashMap::from([("freq_a".to_string(), Value::Integer(7074000))]),
+        })?;
+        drop(message_sender);
+
+        assert!(listeners.receive(&mut receiver).await?);
+        assert_eq!(listeners.device_ids(), [5]);
+        assert_eq!(listeners.state(5).unwrap().read().device_id(), Some(5));
+        assert!(listeners.receive(&mut receiver).await?);
+        assert_eq!(listeners.state(5).unwrap().read().freq_a, 7074000);
+        assert!(!listeners.receive(&mut receiver).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_update() {
+        let mut status = DeviceStatus::new(&settings(1));
+        status.update(HashMap::from([
+            ("freq_a".to_string(), Value::Integer(7074000)),
+            (
+                "mode".to_string(),
+                Value::EnumVariant {
+                    enum_name: "Mode".to_string(),
+                    variant_name: "CWU".to_string(),
+                    value: 0,
+                },
+            ),
+            ("rit".to_string(), Value::Boolean(true)),
+        ]));
+        assert_eq!(status.freq_a, 7074000);
+        assert_eq!(status.mode, "CWU");
+        assert!(status.rit);
+    }
+}
@@ -0,0 +1,205 @@
+//! Translation between the hamlib modes and the `Mode` enum of the transceiver schema.
+
+use crate::runtime::{Passbands, RigFile, SchemaFile};
+
+/// Hamlib modes with their `RIG_MODE_*` bit.
+const HAMLIB_MODES: &[(&str, u64)] = &[
+    ("AM", 1 << 0),
+    ("CW", 1 << 1),
+    ("USB", 1 << 2),
+    ("LSB", 1 << 3),
+    ("RTTY", 1 << 4),
+    ("FM", 1 << 5),
+    ("WFM", 1 << 6),
+    ("CWR", 1 << 7),
+    ("RTTYR", 1 << 8),
+    ("AMS", 1 << 9),
+    ("PKTLSB", 1 << 10),
+    ("PKTUSB", 1 << 11),
+    ("PKTFM", 1 << 12),
+    ("ECSSUSB", 1 << 13),
+    ("ECSSLSB", 1 << 14),
+    ("FAX", 1 << 15),
+    ("SAM", 1 << 16),
+    ("SAL", 1 << 17),
+    ("SAH", 1 << 18),
+    ("DSB", 1 << 19),
+    ("FMN", 1 << 21),
+    ("PKTAM", 1 << 22),
+];
+
+/// Hamlib name of each schema mode, unless the rig file overrides it.
+const DEFAULT_MODES: &[(&str, &str)] = &[
+    ("USB", "USB"),
+    ("LSB", "LSB"),
+    ("CWU", "CW"),
+    ("CWL", "CWR"),
+    ("DIGIU", "PKTUSB"),
+    ("DIGIL", "PKTLSB"),
+    ("AM", "AM"),
+    ("FM", "FM"),
+];
+
+/// Hamlib modes without a schema equivalent, set as the closest schema mode.
+const FALLBACK_MODES: &[(&str, &str)] = &[("RTTY", "DIGIL"), ("RTTYR", "DIGIU"), ("PKTFM", "FM")];
+
+/// `RIG_MODE_*` bit of a hamlib mode.
+fn mode_bit(hamlib: &str) -> u64 {
+    HAMLIB_MODES
+        .iter()
+        .find(|(name, _)| *name == hamlib)
+        .map_or(0, |(_, bit)| *bit)
+}
+
+/// Schema modes of a rig: those declared in its capabilities, else those its `Mode` enum maps,
+/// else every mode of the schema.
+fn rig_modes(rig: Option<&RigFile>, schema: Option<&SchemaFile>) -> Vec<String> {
+    let schema_modes = schema
+        .and_then(|schema| schema.enums.get("Mode"))
+        .cloned()
+        .unwrap_or_default();
+    let Some(rig) = rig else {
+        return schema_modes;
+    };
+    if let Some(capabilities) = rig.capabilities()
+        && !capabilities.modes.is_empty()
+    {
+        return capabilities.modes.clone();
+    }
+    match rig.enum_mappings().get("Mode") {
+        Some(mapping) => schema_modes
+            .into_iter()
+            .filter(|mode| mapping.contains_key(mode))
+            .collect(),
+        None => schema_modes,
+    }
+}
+
+/// Modes of a rig with their hamlib names, and the passbands it declares for them.
+pub(super) struct ModeMap<'a> {
+    /// Schema modes of the rig and their hamlib name, in the order of the rig
+    modes: Vec<(String, String)>,
+    passbands: &'a [Passbands],
+}
+
+impl<'a> ModeMap<'a> {
+    pub fn new(rig: Option<&'a RigFile>, schema: Option<&SchemaFile>) -> Self {
+        let capabilities = rig.and_then(|rig| rig.capabilities());
+        let overrides = capabilities
+            .map(|capabilities| capabilities.hamlib_modes.as_slice())
+            .unwrap_or_default();
+        let modes = rig_modes(rig, schema)
+            .into_iter()
+            .filter_map(|mode| {
+                let hamlib = overrides
+                    .iter()
+                    .find(|hamlib_mode| hamlib_mode.mode == mode)
+                    .map(|hamlib_mode| hamlib_mode.hamlib.clone())
+                    .or_else(|| {
+                        DEFAULT_MODES
+                            .iter()
+                            .find(|(schema_mode, _)| *schema_mode == mode)
+                            .map(|(_, hamlib)| hamlib.to_string())
+                    })?;
+                Some((mode, hamlib))
+            })
+            .collect();
+        Self {
+            modes,
+            passbands: capabilities
+                .map(|capabilities| capabilities.passbands.as_slice())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Hamlib name of a schema mode.
+    pub fn hamlib(&self, mode: &str) -> Option<&str> {
+        self.modes
+            .iter()
+            .find(|(schema_mode, _)| schema_mode == mode)
+            .map(|(_, hamlib)| hamlib.as_str())
+    }
+
+    /// Schema mode a hamlib mode is set as, if the rig supports it.
+    pub fn schema(&self, hamlib: &str) -> Option<&str> {
+        self.modes
+            .iter()
+            .find(|(_, hamlib_mode)| hamlib_mode == hamlib)
+            .map(|(schema_mode, _)| schema_mode.as_str())
+            .or_else(|| {
+                FALLBACK_MODES
+                    .iter()
+                    .find(|(fallback, mode)| *fallback == hamlib && self.hamlib(mode).is_some())
+                    .map(|(_, mode)| *mode)
+            })
+    }
+
+    /// Hamlib names of the modes of the rig.
+    pub fn hamlib_modes(&self) -> Vec<&str> {
+        self.modes
+            .iter()
+            .map(|(_, hamlib)| hamlib.as_str())
+            .collect()
+    }
+
+    /// `RIG_MODE_*` bits of schema modes, every mode of the rig when empty.
+    pub fn mask(&self, modes: &[String]) -> u64 {
+        self.modes
+            .iter()
+            .filter(|(mode, _)| modes.is_empty() || modes.contains(mode))
+            .fold(0, |mask, (_, hamlib)| mask | mode_bit(hamlib))
+    }
+
+    /// Normal passband of a schema mode, the first one the rig file declares, else 0.
+    pub fn passband(&self, mode: &str) -> i64 {
+        self.passbands
+            .iter()
+            .find(|passbands| passbands.mode == mode)
+            .and_then(|passbands| passbands.widths.first())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Filter widths of the rig with the `RIG_MODE_*` bits of the mode they belong to.
+    pub fn filters(&self) -> Vec<(u64, i64)> {
+        self.passbands
+            .iter()
+            .flat_map(|passbands| {
+                let mask = self.mask(std::slice::from_ref(&passbands.mode));
+                passbands.widths.iter().map(move |width| (mask, *width))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::Resources;
+    use crate::rig_settings::DeviceClass;
+    use anyhow::Result;
+
+    #[test]
+    fn test_mode_map() -> Result<()> {
+        let resources = Resources::load()?;
+        let schema = resources.schemas.get(DeviceClass::Transceiver.schema());
+        let rig = resources.rigs.get("IC7300").map(|rig| rig.rig_file());
+
+        let modes = ModeMap::new(rig, schema);
+        assert_eq!(modes.schema("PKTUSB"), Some("DIGIU"));
+        assert_eq!(modes.schema("CWR"), Some("CWL"));
+        assert_eq!(modes.schema("RTTY"), Some("DIGIL"));
+        assert_eq!(modes.schema("WFM"), None);
+        assert_eq!(modes.schema("CWU"), None);
+        assert_eq!(modes.hamlib("DIGIL"), Some("PKTLSB"));
+        assert_eq!(modes.passband("USB"), 2400);
+        assert_eq!(modes.mask(&["USB".to_string(), "CWU".to_string()]), 0x6);
+        assert_eq!(modes.mask(&[]), 0xcaf);
+
+        let modes = ModeMap::new(None, schema);
+        assert_eq!(modes.hamlib_modes().len(), 8);
+        assert_eq!(modes.passband("USB"), 0);
+        assert!(modes.filters().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! State dump of a transceiver, generated from its rig file.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::commands::Context;
+use super::modes::ModeMap;
+use crate::runtime::FrequencyRange;
+
+/// Model reported for rigs without a hamlib model, the hamlib dummy rig.
+const DEFAULT_HAMLIB_MODEL: i64 = 1;
+/// Terminates the frequency range lists.
+const END_OF_RANGES: &str = "0 0 0 0 0 0 0\n";
+
+/// `RIG_FUNC_*` bits of the functions supported by `u`/`U`, with the status field reporting
+/// them and the command setting them.
+const FUNCTIONS: &[(u64, &str, &str)] = &[(1 << 24, "rit", "set_rit"), (1 << 31, "xit", "set_xit")];
+/// `RIG_LEVEL_*` bits of the levels supported by `l`/`L`.
+const LEVELS: &[(u64, &str, &str)] = &[(1 << 11, "cw_pitch", "cw_pitch")];
+/// `RIG_OP_*` bits of the VFO operations supported by `G`, with the command they run.
+const VFO_OPS: &[(u64, &str)] = &[
+    (1 << 0, "vfo_equal"),
+    (1 << 1, "vfo_swap"),
+    (1 << 12, "set_vfo"),
+];
+
+/// Commands and status fields the rig implements, everything when the rig file is unknown.
+struct Features {
+    commands: Option<HashSet<String>>,
+    status: Option<HashSet<String>>,
+}
+
+impl Features {
+    fn new(context: &Context) -> Self {
+        Self {
+            commands: context
+                .rig
+                .map(|rig| rig.impl_block.commands.keys().cloned().collect()),
+            status: context.rig.map(|rig| rig.get_supported_status_fields()),
+        }
+    }
+
+    fn implements(&self, command_name: &str) -> bool {
+        self.commands
+            .as_ref()
+            .is_none_or(|commands| commands.contains(command_name))
+    }
+
+    fn reports(&self, field: &str) -> bool {
+        self.status
+            .as_ref()
+            .is_none_or(|status| status.contains(field))
+    }
+
+    /// Bits of the features whose status is reported, and of those that can be set.
+    fn masks(&self, features: &[(u64, &str, &str)]) -> (u64, u64) {
+        features
+            .iter()
+            .fold((0, 0), |(get, set), (bit, field, command_name)| {
+                (
+                    get | if self.reports(field) { *bit } else { 0 },
+                    set | if self.implements(command_name) {
+                        *bit
+                    } else {
+                        0
+                    },
+                )
+            })
+    }
+}
+
+/// Frequency range lines, with the modes of the rig where the range does not restrict them.
+fn ranges(output: &mut String, ranges: &[FrequencyRange], modes: &ModeMap) {
+    for range in ranges {
+        let modes = modes.mask(&range.modes);
+        // Power levels are unknown, the range applies to both VFOs
+        let _ = writeln!(
+            output,
+            "{} {} {modes:#x} -1 -1 0x3 0x0",
+            range.min, range.max
+        );
+    }
+    output.push_str(END_OF_RANGES);
+}
+
+/// Answer to `\dump_state`, in version 1 of the protocol.
+pub(super) fn dump_state(context: &Context) -> String {
+    let capabilities = context.rig.and_then(|rig| rig.capabilities());
+    let features = Features::new(context);
+    let modes = context.modes();
+    let rig_modes = modes.mask(&[]);
+    let mut output = String::new();
+
+    let hamlib_model = hamlib_model(context);
+    // Protocol version, model and ITU region
+    let _ = write!(output, "1\n{hamlib_model}\n0\n");
+
+    // Without declared ranges, the rig receives what the schema accepts
+    let rx_ranges = match capabilities {
+        Some(capabilities) if !capabilities.rx_ranges.is_empty() => capabilities.rx_ranges.clone(),
+        _ => context
+            .schema
+            .and_then(|schema| schema.commands.get("set_freq"))
+            .and_then(|command| {
+                command
+                    .parameters
+                    .iter()
+                    .find(|parameter| parameter.name == "freq")
+            })
+            .and_then(|parameter| parameter.range.clone())
+            .map(|range| FrequencyRange {
+                min: *range.start(),
+                max: *range.end(),
+                modes: Vec::new(),
+            })
+            .into_iter()
+            .collect(),
+    };
+    ranges(&mut output, &rx_ranges, &modes);
+    let tx_ranges = capabilities.map(|capabilities| capabilities.tx_ranges.as_slice());
+    ranges(&mut output, tx_ranges.unwrap_or_default(), &modes);
+
+    for step in capabilities
+        .map(|capabilities| capabilities.tuning_steps.as_slice())
+        .unwrap_or_default()
+    {
+        let _ = writeln!(output, "{rig_modes:#x} {step}");
+    }
+    output.push_str("0 0\n");
+    for (mode_mask, width) in modes.filters() {
+        let _ = writeln!(output, "{mode_mask:#x} {width}");
+    }
+    output.push_str("0 0\n");
+
+    // Max RIT and XIT, only a zero offset is supported, IF shift and announces
+    output.push_str("0\n0\n0\n0\n");
+    // Preamps and attenuators
+    output.push_str("\n\n");
+
+    let (get_func, set_func) = features.masks(FUNCTIONS);
+    let (get_level, set_level) = features.masks(LEVELS);
+    let _ = write!(
+        output,
+        "{get_func:#x}\n{set_func:#x}\n{get_level:#x}\n{set_level:#x}\n0x0\n0x0\n"
+    );
+
+    let vfo_ops = VFO_OPS
+        .iter()
+        .filter(|(_, command_name)| features.implements(command_name))
+        .fold(0, |ops, (bit, _)| ops | bit);
+    let flag = |supported: bool| u8::from(supported);
+    let _ = writeln!(output, "vfo_ops={vfo_ops:#x}");
+    let _ = writeln!(
+        output,
+        "ptt_type={:#x}",
+        flag(features.implements("transmit"))
+    );
+    let _ = writeln!(
+        output,
+        "targetable_vfo={:#x}",
+        flag(features.implements("set_freq"))
+    );
+    let _ = writeln!(
+        output,
+        "has_set_vfo={}",
+        flag(features.implements("set_vfo"))
+    );
+    let _ = writeln!(output, "has_get_vfo={}", flag(features.reports("vfo")));
+    let _ = writeln!(
+        output,
+        "has_set_freq={}",
+        flag(features.implements("set_freq"))
+    );
+    let _ = writeln!(output, "has_get_freq={}", flag(features.reports("freq_a")));
+    output.push_str("has_set_conf=0\nhas_get_conf=0\n");
+    output.push_str("has_power2mW=0\nhas_mW2power=0\n");
+    let _ = writeln!(output, "timeout={}", context.status.timeout);
+    let _ = writeln!(output, "rig_model={hamlib_model}");
+    let _ = writeln!(
+        output,
+        "rigctld_version=Holyrig {}",
+        env!("CARGO_PKG_VERSION")
+    );
+    output.push_str("done\n");
+    output
+}
+
+/// Hamlib model of the rig, declared by its capabilities.
+pub(super) fn hamlib_model(context: &Context) -> i64 {
+    context
+        .rig
+        .and_then(|rig| rig.capabilities())
+        .and_then(|capabilities| capabilities.hamlib_model)
+        .unwrap_or(DEFAULT_HAMLIB_MODEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::hamlib::DeviceState;
+    use crate::interfaces::rigctld::DeviceStatus;
+    use crate::resources::Resources;
+    use crate::rig_settings::{DeviceClass, RigSettings};
+    use anyhow::Result;
+
+    #[test]
+    fn test_dump_state_without_rig_file() -> Result<()> {
+        let resources = Resources::load()?;
+        let status = DeviceStatus::new(&RigSettings {
+            rig_type: "Unknown".to_string(),
+            timeout: 500,
+            ..RigSettings::default()
+        });
+        let state = dump_state(&Context {
+            status: &status,
+            vfo_mode: false,
+            rig: None,
+            schema: resources.schemas.get(DeviceClass::Transceiver.schema()),
+        });
+        let lines: Vec<_> = state.lines().collect();
+        assert_eq!(
+            lines[..10],
+            [
+                "1",
+                "1",
+                "0",
+                "30000 470000000 0xcaf -1 -1 0x3 0x0",
+                "0 0 0 0 0 0 0",
+                "0 0 0 0 0 0 0",
+                "0 0",
+                "0 0",
+                "0",
+                "0",
+            ]
+        );
+        assert!(state.contains("\n0x81000000\n0x81000000\n0x800\n0x800\n0x0\n0x0\n"));
+        assert!(state.contains("\nvfo_ops=0x1003\n"));
+        assert!(state.contains("\nhas_get_vfo=1\n"));
+        assert!(state.contains("\ntimeout=500\nrig_model=1\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+use crate::runtime::Value;
+use anyhow::{Context, Result, bail};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+use super::hamlib::{DeviceState, Listeners, RigError, SessionFactory, report};
+use crate::resources::Resources;
+use crate::rig_settings::{DeviceClass, RigSettings};
+use crate::serial::ManagerCommand;
+use crate::serial::manager::{CommandResponse, ManagerMessage};
+
+// Hamlib `ROT_MOVE_*` directions
+const MOVE_UP: i32 = 2;
+const MOVE_DOWN: i32 = 4;
+const MOVE_LEFT: i32 = 8;
+const MOVE_RIGHT: i32 = 16;
+
+#[derive(Debug, PartialEq)]
+enum RotctlCommand {
+    SetPosition(f64, f64),
+    GetPosition,
+    Stop,
+    Park,
+    Move { direction: i32, speed: i32 },
+    DumpState,
+    Quit,
+}
+
+fn parse_rotctl_command(line: &str) -> Result<RotctlCommand> {
+    let mut params = line.split_whitespace();
+    let command = params.next().context("Empty command")?;
+
+    let mut arg = |name: &str| -> Result<f64> {
+        Ok(params
+            .next()
+            .with_context(|| format!("Missing {name}"))?
+            .parse()?)
+    };
+
+    let command = match command {
+        "P" | "\\set_pos" => RotctlCommand::SetPosition(arg("azimuth")?, arg("elevation")?),
+        "p" | "\\get_pos" => RotctlCommand::GetPosition,
+        "S" | "\\stop" => RotctlCommand::Stop,
+        "K" | "\\park" => RotctlCommand::Park,
+        "M" | "\\move" => RotctlCommand::Move {
+            direction: arg("direction")? as i32,
+            speed: arg("speed")? as i32,
+        },
+        "\\dump_state" => RotctlCommand::DumpState,
+        "q" | "Q" | "\\quit" => RotctlCommand::Quit,
+        _ => bail!("Unknown command: {command}"),
+    };
+    Ok(command)
+}
+
+/// Schema direction of a hamlib `ROT_MOVE_*` value.
+fn direction(direction: i32) -> Option<&'static str> {
+    match direction {
+        MOVE_UP => Some("Up"),
+        MOVE_DOWN => Some("Down"),
+        MOVE_LEFT => Some("Left"),
+        MOVE_RIGHT => Some("Right"),
+        _ => None,
+    }
+}
+
+/// Schema commands turning the rotator, the schema takes whole degrees.
+fn position_commands(
+    azimuth: f64,
+    elevation: f64,
+    with_elevation: bool,
+) -> Vec<(&'static str, HashMap<String, Value>)> {
+    let degrees = |value: f64| Value::Integer(value.round() as i64);
+    if with_elevation {
+        vec![(
+            "set_position",
+            HashMap::from([
+                ("azimuth".to_string(), degrees(azimuth)),
+                ("elevation".to_string(), degrees(elevation)),
+            ]),
+        )]
+    } else {
+        vec![(
+            "set_azimuth",
+            HashMap::from([("azimuth".to_string(), degrees(azimuth))]),
+        )]
+    }
+}
+
+struct DeviceStatus {
+    device_id: usize,
+    /// Configured rig type of the rotator
+    rig_type: String,
+    connected: bool,
+    azimuth: f64,
+    elevation: f64,
+}
+
+impl DeviceStatus {
+    /// Id and rig type of the rotator, while it is connected.
+    fn device(&self) -> Option<(usize, &str)> {
+        self.connected
+            .then_some((self.device_id, self.rig_type.as_str()))
+    }
+}
+
+impl DeviceState for DeviceStatus {
+    fn new(settings: &RigSettings) -> Self {
+        Self {
+            device_id: settings.id,
+            rig_type: settings.rig_type.clone(),
+            connected: false,
+            azimuth: 0.0,
+            elevation: 0.0,
+        }
+    }
+
+    fn configure(&mut self, settings: &RigSettings) {
+        self.rig_type = settings.rig_type.clone();
+    }
+
+    fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    fn update(&mut self, values: HashMap<String, Value>) {
+        for (name, value) in values {
+            let value = match value {
+                Value::Integer(value) => value as f64,
+                Value::Float(value) => value,
+                _ => continue,
+            };
+            match name.as_str() {
+                "azimuth" => self.azimuth = value,
+                "elevation" => self.elevation = value,
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Client {
+    resources: Arc<Resources>,
+    device_status: Arc<RwLock<DeviceStatus>>,
+    command_sender: Sender<ManagerCommand>,
+}
+
+impl Client {
+    fn implements(&self, rig_model: &str, command_name: &str) -> bool {
+        self.resources.rigs.get(rig_model).is_some_and(|rig| {
+            rig.rig_file()
+                .impl_block
+                .commands
+                .contains_key(command_name)
+        })
+    }
+
+    /// Runs the schema commands in order on the rotator and returns the hamlib outcome.
+    async fn execute(
+        &self,
+        commands: Vec<(&str, HashMap<String, Value>)>,
+    ) -> Result<Result<(), RigError>> {
+        let Some((device_id, rig_model)) = self
+            .device_status
+            .read()
+            .device()
+            .map(|(device_id, rig_model)| (device_id, rig_model.to_string()))
+        else {
+            return Ok(Err(RigError::Io));
+        };
+        if let Some((command_name, _)) = commands
+            .iter()
+            .find(|(command_name, _)| !self.implements(&rig_model, command_name))
+        {
+            eprintln!("Rotator {rig_model} does not implement {command_name}");
+            return Ok(Err(RigError::NotImplemented));
+        }
+
+        for (command_name, params) in commands {
+            let (tx, rx) = oneshot::channel();
+            self.command_sender
+                .send(ManagerCommand::ExecuteCommand {
+                    device_id,
+                    command_name: command_name.to_string(),
+                    params,
+                    response_channel: Some(tx),
+                })
+                .await?;
+            if let CommandResponse::Error(err) = rx.await? {
+                eprintln!("Rotator command {command_name} failed: {err}");
+                return Ok(Err(err.kind.into()));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    /// Position range of the rotator schema, as declared by the `set_position` parameters.
+    fn position_ranges(&self) -> (RangeInclusive<i64>, RangeInclusive<i64>) {
+        let parameters = self
+            .resources
+            .schemas
+            .get(DeviceClass::Rotator.schema())
+            .and_then(|schema| schema.commands.get("set_position"))
+            .map(|command| command.parameters.as_slice())
+            .unwrap_or_default();
+        let range = |name: &str, default: RangeInclusive<i64>| {
+            parameters
+                .iter()
+                .find(|parameter| parameter.name == name)
+                .and_then(|parameter| parameter.range.clone())
+                .unwrap_or(default)
+        };
+        (range("azimuth", 0..=360), range("elevation", 0..=90))
+    }
+
+    /// Answer to a command, `None` when the client quits.
+    async fn handle_command(&self, command: RotctlCommand) -> Result<Option<String>> {
+        let commands = match command {
+            RotctlCommand::SetPosition(azimuth, elevation) => {
+                let rig_model = self
+                    .device_status
+                    .read()
+                    .device()
+                    .map(|(_, rig_model)| rig_model.to_string())
+                    .unwrap_or_default();
+                // Azimuth-only rotators ignore the elevation
+                let with_elevation = self.implements(&rig_model, "set_position")
+                    || !self.implements(&rig_model, "set_azimuth");
+                position_commands(azimuth, elevation, with_elevation)
+            }
+            RotctlCommand::GetPosition => {
+                let device_status = self.device_status.read();
+                return Ok(Some(format!(
+                    "{:.6}\n{:.6}\n",
+                    device_status.azimuth, device_status.elevation
+                )));
+            }
+            RotctlCommand::Stop => vec![("stop", HashMap::new())],
+            RotctlCommand::Park => vec![("park", HashMap::new())],
+            RotctlCommand::Move {
+                direction: value,
+                speed,
+            } => {
+                let Some(direction) = direction(value) else {
+                    return Ok(Some(report(Err(RigError::Invalid))));
+                };
+                let mut commands = Vec::new();
+                // -1 keeps the current speed
+                if speed != -1 {
+                    commands.push((
+                        "set_speed",
+                        HashMap::from([("speed".to_string(), Value::Integer(speed as i64))]),
+                    ));
+                }
+                commands.push((
+                    "rotate",
+                    HashMap::from([(
+                        "direction".to_string(),
+                        Value::String(direction.to_string()),
+                    )]),
+                ));
+                commands
+            }
+            RotctlCommand::DumpState => {
+                let (azimuth, elevation) = self.position_ranges();
+                return Ok(Some(
+                    [
+                        // Protocol version
+                        "1".to_string(),
+                        // Azimuth and elevation limits
+                        format!("{:.6}", *azimuth.start() as f64),
+                        format!("{:.6}", *azimuth.end() as f64),
+                        format!("{:.6}", *elevation.start() as f64),
+                        format!("{:.6}", *elevation.end() as f64),
+                        "south_zero=0".to_string(),
+                        "rot_type=AzEl".to_string(),
+                        "done".to_string(),
+                    ]
+                    .map(|line| line + "\n")
+                    .concat(),
+                ));
+            }
+            RotctlCommand::Quit => return Ok(None),
+        };
+
+        Ok(Some(report(self.execute(commands).await?)))
+    }
+}
+
+async fn handle_client(mut socket: TcpStream, client: Client, addr: SocketAddr) -> Result<()> {
+    let (reader, mut writer) = socket.split();
+
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_rotctl_command(&line) {
+            Ok(command) => match client.handle_command(command).await? {
+                Some(response) => response,
+                None => break,
+            },
+            Err(e) => {
+                eprintln!("Error parsing command from {}: {}", addr, e);
+                report(Err(RigError::Invalid))
+            }
+        };
+        writer.write_all(response.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Rotctld listeners of the rotators.
+fn listeners(
+    resources: Arc<Resources>,
+    command_sender: Sender<ManagerCommand>,
+) -> Listeners<DeviceStatus> {
+    let client_sender = command_sender.clone();
+    let session: SessionFactory<DeviceStatus> = Arc::new(move |socket, addr, device_status| {
+        let client = Client {
+            resources: resources.clone(),
+            device_status,
+            command_sender: client_sender.clone(),
+        };
+        Box::pin(handle_client(socket, client, addr))
+    });
+    Listeners::new("Rotctld", DeviceClass::Rotator, command_sender, session)
+}
+
+pub async fn run_server(
+    resources: Arc<Resources>,
+    command_sender: Sender<ManagerCommand>,
+    message_receiver: Receiver<ManagerMessage>,
+) -> Result<()> {
+    listeners(resources, command_sender)
+        .run(message_receiver)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rig_settings::RotctldSettings;
+    use tokio::sync::mpsc;
+
+    fn settings(id: usize) -> RigSettings {
+        RigSettings {
+            id,
+            device_class: DeviceClass::Rotator,
+            rig_type: "GS232A".to_string(),
+            rotctld: RotctldSettings {
+                enabled: true,
+                address: "127.0.0.1:0".to_string(),
+            },
+            ..RigSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_rotctl_command() -> Result<()> {
+        assert_eq!(
+            parse_rotctl_command("P 180.5 45\n")?,
+            RotctlCommand::SetPosition(180.5, 45.0)
+        );
+        assert_eq!(
+            parse_rotctl_command("\\set_pos 10 0\n")?,
+            RotctlCommand::SetPosition(10.0, 0.0)
+        );
+        assert_eq!(parse_rotctl_command("p\n")?, RotctlCommand::GetPosition);
+        assert_eq!(parse_rotctl_command("S\n")?, RotctlCommand::Stop);
+        assert_eq!(parse_rotctl_command("K\n")?, RotctlCommand::Park);
+        assert_eq!(
+            parse_rotctl_command("M 16 50\n")?,
+            RotctlCommand::Move {
+                direction: MOVE_RIGHT,
+                speed: 50
+            }
+        );
+        assert_eq!(
+            parse_rotctl_command("\\dump_state\n")?,
+            RotctlCommand::DumpState
+        );
+        assert_eq!(parse_rotctl_command("q\n")?, RotctlCommand::Quit);
+
+        assert!(parse_rotctl_command("P 180\n").is_err());
+        assert!(parse_rotctl_command("M left 50\n").is_err());
+        assert!(parse_rotctl_command("F 14074000\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fractional_position() -> Result<()> {
+        let resources = Resources::load()?;
+        let schema = &resources.schemas[DeviceClass::Rotator.schema()];
+        let RotctlCommand::SetPosition(azimuth, elevation) =
+            parse_rotctl_command("P 180.50 45.25\n")?
+        else {
+            panic!("Expected a position");
+        };
+
+        for with_elevation in [true, false] {
+            for (command_name, params) in position_commands(azimuth, elevation, with_elevation) {
+                let args = schema.commands[command_name]
+                    .prepare_args(params, &HashMap::new())
+                    .unwrap();
+                assert_eq!(args["azimuth"], Value::Integer(181));
+                if with_elevation {
+                    assert_eq!(args["elevation"], Value::Integer(45));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction() {
+        assert_eq!(direction(MOVE_LEFT), Some("Left"));
+        assert_eq!(direction(MOVE_UP), Some("Up"));
+        assert_eq!(direction(32), None);
+    }
+
+    #[tokio::test]
+    async fn test_resync() -> Result<()> {
+        let (sender, mut receiver) = mpsc::channel(32);
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                if let ManagerCommand::GetDevices { response_channel } = command {
+                    let transceiver = RigSettings {
+                        device_class: DeviceClass::Transceiver,
+                        ..settings(2)
+                    };
+                    let _ = response_channel.send(vec![(settings(1), true), (transceiver, true)]);
+                }
+            }
+        });
+        let session: SessionFactory<DeviceStatus> = Arc::new(|_, _, _| Box::pin(async { Ok(()) }));
+        let mut listeners = Listeners::new("Rotctld", DeviceClass::Rotator, sender, session);
+        listeners
+            .handle_message(ManagerMessage::DeviceAdded {
+                settings: settings(3),
+            })
+            .await;
+        assert_eq!(listeners.device_ids(), [3]);
+
+        // The messages adding and connecting rotator 1 and removing rotator 3 were missed
+        listeners.resync().await?;
+        assert_eq!(listeners.device_ids(), [1]);
+        let device_status = listeners.state(1).unwrap();
+        assert_eq!(device_status.read().device(), Some((1, "GS232A")));
+        Ok(())
+    }
+}
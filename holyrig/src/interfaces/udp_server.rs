@@ -5,11 +5,12 @@ use tokio::sync::broadcast::Receiver;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
+use crate::runtime::Value;
 use crate::serial::ManagerCommand;
 use crate::serial::manager::{CommandResponse, ManagerMessage};
 
 // Parse a command string in format: "DEVICE_ID COMMAND_NAME PARAM1=VALUE1 PARAM2=VALUE2"
-fn parse_command(cmd: &str) -> Result<(usize, String, HashMap<String, String>)> {
+fn parse_command(cmd: &str) -> Result<(usize, String, HashMap<String, Value>)> {
     let mut parts = cmd.split_whitespace();
 
     let device_id = parts
@@ -31,7 +32,7 @@ fn parse_command(cmd: &str) -> Result<(usize, String, HashMap<String, String>)>
         let value = kv
             .next()
             .ok_or_else(|| anyhow::anyhow!("Invalid parameter format"))?;
-        params.insert(key.to_string(), value.to_string());
+        params.insert(key.to_string(), Value::String(value.to_string()));
     }
 
     Ok((device_id, command_name, params))
@@ -62,7 +63,7 @@ pub async fn run_server(
                         }
                         (response, None)
                     },
-                    ManagerMessage::DeviceConnected { device_id, rig_model: _ } => {
+                    ManagerMessage::DeviceConnected { device_id, .. } => {
                         (format!("Device {device_id} connected"), Some(device_id))
                     },
                     ManagerMessage::DeviceDisconnected { device_id } => {
@@ -76,6 +77,9 @@ pub async fn run_server(
 
                         (format!("Device {device_id} status update:\n{}\n", formatted_values.join("\n")), Some(device_id))
                     }
+                    ManagerMessage::DeviceAdded { .. }
+                    | ManagerMessage::DeviceRemoved { .. }
+                    | ManagerMessage::Trace { .. } => continue,
                 };
                 if let Some(device_id) = device_id {
                     if let Some(addr) = device_id_to_addr.get(&device_id) {
@@ -1,7 +1,11 @@
+pub mod capability_matrix;
 pub mod data_format;
 pub mod gui;
 pub mod interfaces;
+pub mod lsp;
+pub mod omnirig_import;
 pub mod resources;
 pub mod rig_settings;
 pub mod runtime;
+pub mod schema_diff;
 pub mod serial;
@@ -0,0 +1,7 @@
+mod server;
+mod types;
+mod workspace;
+
+pub use server::LspServer;
+pub use types::{CompletionItem, CompletionKind, Diagnostic, Location, Position, Range, Severity};
+pub use workspace::{DocumentKind, Workspace};
@@ -0,0 +1,273 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use super::types::Position;
+use super::workspace::{DocumentKind, Workspace};
+use crate::interfaces::jsonrpc::{Notification, Request, Response, RpcError, VERSION};
+
+/// Language server for rig, rig module and schema files speaking LSP over a pair of streams,
+/// usually stdin and stdout.
+pub struct LspServer<W: Write> {
+    workspace: Workspace,
+    writer: W,
+}
+
+impl<W: Write> LspServer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            workspace: Workspace::new(),
+            writer,
+        }
+    }
+
+    /// Handles messages until the client sends `exit` or closes the stream.
+    pub fn run(&mut self, mut reader: impl BufRead) -> Result<()> {
+        while let Some(body) = read_message(&mut reader)? {
+            let message = match serde_json::from_slice(&body) {
+                Ok(message) => message,
+                Err(err) => {
+                    self.send(&Response::build_error(RpcError::parse_error(&err)))?;
+                    continue;
+                }
+            };
+            if !self.handle_message(message)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, message: Value) -> Result<bool> {
+        if message.get("id").is_some() {
+            let request: Request = match serde_json::from_value(message.clone()) {
+                Ok(request) => request,
+                Err(_) => {
+                    let id = serde_json::from_value(message["id"].clone()).unwrap_or_default();
+                    self.send(&Response::build_error(
+                        RpcError::invalid_request().with_id(&id),
+                    ))?;
+                    return Ok(true);
+                }
+            };
+            let response = match self.handle_request(&request) {
+                Ok(result) => Response::build_result(request.id.clone(), result),
+                Err(err) => Response::build_error(err.with_id(&request.id)),
+            };
+            self.send(&response)?;
+            return Ok(true);
+        }
+
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match message["method"].as_str().unwrap_or_default() {
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.workspace.open(uri, text.to_string());
+                self.publish_diagnostics(uri)?;
+            }
+            "textDocument/didChange" => {
+                // Full document sync, the last change holds the whole text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.workspace.open(uri, text.to_string());
+                    self.publish_diagnostics(uri)?;
+                }
+            }
+            "textDocument/didClose" => {
+                self.workspace.close(uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn handle_request(&mut self, request: &Request) -> Result<Value, RpcError> {
+        let params = request.params.clone().unwrap_or_default();
+        match request.method.as_str() {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {"triggerCharacters": [":", "\""]},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": {"name": "holyrig-lsp"},
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/completion" => {
+                let (uri, position) = text_document_position(&params)?;
+                Ok(json!(self.workspace.completion(&uri, position)))
+            }
+            "textDocument/hover" => {
+                let (uri, position) = text_document_position(&params)?;
+                Ok(self
+                    .workspace
+                    .hover(&uri, position)
+                    .map(|value| json!({"contents": {"kind": "markdown", "value": value}}))
+                    .unwrap_or(Value::Null))
+            }
+            "textDocument/definition" => {
+                let (uri, position) = text_document_position(&params)?;
+                Ok(json!(self.workspace.definition(&uri, position)))
+            }
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    /// Publishes the diagnostics of `uri`, a changed schema or module also affects every open
+    /// rig file.
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<()> {
+        let mut uris = vec![uri.to_string()];
+        if DocumentKind::from_uri(uri) != Some(DocumentKind::Rig) {
+            uris.extend(
+                self.workspace
+                    .uris()
+                    .into_iter()
+                    .filter(|other| DocumentKind::from_uri(other) == Some(DocumentKind::Rig)),
+            );
+        }
+
+        for uri in uris {
+            let diagnostics = self.workspace.diagnostics(&uri);
+            self.notify(
+                "textDocument/publishDiagnostics",
+                json!({"uri": uri, "diagnostics": diagnostics}),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.send(&Notification {
+            jsonrpc: VERSION.to_string(),
+            method: method.to_string(),
+            params,
+        })
+    }
+
+    fn send(&mut self, message: &impl Serialize) -> Result<()> {
+        let body = serde_json::to_string(message)?;
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn text_document_position(params: &Value) -> Result<(String, Position), RpcError> {
+    let uri = params["textDocument"]["uri"]
+        .as_str()
+        .ok_or_else(RpcError::invalid_params)?;
+    let position = serde_json::from_value(params["position"].clone())
+        .map_err(|_| RpcError::invalid_params())?;
+    Ok((uri.to_string(), position))
+}
+
+/// Reads the body of the next message framed with a `Content-Length` header, skipping header
+/// blocks without a valid one, `None` once the stream is closed.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let length = loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("Content-Length")
+            {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        match length {
+            Some(length) => break length,
+            None => eprintln!("Skipped a message without Content-Length header"),
+        }
+    };
+
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .context("Stream closed in the middle of a message")?;
+    Ok(Some(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn frame(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    #[test]
+    fn test_session() -> Result<()> {
+        let schema = "version = 1;\nschema Test {\n    /// Tune.\n    fn set_freq(int freq);\n}\n";
+        let rig = "impl Test for TestRig {\n    fn set_freq(int freq) {\n        write(\"00\")\n    }\n}\n";
+        let input = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": "file:///rigs/test.schema", "text": schema},
+            }}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": "file:///rigs/test.rig", "text": rig},
+            }}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {
+                "textDocument": {"uri": "file:///rigs/test.rig"},
+                "position": {"line": 1, "character": 8},
+            }}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "unknown"}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": 5}),
+        ]
+        .map(frame)
+        .concat()
+            + "Content-Length: 9\r\n\r\n{\"id\": 5"
+            + "Content-Type: text/plain\r\n\r\n"
+            + &frame(json!({"jsonrpc": "2.0", "method": "exit"}));
+
+        let mut output = Vec::new();
+        LspServer::new(&mut output).run(Cursor::new(input))?;
+
+        let mut reader = Cursor::new(output);
+        let mut messages = Vec::new();
+        while let Some(body) = read_message(&mut reader)? {
+            messages.push(serde_json::from_slice::<Value>(&body)?);
+        }
+
+        assert_eq!(messages.len(), 7);
+        assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(messages[1]["params"]["diagnostics"], json!([]));
+        let diagnostics = &messages[2]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().map(Vec::len), Some(1));
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 3);
+        assert!(
+            messages[3]["result"]["contents"]["value"]
+                .as_str()
+                .is_some_and(|value| value.contains("fn set_freq(int freq)"))
+        );
+        assert_eq!(messages[4]["error"]["code"], RpcError::METHOD_NOT_FOUND);
+        assert_eq!(messages[5]["error"]["code"], RpcError::INVALID_REQUEST);
+        assert_eq!(messages[5]["id"], 4);
+        assert_eq!(messages[6]["error"]["code"], RpcError::PARSE_ERROR);
+        assert_eq!(messages[6]["id"], Value::Null);
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::SourcePosition;
+
+/// Zero based position as used by the language server protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl Position {
+    pub fn new(line: u32, character: u32) -> Self {
+        Self { line, character }
+    }
+}
+
+impl From<&SourcePosition> for Position {
+    fn from(position: &SourcePosition) -> Self {
+        Self {
+            line: position.line.saturating_sub(1) as u32,
+            character: position.column.saturating_sub(1) as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum Severity {
+    Error = 1,
+    Warning = 2,
+}
+
+impl From<Severity> for u8 {
+    fn from(severity: Severity) -> Self {
+        severity as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub source: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(range: Range, message: impl Into<String>) -> Self {
+        Self {
+            range,
+            severity: Severity::Error,
+            source: "holyrig",
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(range: Range, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(range, message)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum CompletionKind {
+    Function = 3,
+    Field = 5,
+    Variable = 6,
+    Enum = 13,
+    Keyword = 14,
+    EnumMember = 20,
+}
+
+impl From<CompletionKind> for u8 {
+    fn from(kind: CompletionKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text: Option<String>,
+}
+
+impl CompletionItem {
+    pub fn new(label: impl Into<String>, kind: CompletionKind) -> Self {
+        Self {
+            label: label.into(),
+            kind,
+            detail: None,
+            documentation: None,
+            insert_text: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_documentation(mut self, documentation: Option<String>) -> Self {
+        self.documentation = documentation;
+        self
+    }
+
+    pub fn with_insert_text(mut self, insert_text: impl Into<String>) -> Self {
+        self.insert_text = Some(insert_text.into());
+        self
+    }
+}
@@ -0,0 +1,932 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range as ByteRange;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use logos::Logos;
+
+use super::types::{CompletionItem, CompletionKind, Diagnostic, Location, Position, Range};
+use crate::data_format::DataFormat;
+use crate::runtime::{
+    ParseError, Recovered, RigFile, SchemaCommand, SchemaFile, SemanticAnalyzer, SourcePosition,
+    Token, filter_cascading_errors, module_loader, parse_rig_file_with_recovery,
+    parse_rig_module_with_recovery, parse_schema, parse_schema_with_recovery,
+};
+
+const BUILTINS: [(&str, &str, &str); 8] = [
+    ("write", "write(bytes data)", "Sends the bytes to the rig."),
+    (
+        "read",
+        "read(bytes template)",
+        "Reads a reply matching the template and binds its fields.",
+    ),
+    (
+        "set_var",
+        "set_var(string name, value)",
+        "Publishes the value of a status field.",
+    ),
+    ("error", "error(string message)", "Fails the command."),
+    (
+        "call",
+        "call command(args...)",
+        "Test step running a command, `init` or `status`.",
+    ),
+    (
+        "expect_write",
+        "expect_write(bytes data)",
+        "Test step expecting the bytes written by the last call.",
+    ),
+    (
+        "reply",
+        "reply(bytes data)",
+        "Test step answering the next read of the last call.",
+    ),
+    (
+        "expect_status",
+        "expect_status(field = value, ...)",
+        "Test step checking the published status fields.",
+    ),
+];
+
+const DATA_FORMATS: [DataFormat; 9] = [
+    DataFormat::BcdBs,
+    DataFormat::BcdBu,
+    DataFormat::BcdLs,
+    DataFormat::BcdLu,
+    DataFormat::IntBs,
+    DataFormat::IntBu,
+    DataFormat::IntLs,
+    DataFormat::IntLu,
+    DataFormat::Text,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Rig,
+    Module,
+    Schema,
+}
+
+impl DocumentKind {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match Path::new(uri).extension()?.to_str()? {
+            "rig" => Some(DocumentKind::Rig),
+            "rigmod" => Some(DocumentKind::Module),
+            "schema" => Some(DocumentKind::Schema),
+            _ => None,
+        }
+    }
+}
+
+struct SchemaDocument {
+    uri: String,
+    source: String,
+    schema: SchemaFile,
+}
+
+/// What the text in front of the cursor asks to complete.
+#[derive(Debug, PartialEq)]
+enum CompletionContext {
+    DataFormat,
+    EnumVariant(String),
+    StatusField,
+    CommandDeclaration,
+    Statement,
+    None,
+}
+
+/// Open documents of the editor. Schemas and imported modules that aren't open are read from
+/// disk, next to the rig file or in the `schema` directory beside its directory.
+#[derive(Default)]
+pub struct Workspace {
+    documents: HashMap<String, String>,
+    /// Last version of every rig file that parsed, completion keeps working while a file is
+    /// being edited.
+    parsed: HashMap<String, RigFile>,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self, uri: &str, text: String) {
+        self.documents.insert(uri.to_string(), text);
+    }
+
+    pub fn close(&mut self, uri: &str) {
+        self.documents.remove(uri);
+        self.parsed.remove(uri);
+    }
+
+    pub fn uris(&self) -> Vec<String> {
+        self.documents.keys().cloned().collect()
+    }
+
+    pub fn diagnostics(&mut self, uri: &str) -> Vec<Diagnostic> {
+        let Some(text) = self.documents.get(uri).cloned() else {
+            return Vec::new();
+        };
+
+        match DocumentKind::from_uri(uri) {
+            Some(DocumentKind::Rig) => self.rig_diagnostics(uri, &text),
+            Some(DocumentKind::Module) => {
+                parse_errors(&text, parse_rig_module_with_recovery(&text))
+            }
+            Some(DocumentKind::Schema) => parse_errors(&text, parse_schema_with_recovery(&text)),
+            None => Vec::new(),
+        }
+    }
+
+    fn rig_diagnostics(&mut self, uri: &str, text: &str) -> Vec<Diagnostic> {
+        let Recovered {
+            value,
+            errors,
+            skipped,
+        } = parse_rig_file_with_recovery(text);
+        let mut diagnostics: Vec<_> = errors.iter().map(|err| parse_error(text, err)).collect();
+        let Some(mut rig_file) = value else {
+            return diagnostics;
+        };
+
+        let dir = uri_to_path(uri)
+            .and_then(|path| path.parent().map(PathBuf::from))
+            .unwrap_or_default();
+        let imports = rig_file.resolve_imports(module_loader(&dir, |path| self.read(path)));
+        self.parsed.insert(uri.to_string(), rig_file.clone());
+        if let Err(err) = imports {
+            let range = Range::new(
+                position_at(text, err.span.start.offset),
+                position_at(text, err.span.end.offset),
+            );
+            diagnostics.push(Diagnostic::error(range, err.to_string()));
+            return diagnostics;
+        }
+
+        let Some(schema) = self.schema_for(uri, text) else {
+            let range = find_token(text, |tokens| {
+                tokens.windows(2).find_map(|window| match window {
+                    [(Token::Impl, _), (Token::Id(_), range)] => Some(range.clone()),
+                    _ => None,
+                })
+            });
+            diagnostics.push(Diagnostic::error(
+                range.unwrap_or_default(),
+                format!("Unknown schema: {}", rig_file.impl_block.schema),
+            ));
+            return diagnostics;
+        };
+
+        let (errors, warnings) = SemanticAnalyzer::new(schema.schema).diagnose(&rig_file);
+        let errors = filter_cascading_errors(errors, &skipped);
+        diagnostics.extend(errors.iter().map(|err| {
+            Diagnostic::error(word_range(text, err.position.as_ref()), err.to_string())
+        }));
+        // Statements dropped by the recovery would show up as unused variables and unread writes.
+        if skipped.is_empty() {
+            diagnostics.extend(warnings.iter().map(|warning| {
+                let range = word_range(text, warning.position.as_ref());
+                Diagnostic::warning(range, warning.to_string())
+            }));
+        }
+        diagnostics
+    }
+
+    pub fn completion(&self, uri: &str, position: Position) -> Vec<CompletionItem> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let offset = offset_at(text, position);
+        let line_start = text[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let schema = self.schema_for(uri, text).map(|document| document.schema);
+        let rig_file = self.parsed.get(uri);
+
+        match completion_context(&text[line_start..offset]) {
+            CompletionContext::DataFormat => DATA_FORMATS
+                .iter()
+                .map(|format| CompletionItem::new(format.to_string(), CompletionKind::Keyword))
+                .collect(),
+            CompletionContext::EnumVariant(enum_name) => {
+                let rig_enum = rig_file.and_then(|rig_file| {
+                    rig_file
+                        .impl_block
+                        .enums
+                        .iter()
+                        .find(|item| item.name == enum_name)
+                });
+                let variants: Vec<String> = match rig_enum {
+                    Some(rig_enum) => rig_enum.variants.keys().cloned().collect(),
+                    None => schema
+                        .as_ref()
+                        .and_then(|schema| schema.enums.get(&enum_name))
+                        .cloned()
+                        .unwrap_or_default(),
+                };
+                variants
+                    .into_iter()
+                    .map(|variant| CompletionItem::new(variant, CompletionKind::EnumMember))
+                    .collect()
+            }
+            CompletionContext::StatusField => schema
+                .iter()
+                .flat_map(|schema| &schema.status)
+                .map(|(name, field)| {
+                    CompletionItem::new(name, CompletionKind::Field)
+                        .with_detail(field.to_string())
+                        .with_documentation(field.doc.clone())
+                })
+                .collect(),
+            CompletionContext::CommandDeclaration => schema
+                .iter()
+                .flat_map(|schema| &schema.commands)
+                .filter(|(name, _)| {
+                    rig_file.is_none_or(|rig_file| {
+                        !rig_file.impl_block.commands.contains_key(name.as_str())
+                    })
+                })
+                .map(|(name, command)| {
+                    let parameters = command
+                        .parameters
+                        .iter()
+                        .map(|param| format!("{} {}", param.param_type, param.name))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    CompletionItem::new(name, CompletionKind::Function)
+                        .with_detail(command_signature(name, command))
+                        .with_documentation(command.doc.clone())
+                        .with_insert_text(format!("{name}({parameters})"))
+                })
+                .collect(),
+            CompletionContext::Statement => {
+                let mut items: Vec<_> = BUILTINS
+                    .iter()
+                    .map(|(name, signature, doc)| {
+                        CompletionItem::new(*name, CompletionKind::Function)
+                            .with_detail(*signature)
+                            .with_documentation(Some(doc.to_string()))
+                    })
+                    .collect();
+                if let Some(rig_file) = rig_file {
+                    items.extend(rig_file.impl_block.commands.values().flat_map(|command| {
+                        command.parameters.iter().map(|param| {
+                            CompletionItem::new(&param.name, CompletionKind::Variable)
+                                .with_detail(format!("{} {}", param.param_type, param.name))
+                        })
+                    }));
+                    items.extend(
+                        rig_file
+                            .impl_block
+                            .enums
+                            .iter()
+                            .map(|item| CompletionItem::new(&item.name, CompletionKind::Enum)),
+                    );
+                }
+                if let Some(schema) = &schema {
+                    items.extend(schema.commands.iter().map(|(name, command)| {
+                        CompletionItem::new(name, CompletionKind::Function)
+                            .with_detail(command_signature(name, command))
+                            .with_documentation(command.doc.clone())
+                    }));
+                    items.extend(
+                        schema
+                            .enums
+                            .keys()
+                            .map(|name| CompletionItem::new(name, CompletionKind::Enum)),
+                    );
+                }
+                let mut seen = HashSet::new();
+                items.retain(|item| seen.insert(item.label.clone()));
+                items
+            }
+            CompletionContext::None => Vec::new(),
+        }
+    }
+
+    pub fn hover(&self, uri: &str, position: Position) -> Option<String> {
+        let text = self.documents.get(uri)?;
+        let range = word_at(text, offset_at(text, position))?;
+        let word = &text[range.clone()];
+        let schema = self.schema_for(uri, text).map(|document| document.schema);
+        let rig_file = self.parsed.get(uri);
+
+        let qualifier = text[..range.start]
+            .strip_suffix("::")
+            .and_then(|before| word_at(before, before.len()).map(|range| &before[range]));
+        let enum_name = qualifier.unwrap_or(word);
+        if let Some(rig_enum) = rig_file.and_then(|rig_file| {
+            rig_file
+                .impl_block
+                .enums
+                .iter()
+                .find(|item| item.name == enum_name)
+        }) {
+            let variants = rig_enum
+                .variants
+                .iter()
+                .map(|(name, value)| format!("{name} = {value}"))
+                .collect::<Vec<_>>();
+            return Some(code_block(&format!(
+                "enum {} {{ {} }}",
+                rig_enum.name,
+                variants.join(", ")
+            )));
+        }
+        if let Some(variants) = schema
+            .as_ref()
+            .and_then(|schema| schema.enums.get(enum_name))
+        {
+            return Some(code_block(&format!(
+                "enum {enum_name} {{ {} }}",
+                variants.join(", ")
+            )));
+        }
+
+        if let Some(schema) = &schema {
+            let in_string = text[..range.start].ends_with("s\"");
+            if let Some(command) = schema.commands.get(word).filter(|_| !in_string) {
+                return Some(with_doc(
+                    code_block(&command_signature(word, command)),
+                    &command.doc,
+                ));
+            }
+            if let Some(field) = schema.status.get(word) {
+                return Some(with_doc(code_block(&format!("status {field}")), &field.doc));
+            }
+        }
+
+        BUILTINS
+            .iter()
+            .find(|(name, ..)| *name == word)
+            .map(|(_, signature, doc)| with_doc(code_block(signature), &Some(doc.to_string())))
+    }
+
+    pub fn definition(&self, uri: &str, position: Position) -> Vec<Location> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let Some(range) = word_at(text, offset_at(text, position)) else {
+            return Vec::new();
+        };
+        let word = &text[range.clone()];
+
+        if DocumentKind::from_uri(uri) == Some(DocumentKind::Schema) {
+            let Ok(schema) = parse_schema(text) else {
+                return Vec::new();
+            };
+            return self
+                .documents
+                .iter()
+                .filter(|(uri, _)| DocumentKind::from_uri(uri) == Some(DocumentKind::Rig))
+                .filter(|(_, source)| impl_schema_name(source).as_deref() == Some(&schema.name))
+                .filter_map(|(uri, source)| {
+                    Some(Location {
+                        uri: uri.clone(),
+                        range: find_declaration(source, word)?,
+                    })
+                })
+                .collect();
+        }
+
+        // Uses of a command or enum jump to its declaration in the rig file, the declaration
+        // itself jumps to the schema.
+        let word_range = Range::new(position_at(text, range.start), position_at(text, range.end));
+        if let Some(declaration) = find_declaration(text, word)
+            && declaration != word_range
+        {
+            return vec![Location {
+                uri: uri.to_string(),
+                range: declaration,
+            }];
+        }
+
+        let Some(schema) = self.schema_for(uri, text) else {
+            return Vec::new();
+        };
+        find_declaration(&schema.source, word)
+            .or_else(|| find_status_field(&schema.source, word))
+            .map(|range| Location {
+                uri: schema.uri,
+                range,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn read(&self, path: &Path) -> Result<String> {
+        let uri = path_to_uri(path);
+        match self.documents.get(&uri) {
+            Some(text) => Ok(text.clone()),
+            None => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Schema implemented by a rig file, or the schema document itself.
+    fn schema_for(&self, uri: &str, text: &str) -> Option<SchemaDocument> {
+        if DocumentKind::from_uri(uri) == Some(DocumentKind::Schema) {
+            return Some(SchemaDocument {
+                uri: uri.to_string(),
+                source: text.to_string(),
+                schema: parse_schema(text).ok()?,
+            });
+        }
+
+        let name = impl_schema_name(text)?;
+        self.schemas(uri)
+            .into_iter()
+            .find(|document| document.schema.name == name)
+    }
+
+    fn schemas(&self, uri: &str) -> Vec<SchemaDocument> {
+        let mut sources: HashMap<String, String> = HashMap::new();
+
+        if let Some(dir) = uri_to_path(uri).and_then(|path| path.parent().map(PathBuf::from)) {
+            let dirs = [dir.clone(), dir.parent().unwrap_or(&dir).join("schema")];
+            for entry in dirs.iter().filter_map(|dir| dir.read_dir().ok()).flatten() {
+                let Ok(entry) = entry else {
+                    continue;
+                };
+                let path = entry.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "schema")
+                    && let Ok(source) = std::fs::read_to_string(&path)
+                {
+                    sources.insert(path_to_uri(&path), source);
+                }
+            }
+        }
+
+        for (uri, text) in &self.documents {
+            if DocumentKind::from_uri(uri) == Some(DocumentKind::Schema) {
+                sources.insert(uri.clone(), text.clone());
+            }
+        }
+
+        sources
+            .into_iter()
+            .filter_map(|(uri, source)| {
+                let schema = parse_schema(&source).ok()?;
+                Some(SchemaDocument {
+                    uri,
+                    source,
+                    schema,
+                })
+            })
+            .collect()
+    }
+}
+
+fn completion_context(prefix: &str) -> CompletionContext {
+    let word_start = prefix
+        .rfind(|char: char| !is_word_char(char))
+        .map_or(0, |index| index + 1);
+    let before = &prefix[..word_start];
+
+    if before.matches('"').count() % 2 == 1 {
+        let quote = before.rfind('"').unwrap_or_default();
+        if before[..quote].ends_with('s') {
+            return if before[..quote - 1].trim_end().ends_with("set_var(") {
+                CompletionContext::StatusField
+            } else {
+                CompletionContext::None
+            };
+        }
+
+        let literal = &before[quote..];
+        return match literal.rfind('{') {
+            Some(brace)
+                if !literal[brace..].contains('}')
+                    && literal[brace..].matches(':').count() == 1 =>
+            {
+                CompletionContext::DataFormat
+            }
+            _ => CompletionContext::None,
+        };
+    }
+
+    if let Some(qualified) = before.strip_suffix("::") {
+        let enum_start = qualified
+            .rfind(|char: char| !is_word_char(char))
+            .map_or(0, |index| index + 1);
+        return CompletionContext::EnumVariant(qualified[enum_start..].to_string());
+    }
+
+    let before = before.trim_end();
+    if before == "fn" || before.ends_with(" fn") || before.ends_with("\tfn") {
+        return CompletionContext::CommandDeclaration;
+    }
+
+    if before.ends_with('(') && before.trim_end_matches('(').ends_with("expect_status") {
+        return CompletionContext::StatusField;
+    }
+
+    CompletionContext::Statement
+}
+
+fn command_signature(name: &str, command: &SchemaCommand) -> String {
+    let parameters = command
+        .parameters
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("fn {name}({parameters})")
+}
+
+fn code_block(code: &str) -> String {
+    format!("```holyrig\n{code}\n```")
+}
+
+fn with_doc(markdown: String, doc: &Option<String>) -> String {
+    match doc {
+        Some(doc) => format!("{markdown}\n\n{doc}"),
+        None => markdown,
+    }
+}
+
+fn parse_error(text: &str, err: &ParseError) -> Diagnostic {
+    Diagnostic::error(word_range(text, Some(&err.position)), err.message())
+}
+
+fn parse_errors<T>(text: &str, recovered: Recovered<T>) -> Vec<Diagnostic> {
+    recovered
+        .errors
+        .iter()
+        .map(|err| parse_error(text, err))
+        .collect()
+}
+
+fn is_word_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '_'
+}
+
+/// Range of the word starting at `position`, at least one character wide.
+fn word_range(text: &str, position: Option<&SourcePosition>) -> Range {
+    let Some(position) = position.filter(|position| position.line > 0) else {
+        return Range::default();
+    };
+
+    let start = position.offset.min(text.len());
+    let end = match text[start..].find(|char| !is_word_char(char)) {
+        Some(0) => start + text[start..].chars().next().map_or(0, char::len_utf8),
+        Some(length) => start + length,
+        None => text.len(),
+    };
+    Range::new(position_at(text, start), position_at(text, end))
+}
+
+fn word_at(text: &str, offset: usize) -> Option<ByteRange<usize>> {
+    let start = text[..offset]
+        .rfind(|char: char| !is_word_char(char))
+        .map_or(0, |index| index + 1);
+    let end = text[offset..]
+        .find(|char: char| !is_word_char(char))
+        .map_or(text.len(), |index| offset + index);
+    (start < end).then_some(start..end)
+}
+
+/// Byte offset of a position, whose character counts UTF-16 code units as in the protocol.
+fn offset_at(text: &str, position: Position) -> usize {
+    let line_start = if position.line == 0 {
+        0
+    } else {
+        text.match_indices('\n')
+            .nth(position.line as usize - 1)
+            .map_or(text.len(), |(index, _)| index + 1)
+    };
+
+    let mut units = 0;
+    text[line_start..]
+        .char_indices()
+        .take_while(|(_, char)| *char != '\n')
+        .find(|(_, char)| {
+            units += char.len_utf16();
+            units > position.character as usize
+        })
+        .map_or_else(
+            || {
+                text[line_start..]
+                    .find('\n')
+                    .map_or(text.len(), |index| line_start + index)
+            },
+            |(index, _)| line_start + index,
+        )
+}
+
+/// Position of a byte offset, in UTF-16 code units as in the protocol.
+fn position_at(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position::new(
+        line as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// Runs `find` over the tokens of `source` and converts the byte range it returns.
+fn find_token(
+    source: &str,
+    find: impl FnOnce(&[(Token<'_>, ByteRange<usize>)]) -> Option<ByteRange<usize>>,
+) -> Option<Range> {
+    let mut lexer = Token::lexer(source);
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.next() {
+        match token {
+            Ok(Token::NewLine | Token::Comment(_) | Token::DocComment(_)) | Err(_) => {}
+            Ok(token) => tokens.push((token, lexer.span())),
+        }
+    }
+
+    find(&tokens).map(|range| {
+        Range::new(
+            position_at(source, range.start),
+            position_at(source, range.end),
+        )
+    })
+}
+
+/// Finds the `fn` or `enum` declaring `name`.
+fn find_declaration(source: &str, name: &str) -> Option<Range> {
+    find_token(source, |tokens| {
+        tokens.windows(2).find_map(|window| match window {
+            [(Token::Fn | Token::Enum, _), (Token::Id(id), range)] if *id == name => {
+                Some(range.clone())
+            }
+            _ => None,
+        })
+    })
+}
+
+fn find_status_field(source: &str, name: &str) -> Option<Range> {
+    find_token(source, |tokens| {
+        let status = tokens
+            .iter()
+            .position(|(token, _)| matches!(token, Token::Status))?;
+        tokens[status..].windows(2).find_map(|window| match window {
+            [
+                (Token::Id(id), range),
+                (Token::Semicolon | Token::BracketOpen, _),
+            ] if *id == name => Some(range.clone()),
+            _ => None,
+        })
+    })
+}
+
+fn impl_schema_name(source: &str) -> Option<String> {
+    let mut lexer = Token::lexer(source).flatten();
+    while let Some(token) = lexer.next() {
+        if let Token::Impl = token {
+            return match lexer.find(|token| !matches!(token, Token::NewLine | Token::Comment(_))) {
+                Some(Token::Id(name)) => Some(name.to_string()),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+pub(crate) fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let digits = [bytes.next()?, bytes.next()?];
+            decoded.push(u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // Windows paths keep their drive letter after the slash, `file:///C:/rigs`
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+pub(crate) fn path_to_uri(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for char in path.chars() {
+        match char {
+            ' ' | '%' | '#' | '?' => uri.push_str(&format!("%{:02X}", char as u32)),
+            _ => uri.push(char),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::types::Severity;
+
+    const SCHEMA_URI: &str = "file:///rigs/transceiver.schema";
+    const RIG_URI: &str = "file:///rigs/test.rig";
+
+    const SCHEMA: &str = r#"
+        version = 1;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                B,
+            }
+
+            /// Tune a VFO to the given frequency.
+            fn set_freq(int<30000..470000000> freq [unit = s"Hz"], Vfo target [default = Vfo::A]);
+            fn vfo_swap();
+
+            status {
+                /// Frequency of VFO A.
+                int freq_a [unit = s"Hz"];
+            }
+        }
+    "#;
+
+    const RIG: &str = r#"impl Transceiver for TestRig {
+    enum Vfo {
+        A = 0,
+        B = 1,
+    }
+
+    fn set_freq(int freq, Vfo target) {
+        write("FEFE94E0.25.{target:1}.{freq:bcd_lu:5}.FD");
+    }
+
+    status {
+        write("FEFE94E0.2500.FD");
+        read("FEFEE094.2500.{freq_a:bcd_lu:5}.FD");
+        set_var(s"freq_a", freq_a);
+    }
+
+    test s"set_freq" {
+        call set_freq(14074000, Vfo::A);
+    }
+}
+"#;
+
+    fn workspace(rig: &str) -> Workspace {
+        let mut workspace = Workspace::new();
+        workspace.open(SCHEMA_URI, SCHEMA.to_string());
+        workspace.open(RIG_URI, rig.to_string());
+        workspace.diagnostics(RIG_URI);
+        workspace
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let errors = |diagnostics: Vec<Diagnostic>| -> Vec<Diagnostic> {
+            diagnostics
+                .into_iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .collect()
+        };
+
+        let mut workspace = workspace(RIG);
+        let diagnostics = workspace.diagnostics(RIG_URI);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.severity, diagnostic.range.start.line))
+                .collect::<Vec<_>>(),
+            vec![(Severity::Warning, 0), (Severity::Warning, 7)]
+        );
+        assert!(diagnostics[0].message.contains("vfo_swap"));
+
+        let rig = RIG.replace("set_var(s\"freq_a\"", "set_var(s\"freq_c\"");
+        workspace.open(RIG_URI, rig);
+        let diagnostics = errors(workspace.diagnostics(RIG_URI));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("freq_c"));
+
+        let rig = RIG.replace(
+            "write(\"FEFE94E0.2500.FD\");",
+            "write(\"FEFE94E0.2500.FD\")",
+        );
+        workspace.open(RIG_URI, rig);
+        let diagnostics = workspace.diagnostics(RIG_URI);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 12);
+
+        // Missing version and closing brace
+        workspace.open(SCHEMA_URI, "schema {".to_string());
+        assert_eq!(workspace.diagnostics(SCHEMA_URI).len(), 2);
+    }
+
+    #[test]
+    fn test_completion() {
+        // The edited file doesn't parse, the last parsed version provides the rig enums
+        let rig = RIG.replace(
+            "        call set_freq",
+            "        Vfo::\n        call set_freq",
+        );
+        let mut workspace = workspace(RIG);
+        workspace.open(RIG_URI, rig);
+
+        let items = workspace.completion(RIG_URI, Position::new(17, 13));
+        assert_eq!(labels(&items), vec!["A", "B"]);
+
+        let items = workspace.completion(RIG_URI, Position::new(7, 47));
+        assert!(labels(&items).contains(&"bcd_lu"));
+
+        let items = workspace.completion(RIG_URI, Position::new(13, 19));
+        assert_eq!(labels(&items), vec!["freq_a"]);
+
+        let items = workspace.completion(RIG_URI, Position::new(8, 4));
+        assert!(labels(&items).contains(&"write"));
+        assert!(labels(&items).contains(&"vfo_swap"));
+    }
+
+    #[test]
+    fn test_command_declaration_completion() {
+        let rig = RIG.replace("    status {", "    fn \n    status {");
+        let mut workspace = workspace(RIG);
+        workspace.open(RIG_URI, rig);
+
+        let items = workspace.completion(RIG_URI, Position::new(10, 7));
+        assert_eq!(labels(&items), vec!["vfo_swap"]);
+        assert_eq!(items[0].insert_text.as_deref(), Some("vfo_swap()"));
+    }
+
+    #[test]
+    fn test_hover() {
+        let workspace = workspace(RIG);
+
+        let hover = workspace.hover(RIG_URI, Position::new(6, 8)).unwrap();
+        assert!(hover.contains(
+            "fn set_freq(int<30000..470000000> freq [unit = s\"Hz\"], Vfo target [default = Vfo::A])"
+        ));
+        assert!(hover.contains("Tune a VFO"));
+
+        let hover = workspace.hover(RIG_URI, Position::new(13, 19)).unwrap();
+        assert!(hover.contains("status int freq_a [unit = s\"Hz\"]"));
+
+        let hover = workspace.hover(RIG_URI, Position::new(7, 9)).unwrap();
+        assert!(hover.contains("write(bytes data)"));
+
+        let hover = workspace.hover(RIG_URI, Position::new(17, 34)).unwrap();
+        assert!(hover.contains("enum Vfo { A = 0, B = 1 }"));
+    }
+
+    #[test]
+    fn test_definition() {
+        let workspace = workspace(RIG);
+
+        let locations = workspace.definition(RIG_URI, Position::new(6, 8));
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].uri, SCHEMA_URI);
+        assert_eq!(locations[0].range.start, Position::new(10, 15));
+
+        let locations = workspace.definition(RIG_URI, Position::new(17, 15));
+        assert_eq!(locations[0].uri, RIG_URI);
+        assert_eq!(locations[0].range.start, Position::new(6, 7));
+
+        let locations = workspace.definition(SCHEMA_URI, Position::new(10, 17));
+        assert_eq!(locations[0].uri, RIG_URI);
+        assert_eq!(locations[0].range.start, Position::new(6, 7));
+    }
+
+    #[test]
+    fn test_uri_to_path() {
+        let path = uri_to_path("file:///home/user/my%20rigs/IC-7300.rig").unwrap();
+        assert_eq!(path, PathBuf::from("/home/user/my rigs/IC-7300.rig"));
+        assert_eq!(
+            path_to_uri(&path),
+            "file:///home/user/my%20rigs/IC-7300.rig"
+        );
+
+        let path = uri_to_path("file:///C:/rigs/IC-7300.rig").unwrap();
+        assert_eq!(path, PathBuf::from("C:/rigs/IC-7300.rig"));
+        assert_eq!(path_to_uri(&path), "file:///C:/rigs/IC-7300.rig");
+        assert_eq!(
+            uri_to_path("file:///c%3A/rigs/IC-7300.rig"),
+            Some(PathBuf::from("c:/rigs/IC-7300.rig"))
+        );
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let text = "// é𝄞 x\nab";
+        let offset = text.find('x').unwrap();
+        assert_eq!(position_at(text, offset), Position::new(0, 7));
+        assert_eq!(offset_at(text, Position::new(0, 7)), offset);
+        assert_eq!(offset_at(text, Position::new(1, 1)), text.len() - 1);
+        assert_eq!(
+            offset_at(text, Position::new(0, 20)),
+            text.find('\n').unwrap()
+        );
+    }
+}
@@ -4,7 +4,7 @@ use holyrig::interfaces::jsonrpc::JsonRpcServer;
 use holyrig::resources::Resources;
 use tokio::sync::mpsc;
 
-use holyrig::interfaces::{rigctld, udp_server};
+use holyrig::interfaces::{rigctld, rotctld, udp_server};
 use holyrig::{gui, serial};
 
 use gui::GuiMessage;
@@ -22,6 +22,8 @@ async fn main() -> Result<()> {
     let rigctld_command_sender = device_manager.sender();
     let udp_message_receiver = device_manager.receiver();
     let rigctld_message_receiver = device_manager.receiver();
+    let rotctld_command_sender = device_manager.sender();
+    let rotctld_message_receiver = device_manager.receiver();
 
     let jsonrpc_command_sender = device_manager.sender();
     let jsonrpc_command_receiver = device_manager.receiver();
@@ -31,6 +33,7 @@ async fn main() -> Result<()> {
         resources.clone(),
         jsonrpc_command_sender,
         jsonrpc_command_receiver,
+        device_manager.debuggers(),
     )?;
 
     tokio::spawn(async move { jsonrpc_server.run().await });
@@ -43,17 +46,35 @@ async fn main() -> Result<()> {
         }
     });
 
+    let rigctld_resources = resources.clone();
     tokio::spawn(async move {
-        if let Err(err) =
-            rigctld::run_server(rigctld_command_sender, rigctld_message_receiver).await
+        if let Err(err) = rigctld::run_server(
+            rigctld_resources,
+            rigctld_command_sender,
+            rigctld_message_receiver,
+        )
+        .await
         {
             eprintln!("Rigctld server error: {err}");
         }
     });
 
+    let rotctld_resources = resources.clone();
+    tokio::spawn(async move {
+        if let Err(err) = rotctld::run_server(
+            rotctld_resources,
+            rotctld_command_sender,
+            rotctld_message_receiver,
+        )
+        .await
+        {
+            eprintln!("Rotctld server error: {err}");
+        }
+    });
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([350.0, 440.0])
+            .with_inner_size([350.0, 470.0])
             .with_resizable(false),
         ..Default::default()
     };
@@ -64,7 +85,7 @@ async fn main() -> Result<()> {
             Ok(Box::new(gui::App::new(
                 gui_receiver,
                 gui_command_sender,
-                resources.rigs.keys().cloned().collect(),
+                resources.rig_types(),
             )))
         }),
     )
@@ -0,0 +1,1059 @@
+//! Converts OmniRig rig definitions (`.ini` files) into rig files implementing the
+//! `Transceiver` schema.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+
+use anyhow::{Result, anyhow, bail};
+use configparser::ini::Ini;
+
+use crate::data_format::DataFormat;
+use crate::runtime::{format_rig_file, parse_hex};
+
+/// Construct of an OmniRig definition the rig DSL cannot express. The converted rig file
+/// lists these at its top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    pub section: String,
+    pub message: String,
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.section, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// Source of the rig file in canonical formatting.
+    pub rig: String,
+    pub unsupported: Vec<Unsupported>,
+}
+
+/// Maps an OmniRig value format to the matching data format, `None` for the formats the rig
+/// DSL has no equivalent for (`vfYaesu`, `vfDPIcom`, `vfTextUD`, `vfFloat`).
+pub fn data_format(name: &str) -> Option<DataFormat> {
+    let format = match name {
+        "vfText" => DataFormat::Text,
+        "vfBinL" => DataFormat::IntLs,
+        "vfBinB" => DataFormat::IntBs,
+        "vfBcdLU" => DataFormat::BcdLu,
+        "vfBcdLS" => DataFormat::BcdLs,
+        "vfBcdBU" => DataFormat::BcdBu,
+        "vfBcdBS" => DataFormat::BcdBs,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Converts the OmniRig definition `ini` into a rig file for the rig `name`.
+pub fn convert(ini: &str, name: &str) -> Result<Conversion> {
+    let mut defaults = Ini::new_cs().defaults();
+    defaults.delimiters = vec!['='];
+    // Commands like `(FA;)` contain the comment character
+    defaults.inline_comment_symbols = Some(Vec::new());
+    let mut parser = Ini::new_from_defaults(defaults);
+    let map = parser.read(ini.to_string()).map_err(|err| anyhow!(err))?;
+
+    let sections = map
+        .into_iter()
+        .map(|(section, entries)| {
+            let entries = entries
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_lowercase(), value?)))
+                .collect();
+            (section, entries)
+        })
+        .collect();
+
+    let mut converter = Converter {
+        sections,
+        used: Vec::new(),
+        unsupported: Vec::new(),
+        uses_vfo: false,
+        uses_mode: false,
+    };
+    let rig = converter.rig_file(&rig_name(name))?;
+    Ok(Conversion {
+        rig,
+        unsupported: converter.unsupported,
+    })
+}
+
+/// Turns a rig name like `IC-7300` into an identifier.
+fn rig_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    match name.chars().next() {
+        Some(first) if !first.is_ascii_digit() => name,
+        _ => format!("Rig{name}"),
+    }
+}
+
+const MODES: [(&str, &str); 8] = [
+    ("CWU", "pmCW_U"),
+    ("CWL", "pmCW_L"),
+    ("USB", "pmSSB_U"),
+    ("LSB", "pmSSB_L"),
+    ("DIGIU", "pmDIG_U"),
+    ("DIGIL", "pmDIG_L"),
+    ("AM", "pmAM"),
+    ("FM", "pmFM"),
+];
+
+/// Status field and value set by an OmniRig flag parameter.
+fn flag_field(param: &str) -> Option<(&'static str, &'static str)> {
+    if let Some((mode, _)) = MODES.iter().find(|(_, name)| *name == param) {
+        return Some(("mode", mode));
+    }
+    let field = match param {
+        "pmVfoAA" | "pmVfoAB" | "pmVfoA" => ("vfo", "A"),
+        "pmVfoBA" | "pmVfoBB" | "pmVfoB" => ("vfo", "B"),
+        "pmTx" => ("transmit", "1"),
+        "pmRx" => ("transmit", "0"),
+        "pmRitOn" => ("rit", "1"),
+        "pmRitOff" => ("rit", "0"),
+        "pmXitOn" => ("xit", "1"),
+        "pmXitOff" => ("xit", "0"),
+        _ => return None,
+    };
+    Some(field)
+}
+
+/// Bytes of a command or reply, `FEFE94E0.25.FD` in hex or `(FA;)` in ASCII. The dots of hex
+/// strings are kept as group boundaries.
+#[derive(Debug, Default)]
+struct ByteString {
+    bytes: Vec<u8>,
+    /// Indices of the bytes starting a new group.
+    groups: Vec<usize>,
+    ascii: bool,
+}
+
+impl ByteString {
+    fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if let Some(ascii) = text
+            .strip_prefix('(')
+            .and_then(|text| text.strip_suffix(')'))
+        {
+            return Ok(Self {
+                bytes: ascii.as_bytes().to_vec(),
+                groups: Vec::new(),
+                ascii: true,
+            });
+        }
+
+        let mut string = Self::default();
+        for group in text.split('.') {
+            string.groups.push(string.bytes.len());
+            string.bytes.extend(parse_hex(group)?);
+        }
+        Ok(string)
+    }
+}
+
+/// Placement of a value in a command or reply, OmniRig's `start|length|format|multiply|add`.
+#[derive(Debug)]
+struct ValueSpec {
+    start: usize,
+    length: usize,
+    format: DataFormat,
+    multiply: f64,
+    add: f64,
+    param: Option<String>,
+}
+
+impl ValueSpec {
+    fn parse(text: &str) -> Result<Self> {
+        let fields: Vec<_> = text.split('|').map(str::trim).collect();
+        let [start, length, format, multiply, add, rest @ ..] = fields.as_slice() else {
+            bail!("Malformed value '{text}'");
+        };
+        let Some(format) = data_format(format) else {
+            bail!("Value format {format} has no equivalent data format");
+        };
+        Ok(Self {
+            start: start.parse()?,
+            length: length.parse()?,
+            format,
+            multiply: multiply.parse()?,
+            add: add.parse()?,
+            param: rest.first().map(|param| param.to_string()),
+        })
+    }
+
+    /// Template field of the value named `name`.
+    fn field(&self, name: &str) -> String {
+        format!("{{{name}:{}:{}}}", self.format, self.length)
+    }
+
+    /// Expression applying the scaling of the value to the variable `name`.
+    fn scaled(&self, name: &str) -> String {
+        let mut expr = match self.multiply {
+            1.0 => name.to_string(),
+            multiply if multiply < 0.0 => format!("(0 - {name}) * {}", -multiply),
+            multiply => format!("{name} * {multiply}"),
+        };
+        if self.add > 0.0 {
+            expr.push_str(&format!(" + {}", self.add));
+        } else if self.add < 0.0 {
+            expr.push_str(&format!(" - {}", -self.add));
+        }
+        expr
+    }
+}
+
+enum Part {
+    Literal(Vec<u8>),
+    Wildcard(usize),
+    Field(String),
+}
+
+/// Builds a byte template, consecutive literal bytes and wildcards are merged.
+#[derive(Default)]
+struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    fn byte(&mut self, byte: u8, new_group: bool) {
+        match self.parts.last_mut() {
+            Some(Part::Literal(bytes)) if !new_group => bytes.push(byte),
+            _ => self.parts.push(Part::Literal(vec![byte])),
+        }
+    }
+
+    fn wildcard(&mut self) {
+        match self.parts.last_mut() {
+            Some(Part::Wildcard(length)) => *length += 1,
+            _ => self.parts.push(Part::Wildcard(1)),
+        }
+    }
+
+    fn field(&mut self, field: String) {
+        self.parts.push(Part::Field(field));
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<_> = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(bytes) => bytes.iter().map(|byte| format!("{byte:02X}")).collect(),
+                Part::Wildcard(length) => format!("{{_:{length}}}"),
+                Part::Field(field) => field.clone(),
+            })
+            .collect();
+        write!(f, "\"{}\"", parts.join("."))
+    }
+}
+
+/// Reply validation, OmniRig's `mask|value` or a plain value checked in full.
+struct Validation {
+    mask: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Validation {
+    fn parse(text: &str, length: usize) -> Result<Self> {
+        let (mask, value) = match text.split_once('|') {
+            Some((mask, value)) => (
+                ByteString::parse(mask)?.bytes,
+                ByteString::parse(value)?.bytes,
+            ),
+            None => {
+                let value = ByteString::parse(text)?.bytes;
+                (vec![0xFF; value.len()], value)
+            }
+        };
+        if mask.len() != value.len() || value.len() > length {
+            bail!("Validation '{text}' does not match the reply length {length}");
+        }
+
+        // A shorter validation checks the end of the reply, e.g. the acknowledge after an echo
+        let padding = length - value.len();
+        Ok(Self {
+            mask: [vec![0; padding], mask].concat(),
+            value: [vec![0; padding], value].concat(),
+        })
+    }
+}
+
+struct Converter {
+    /// Entries of every section, keys in lowercase.
+    sections: BTreeMap<String, HashMap<String, String>>,
+    /// Sections used by the conversion, the others are reported as unsupported.
+    used: Vec<String>,
+    unsupported: Vec<Unsupported>,
+    uses_vfo: bool,
+    uses_mode: bool,
+}
+
+impl Converter {
+    fn unsupported(&mut self, section: &str, message: impl Into<String>) {
+        self.unsupported.push(Unsupported {
+            section: section.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn section(&mut self, name: &str) -> Option<HashMap<String, String>> {
+        let (name, entries) = self
+            .sections
+            .iter()
+            .find(|(section, _)| section.eq_ignore_ascii_case(name))?;
+        self.used.push(name.clone());
+        Some(entries.clone())
+    }
+
+    /// Sections named `prefix`, `prefix1`, `prefix2`, ... in numeric order.
+    fn numbered_sections(&self, prefix: &str) -> Vec<String> {
+        let mut sections: Vec<_> = self
+            .sections
+            .keys()
+            .filter_map(|section| {
+                let suffix = section
+                    .get(..prefix.len())
+                    .filter(|start| start.eq_ignore_ascii_case(prefix))
+                    .map(|_| &section[prefix.len()..])?;
+                let number = match suffix {
+                    "" => 0,
+                    suffix => suffix.parse::<u32>().ok()?,
+                };
+                Some((number, section.clone()))
+            })
+            .collect();
+        sections.sort();
+        sections.into_iter().map(|(_, section)| section).collect()
+    }
+
+    fn rig_file(&mut self, name: &str) -> Result<String> {
+        let mut members = Vec::new();
+
+        let init: Vec<_> = self
+            .numbered_sections("INIT")
+            .iter()
+            .filter_map(|section| self.exchange(section, None))
+            .flatten()
+            .collect();
+        if !init.is_empty() {
+            members.push(block("init {", init));
+        }
+
+        members.extend(self.commands());
+
+        let status: Vec<_> = self
+            .numbered_sections("STATUS")
+            .iter()
+            .flat_map(|section| self.status(section))
+            .collect();
+        if !status.is_empty() {
+            members.push(block("status {", status));
+        }
+
+        let mut enums = Vec::new();
+        if self.uses_vfo {
+            enums.push(block(
+                "enum Vfo {",
+                lines(&["Current = 0,", "A = 1,", "B = 2,", "Unknown = 3,"]),
+            ));
+        }
+        if self.uses_mode {
+            let variants = MODES
+                .iter()
+                .enumerate()
+                .map(|(value, (mode, _))| format!("{mode} = {value},"))
+                .collect();
+            enums.push(block("enum Mode {", variants));
+        }
+        members.splice(0..0, enums);
+
+        for section in self.sections.keys().cloned().collect::<Vec<_>>() {
+            if !self.used.contains(&section) && !section.eq_ignore_ascii_case("default") {
+                self.unsupported(&section, "no equivalent in the Transceiver schema");
+            }
+        }
+
+        let mut source = String::new();
+        if !self.unsupported.is_empty() {
+            source.push_str("// Not converted from the OmniRig definition:\n");
+            for unsupported in &self.unsupported {
+                source.push_str(&format!("// {unsupported}\n"));
+            }
+            source.push('\n');
+        }
+        source.push_str(&format!("version = 2;\n\nimpl Transceiver for {name} {{\n"));
+        for member in members {
+            for line in member {
+                source.push_str(&line);
+                source.push('\n');
+            }
+        }
+        source.push_str("}\n");
+
+        format_rig_file(&source).map_err(|err| anyhow!("Generated an invalid rig file:\n{err}"))
+    }
+
+    /// Writes the command of `section` and reads its reply. `param` is the variable the value of
+    /// the section is taken from. `None` if the rig does not support the parameter.
+    fn exchange(&mut self, section: &str, param: Option<&str>) -> Option<Vec<String>> {
+        let entries = self.section(section)?;
+        let command = entries.get("command")?;
+        match self.try_exchange(section, &entries, command, param) {
+            Ok(statements) => Some(statements),
+            Err(err) => {
+                self.unsupported(section, err.to_string());
+                None
+            }
+        }
+    }
+
+    fn try_exchange(
+        &mut self,
+        section: &str,
+        entries: &HashMap<String, String>,
+        command: &str,
+        param: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let command_bytes = ByteString::parse(command)?;
+        let value = entries
+            .get("value")
+            .map(|value| ValueSpec::parse(value))
+            .transpose()?;
+
+        let mut statements = Vec::new();
+        if command_bytes.ascii {
+            statements.push(format!("// {command}"));
+        }
+
+        let mut template = Template::default();
+        let mut index = 0;
+        while index < command_bytes.bytes.len() {
+            match (&value, param) {
+                (Some(value), Some(param)) if value.start == index => {
+                    if value.start + value.length > command_bytes.bytes.len() {
+                        bail!("Value exceeds the command");
+                    }
+                    if value.multiply != 1.0 || value.add != 0.0 {
+                        statements.push(format!("{param} = {};", value.scaled(param)));
+                    }
+                    template.field(value.field(param));
+                    index += value.length;
+                    continue;
+                }
+                _ => {}
+            }
+            let new_group = command_bytes.groups.contains(&index);
+            template.byte(command_bytes.bytes[index], new_group);
+            index += 1;
+        }
+        if value.is_some() && param.is_none() {
+            bail!("Command value has no parameter in the Transceiver schema");
+        }
+        statements.push(format!("write({template});"));
+
+        if let Some(end) = entries.get("replyend") {
+            self.unsupported(
+                section,
+                format!("replies ending with {end} have no fixed length and are not read"),
+            );
+        } else if let Some(length) = entries.get("replylength") {
+            let length: usize = length.trim().parse()?;
+            if length > 0 {
+                let validation = entries
+                    .get("validate")
+                    .map(|text| Validation::parse(text, length))
+                    .transpose()?;
+                let template = self.reply_template(section, length, validation.as_ref(), &[]);
+                statements.push(format!("read({template});"));
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Template of a reply of `length` bytes, `fields` maps byte offsets to template fields.
+    fn reply_template(
+        &mut self,
+        section: &str,
+        length: usize,
+        validation: Option<&Validation>,
+        fields: &[(usize, usize, String)],
+    ) -> Template {
+        let mut template = Template::default();
+        let mut partial_mask = false;
+        let mut index = 0;
+        while index < length {
+            if let Some((_, field_length, field)) =
+                fields.iter().find(|(start, ..)| *start == index)
+            {
+                template.field(field.clone());
+                index += field_length;
+                continue;
+            }
+
+            match validation.map(|validation| (validation.mask[index], validation.value[index])) {
+                Some((0xFF, byte)) => template.byte(byte, false),
+                Some((0, _)) | None => template.wildcard(),
+                Some(_) => {
+                    partial_mask = true;
+                    template.wildcard();
+                }
+            }
+            index += 1;
+        }
+
+        if partial_mask {
+            self.unsupported(
+                section,
+                "validation of single bits is not supported, those bytes are not checked",
+            );
+        }
+        template
+    }
+
+    fn commands(&mut self) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+
+        let freq = self.exchange("pmFreq", Some("freq"));
+        let freq_a = self.exchange("pmFreqA", Some("freq"));
+        let freq_b = self.exchange("pmFreqB", Some("freq"));
+        if freq.is_some() || freq_a.is_some() || freq_b.is_some() {
+            self.uses_vfo = true;
+            let body = match (freq, freq_a, freq_b) {
+                (Some(freq), None, None) => freq,
+                (freq, freq_a, freq_b) => match_statement(
+                    "target",
+                    [("Vfo::A", freq_a), ("Vfo::B", freq_b)],
+                    freq.unwrap_or_else(|| unsupported_call("VFO")),
+                ),
+            };
+            commands.push(block("fn set_freq(int freq, Vfo target) {", body));
+        }
+
+        if let Some(body) = self.exchange("pmRit0", None) {
+            commands.push(block("fn clear_rit() {", body));
+        }
+        if let Some(body) = self.exchange("pmPitch", Some("pitch")) {
+            commands.push(block("fn cw_pitch(int pitch) {", body));
+        }
+
+        let switches = [
+            (
+                "fn set_split(bool split) {",
+                "split",
+                "pmSplitOn",
+                "pmSplitOff",
+            ),
+            ("fn set_rit(bool rit) {", "rit", "pmRitOn", "pmRitOff"),
+            ("fn set_xit(bool xit) {", "xit", "pmXitOn", "pmXitOff"),
+            ("fn transmit(bool tx) {", "tx", "pmTx", "pmRx"),
+        ];
+        for (header, param, on, off) in switches {
+            let on = self.exchange(on, None);
+            let off = self.exchange(off, None);
+            if on.is_none() && off.is_none() {
+                continue;
+            }
+            let mut body = vec![format!("if {param} {{")];
+            body.extend(on.unwrap_or_else(|| unsupported_call("value")));
+            body.push("} else {".to_string());
+            body.extend(off.unwrap_or_else(|| unsupported_call("value")));
+            body.push("}".to_string());
+            commands.push(block(header, body));
+        }
+
+        if let Some(body) = self.exchange("pmVfoEqual", None) {
+            commands.push(block("fn vfo_equal() {", body));
+        }
+        if let Some(body) = self.exchange("pmVfoSwap", None) {
+            commands.push(block("fn vfo_swap() {", body));
+        }
+
+        let vfo_aa = self
+            .exchange("pmVfoAA", None)
+            .or_else(|| self.exchange("pmVfoA", None));
+        let vfo_ab = self.exchange("pmVfoAB", None);
+        let vfo_ba = self.exchange("pmVfoBA", None);
+        let vfo_bb = self
+            .exchange("pmVfoBB", None)
+            .or_else(|| self.exchange("pmVfoB", None));
+        if vfo_aa.is_some() || vfo_ab.is_some() || vfo_ba.is_some() || vfo_bb.is_some() {
+            self.uses_vfo = true;
+            let body = match_statement(
+                "(rx, tx)",
+                [
+                    ("(Vfo::A, Vfo::A)", vfo_aa),
+                    ("(Vfo::A, Vfo::B)", vfo_ab),
+                    ("(Vfo::B, Vfo::A)", vfo_ba),
+                    ("(Vfo::B, Vfo::B)", vfo_bb),
+                ],
+                unsupported_call("VFO combination"),
+            );
+            commands.push(block("fn set_vfo(Vfo rx, Vfo tx) {", body));
+        }
+
+        let modes: Vec<_> = MODES
+            .iter()
+            .map(|(mode, section)| (format!("Mode::{mode}"), self.exchange(section, None)))
+            .collect();
+        if modes.iter().any(|(_, body)| body.is_some()) {
+            self.uses_mode = true;
+            let body = match_statement("mode", modes, unsupported_call("mode"));
+            commands.push(block("fn set_mode(Mode mode) {", body));
+        }
+
+        commands
+    }
+
+    fn status(&mut self, section: &str) -> Vec<String> {
+        let Some(entries) = self.section(section) else {
+            return Vec::new();
+        };
+        match self.try_status(section, &entries) {
+            Ok(statements) => statements,
+            Err(err) => {
+                self.unsupported(section, err.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    fn try_status(
+        &mut self,
+        section: &str,
+        entries: &HashMap<String, String>,
+    ) -> Result<Vec<String>> {
+        let Some(command) = entries.get("command") else {
+            bail!("status without command");
+        };
+        if let Some(end) = entries.get("replyend") {
+            bail!("replies ending with {end} have no fixed length and cannot be read");
+        }
+        let length: usize = entries
+            .get("replylength")
+            .ok_or_else(|| anyhow!("status without reply length"))?
+            .trim()
+            .parse()?;
+        let validation = entries
+            .get("validate")
+            .map(|text| Validation::parse(text, length))
+            .transpose()?;
+
+        let mut fields = Vec::new();
+        let mut assignments = Vec::new();
+        let has_freq_a = self.sections.values().any(|entries| {
+            entries.iter().any(|(key, value)| {
+                key.starts_with("value") && value.trim_end().ends_with("pmFreqA")
+            })
+        });
+
+        let mut values: Vec<_> = entries
+            .iter()
+            .filter(|(key, _)| key.starts_with("value"))
+            .collect();
+        values.sort();
+        for (_, value) in values {
+            let spec = match ValueSpec::parse(value) {
+                Ok(spec) => spec,
+                Err(err) => {
+                    self.unsupported(section, err.to_string());
+                    continue;
+                }
+            };
+            let field = match spec.param.as_deref() {
+                Some("pmFreqA") => "freq_a",
+                Some("pmFreq") if !has_freq_a => "freq_a",
+                Some("pmFreqB") => "freq_b",
+                Some("pmPitch") => "cw_pitch",
+                param => {
+                    self.unsupported(
+                        section,
+                        format!(
+                            "value {} has no status field",
+                            param.unwrap_or("without parameter")
+                        ),
+                    );
+                    continue;
+                }
+            };
+            if spec.start + spec.length > length {
+                self.unsupported(section, format!("value {field} exceeds the reply"));
+                continue;
+            }
+            fields.push((spec.start, spec.length, spec.field(field)));
+            assignments.push(format!("set_var(s\"{field}\", {});", spec.scaled(field)));
+        }
+
+        // Flags test single reply bytes, those are read into variables of their own
+        let mut flags: Vec<(&str, Vec<(String, &str)>)> = Vec::new();
+        let mut captured = Vec::new();
+        let mut keys: Vec<_> = entries
+            .iter()
+            .filter(|(key, _)| key.starts_with("flag"))
+            .collect();
+        keys.sort();
+        for (_, flag) in keys {
+            let parsed = flag.rsplit_once('|').and_then(|(test, param)| {
+                let (mask, value) = test.split_once('|')?;
+                Some((mask, value, param.trim()))
+            });
+            let Some((mask, value, param)) = parsed else {
+                self.unsupported(section, format!("malformed flag '{flag}'"));
+                continue;
+            };
+            let Some((field, field_value)) = flag_field(param) else {
+                self.unsupported(section, format!("flag {param} has no status field"));
+                continue;
+            };
+            let mask = ByteString::parse(mask)?.bytes;
+            let value = ByteString::parse(value)?.bytes;
+            if mask.len() != length || value.len() != length {
+                self.unsupported(
+                    section,
+                    format!("flag {param} does not match the reply length"),
+                );
+                continue;
+            }
+            let Some(condition) = flag_condition(&mask, &value) else {
+                self.unsupported(
+                    section,
+                    format!("flag {param} tests bits that are not adjacent"),
+                );
+                continue;
+            };
+            let overlaps = mask.iter().enumerate().any(|(index, byte)| {
+                *byte != 0
+                    && fields
+                        .iter()
+                        .any(|(start, length, _)| (*start..start + length).contains(&index))
+            });
+            if overlaps {
+                self.unsupported(section, format!("flag {param} overlaps a value"));
+                continue;
+            }
+
+            captured.extend(
+                mask.iter()
+                    .enumerate()
+                    .filter(|(_, byte)| **byte != 0)
+                    .map(|(index, _)| index),
+            );
+            match flags.iter_mut().find(|(name, _)| *name == field) {
+                Some((_, tests)) => tests.push((condition, field_value)),
+                None => flags.push((field, vec![(condition, field_value)])),
+            }
+        }
+        captured.sort();
+        captured.dedup();
+        fields.extend(
+            captured
+                .into_iter()
+                .map(|index| (index, 1, format!("{{byte{index}:int_bu:1}}"))),
+        );
+
+        let reply = self.reply_template(section, length, validation.as_ref(), &fields);
+        let mut statements = vec![
+            format!(
+                "write({});",
+                ByteString::parse(command).map(|bytes| {
+                    let mut template = Template::default();
+                    for (index, byte) in bytes.bytes.iter().enumerate() {
+                        template.byte(*byte, bytes.groups.contains(&index));
+                    }
+                    template
+                })?
+            ),
+            format!("read({reply});"),
+        ];
+        statements.extend(assignments);
+        for (field, tests) in flags {
+            statements.extend(self.flag_statements(field, &tests));
+        }
+        Ok(statements)
+    }
+
+    fn flag_statements(&mut self, field: &str, tests: &[(String, &str)]) -> Vec<String> {
+        let enum_name = match field {
+            "mode" => Some("Mode"),
+            "vfo" => Some("Vfo"),
+            _ => None,
+        };
+        let Some(enum_name) = enum_name else {
+            // Switches: the flag of the switched on state is enough
+            return match tests.iter().find(|(_, value)| *value == "1") {
+                Some((condition, _)) => vec![format!("set_var(s\"{field}\", {condition});")],
+                None => {
+                    let (condition, _) = &tests[0];
+                    lines(&[
+                        &format!("if {condition} {{"),
+                        &format!("set_var(s\"{field}\", 0 as bool);"),
+                        "} else {",
+                        &format!("set_var(s\"{field}\", 1 as bool);"),
+                        "}",
+                    ])
+                }
+            };
+        };
+
+        match enum_name {
+            "Mode" => self.uses_mode = true,
+            _ => self.uses_vfo = true,
+        }
+        let mut statements = Vec::new();
+        for (index, (condition, value)) in tests.iter().enumerate() {
+            let prefix = if index == 0 { "" } else { "} else " };
+            statements.push(format!("{prefix}if {condition} {{"));
+            statements.push(format!("set_var(s\"{field}\", {enum_name}::{value});"));
+        }
+        statements.push("}".to_string());
+        statements
+    }
+}
+
+/// Condition of an OmniRig flag on the bytes read into `byte<index>`, `None` if a byte tests
+/// bits that are not adjacent, the DSL has no bitwise operators.
+fn flag_condition(mask: &[u8], value: &[u8]) -> Option<String> {
+    let mut tests = Vec::new();
+    for (index, (mask, value)) in mask.iter().zip(value).enumerate() {
+        if *mask == 0 {
+            continue;
+        }
+        let shift = mask.trailing_zeros();
+        let width = mask.count_ones();
+        if u32::from(*mask >> shift) != (1 << width) - 1 {
+            return None;
+        }
+
+        let mut operand = format!("byte{index}");
+        if shift > 0 {
+            operand = format!("{operand} / {}", 1 << shift);
+        }
+        if shift + width < 8 {
+            operand = format!("{operand} % {}", 1 << width);
+        }
+        tests.push(format!("{operand} == {}", (value & mask) >> shift));
+    }
+    Some(tests.join(" && "))
+}
+
+fn lines(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| line.to_string()).collect()
+}
+
+fn block(header: &str, body: Vec<String>) -> Vec<String> {
+    let mut lines = vec![header.to_string()];
+    lines.extend(body);
+    lines.push("}".to_string());
+    lines
+}
+
+fn unsupported_call(what: &str) -> Vec<String> {
+    vec![format!("error(s\"Unsupported {what}\");")]
+}
+
+fn match_statement(
+    subject: &str,
+    arms: impl IntoIterator<Item = (impl Display, Option<Vec<String>>)>,
+    fallback: Vec<String>,
+) -> Vec<String> {
+    let mut lines = vec![format!("match {subject} {{")];
+    for (pattern, body) in arms {
+        if let Some(body) = body {
+            lines.extend(block(&format!("{pattern} => {{"), body));
+        }
+    }
+    let wildcard = match subject.matches(',').count() {
+        0 => "_".to_string(),
+        commas => format!("({})", vec!["_"; commas + 1].join(", ")),
+    };
+    lines.extend(block(&format!("{wildcard} => {{"), fallback));
+    lines.push("}".to_string());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{
+        DryRunApi, Interpreter, TranscriptEntry, Value, parse_and_validate_with_schema,
+        parse_rig_file, parse_schema,
+    };
+
+    const ICOM: &str = r#"
+;-------------------------------------------------------------------------------
+;                      Icom IC-7300 command set
+;-------------------------------------------------------------------------------
+
+[INIT1]
+;Transceive off
+Command=FEFE94E0.1A050071.00.FD
+ReplyLength=16
+Validate=FEFE94E01A05007100FD.FEFEE094FBFD
+
+[pmFreqA]
+Command=FEFE94E0.25.00.0000000000.FD
+Value=6|5|vfBcdLU|1|0
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmFreqB]
+Command=FEFE94E0.25.01.0000000000.FD
+Value=6|5|vfBcdLU|1|0
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmPitch]
+Command=FEFE94E0.14.09.0000.FD
+Value=6|2|vfBcdBU|0.425|-127.5
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmRitOffset]
+Command=FEFE94E0.21.00.000000.FD
+Value=6|3|vfYaesu|1|0
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmTx]
+Command=FEFE94E0.1C00.01.FD
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmRx]
+Command=FEFE94E0.1C00.00.FD
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmCW_U]
+Command=FEFE94E0.06.03.FD
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmSSB_U]
+Command=FEFE94E0.06.01.FD
+ReplyLength=6
+Validate=FEFEE094FBFD
+
+[pmRit0]
+
+[STATUS1]
+Command=FEFE94E0.25.00.FD
+ReplyLength=12
+Validate=FFFFFFFFFFFF.0000000000.FF|FEFEE0942500.0000000000.FD
+Value1=6|5|vfBcdLU|1|0|pmFreqA
+
+[STATUS2]
+Command=FEFE94E0.04.FD
+ReplyLength=8
+Validate=FFFFFFFFFF.0000.FF|FEFEE09404.0000.FD
+Flag1=0000000000.FF00.00|0000000000.0300.00|pmCW_U
+Flag2=0000000000.FF00.00|0000000000.0100.00|pmSSB_U
+Flag3=0000000000.00F0.00|0000000000.0010.00|pmTx
+"#;
+
+    #[test]
+    fn test_data_format() {
+        assert_eq!(data_format("vfBcdLU"), Some(DataFormat::BcdLu));
+        assert_eq!(data_format("vfBinB"), Some(DataFormat::IntBs));
+        assert_eq!(data_format("vfText"), Some(DataFormat::Text));
+        assert_eq!(data_format("vfYaesu"), None);
+    }
+
+    #[test]
+    fn test_convert() -> Result<()> {
+        let conversion = convert(ICOM, "IC-7300")?;
+        let rig = &conversion.rig;
+
+        assert!(rig.contains("impl Transceiver for IC7300 {"));
+        assert!(rig.contains(r#"write("FEFE94E0.25.00.{freq:bcd_lu:5}.FD");"#));
+        assert!(rig.contains("pitch = pitch * 0.425 - 127.5;"));
+        assert!(rig.contains(r#"read("FEFEE0942500.{freq_a:bcd_lu:5}.FD");"#));
+        assert!(rig.contains("set_var(s\"transmit\", byte6 / 16 == 1);"));
+        assert_eq!(
+            conversion.unsupported,
+            vec![Unsupported {
+                section: "pmRitOffset".to_string(),
+                message: "no equivalent in the Transceiver schema".to_string(),
+            }]
+        );
+        assert!(rig.starts_with("// Not converted from the OmniRig definition:\n"));
+
+        let schema = parse_schema(&std::fs::read_to_string("../schema/transceiver.schema")?)?;
+        let schemas = HashMap::from([(schema.name.clone(), schema)]);
+        let rig_file = parse_and_validate_with_schema(rig, &schemas)
+            .map_err(|errors| anyhow!("{errors:?}\n{rig}"))?;
+        assert!(rig_file.get_supported_status_fields().contains("mode"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_converted_rig_runs() -> Result<()> {
+        let conversion = convert(ICOM, "IC-7300")?;
+        let interpreter = Interpreter::new(parse_rig_file(&conversion.rig)?);
+
+        let params = HashMap::from([
+            ("freq".to_string(), Value::Integer(14074000)),
+            ("target".to_string(), Value::String("B".to_string())),
+        ]);
+        let transcript = interpreter.dry_run("set_freq", params, vec![]).await;
+        assert!(transcript.error.is_none());
+        assert_eq!(
+            transcript.entries[0],
+            TranscriptEntry::Write(parse_hex("FEFE94E0.25.01.0040071400.FD")?)
+        );
+
+        let api = DryRunApi::new(vec![
+            parse_hex("FEFEE0942500.0040071400.FD")?,
+            parse_hex("FEFEE09404.03.10.FD")?,
+        ]);
+        interpreter.execute_status(&api).await?;
+        let transcript = api.into_transcript(Ok(()));
+        let set_var = |name: &str, value| TranscriptEntry::SetVar {
+            name: name.to_string(),
+            value,
+        };
+        assert!(
+            transcript
+                .entries
+                .contains(&set_var("freq_a", Value::Integer(14074000)))
+        );
+        assert!(
+            transcript
+                .entries
+                .contains(&set_var("transmit", Value::Boolean(true)))
+        );
+        assert!(transcript.entries.iter().any(|entry| matches!(
+            entry,
+            TranscriptEntry::SetVar { name, value: Value::EnumVariant { variant_name, .. } }
+                if name == "mode" && variant_name == "CWU"
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_ascii_rig() -> Result<()> {
+        let ini = "[pmFreqA]\nCommand=(FA00000000000;)\nValue=2|11|vfText|1|0\nReplyLength=0\n\n[pmRx]\nCommand=(RX;)\nReplyEnd=(;)\n\n[pmVfoSplitOn]\nCommand=(FT1;)\n";
+        let conversion = convert(ini, "TS-590")?;
+        assert!(
+            conversion
+                .rig
+                .contains(r#"write("4641.{freq:text:11}.3B");"#)
+        );
+        assert!(conversion.rig.contains("// (RX;)"));
+        assert_eq!(
+            conversion
+                .unsupported
+                .iter()
+                .map(|unsupported| unsupported.section.as_str())
+                .collect::<Vec<_>>(),
+            ["pmRx", "pmVfoSplitOn"]
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,15 @@
-use anyhow::Result;
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use anyhow::{Result, anyhow, bail};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::runtime::{Interpreter, SchemaFile, parse_and_validate_with_schema, parse_schema};
+use crate::rig_settings::DeviceClass;
+use crate::runtime::{
+    Interpreter, RigFile, SchemaFile, module_loader, parse_and_validate_with_imports, parse_schema,
+    read_module,
+};
 
 pub struct Resources {
     pub schemas: HashMap<String, SchemaFile>,
@@ -10,26 +18,64 @@ pub struct Resources {
 
 impl Resources {
     pub fn load() -> Result<Arc<Self>> {
-        let schemas = Self::load_schemas()?;
-        let rigs = Self::load_rig_files(&schemas)?;
+        let base_dir = Self::base_dir()?;
+        let schemas = Self::load_schemas_from(&base_dir.join("schema"))?;
+        let mut rigs = HashMap::new();
+        for class in DeviceClass::iter_classes() {
+            let dir = base_dir.join(class.rigs_dir());
+            // Only transceivers are mandatory, the other classes may have no rig files yet
+            if class != DeviceClass::Transceiver && !dir.is_dir() {
+                continue;
+            }
+            for (name, rig_file) in Self::load_rig_files_from(&dir, &schemas)? {
+                if rig_file.impl_block.schema != class.schema() {
+                    bail!(
+                        "Rig {name} in {} implements {}, expected {class}",
+                        dir.display(),
+                        rig_file.impl_block.schema
+                    );
+                }
+                rigs.insert(name, Interpreter::new(rig_file));
+            }
+        }
         Ok(Arc::new(Self { schemas, rigs }))
     }
 
+    /// Class of a rig, from the schema it implements.
+    pub fn rig_class(&self, rig_type: &str) -> Option<DeviceClass> {
+        let rig = self.rigs.get(rig_type)?;
+        DeviceClass::from_schema(&rig.rig_file().impl_block.schema)
+    }
+
+    /// Sorted names of the rigs of every device class.
+    pub fn rig_types(&self) -> HashMap<DeviceClass, Vec<String>> {
+        let mut rig_types: HashMap<DeviceClass, Vec<String>> = HashMap::new();
+        for name in self.rigs.keys() {
+            if let Some(class) = self.rig_class(name) {
+                rig_types.entry(class).or_default().push(name.clone());
+            }
+        }
+        for names in rig_types.values_mut() {
+            names.sort();
+        }
+        rig_types
+    }
+
+    fn base_dir() -> Result<PathBuf> {
+        if cfg!(debug_assertions) {
+            Ok(PathBuf::from(".."))
+        } else {
+            dirs::config_dir().ok_or_else(|| anyhow!("Could not find config directory"))
+        }
+    }
+
     fn load_resources<T, C, F: Fn(PathBuf, &C) -> Result<(String, T)>>(
         extension: &[u8],
-        dir: &str,
+        dir: &Path,
         context: C,
         load_fn: F,
     ) -> Result<HashMap<String, T>> {
-        let schema_dir = if cfg!(debug_assertions) {
-            PathBuf::from("..")
-        } else {
-            dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
-        };
-
-        schema_dir
-            .join(dir)
-            .read_dir()?
+        dir.read_dir()?
             .filter_map(|entry| {
                 if entry.is_err() {
                     return None;
@@ -49,21 +95,52 @@ impl Resources {
             .collect()
     }
 
-    fn load_schemas() -> Result<HashMap<String, SchemaFile>> {
-        Self::load_resources(b"schema", "schema", (), |path, _| {
+    /// Parses the schema files in `dir`, by schema name.
+    pub fn load_schemas_from(dir: &Path) -> Result<HashMap<String, SchemaFile>> {
+        Self::load_resources(b"schema", dir, (), |path, _| {
             let schema = parse_schema(&std::fs::read_to_string(path)?)?;
             Ok((schema.name.clone(), schema))
         })
     }
 
-    fn load_rig_files(
+    /// Parses the rig files in `dir` and validates them against `schemas`, by rig name.
+    pub fn load_rig_files_from(
+        dir: &Path,
         schemas: &HashMap<String, SchemaFile>,
-    ) -> Result<HashMap<String, Interpreter>> {
-        Self::load_resources(b"rig", "rigs", schemas, |path, schemas| {
-            let source = std::fs::read_to_string(path)?;
-            // TODO: remove unwrap
-            let rig_file = parse_and_validate_with_schema(&source, schemas).unwrap();
-            Ok((rig_file.impl_block.name.clone(), Interpreter::new(rig_file)))
+    ) -> Result<HashMap<String, RigFile>> {
+        Self::load_resources(b"rig", dir, schemas, |path, schemas| {
+            let source = std::fs::read_to_string(&path)?;
+            let rigs_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+            let rig_file = parse_and_validate_with_imports(
+                &source,
+                schemas,
+                module_loader(&rigs_dir, read_module),
+            )
+            .map_err(|errors| {
+                let errors: Vec<_> = errors.iter().map(|err| err.to_string()).collect();
+                anyhow!("{}:\n{}", path.display(), errors.join("\n"))
+            })?;
+            Ok((rig_file.impl_block.name.clone(), rig_file))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_device_classes() -> Result<()> {
+        let resources = Resources::load()?;
+        assert_eq!(
+            resources.rig_class("IC7300"),
+            Some(DeviceClass::Transceiver)
+        );
+        assert_eq!(resources.rig_class("GS232A"), Some(DeviceClass::Rotator));
+
+        let rig_types = resources.rig_types();
+        assert_eq!(rig_types[&DeviceClass::Rotator], ["GS232A"]);
+        assert!(!rig_types.contains_key(&DeviceClass::Amplifier));
+        Ok(())
+    }
+}
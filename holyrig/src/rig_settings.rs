@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::net::SocketAddr;
 
 #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BaudRate {
@@ -111,9 +112,59 @@ impl Display for StopBits {
     }
 }
 
+/// Kind of device, every class is driven through its own schema.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeviceClass {
+    #[default]
+    Transceiver,
+    Rotator,
+    Amplifier,
+}
+
+impl Display for DeviceClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.schema())
+    }
+}
+
+impl DeviceClass {
+    pub fn iter_classes() -> impl Iterator<Item = DeviceClass> {
+        [
+            DeviceClass::Transceiver,
+            DeviceClass::Rotator,
+            DeviceClass::Amplifier,
+        ]
+        .into_iter()
+    }
+
+    /// Name of the schema the rig files of this class implement.
+    pub fn schema(&self) -> &'static str {
+        match self {
+            DeviceClass::Transceiver => "Transceiver",
+            DeviceClass::Rotator => "Rotator",
+            DeviceClass::Amplifier => "Amplifier",
+        }
+    }
+
+    pub fn from_schema(schema: &str) -> Option<Self> {
+        Self::iter_classes().find(|class| class.schema() == schema)
+    }
+
+    /// Directory, next to the schema directory, holding the rig files of this class.
+    pub fn rigs_dir(&self) -> &'static str {
+        match self {
+            DeviceClass::Transceiver => "rigs",
+            DeviceClass::Rotator => "rotators",
+            DeviceClass::Amplifier => "amplifiers",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RigSettings {
     pub id: usize,
+    #[serde(default)]
+    pub device_class: DeviceClass,
     #[serde(default = "default_rig_type")]
     pub rig_type: String,
     pub port: String,
@@ -128,6 +179,50 @@ pub struct RigSettings {
     pub poll_interval: u16,
     #[serde(default = "default_timeout")]
     pub timeout: u16,
+    #[serde(default)]
+    pub rigctld: RigctldSettings,
+    #[serde(default)]
+    pub rotctld: RotctldSettings,
+}
+
+/// Hamlib rigctld listener serving a transceiver.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RigctldSettings {
+    pub enabled: bool,
+    /// IP address and port the listener binds to.
+    pub address: String,
+}
+
+/// Port hamlib clients connect to by default.
+const RIGCTLD_PORT: u16 = 4532;
+
+impl Default for RigctldSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: format!("127.0.0.1:{RIGCTLD_PORT}"),
+        }
+    }
+}
+
+/// Hamlib rotctld listener serving a rotator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotctldSettings {
+    pub enabled: bool,
+    /// IP address and port the listener binds to.
+    pub address: String,
+}
+
+/// Port hamlib rotator clients connect to by default.
+const ROTCTLD_PORT: u16 = 4533;
+
+impl Default for RotctldSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: format!("127.0.0.1:{ROTCTLD_PORT}"),
+        }
+    }
 }
 
 fn default_rig_type() -> String {
@@ -143,7 +238,8 @@ fn default_timeout() -> u16 {
 }
 
 impl RigSettings {
-    pub fn validate(&self) -> Result<(), String> {
+    /// Checks the settings, `rigs` being the settings of every rig the listeners are shared with.
+    pub fn validate(&self, rigs: &[RigSettings]) -> Result<(), String> {
         if self.rig_type == "unspecified" {
             return Err("Rig type must be specified".to_string());
         }
@@ -160,6 +256,30 @@ impl RigSettings {
             return Err("Timeout must be between 100ms and 10000ms".to_string());
         }
 
+        if self.device_class == DeviceClass::Transceiver
+            && self.rigctld.enabled
+            && self.rigctld.address.parse::<SocketAddr>().is_err()
+        {
+            return Err("Rigctld address must be an IP address and a port".to_string());
+        }
+
+        if self.device_class == DeviceClass::Rotator
+            && self.rotctld.enabled
+            && self.rotctld.address.parse::<SocketAddr>().is_err()
+        {
+            return Err("Rotctld address must be an IP address and a port".to_string());
+        }
+
+        if let Some(address) = self.listener_address()
+            && rigs
+                .iter()
+                .filter(|rig| rig.id != self.id)
+                .filter_map(RigSettings::listener_address)
+                .any(|other| conflicts(address, other))
+        {
+            return Err(format!("Address {address} is already used by another rig"));
+        }
+
         Ok(())
     }
 
@@ -167,6 +287,65 @@ impl RigSettings {
         self.id = id;
         self
     }
+
+    /// Moves the rigctld and rotctld listeners to the first port from their default one that
+    /// none of `rigs` listens on. Transceivers skip the default rotctld port.
+    pub fn with_free_ports(mut self, rigs: &[RigSettings]) -> Self {
+        let used: Vec<_> = rigs
+            .iter()
+            .filter_map(RigSettings::listener_address)
+            .collect();
+        let free = |from: u16, skipped: Option<u16>| {
+            (from..=u16::MAX)
+                .filter(|port| Some(*port) != skipped)
+                .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+                .find(|address| !used.iter().any(|other| conflicts(*address, *other)))
+        };
+        if let Some(address) = free(RIGCTLD_PORT, Some(ROTCTLD_PORT)) {
+            self.rigctld.address = address.to_string();
+        }
+        if let Some(address) = free(ROTCTLD_PORT, None) {
+            self.rotctld.address = address.to_string();
+        }
+        self
+    }
+
+    /// Moves the hamlib listeners to free ports when the one serving the rig conflicts with
+    /// a listener of `rigs`.
+    pub fn without_conflicts(self, rigs: &[RigSettings]) -> Self {
+        let conflicting = self.listener_address().is_some_and(|address| {
+            rigs.iter()
+                .filter_map(RigSettings::listener_address)
+                .any(|other| conflicts(address, other))
+        });
+        if conflicting {
+            self.with_free_ports(rigs)
+        } else {
+            self
+        }
+    }
+
+    /// Configured address of the hamlib listener serving the rig, if it has one.
+    pub fn hamlib_address(&self) -> Option<&str> {
+        match self.device_class {
+            DeviceClass::Transceiver if self.rigctld.enabled => Some(&self.rigctld.address),
+            DeviceClass::Rotator if self.rotctld.enabled => Some(&self.rotctld.address),
+            _ => None,
+        }
+    }
+
+    /// Address the hamlib listener of the rig binds to, if it has one.
+    fn listener_address(&self) -> Option<SocketAddr> {
+        self.hamlib_address()?.parse().ok()
+    }
+}
+
+/// Whether listeners bound to both addresses would share a port.
+fn conflicts(address: SocketAddr, other: SocketAddr) -> bool {
+    address.port() == other.port()
+        && (address.ip() == other.ip()
+            || address.ip().is_unspecified()
+            || other.ip().is_unspecified())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -187,3 +366,68 @@ impl From<Vec<RigSettings>> for Settings {
         Self { rigs }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transceiver(id: usize, address: &str) -> RigSettings {
+        RigSettings {
+            id,
+            rig_type: "IC7300".to_string(),
+            port: "/dev/ttyUSB0".to_string(),
+            poll_interval: default_poll_interval(),
+            timeout: default_timeout(),
+            rigctld: RigctldSettings {
+                enabled: true,
+                address: address.to_string(),
+            },
+            ..RigSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_rigctld_ports() {
+        let rigs = [
+            transceiver(1, "127.0.0.1:4532"),
+            transceiver(2, "0.0.0.0:4534"),
+        ];
+        assert_eq!(rigs[0].validate(&rigs), Ok(()));
+        assert_eq!(
+            transceiver(3, "127.0.0.1:4534").validate(&rigs),
+            Err("Address 127.0.0.1:4534 is already used by another rig".to_string())
+        );
+        assert_eq!(transceiver(3, "127.0.0.2:4532").validate(&rigs), Ok(()));
+
+        // The default rotctld port is left to rotators
+        let rig = RigSettings::default()
+            .with_id(3)
+            .with_free_ports(&rigs[..1]);
+        assert_eq!(rig.rigctld.address, "127.0.0.1:4534");
+        let rig = RigSettings::default().with_id(3).with_free_ports(&rigs);
+        assert_eq!(rig.rigctld.address, "127.0.0.1:4535");
+    }
+
+    #[test]
+    fn test_rotctld_ports() {
+        let rotator = RigSettings {
+            device_class: DeviceClass::Rotator,
+            rig_type: "GS232A".to_string(),
+            ..transceiver(3, "127.0.0.1:4532")
+        };
+        let rigs = [transceiver(1, "127.0.0.1:4532"), rotator.clone()];
+        assert_eq!(rotator.validate(&rigs), Ok(()));
+        assert_eq!(
+            transceiver(2, "0.0.0.0:4533").validate(&rigs),
+            Err("Address 0.0.0.0:4533 is already used by another rig".to_string())
+        );
+
+        let rig = RigSettings::default().with_id(4).with_free_ports(&rigs);
+        assert_eq!(rig.rigctld.address, "127.0.0.1:4534");
+        assert_eq!(rig.rotctld.address, "127.0.0.1:4534");
+        let rig = RigSettings::default()
+            .with_id(4)
+            .with_free_ports(&rigs[..1]);
+        assert_eq!(rig.rotctld.address, "127.0.0.1:4533");
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use super::parser_errors::ParseError;
+use super::semantic_analyzer::SemanticWarning;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn sarif_level(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Error or warning of a checked file in the shape tools consume, see `diagnostics_to_json` and
+/// `diagnostics_to_sarif`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn from_parse_error(file: &Path, error: &ParseError) -> Self {
+        Self {
+            file: file.display().to_string(),
+            line: error.position.line,
+            column: error.position.column,
+            severity: Severity::Error,
+            code: error.code(),
+            message: error.message(),
+        }
+    }
+
+    /// Warnings without a position are reported on the first line of the file.
+    pub fn from_warning(file: &Path, warning: &SemanticWarning) -> Self {
+        let (line, column) = warning
+            .position
+            .as_ref()
+            .map_or((1, 1), |position| (position.line, position.column));
+        Self {
+            file: file.display().to_string(),
+            line,
+            column,
+            severity: Severity::Warning,
+            code: warning.warning_type.code(),
+            message: warning.to_string(),
+        }
+    }
+}
+
+/// `{"diagnostics": [...]}` with one object per diagnostic.
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic]) -> Value {
+    json!({ "diagnostics": diagnostics })
+}
+
+/// SARIF 2.1.0 log with a single run, as read by code scanning tools.
+pub fn diagnostics_to_sarif(diagnostics: &[Diagnostic]) -> Value {
+    let mut rules: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    rules.sort();
+    rules.dedup();
+
+    let results: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "ruleId": diagnostic.code,
+                "level": diagnostic.severity.sarif_level(),
+                "message": {"text": diagnostic.message},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": diagnostic.file.replace('\\', "/")},
+                        "region": {
+                            "startLine": diagnostic.line.max(1),
+                            "startColumn": diagnostic.column.max(1),
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "holyrig",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|id| json!({"id": id})).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::runtime::{parse_and_validate_with_schema, parse_rig_file, parse_schema};
+
+    #[test]
+    fn test_diagnostics_output() {
+        let error = parse_rig_file("impl Transceiver for Test {\n    fn set_freq( {\n}\n")
+            .expect_err("Invalid rig file parsed");
+        let diagnostic = Diagnostic::from_parse_error(Path::new("rigs/test.rig"), &error);
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(diagnostic.code, ParseError::SYNTAX_CODE);
+
+        let diagnostics = [diagnostic.clone()];
+        let output = diagnostics_to_json(&diagnostics);
+        assert_eq!(output["diagnostics"][0]["file"], "rigs/test.rig");
+        assert_eq!(output["diagnostics"][0]["severity"], "error");
+        assert_eq!(output["diagnostics"][0]["code"], "E0002");
+
+        let sarif = diagnostics_to_sarif(&diagnostics);
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "E0002");
+        assert_eq!(result["message"]["text"], diagnostic.message.as_str());
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startLine"],
+            2
+        );
+        assert_eq!(
+            sarif["runs"][0]["tool"]["driver"]["rules"][0]["id"],
+            "E0002"
+        );
+    }
+
+    #[test]
+    fn test_semantic_error_location() {
+        let schema = parse_schema("version = 1;\nschema Test {\n    fn set_freq(int freq);\n}\n")
+            .expect("Invalid schema");
+        let schemas = HashMap::from([("Test".to_string(), schema)]);
+        let source = "impl Test for TestRig {\n    fn set_freq(int freq) {\n        write(\"00\" + missing);\n    }\n}\n";
+        let errors = parse_and_validate_with_schema(source, &schemas).unwrap_err();
+        let diagnostics: Vec<_> = errors
+            .iter()
+            .map(|error| Diagnostic::from_parse_error(Path::new("rigs/test.rig"), error))
+            .collect();
+
+        let output = diagnostics_to_json(&diagnostics);
+        assert_eq!(output["diagnostics"][0]["line"], 3);
+        assert_eq!(output["diagnostics"][0]["column"], 15);
+        let sarif = diagnostics_to_sarif(&diagnostics);
+        let region = &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 3);
+        assert_eq!(region["startColumn"], 15);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use anyhow::{Result, bail};
+use parking_lot::Mutex;
+use serde_json::json;
+
+use super::interpreter::{ExternalApi, Value};
+use super::tracer::hex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEntry {
+    Write(Vec<u8>),
+    Read {
+        data: Vec<u8>,
+        /// The reply was built from the read template instead of taken from the scripted ones.
+        synthesized: bool,
+    },
+    SetVar {
+        name: String,
+        value: Value,
+    },
+}
+
+impl fmt::Display for TranscriptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptEntry::Write(data) => write!(f, ">> {}", hex(data)),
+            TranscriptEntry::Read {
+                data,
+                synthesized: true,
+            } => write!(f, "<< {} (synthesized)", hex(data)),
+            TranscriptEntry::Read { data, .. } => write!(f, "<< {}", hex(data)),
+            TranscriptEntry::SetVar { name, value } => write!(f, "{name} = {value}"),
+        }
+    }
+}
+
+impl From<&TranscriptEntry> for serde_json::Value {
+    fn from(entry: &TranscriptEntry) -> Self {
+        match entry {
+            TranscriptEntry::Write(data) => json!({"event": "write", "data": hex(data)}),
+            TranscriptEntry::Read { data, synthesized } => {
+                json!({"event": "read", "data": hex(data), "synthesized": synthesized})
+            }
+            TranscriptEntry::SetVar { name, value } => {
+                json!({"event": "set_var", "name": name, "value": serde_json::Value::from(value)})
+            }
+        }
+    }
+}
+
+/// Result of a dry run: everything written and read until execution finished or failed.
+#[derive(Debug)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+    pub error: Option<anyhow::Error>,
+}
+
+/// `ExternalApi` that records the traffic instead of talking to a rig. Reads are answered with
+/// the scripted replies in order, once they run out replies are synthesized from the expected
+/// bytes with zeros in place of the values the rig would fill in.
+#[derive(Default)]
+pub struct DryRunApi {
+    replies: Mutex<VecDeque<Vec<u8>>>,
+    entries: Mutex<Vec<TranscriptEntry>>,
+}
+
+impl DryRunApi {
+    pub fn new(replies: Vec<Vec<u8>>) -> Self {
+        Self {
+            replies: Mutex::new(replies.into()),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn into_transcript(self, result: Result<()>) -> Transcript {
+        Transcript {
+            entries: self.entries.into_inner(),
+            error: result.err(),
+        }
+    }
+}
+
+impl ExternalApi for DryRunApi {
+    async fn write(&self, data: &[u8]) -> Result<()> {
+        self.entries
+            .lock()
+            .push(TranscriptEntry::Write(data.to_vec()));
+        Ok(())
+    }
+
+    async fn read(&self, size: usize) -> Result<Vec<u8>> {
+        self.read_expected(&vec![None; size]).await
+    }
+
+    async fn read_expected(&self, expected: &[Option<u8>]) -> Result<Vec<u8>> {
+        let (data, synthesized) = match self.replies.lock().pop_front() {
+            Some(reply) => (reply, false),
+            None => (
+                expected.iter().map(|byte| byte.unwrap_or(0)).collect(),
+                true,
+            ),
+        };
+        self.entries.lock().push(TranscriptEntry::Read {
+            data: data.clone(),
+            synthesized,
+        });
+        Ok(data)
+    }
+
+    fn set_var(&self, var: &str, value: Value) -> Result<()> {
+        self.entries.lock().push(TranscriptEntry::SetVar {
+            name: var.to_string(),
+            value,
+        });
+        Ok(())
+    }
+}
+
+/// Parses hex bytes written like rig file literals, e.g. `FEFE94E0` or `FE.FE.94.E0`.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .bytes()
+        .filter(|byte| !matches!(byte, b'.' | b' '))
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        bail!("Odd number of hex digits in '{text}'");
+    }
+
+    digits
+        .chunks(2)
+        .map(|chunk| {
+            std::str::from_utf8(chunk)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid hex byte in '{text}'"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex() -> Result<()> {
+        assert_eq!(parse_hex("FEFE94e0")?, vec![0xFE, 0xFE, 0x94, 0xE0]);
+        assert_eq!(parse_hex("FE.FE 00")?, vec![0xFE, 0xFE, 0x00]);
+        assert!(parse_hex("FEF").is_err());
+        assert!(parse_hex("ZZ").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,1067 @@
+use std::iter;
+use std::ops::Range;
+
+use logos::Logos;
+
+use super::parser::{
+    Capabilities, Command, Enum, Expr, FrequencyRange, Id, MatchArm, RigFile, RigModule, RigTest,
+    Statement, StatementKind, Token, parse_rig_file, parse_rig_module,
+};
+use super::parser_errors::ParseError;
+use super::schema_parser::{SchemaCommand, SchemaFile, parse_schema};
+
+const INDENT: &str = "    ";
+/// Lists and parameter lists that do not fit into this many columns get one item per line.
+const MAX_WIDTH: usize = 100;
+
+/// Formats a rig file into its canonical source, keeping its comments.
+pub fn format_rig_file(source: &str) -> Result<String, ParseError> {
+    let rig_file = parse_rig_file(source)?;
+    let map = SourceMap::new(source);
+    let mut printer = Printer::new(&map);
+    printer.rig_file(&rig_file);
+    Ok(printer.finish())
+}
+
+/// Formats a rig module into its canonical source, keeping its comments.
+pub fn format_rig_module(source: &str) -> Result<String, ParseError> {
+    let module = parse_rig_module(source)?;
+    let map = SourceMap::new(source);
+    let mut printer = Printer::new(&map);
+    printer.rig_module(&module);
+    Ok(printer.finish())
+}
+
+/// Formats a schema file into its canonical source, keeping its comments.
+pub fn format_schema(source: &str) -> Result<String, ParseError> {
+    let schema = parse_schema(source)?;
+    let map = SourceMap::new(source);
+    let mut printer = Printer::new(&map);
+    printer.schema(&schema);
+    Ok(printer.finish())
+}
+
+/// Tokens and comments of the formatted source with their byte offsets. The AST does not keep
+/// the order of members, the spelling of enum values or comments, they are recovered from here.
+struct SourceMap<'source> {
+    source: &'source str,
+    line_starts: Vec<usize>,
+    tokens: Vec<Token<'source>>,
+    offsets: Vec<usize>,
+    comments: Vec<(&'source str, usize)>,
+}
+
+impl<'source> SourceMap<'source> {
+    fn new(source: &'source str) -> Self {
+        let mut tokens = Vec::new();
+        let mut offsets = Vec::new();
+        let mut comments = Vec::new();
+
+        let mut lexer = Token::lexer(source);
+        while let Some(token) = lexer.next() {
+            let offset = lexer.span().start;
+            match token {
+                Ok(Token::Comment(text) | Token::DocComment(text)) => {
+                    comments.push((text.trim_end(), offset))
+                }
+                Ok(Token::NewLine) | Err(_) => {}
+                Ok(token) => {
+                    tokens.push(token);
+                    offsets.push(offset);
+                }
+            }
+        }
+
+        let line_starts = iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Self {
+            source,
+            line_starts,
+            tokens,
+            offsets,
+            comments,
+        }
+    }
+
+    /// One based line of a byte offset.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset)
+    }
+
+    /// Whether code precedes `offset` on its line, i.e. a comment at `offset` is a trailing one.
+    fn follows_code(&self, offset: usize) -> bool {
+        let start = self.line_starts[self.line(offset) - 1];
+        !self.source[start..offset].trim().is_empty()
+    }
+
+    fn index(&self, offset: usize) -> usize {
+        self.offsets.partition_point(|start| *start < offset)
+    }
+
+    /// Offset of the first token in `range` at which `pattern` matches the remaining tokens.
+    fn find(
+        &self,
+        range: Range<usize>,
+        pattern: impl Fn(&[Token<'source>]) -> bool,
+    ) -> Option<usize> {
+        (self.index(range.start)..self.tokens.len())
+            .take_while(|index| self.offsets[*index] < range.end)
+            .find(|index| pattern(&self.tokens[*index..]))
+            .map(|index| self.offsets[index])
+    }
+
+    /// Offset of the token following the one at `offset`.
+    fn next(&self, offset: usize) -> usize {
+        self.offsets
+            .get(self.index(offset) + 1)
+            .copied()
+            .unwrap_or(self.source.len())
+    }
+
+    /// Offset of the first `;` at or after `offset`.
+    fn semicolon(&self, offset: usize) -> usize {
+        self.find(offset..usize::MAX, |tokens| {
+            matches!(tokens, [Token::Semicolon, ..])
+        })
+        .unwrap_or(offset)
+    }
+
+    /// Offset of the `}` closing the first block opened at or after `offset`.
+    fn block_end(&self, offset: usize) -> usize {
+        let mut depth = 0;
+        for index in self.index(offset)..self.tokens.len() {
+            match self.tokens[index] {
+                Token::BraceOpen => depth += 1,
+                Token::BraceClose if depth == 1 => return self.offsets[index],
+                Token::BraceClose => depth -= 1,
+                _ => {}
+            }
+        }
+        self.source.len()
+    }
+
+    /// Replaces the byte literals of `rendered`, code printed from the AST, with the normalized
+    /// literals of the source in `range`. The AST merges the byte groups an author wrote.
+    fn restore_bytes(&self, rendered: &str, range: Range<usize>) -> String {
+        let start = self.index(range.start);
+        let end = self.index(range.end);
+        let mut literals = self.tokens[start..end]
+            .iter()
+            .filter_map(|token| match token {
+                Token::Bytes(literal) => Some(literal),
+                _ => None,
+            })
+            .peekable();
+
+        let mut output = String::new();
+        let mut rest = rendered;
+        while let Some(quote) = rest.find('"') {
+            let Some(length) = rest[quote + 1..].find('"') else {
+                break;
+            };
+            let literal_end = quote + length + 2;
+            output.push_str(&rest[..quote]);
+            // Strings are printed as they are, their prefix is the only difference to bytes
+            let is_string = rest[..quote].ends_with('s');
+            match (is_string, literals.next_if(|_| !is_string)) {
+                (false, Some(literal)) => output.push_str(&normalize_bytes(literal)),
+                _ => output.push_str(&rest[quote..literal_end]),
+            }
+            rest = &rest[literal_end..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// Offsets of the items of the first `[...]` list at or after `offset`.
+    fn list_items(&self, offset: usize) -> Vec<usize> {
+        let mut items = Vec::new();
+        let mut depth = 0;
+        for index in self.index(offset)..self.tokens.len() {
+            let item_follows = match self.tokens[index] {
+                Token::BracketOpen => {
+                    depth += 1;
+                    depth == 1
+                }
+                Token::BracketClose if depth == 1 => break,
+                Token::BracketClose => {
+                    depth -= 1;
+                    false
+                }
+                Token::Comma => depth == 1,
+                _ => false,
+            };
+            if item_follows
+                && let Some(next) = self.tokens.get(index + 1)
+                && !matches!(next, Token::BracketClose)
+            {
+                items.push(self.offsets[index + 1]);
+            }
+        }
+        items
+    }
+}
+
+/// Uppercases the hex of a byte literal and separates every template field from its
+/// neighbours with a dot, e.g. `"fefe{freq:bcd_lu:5}fd"` becomes `"FEFE.{freq:bcd_lu:5}.FD"`.
+/// The grouping of the remaining bytes is kept.
+fn normalize_bytes(literal: &str) -> String {
+    let content = &literal[1..literal.len() - 1];
+    let mut groups = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let (group, remainder) = match rest.find(['.', '{']) {
+            Some(0) if rest.starts_with('{') => {
+                let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+                rest.split_at(end)
+            }
+            Some(0) => ("", &rest[1..]),
+            Some(end) => rest.split_at(end),
+            None => (rest, ""),
+        };
+        if !group.is_empty() {
+            groups.push(group.to_string());
+        }
+        rest = remainder;
+    }
+
+    for group in &mut groups {
+        // Odd or non hex groups are ASCII text whose case matters
+        if group.len() % 2 == 0 && group.chars().all(|c| c.is_ascii_hexdigit()) {
+            *group = group.to_uppercase();
+        }
+    }
+    format!("\"{}\"", groups.join("."))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ItemKind {
+    Setting,
+    Import,
+    Const,
+    Enum,
+}
+
+enum Item<'a> {
+    Setting(&'a Id, &'a Expr),
+    Import(&'a str),
+    Const(&'a Id, &'a Expr),
+    Enum(&'a Enum),
+}
+
+impl Item<'_> {
+    fn kind(&self) -> ItemKind {
+        match self {
+            Item::Setting(..) => ItemKind::Setting,
+            Item::Import(_) => ItemKind::Import,
+            Item::Const(..) => ItemKind::Const,
+            Item::Enum(_) => ItemKind::Enum,
+        }
+    }
+}
+
+enum ImplMember<'a> {
+    Enum(&'a Enum),
+    Init(&'a [Statement]),
+    Command(&'a Command),
+    Status(&'a [Statement]),
+    Capabilities(&'a Capabilities),
+    Test(&'a RigTest),
+}
+
+enum SchemaMember<'a> {
+    Enum(&'a str, &'a [String]),
+    Command(&'a str, &'a SchemaCommand),
+    Status,
+}
+
+/// Writes the canonical source line by line. Comments are printed before the first line
+/// anchored after them, at most one blank line of the source is kept between two lines.
+struct Printer<'a, 'source> {
+    map: &'a SourceMap<'source>,
+    lines: Vec<String>,
+    depth: usize,
+    next_comment: usize,
+    /// Source line of the last printed code, zero before the first one.
+    last_line: usize,
+    /// Whether the last printed line opened a block.
+    opened: bool,
+}
+
+impl<'a, 'source> Printer<'a, 'source> {
+    fn new(map: &'a SourceMap<'source>) -> Self {
+        Self {
+            map,
+            lines: Vec::new(),
+            depth: 0,
+            next_comment: 0,
+            last_line: 0,
+            opened: false,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_comments(usize::MAX);
+        self.trim_blank_lines();
+        let mut output = self.lines.join("\n");
+        output.push('\n');
+        output
+    }
+
+    fn flush_comments(&mut self, offset: usize) {
+        while let Some(&(text, at)) = self.map.comments.get(self.next_comment)
+            && at < offset
+        {
+            self.next_comment += 1;
+            if self.is_trailing(text, at) {
+                self.append(text);
+            } else {
+                self.push(text, self.map.line(at));
+            }
+        }
+    }
+
+    /// Prints the comments following the last printed line on the same source line.
+    fn flush_trailing(&mut self) {
+        while let Some(&(text, at)) = self.map.comments.get(self.next_comment)
+            && self.is_trailing(text, at)
+        {
+            self.next_comment += 1;
+            self.append(text);
+        }
+    }
+
+    /// Documentation always gets a line of its own, it belongs to the following declaration.
+    fn is_trailing(&self, text: &str, at: usize) -> bool {
+        !text.starts_with("///")
+            && self.map.line(at) == self.last_line
+            && self.map.follows_code(at)
+            && self.lines.last().is_some_and(|line| !line.is_empty())
+    }
+
+    fn append(&mut self, text: &str) {
+        if let Some(last) = self.lines.last_mut() {
+            last.push(' ');
+            last.push_str(text);
+        }
+    }
+
+    fn push(&mut self, text: &str, line: usize) {
+        let closing = text.starts_with(['}', ')', ']']);
+        if self.last_line > 0 && line > self.last_line + 1 && !closing {
+            self.blank();
+        }
+        self.lines
+            .push(format!("{}{text}", INDENT.repeat(self.depth)));
+        self.last_line = line;
+        self.opened = false;
+    }
+
+    /// Separates two items by a blank line, except at the start of a block.
+    fn blank(&mut self) {
+        self.flush_trailing();
+        if !self.opened && self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn trim_blank_lines(&mut self) {
+        while self.lines.last().is_some_and(String::is_empty) {
+            self.lines.pop();
+        }
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        INDENT.len() * self.depth + text.len() <= MAX_WIDTH
+    }
+
+    fn line(&mut self, text: &str, offset: usize) {
+        self.flush_comments(offset);
+        self.push(text, self.map.line(offset));
+    }
+
+    /// Prints a construct spanning several source lines on a single line.
+    fn item(&mut self, text: &str, start: usize, end: usize) {
+        let text = self.map.restore_bytes(text, start..end + 1);
+        self.line(&text, start);
+        self.last_line = self.map.line(end);
+    }
+
+    fn open(&mut self, text: &str, offset: usize) {
+        self.line(text, offset);
+        self.depth += 1;
+        self.opened = true;
+    }
+
+    fn dedent(&mut self, offset: usize) {
+        self.flush_comments(offset);
+        self.trim_blank_lines();
+        self.depth -= 1;
+    }
+
+    /// Closes the block ending with the token at `offset`.
+    fn close(&mut self, text: &str, offset: usize) {
+        self.dedent(offset);
+        self.push(text, self.map.line(offset));
+    }
+
+    fn items(&mut self, mut items: Vec<(usize, Item)>) {
+        items.sort_by_key(|(offset, _)| *offset);
+
+        let mut previous = None;
+        for (offset, item) in items {
+            let kind = item.kind();
+            if previous.is_some_and(|previous| previous != kind || kind == ItemKind::Enum) {
+                self.blank();
+            }
+            previous = Some(kind);
+
+            let end = self.map.semicolon(offset);
+            match item {
+                Item::Setting(id, expr) => self.item(&format!("{id} = {expr};"), offset, end),
+                Item::Import(path) => self.item(&format!("import s\"{path}\";"), offset, end),
+                Item::Const(id, expr) => self.item(&format!("const {id} = {expr};"), offset, end),
+                Item::Enum(enum_def) => self.enum_block(enum_def, offset),
+            }
+        }
+    }
+
+    fn find_import(&self, range: Range<usize>, path: &str) -> usize {
+        self.map
+            .find(range.clone(), |tokens| {
+                matches!(tokens, [Token::Import, Token::Str(literal), ..] if literal[2..literal.len() - 1] == *path)
+            })
+            .unwrap_or(range.end)
+    }
+
+    fn find_const(&self, range: Range<usize>, name: &Id) -> usize {
+        self.map
+            .find(range.clone(), |tokens| {
+                matches!(tokens, [Token::Const, Token::Id(id), ..] if *id == name.as_str())
+            })
+            .unwrap_or(range.end)
+    }
+
+    fn find_enum(&self, range: Range<usize>, name: &str) -> usize {
+        self.map
+            .find(
+                range.clone(),
+                |tokens| matches!(tokens, [Token::Enum, Token::Id(id), ..] if *id == name),
+            )
+            .unwrap_or(range.end)
+    }
+
+    fn rig_file(&mut self, rig_file: &RigFile) {
+        let impl_offset = self
+            .map
+            .find(0..usize::MAX, |tokens| matches!(tokens, [Token::Impl, ..]))
+            .unwrap_or(self.map.source.len());
+        let header = 0..impl_offset;
+
+        let mut items = Vec::new();
+        for (id, expr) in &rig_file.settings.settings {
+            let offset = self.map.find(header.clone(), |tokens| {
+                matches!(tokens, [Token::Id(name), Token::EqualAssign, ..] if *name == id.as_str())
+            });
+            items.push((offset.unwrap_or(impl_offset), Item::Setting(id, expr)));
+        }
+        for import in &rig_file.imports {
+            items.push((
+                self.find_import(header.clone(), &import.path),
+                Item::Import(&import.path),
+            ));
+        }
+        for constant in &rig_file.constants {
+            items.push((
+                self.find_const(header.clone(), &constant.id),
+                Item::Const(&constant.id, &constant.expr),
+            ));
+        }
+        self.items(items);
+
+        self.blank();
+        self.impl_block(rig_file, impl_offset);
+    }
+
+    fn rig_module(&mut self, module: &RigModule) {
+        let all = 0..self.map.source.len();
+        let mut items = Vec::new();
+        for import in &module.imports {
+            items.push((
+                self.find_import(all.clone(), &import.path),
+                Item::Import(&import.path),
+            ));
+        }
+        for constant in &module.constants {
+            items.push((
+                self.find_const(all.clone(), &constant.id),
+                Item::Const(&constant.id, &constant.expr),
+            ));
+        }
+        for enum_def in &module.enums {
+            items.push((
+                self.find_enum(all.clone(), &enum_def.name),
+                Item::Enum(enum_def),
+            ));
+        }
+        self.items(items);
+    }
+
+    fn impl_block(&mut self, rig_file: &RigFile, offset: usize) {
+        let impl_block = &rig_file.impl_block;
+        let end = self.map.block_end(offset);
+        let body = offset..end;
+        let find_block = |keyword: fn(&Token) -> bool| {
+            self.map
+                .find(
+                    body.clone(),
+                    |tokens| matches!(tokens, [token, Token::BraceOpen, ..] if keyword(token)),
+                )
+                .unwrap_or(end)
+        };
+
+        let mut members = Vec::new();
+        for enum_def in &impl_block.enums {
+            let offset = self.find_enum(body.clone(), &enum_def.name);
+            members.push((offset, ImplMember::Enum(enum_def)));
+        }
+        if let Some(init) = &impl_block.init {
+            let offset = find_block(|token| matches!(token, Token::Init));
+            members.push((offset, ImplMember::Init(&init.statements)));
+        }
+        for command in impl_block.commands.values() {
+            let offset = self
+                .map
+                .find(body.clone(), |tokens| {
+                    matches!(tokens, [Token::Fn, Token::Id(id), ..] if *id == command.name)
+                })
+                .unwrap_or(end);
+            members.push((offset, ImplMember::Command(command)));
+        }
+        if let Some(status) = &impl_block.status {
+            let offset = find_block(|token| matches!(token, Token::Status));
+            members.push((offset, ImplMember::Status(&status.statements)));
+        }
+        if let Some(capabilities) = &impl_block.capabilities {
+            let offset = find_block(|token| matches!(token, Token::Id("capabilities")));
+            members.push((offset, ImplMember::Capabilities(capabilities)));
+        }
+        for test in &impl_block.tests {
+            members.push((test.span.start.offset, ImplMember::Test(test)));
+        }
+        members.sort_by_key(|(offset, _)| *offset);
+
+        self.open(
+            &format!("impl {} for {} {{", impl_block.schema, impl_block.name),
+            offset,
+        );
+        for (offset, member) in members {
+            self.blank();
+            match member {
+                ImplMember::Enum(enum_def) => self.enum_block(enum_def, offset),
+                ImplMember::Init(statements) => self.block("init {", statements, offset),
+                ImplMember::Command(command) => {
+                    let parameters: Vec<_> = command
+                        .parameters
+                        .iter()
+                        .map(|param| format!("{} {}", param.param_type, param.name))
+                        .collect();
+                    let header = format!("fn {}({}) {{", command.name, parameters.join(", "));
+                    self.block(&header, &command.statements, offset);
+                }
+                ImplMember::Status(statements) => self.block("status {", statements, offset),
+                ImplMember::Capabilities(capabilities) => self.capabilities(capabilities, offset),
+                ImplMember::Test(test) => self.test(test, offset),
+            }
+        }
+        self.close("}", end);
+    }
+
+    fn enum_block(&mut self, enum_def: &Enum, offset: usize) {
+        let end = self.map.block_end(offset);
+        let mut variants: Vec<_> = enum_def
+            .variants
+            .iter()
+            .map(|(name, value)| {
+                let anchor = self.map.find(offset..end, |tokens| {
+                    matches!(tokens, [Token::Id(id), Token::EqualAssign, ..] if *id == name.as_str())
+                });
+                // Hex values are kept in hex, they usually mirror a protocol byte
+                let value = match anchor.map(|anchor| self.map.index(anchor) + 2) {
+                    Some(index) => match self.map.tokens.get(index) {
+                        Some(Token::HexNumber(hex)) => format!("0x{}", hex[2..].to_uppercase()),
+                        _ => value.to_string(),
+                    },
+                    None => value.to_string(),
+                };
+                (anchor.unwrap_or(end), format!("{name} = {value},"))
+            })
+            .collect();
+        variants.sort();
+
+        self.open(&format!("enum {} {{", enum_def.name), offset);
+        for (anchor, variant) in variants {
+            self.line(&variant, anchor);
+        }
+        self.close("}", end);
+    }
+
+    fn block(&mut self, header: &str, statements: &[Statement], offset: usize) {
+        self.open(header, offset);
+        self.statements(statements);
+        self.close("}", self.map.block_end(offset));
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let offset = statement.span.start.offset;
+            match &statement.kind {
+                StatementKind::If { .. } => self.if_statement(statement, ""),
+                StatementKind::Match { exprs, arms } => self.match_statement(exprs, arms, offset),
+                kind => self.item(&kind.to_string(), offset, statement.span.end.offset),
+            }
+        }
+    }
+
+    fn if_statement(&mut self, statement: &Statement, prefix: &str) {
+        let StatementKind::If {
+            condition,
+            then_body,
+            else_body,
+        } = &statement.kind
+        else {
+            return;
+        };
+        let offset = statement.span.start.offset;
+        let then_end = self.map.block_end(offset);
+
+        let header = format!("{prefix}if {condition} {{");
+        self.open(&self.map.restore_bytes(&header, offset..then_end), offset);
+        self.statements(then_body);
+        match else_body.as_deref() {
+            None => self.close("}", then_end),
+            Some([nested]) if matches!(nested.kind, StatementKind::If { .. }) => {
+                self.dedent(then_end);
+                self.if_statement(nested, "} else ");
+            }
+            Some(body) => {
+                let else_offset = self.map.next(then_end);
+                self.dedent(then_end);
+                self.block("} else {", body, else_offset);
+            }
+        }
+    }
+
+    fn match_statement(&mut self, exprs: &[Expr], arms: &[MatchArm], offset: usize) {
+        let end = self.map.block_end(offset);
+        let exprs: Vec<_> = exprs.iter().map(|expr| expr.to_string()).collect();
+        let header = match exprs.as_slice() {
+            [expr] => format!("match {expr} {{"),
+            exprs => format!("match ({}) {{", exprs.join(", ")),
+        };
+
+        self.open(&self.map.restore_bytes(&header, offset..end), offset);
+        let mut cursor = self.map.next(
+            self.map
+                .find(offset..end, |tokens| {
+                    matches!(tokens, [Token::BraceOpen, ..])
+                })
+                .unwrap_or(end),
+        );
+        for arm in arms {
+            let arm_offset = self
+                .map
+                .find(cursor..end, |tokens| !matches!(tokens, [Token::Comma, ..]))
+                .unwrap_or(end);
+            let patterns: Vec<_> = arm
+                .patterns
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect();
+            let patterns = match patterns.as_slice() {
+                [pattern] => pattern.clone(),
+                patterns => format!("({})", patterns.join(", ")),
+            };
+            self.block(&format!("{patterns} => {{"), &arm.body, arm_offset);
+            cursor = self.map.next(self.map.block_end(arm_offset));
+        }
+        self.close("}", end);
+    }
+
+    fn capabilities(&mut self, capabilities: &Capabilities, offset: usize) {
+        let end = self.map.block_end(offset);
+        let ranges = |ranges: &[FrequencyRange]| -> Vec<String> {
+            ranges
+                .iter()
+                .map(|range| {
+                    let modes: Vec<_> = range
+                        .modes
+                        .iter()
+                        .map(|mode| format!("Mode::{mode}"))
+                        .collect();
+                    if modes.is_empty() {
+                        format!("{}..{}", range.min, range.max)
+                    } else {
+                        format!("{}..{}: [{}]", range.min, range.max, modes.join(", "))
+                    }
+                })
+                .collect()
+        };
+        let entries = [
+            ("rx_ranges", Some(ranges(&capabilities.rx_ranges))),
+            ("tx_ranges", Some(ranges(&capabilities.tx_ranges))),
+            (
+                "modes",
+                Some(
+                    capabilities
+                        .modes
+                        .iter()
+                        .map(|mode| format!("Mode::{mode}"))
+                        .collect(),
+                ),
+            ),
+            (
+                "tuning_steps",
+                Some(
+                    capabilities
+                        .tuning_steps
+                        .iter()
+                        .map(i64::to_string)
+                        .collect(),
+                ),
+            ),
+            ("hamlib_model", None),
+            (
+                "hamlib_modes",
+                Some(
+                    capabilities
+                        .hamlib_modes
+                        .iter()
+                        .map(|mode| format!("Mode::{}: {}", mode.mode, mode.hamlib))
+                        .collect(),
+                ),
+            ),
+            (
+                "passbands",
+                Some(
+                    capabilities
+                        .passbands
+                        .iter()
+                        .map(|passbands| {
+                            let widths: Vec<_> =
+                                passbands.widths.iter().map(i64::to_string).collect();
+                            format!("Mode::{}: [{}]", passbands.mode, widths.join(", "))
+                        })
+                        .collect(),
+                ),
+            ),
+        ];
+
+        let mut entries: Vec<_> = entries
+            .into_iter()
+            .filter_map(|(name, list)| {
+                let anchor = self.map.find(offset..end, |tokens| {
+                    matches!(tokens, [Token::Id(id), Token::EqualAssign, ..] if *id == name)
+                })?;
+                Some((anchor, name, list))
+            })
+            .collect();
+        entries.sort_by_key(|(anchor, ..)| *anchor);
+
+        self.open("capabilities {", offset);
+        for (anchor, name, list) in entries {
+            let end = self.map.semicolon(anchor);
+            match list {
+                Some(items) => self.list(name, &items, anchor, end),
+                None => {
+                    if let Some(value) = capabilities.hamlib_model {
+                        self.item(&format!("{name} = {value};"), anchor, end);
+                    }
+                }
+            }
+        }
+        self.close("}", end);
+    }
+
+    fn list(&mut self, name: &str, items: &[String], start: usize, end: usize) {
+        let single = format!("{name} = [{}];", items.join(", "));
+        if self.fits(&single) {
+            self.item(&single, start, end);
+            return;
+        }
+
+        let anchors = self.map.list_items(start);
+        self.open(&format!("{name} = ["), start);
+        for (index, item) in items.iter().enumerate() {
+            match anchors.get(index) {
+                Some(anchor) => self.line(&format!("{item},"), *anchor),
+                None => self.push(&format!("{item},"), self.last_line),
+            }
+        }
+        self.close("];", end);
+    }
+
+    fn test(&mut self, test: &RigTest, offset: usize) {
+        self.open(&format!("test s\"{}\" {{", test.name), offset);
+        for step in &test.steps {
+            self.item(
+                &step.kind.to_string(),
+                step.span.start.offset,
+                step.span.end.offset,
+            );
+        }
+        self.close("}", self.map.block_end(offset));
+    }
+
+    fn schema(&mut self, schema: &SchemaFile) {
+        let version = self
+            .map
+            .find(0..usize::MAX, |tokens| {
+                matches!(tokens, [Token::Id("version"), Token::EqualAssign, ..])
+            })
+            .unwrap_or_default();
+        self.item(
+            &format!("version = {};", schema.version),
+            version,
+            self.map.semicolon(version),
+        );
+
+        let offset = self
+            .map
+            .find(0..usize::MAX, |tokens| {
+                matches!(tokens, [Token::Schema, ..])
+            })
+            .unwrap_or_default();
+        let end = self.map.block_end(offset);
+        let body = offset..end;
+
+        let mut members = Vec::new();
+        for (name, variants) in &schema.enums {
+            let offset = self.find_enum(body.clone(), name);
+            members.push((offset, SchemaMember::Enum(name, variants)));
+        }
+        for (name, command) in &schema.commands {
+            let offset = self
+                .map
+                .find(body.clone(), |tokens| {
+                    matches!(tokens, [Token::Fn, Token::Id(id), ..] if *id == name.as_str())
+                })
+                .unwrap_or(end);
+            members.push((offset, SchemaMember::Command(name, command)));
+        }
+        if let Some(offset) = self.map.find(body.clone(), |tokens| {
+            matches!(tokens, [Token::Status, Token::BraceOpen, ..])
+        }) {
+            members.push((offset, SchemaMember::Status));
+        }
+        members.sort_by_key(|(offset, _)| *offset);
+
+        self.blank();
+        self.open(&format!("schema {} {{", schema.name), offset);
+        let mut previous_block = false;
+        for (offset, member) in members {
+            let is_block = !matches!(member, SchemaMember::Command(..));
+            if previous_block || is_block {
+                self.blank();
+            }
+            previous_block = is_block;
+
+            match member {
+                SchemaMember::Enum(name, variants) => self.schema_enum(name, variants, offset),
+                SchemaMember::Command(name, command) => self.schema_command(name, command, offset),
+                SchemaMember::Status => self.schema_status(schema, offset),
+            }
+        }
+        self.close("}", end);
+    }
+
+    fn schema_enum(&mut self, name: &str, variants: &[String], offset: usize) {
+        let end = self.map.block_end(offset);
+        self.open(&format!("enum {name} {{"), offset);
+        for variant in variants {
+            let anchor = self
+                .map
+                .find(offset..end, |tokens| {
+                    matches!(tokens, [Token::Id(id), Token::Comma | Token::BraceClose, ..] if *id == variant.as_str())
+                })
+                .unwrap_or(end);
+            self.line(&format!("{variant},"), anchor);
+        }
+        self.close("}", end);
+    }
+
+    fn schema_command(&mut self, name: &str, command: &SchemaCommand, offset: usize) {
+        let end = self.map.semicolon(offset);
+        let parameters: Vec<_> = command
+            .parameters
+            .iter()
+            .map(|param| param.to_string())
+            .collect();
+
+        // Comments between the parameters must stay between them, documentation of a
+        // parameter would otherwise turn into documentation of the command
+        let single = format!("fn {name}({});", parameters.join(", "));
+        let has_comments = self
+            .map
+            .comments
+            .iter()
+            .any(|(_, at)| (offset..end).contains(at));
+        if !has_comments && self.fits(&single) {
+            self.item(&single, offset, end);
+            return;
+        }
+
+        self.open(&format!("fn {name}("), offset);
+        for (param, text) in command.parameters.iter().zip(parameters) {
+            let anchor = self
+                .map
+                .find(offset..end, |tokens| {
+                    matches!(
+                        tokens,
+                        [Token::Id(id), Token::Comma | Token::BracketOpen | Token::ParenClose, ..]
+                            if *id == param.name.as_str()
+                    )
+                })
+                .unwrap_or(end);
+            self.line(&format!("{text},"), anchor);
+        }
+        self.close(");", end);
+    }
+
+    fn schema_status(&mut self, schema: &SchemaFile, offset: usize) {
+        let end = self.map.block_end(offset);
+        let mut fields: Vec<_> = schema
+            .status
+            .values()
+            .map(|field| {
+                let anchor = self.map.find(offset..end, |tokens| {
+                    matches!(
+                        tokens,
+                        [Token::Id(id), Token::Semicolon | Token::BracketOpen, ..]
+                            if *id == field.name.as_str()
+                    )
+                });
+                (anchor.unwrap_or(end), format!("{field};"))
+            })
+            .collect();
+        fields.sort();
+
+        self.open("status {", offset);
+        for (anchor, field) in fields {
+            self.line(&field, anchor);
+        }
+        self.close("}", end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RIG: &str = r#"version=1;
+import s"icom.rigmod";
+const OK="FEFEE094FBFD";   // Positive acknowledge
+impl Transceiver for TestRig {
+  fn set_freq(int freq,Vfo target){ // Tune
+      write("fefe94e0.25.{target:1}.{freq:bcd_lu:5}.fd");
+
+
+      // Acknowledge
+      read(OK);
+  }
+  enum Vfo { B = 1, A = 0x0f, }
+  init {
+    if x == 1 { write("00"); } else { if x == 2 { write("01"); } else { write("02"); } }
+    match (a, b) { (Vfo::A, _) => { y = (x + 1) * 2; }, _ => { y = x as int; } }
+  }
+}
+// Trailing comment
+"#;
+
+    const FORMATTED: &str = r#"version = 1;
+
+import s"icom.rigmod";
+
+const OK = "FEFEE094FBFD"; // Positive acknowledge
+
+impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) { // Tune
+        write("FEFE94E0.25.{target:1}.{freq:bcd_lu:5}.FD");
+
+        // Acknowledge
+        read(OK);
+    }
+
+    enum Vfo {
+        B = 1,
+        A = 0x0F,
+    }
+
+    init {
+        if x == 1 {
+            write("00");
+        } else if x == 2 {
+            write("01");
+        } else {
+            write("02");
+        }
+        match (a, b) {
+            (Vfo::A, _) => {
+                y = (x + 1) * 2;
+            }
+            _ => {
+                y = x as int;
+            }
+        }
+    }
+}
+// Trailing comment
+"#;
+
+    #[test]
+    fn test_format_rig_file() -> anyhow::Result<()> {
+        let formatted = format_rig_file(RIG)?;
+        assert_eq!(formatted, FORMATTED);
+        assert_eq!(format_rig_file(&formatted)?, formatted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_schema() -> anyhow::Result<()> {
+        let source = "version = 1;\n/// Test schema.\nschema Test {\n  status { int b; /// Doc of a.\n bool a; }\n  /// Tune.\n  fn set_freq(\n  /// Target frequency.\n  int<1..10> freq [unit = s\"Hz\"], Vfo vfo [default = Vfo::A]);\n  enum Vfo { A, B }\n}\n";
+        let formatted = format_schema(source)?;
+        assert_eq!(
+            formatted,
+            "version = 1;\n\n/// Test schema.\nschema Test {\n    status {\n        int b;\n        /// Doc of a.\n        bool a;\n    }\n\n    /// Tune.\n    fn set_freq(\n        /// Target frequency.\n        int<1..10> freq [unit = s\"Hz\"],\n        Vfo vfo [default = Vfo::A],\n    );\n\n    enum Vfo {\n        A,\n        B,\n    }\n}\n"
+        );
+        assert_eq!(format_schema(&formatted)?, formatted);
+
+        let schema = parse_schema(&formatted)?;
+        assert_eq!(
+            schema.commands["set_freq"].parameters[0].doc.as_deref(),
+            Some("Target frequency.")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_repository_files_are_formatted() -> anyhow::Result<()> {
+        let rig = std::fs::read_to_string("../rigs/IC-7300.rig")?;
+        let module = std::fs::read_to_string("../rigs/icom_civ.rigmod")?;
+        let schema = std::fs::read_to_string("../schema/transceiver.schema")?;
+
+        assert_eq!(format_rig_file(&rig)?, rig);
+        assert_eq!(format_rig_module(&module)?, module);
+        assert_eq!(format_schema(&schema)?, schema);
+
+        for path in ["../schema/rotator.schema", "../schema/amplifier.schema"] {
+            let schema = std::fs::read_to_string(path)?;
+            assert_eq!(format_schema(&schema)?, schema, "{path}");
+        }
+        let rotator = std::fs::read_to_string("../rotators/GS-232A.rig")?;
+        assert_eq!(format_rig_file(&rotator)?, rotator);
+        Ok(())
+    }
+}
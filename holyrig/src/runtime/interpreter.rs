@@ -1,10 +1,16 @@
 use anyhow::{Context, Result, anyhow, bail};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::sync::Arc;
 
+use super::dry_run::{DryRunApi, Transcript};
 use super::parser::{
-    BinaryOp, DataType, Expr, Id, InterpolationPart, RigFile, Statement, parse_atomic_expr,
+    BinaryOp, DataType, Expr, InterpolationPart, Pattern, RigFile, RigTest, Statement,
+    StatementKind, TestStepKind, parse_atomic_expr,
 };
+use super::rig_test::{Exchange, ScriptedApi};
+use super::runtime_errors::{RuntimeError, RuntimeErrorKind, locate, with_source};
+use super::tracer::{TraceEvent, Tracer, hex};
 use crate::{data_format::DataFormat, runtime::parser::Enum};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -33,11 +39,191 @@ impl From<&Value> for serde_json::Value {
         match value {
             Value::Integer(integer) => (*integer).into(),
             Value::Float(float) => (*float).into(),
-            Value::Bytes(_) => todo!(),
+            Value::Bytes(bytes) => hex(bytes).into(),
             Value::String(string) => string.clone().into(),
             Value::Boolean(boolean) => (*boolean).into(),
             Value::EnumVariant { variant_name, .. } => variant_name.as_str().into(),
-            Value::Unit => todo!(),
+            Value::Unit => serde_json::Value::Null,
+        }
+    }
+}
+
+impl TryFrom<&serde_json::Value> for Value {
+    type Error = ValueError;
+
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Null => Ok(Value::Unit),
+            serde_json::Value::Bool(boolean) => Ok(Value::Boolean(*boolean)),
+            serde_json::Value::Number(number) => {
+                if let Some(integer) = number.as_i64() {
+                    Ok(Value::Integer(integer))
+                } else if let Some(float) = number.as_f64() {
+                    Ok(Value::Float(float))
+                } else {
+                    Err(ValueError::OutOfRange {
+                        value: number.to_string(),
+                        min: i64::MIN,
+                        max: i64::MAX,
+                    })
+                }
+            }
+            serde_json::Value::String(string) => Ok(Value::String(string.clone())),
+            serde_json::Value::Array(_) => Err(ValueError::Unsupported {
+                found: "array".to_string(),
+            }),
+            serde_json::Value::Object(_) => Err(ValueError::Unsupported {
+                found: "object".to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    WrongType {
+        expected: DataType,
+        found: String,
+    },
+    OutOfRange {
+        value: String,
+        min: i64,
+        max: i64,
+    },
+    UnknownEnumVariant {
+        enum_name: String,
+        variant_name: String,
+    },
+    InvalidStep {
+        value: i64,
+        step: i64,
+    },
+    Unsupported {
+        found: String,
+    },
+    NotSupported {
+        value: String,
+    },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::WrongType { expected, found } => {
+                write!(f, "Expected {expected}, found {found}")
+            }
+            ValueError::OutOfRange { value, min, max } => {
+                write!(f, "Value {value} is out of range [{min}, {max}]")
+            }
+            ValueError::UnknownEnumVariant {
+                enum_name,
+                variant_name,
+            } => {
+                write!(f, "Unknown variant '{variant_name}' for enum '{enum_name}'")
+            }
+            ValueError::InvalidStep { value, step } => {
+                write!(f, "Value {value} is not a multiple of step {step}")
+            }
+            ValueError::Unsupported { found } => {
+                write!(f, "Unsupported value type: {found}")
+            }
+            ValueError::NotSupported { value } => {
+                write!(f, "Value {value} is not supported by this rig")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+impl Value {
+    fn type_name(&self) -> String {
+        match self {
+            Value::Integer(_) => "int".to_string(),
+            Value::Float(_) => "float".to_string(),
+            Value::Bytes(_) => "bytes".to_string(),
+            Value::String(_) => "string".to_string(),
+            Value::Boolean(_) => "bool".to_string(),
+            Value::EnumVariant { enum_name, .. } => enum_name.clone(),
+            Value::Unit => "null".to_string(),
+        }
+    }
+
+    /// Converts an externally supplied value to the declared parameter type.
+    ///
+    /// Strings are accepted for every type so that text based interfaces (UDP, rigctld) can pass
+    /// their arguments unchanged. Enum variants are resolved using `enums`, which maps every enum
+    /// name to its variants and their numeric values.
+    pub fn coerce(
+        self,
+        data_type: &DataType,
+        enums: &HashMap<String, HashMap<String, u32>>,
+    ) -> Result<Value, ValueError> {
+        let wrong_type = |value: &Value| ValueError::WrongType {
+            expected: data_type.clone(),
+            found: value.type_name(),
+        };
+
+        match (data_type, self) {
+            (DataType::Int, Value::Integer(integer)) => {
+                if i32::try_from(integer).is_ok() {
+                    Ok(Value::Integer(integer))
+                } else {
+                    Err(ValueError::OutOfRange {
+                        value: integer.to_string(),
+                        min: i32::MIN.into(),
+                        max: i32::MAX.into(),
+                    })
+                }
+            }
+            (DataType::Int, Value::Float(float)) if float.fract() == 0.0 => {
+                Value::Integer(float as i64).coerce(data_type, enums)
+            }
+            (DataType::Int, Value::String(string)) => match parse_atomic_expr(string.trim()) {
+                Ok(Expr::Integer(integer)) => Value::Integer(integer).coerce(data_type, enums),
+                Ok(Expr::Float(float)) => Value::Float(float).coerce(data_type, enums),
+                _ => Err(wrong_type(&Value::String(string))),
+            },
+            (DataType::Float, Value::Float(float)) => Ok(Value::Float(float)),
+            (DataType::Float, Value::Integer(integer)) => Ok(Value::Float(integer as f64)),
+            (DataType::Float, Value::String(string)) => string
+                .trim()
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| wrong_type(&Value::String(string))),
+            (DataType::Bool, Value::Boolean(boolean)) => Ok(Value::Boolean(boolean)),
+            (DataType::Bool, Value::Integer(integer @ (0 | 1))) => Ok(Value::Boolean(integer == 1)),
+            (DataType::Bool, Value::String(string)) => match string.trim() {
+                "true" | "1" => Ok(Value::Boolean(true)),
+                "false" | "0" => Ok(Value::Boolean(false)),
+                _ => Err(wrong_type(&Value::String(string))),
+            },
+            (DataType::Enum(enum_name), Value::String(variant_name))
+            | (
+                DataType::Enum(enum_name),
+                Value::EnumVariant {
+                    variant_name,
+                    enum_name: _,
+                    value: _,
+                },
+            ) => {
+                let value = enums
+                    .get(enum_name)
+                    .and_then(|variants| variants.get(variant_name.trim()))
+                    .copied()
+                    .ok_or_else(|| ValueError::UnknownEnumVariant {
+                        enum_name: enum_name.clone(),
+                        variant_name: variant_name.clone(),
+                    })?;
+                Ok(Value::EnumVariant {
+                    enum_name: enum_name.clone(),
+                    variant_name: variant_name.trim().to_string(),
+                    value,
+                })
+            }
+            (DataType::Bytes, Value::Bytes(bytes)) => Ok(Value::Bytes(bytes)),
+            (DataType::String, Value::String(string)) => Ok(Value::String(string)),
+            (_, value) => Err(wrong_type(&value)),
         }
     }
 }
@@ -131,6 +317,21 @@ impl Env {
             })
     }
 
+    /// All visible variables, inner scopes shadowing outer ones.
+    pub fn bindings(&self) -> BTreeMap<String, Value> {
+        let mut bindings = self
+            .parent
+            .as_ref()
+            .map(|parent| parent.bindings())
+            .unwrap_or_default();
+        bindings.extend(
+            self.variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        bindings
+    }
+
     pub fn register_enum(&mut self, enum_def: &Enum) {
         self.enums.insert(
             enum_def.name.clone(),
@@ -143,16 +344,46 @@ pub trait ExternalApi: Send + Sync {
     fn write(&self, data: &[u8]) -> impl Future<Output = Result<()>> + Send;
     fn read(&self, size: usize) -> impl Future<Output = Result<Vec<u8>>> + Send;
     fn set_var(&self, var: &str, value: Value) -> Result<()>;
+
+    /// Reads a response the rig file describes byte by byte, `None` marks bytes the rig fills
+    /// in. Rigs only care about the length, dry runs use it to synthesize replies.
+    fn read_expected(
+        &self,
+        expected: &[Option<u8>],
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send {
+        self.read(expected.len())
+    }
 }
 
 #[derive(Clone)]
 pub struct Interpreter {
     rig_file: RigFile,
+    tracer: Option<Arc<dyn Tracer>>,
 }
 
 impl Interpreter {
     pub fn new(rig_file: RigFile) -> Self {
-        Self { rig_file }
+        Self {
+            rig_file,
+            tracer: None,
+        }
+    }
+
+    pub fn with_tracer(mut self, tracer: Arc<dyn Tracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Arc<dyn Tracer>>) {
+        self.tracer = tracer;
+    }
+
+    async fn trace(&self, event: impl FnOnce() -> TraceEvent) {
+        if let Some(tracer) = &self.tracer
+            && tracer.is_enabled()
+        {
+            tracer.trace(event()).await;
+        }
     }
 
     pub fn rig_file(&self) -> &RigFile {
@@ -162,13 +393,22 @@ impl Interpreter {
     pub fn create_env(&self) -> Result<Env> {
         let mut env = Env::new();
 
+        for enum_def in &self.rig_file.impl_block.enums {
+            env.register_enum(enum_def);
+        }
+
         for (id, expr) in &self.rig_file.settings.settings {
-            let value = self.evaluate_expression(expr, &mut env)?;
+            let value = self
+                .evaluate_expression(expr, &mut env)
+                .map_err(|err| with_source(err, &self.rig_file.source))?;
             env.set(id.to_string(), value);
         }
 
-        for enum_def in &self.rig_file.impl_block.enums {
-            env.register_enum(enum_def);
+        for constant in &self.rig_file.constants {
+            let value = self
+                .evaluate_expression(&constant.expr, &mut env)
+                .map_err(|err| with_source(locate(err, &constant.span), &self.rig_file.source))?;
+            env.set(constant.id.to_string(), value);
         }
 
         Ok(env)
@@ -203,7 +443,8 @@ impl Interpreter {
 
         for statement in &command.statements {
             self.execute_statement(statement, api, &mut local_env)
-                .await?;
+                .await
+                .map_err(|err| with_source(err, &self.rig_file.source))?;
         }
 
         Ok(())
@@ -212,7 +453,9 @@ impl Interpreter {
     pub async fn execute_init_with_env(&self, api: &impl ExternalApi, env: &mut Env) -> Result<()> {
         if let Some(init) = &self.rig_file.impl_block.init {
             for statement in &init.statements {
-                self.execute_statement(statement, api, env).await?;
+                self.execute_statement(statement, api, env)
+                    .await
+                    .map_err(|err| with_source(err, &self.rig_file.source))?;
             }
         }
         Ok(())
@@ -225,7 +468,9 @@ impl Interpreter {
     ) -> Result<()> {
         if let Some(status) = &self.rig_file.impl_block.status {
             for statement in &status.statements {
-                self.execute_statement(statement, api, env).await?;
+                self.execute_statement(statement, api, env)
+                    .await
+                    .map_err(|err| with_source(err, &self.rig_file.source))?;
             }
         }
         Ok(())
@@ -241,23 +486,44 @@ impl Interpreter {
         match name {
             "read" => {
                 match args {
-                    [Expr::StringInterpolation { parts }] => {
-                        let expected_length = parts
+                    [expr] if !expr.read_templates().is_empty() => {
+                        let parts = self.template_parts(expr, env)?;
+                        let expected: Vec<_> = parts
                             .iter()
-                            .map(|part| match part {
-                                InterpolationPart::Literal(bytes) => bytes.len(),
-                                InterpolationPart::Variable { length, .. } => *length,
+                            .flat_map(|part| match part {
+                                InterpolationPart::Literal(bytes) => {
+                                    bytes.iter().copied().map(Some).collect()
+                                }
+                                InterpolationPart::Variable { length, .. } => vec![None; *length],
                             })
-                            .sum();
+                            .collect();
 
-                        let response = api.read(expected_length).await?;
+                        let response = api.read_expected(&expected).await?;
+                        self.trace(|| TraceEvent::Read(response.clone())).await;
 
-                        parse_response_with_template(parts, &response, env)?;
+                        parse_response_with_template(&parts, &response, env).map_err(|err| {
+                            match expr.span() {
+                                Some(span) => locate(err, span),
+                                None => err,
+                            }
+                        })?;
                     }
-                    [Expr::Bytes(bytes)] => {
-                        let response = api.read(bytes.len()).await?;
-                        if &response != bytes {
-                            bail!("Got invalid response: {response:?}");
+                    [expr] => {
+                        let Value::Bytes(bytes) = self.evaluate_expression(expr, env)? else {
+                            bail!("Expected template string in parse, got: {args:?}");
+                        };
+                        let expected: Vec<_> = bytes.iter().copied().map(Some).collect();
+                        let response = api.read_expected(&expected).await?;
+                        self.trace(|| TraceEvent::Read(response.clone())).await;
+                        if response != bytes {
+                            let error = RuntimeError::new(RuntimeErrorKind::UnexpectedResponse {
+                                expected: bytes,
+                                found: response,
+                            });
+                            return Err(match expr.span() {
+                                Some(span) => locate(error.into(), span),
+                                None => error.into(),
+                            });
                         }
                     }
                     _ => {
@@ -276,6 +542,7 @@ impl Interpreter {
                     bail!("Expected one bytes argument in write, got: {args:?}");
                 };
                 api.write(bytes).await?;
+                self.trace(|| TraceEvent::Write(bytes.clone())).await;
                 Ok(())
             }
             "set_var" => {
@@ -287,6 +554,11 @@ impl Interpreter {
                 let [Value::String(var), value] = &args[..] else {
                     bail!("Expected string and value arguments in set_var, got: {args:?}");
                 };
+                self.trace(|| TraceEvent::Evaluate {
+                    expr: var.clone(),
+                    value: value.clone(),
+                })
+                .await;
 
                 api.set_var(var, value.clone())?;
                 Ok(())
@@ -300,21 +572,52 @@ impl Interpreter {
         statement: &Statement,
         api: &impl ExternalApi,
         env: &mut Env,
+    ) -> Result<()> {
+        self.trace(|| TraceEvent::Statement {
+            statement: statement.to_string(),
+            position: statement
+                .span
+                .is_known()
+                .then(|| statement.span.start.clone()),
+            bindings: env.bindings(),
+        })
+        .await;
+
+        self.execute_statement_kind(&statement.kind, api, env)
+            .await
+            .map_err(|err| locate(err, &statement.span))
+    }
+
+    async fn execute_statement_kind(
+        &self,
+        statement: &StatementKind,
+        api: &impl ExternalApi,
+        env: &mut Env,
     ) -> Result<()> {
         match statement {
-            Statement::Assign(id, expr) => {
+            StatementKind::Assign(id, expr) => {
                 let value = self.evaluate_expression(expr, env)?;
+                self.trace(|| TraceEvent::Evaluate {
+                    expr: expr.to_string(),
+                    value: value.clone(),
+                })
+                .await;
                 env.set(id.to_string(), value);
             }
-            Statement::FunctionCall { name, args } => {
+            StatementKind::FunctionCall { name, args } => {
                 self.execute_function_call(name, args, api, env).await?
             }
-            Statement::If {
+            StatementKind::If {
                 condition,
                 then_body,
                 else_body,
             } => {
                 let condition_value = self.evaluate_expression(condition, env)?;
+                self.trace(|| TraceEvent::Evaluate {
+                    expr: condition.to_string(),
+                    value: condition_value.clone(),
+                })
+                .await;
                 match condition_value {
                     Value::Boolean(true) => {
                         for stmt in then_body {
@@ -336,10 +639,74 @@ impl Interpreter {
                     }
                 }
             }
+            StatementKind::Match { exprs, arms } => {
+                let values = exprs
+                    .iter()
+                    .map(|expr| self.evaluate_expression(expr, env))
+                    .collect::<Result<Vec<_>>>()?;
+                for (expr, value) in exprs.iter().zip(&values) {
+                    self.trace(|| TraceEvent::Evaluate {
+                        expr: expr.to_string(),
+                        value: value.clone(),
+                    })
+                    .await;
+                }
+
+                let arm = arms
+                    .iter()
+                    .find(|arm| {
+                        arm.patterns_for(values.len())
+                            .iter()
+                            .zip(&values)
+                            .all(|(pattern, value)| Self::pattern_matches(pattern, value))
+                    })
+                    .ok_or_else(|| anyhow!("No match arm matches {values:?}"))?;
+
+                for stmt in &arm.body {
+                    Box::pin(self.execute_statement(stmt, api, env)).await?;
+                }
+            }
         }
         Ok(())
     }
 
+    fn pattern_matches(pattern: &Pattern, value: &Value) -> bool {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Integer(expected), Value::Integer(integer)) => expected == integer,
+            (
+                Pattern::EnumVariant(scope, id),
+                Value::EnumVariant {
+                    enum_name,
+                    variant_name,
+                    ..
+                },
+            ) => scope.as_str() == enum_name && id.as_str() == variant_name,
+            _ => false,
+        }
+    }
+
+    /// Parts of a read template, with the bytes joined to its string interpolations evaluated.
+    fn template_parts(&self, expr: &Expr, env: &mut Env) -> Result<Vec<InterpolationPart>> {
+        match expr {
+            Expr::StringInterpolation { parts, .. } => Ok(parts.clone()),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOp::Add,
+                right,
+                ..
+            } if !expr.read_templates().is_empty() => {
+                let mut parts = self.template_parts(left, env)?;
+                parts.extend(self.template_parts(right, env)?);
+                Ok(parts)
+            }
+            expr => match self.evaluate_expression(expr, env)? {
+                Value::Bytes(bytes) => Ok(vec![InterpolationPart::Literal(bytes)]),
+                value => bail!("Expected bytes in read template, got: {value:?}"),
+            },
+        }
+    }
+
     pub fn evaluate_expression(&self, expr: &Expr, env: &mut Env) -> Result<Value> {
         match expr {
             Expr::Integer(i) => Ok(Value::Integer(*i)),
@@ -364,17 +731,27 @@ impl Interpreter {
                     ))
                 }
             }
-            Expr::BinaryOp { left, op, right } => {
+            Expr::BinaryOp {
+                left,
+                op,
+                right,
+                span,
+            } => {
                 let left_val = self.evaluate_expression(left, env)?;
                 let right_val = self.evaluate_expression(right, env)?;
-                Self::apply_binary_op(&left_val, op, &right_val)
+                Self::apply_binary_op(&left_val, op, &right_val).map_err(|err| locate(err, span))
             }
-            Expr::StringInterpolation { parts } => {
-                self.process_parsed_string_interpolation(parts, env)
-            }
-            Expr::Cast { expr, target_type } => {
+            Expr::StringInterpolation { parts, span } => self
+                .process_parsed_string_interpolation(parts, env)
+                .map_err(|err| locate(err, span)),
+            Expr::Cast {
+                expr,
+                target_type,
+                span,
+            } => {
                 let value = self.evaluate_expression(expr, env)?;
                 self.apply_cast(&value, target_type, env)
+                    .map_err(|err| locate(err, span))
             }
         }
     }
@@ -385,20 +762,12 @@ impl Interpreter {
                 BinaryOp::Add => Ok(Value::Integer(a + b)),
                 BinaryOp::Subtract => Ok(Value::Integer(a - b)),
                 BinaryOp::Multiply => Ok(Value::Integer(a * b)),
-                BinaryOp::Divide => {
-                    if *b == 0 {
-                        Err(anyhow!("Division by zero"))
-                    } else {
-                        Ok(Value::Integer(a / b))
-                    }
-                }
-                BinaryOp::Modulo => {
-                    if *b == 0 {
-                        Err(anyhow!("Modulo by zero"))
-                    } else {
-                        Ok(Value::Integer(a % b))
-                    }
-                }
+                BinaryOp::Divide | BinaryOp::Modulo if *b == 0 => Err(RuntimeError::new(
+                    RuntimeErrorKind::DivisionByZero { op: op.clone() },
+                )
+                .into()),
+                BinaryOp::Divide => Ok(Value::Integer(a / b)),
+                BinaryOp::Modulo => Ok(Value::Integer(a % b)),
                 BinaryOp::Equal => Ok(Value::Boolean(a == b)),
                 BinaryOp::NotEqual => Ok(Value::Boolean(a != b)),
                 BinaryOp::Less => Ok(Value::Boolean(a < b)),
@@ -539,7 +908,11 @@ impl Interpreter {
                         value: *i as u32,
                     })
                 } else {
-                    Err(anyhow!("Invalid enum value: {} for enum {}", i, enum_name))
+                    Err(RuntimeError::new(RuntimeErrorKind::InvalidEnumValue {
+                        enum_name: enum_name.clone(),
+                        value: *i,
+                    })
+                    .into())
                 }
             }
             (Value::Float(f), DataType::Int) => Ok(Value::Integer(*f as i64)),
@@ -556,8 +929,7 @@ impl Interpreter {
     pub fn eval_external_args(
         &self,
         name: &str,
-        args: HashMap<String, String>,
-        env: &mut Env,
+        args: HashMap<String, Value>,
     ) -> Result<Vec<Value>> {
         let params = &self
             .rig_file
@@ -566,22 +938,21 @@ impl Interpreter {
             .get(name)
             .context("Unknown command")?
             .parameters;
+        let enums = self.rig_file.enum_mappings();
 
         let mut evaluated_args = args
-            .iter()
+            .into_iter()
             .map(|(key, value)| {
                 let param_type = &params
                     .iter()
-                    .find(|param| &param.name == key)
+                    .find(|param| param.name == key)
                     .context(format!("Unknown param: {key} in command {name}"))?
                     .param_type;
 
-                let parsed = if let DataType::Enum(enum_name) = param_type {
-                    Expr::QualifiedIdentifier(Id::new(enum_name), Id::new(value))
-                } else {
-                    parse_atomic_expr(value).map_err(|err| anyhow!(err.to_string()))?
-                };
-                Ok((key.clone(), self.evaluate_expression(&parsed, env)?))
+                let value = value
+                    .coerce(param_type, &enums)
+                    .with_context(|| format!("Invalid parameter {key} in command {name}"))?;
+                Ok((key, value))
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
@@ -613,12 +984,12 @@ impl Interpreter {
     pub async fn execute_command(
         &self,
         command_name: &str,
-        params: HashMap<String, String>,
+        params: HashMap<String, Value>,
         external: &impl ExternalApi,
     ) -> Result<HashMap<String, Value>> {
         let mut env = self.create_env()?;
 
-        let args = self.eval_external_args(command_name, params, &mut self.create_env()?)?;
+        let args = self.eval_external_args(command_name, params)?;
         self.execute_command_with_env(command_name, &args, external, &mut env)
             .await?;
 
@@ -629,6 +1000,147 @@ impl Interpreter {
         let mut env = self.create_env()?;
         self.execute_status_with_env(external, &mut env).await
     }
+
+    /// Runs a command without a rig attached and returns the bytes it would send and receive.
+    /// Reads are answered with `replies` in order and synthesized once they run out.
+    pub async fn dry_run(
+        &self,
+        command_name: &str,
+        params: HashMap<String, Value>,
+        replies: Vec<Vec<u8>>,
+    ) -> Transcript {
+        let api = DryRunApi::new(replies);
+        let result = self
+            .execute_command(command_name, params, &api)
+            .await
+            .map(|_| ());
+        api.into_transcript(result)
+    }
+
+    /// Runs a test block of the rig file against its scripted exchanges. Fails with the first
+    /// step that doesn't hold.
+    pub async fn run_test(&self, test: &RigTest) -> Result<()> {
+        let mut env = self.create_env()?;
+        let api = ScriptedApi::default();
+        let mut steps = test.steps.iter().peekable();
+
+        while let Some(step) = steps.next() {
+            let result: Result<()> = async {
+                match &step.kind {
+                    TestStepKind::Call { command, args } => {
+                        let mut exchanges = Vec::new();
+                        while let Some(next) = steps.peek() {
+                            let exchange = match &next.kind {
+                                TestStepKind::ExpectWrite(data) => {
+                                    Exchange::Write(self.evaluate_bytes(data, &mut env)?)
+                                }
+                                TestStepKind::Reply(data) => {
+                                    Exchange::Reply(self.evaluate_bytes(data, &mut env)?)
+                                }
+                                _ => break,
+                            };
+                            exchanges.push(exchange);
+                            steps.next();
+                        }
+                        api.expect(exchanges);
+
+                        let args = args
+                            .iter()
+                            .map(|arg| self.evaluate_expression(arg, &mut env))
+                            .collect::<Result<Vec<_>>>()?;
+                        self.execute_test_call(command, args, &api, &mut env)
+                            .await?;
+                        api.finish()
+                    }
+                    TestStepKind::ExpectWrite(_) | TestStepKind::Reply(_) => {
+                        bail!("Expected writes and replies must follow a call")
+                    }
+                    TestStepKind::ExpectStatus(fields) => {
+                        for (name, expr) in fields {
+                            let actual = api
+                                .status(name.as_str())
+                                .ok_or_else(|| anyhow!("Status {name} was not set"))?;
+                            let expected =
+                                match (&actual, self.evaluate_expression(expr, &mut env)?) {
+                                    // There are no boolean literals, flags are written as 0 or 1
+                                    (Value::Boolean(_), expected) => {
+                                        expected.coerce(&DataType::Bool, &HashMap::new())?
+                                    }
+                                    (_, expected) => expected,
+                                };
+                            let equal = matches!(
+                                Self::apply_binary_op(&actual, &BinaryOp::Equal, &expected),
+                                Ok(Value::Boolean(true))
+                            );
+                            if !equal && actual != expected {
+                                bail!("Status {name} is {actual}, expected {expected}");
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            .await;
+
+            result.map_err(|err| {
+                anyhow!(
+                    "Step at line {} failed: {}\n{err:#}",
+                    step.span.start.line,
+                    step.kind
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn execute_test_call(
+        &self,
+        command: &str,
+        args: Vec<Value>,
+        api: &ScriptedApi,
+        env: &mut Env,
+    ) -> Result<()> {
+        match command {
+            "init" | "status" if !args.is_empty() => {
+                bail!("'{command}' doesn't take arguments")
+            }
+            "init" => self.execute_init_with_env(api, env).await,
+            "status" => self.execute_status_with_env(api, env).await,
+            _ => {
+                let parameters = &self
+                    .rig_file
+                    .impl_block
+                    .commands
+                    .get(command)
+                    .with_context(|| format!("Unknown command: {command}"))?
+                    .parameters;
+                if args.len() != parameters.len() {
+                    bail!(
+                        "Command '{command}' expects {} arguments, got {}",
+                        parameters.len(),
+                        args.len()
+                    );
+                }
+
+                let params = parameters
+                    .iter()
+                    .map(|param| param.name.clone())
+                    .zip(args)
+                    .collect();
+                let args = self.eval_external_args(command, params)?;
+                self.execute_command_with_env(command, &args, api, env)
+                    .await
+            }
+        }
+    }
+
+    fn evaluate_bytes(&self, expr: &Expr, env: &mut Env) -> Result<Vec<u8>> {
+        match self.evaluate_expression(expr, env)? {
+            Value::Bytes(bytes) => Ok(bytes),
+            value => bail!("Expected bytes, got {value}"),
+        }
+    }
 }
 
 impl Default for Interpreter {
@@ -648,21 +1160,19 @@ fn parse_response_with_template(
         match part {
             InterpolationPart::Literal(expected_bytes) => {
                 if offset + expected_bytes.len() > response.len() {
-                    bail!(
-                        "Response too short: expected {} bytes at offset {}",
-                        expected_bytes.len(),
-                        offset
-                    );
+                    bail!(RuntimeError::new(RuntimeErrorKind::ResponseTooShort {
+                        expected: expected_bytes.len(),
+                        offset,
+                    }));
                 }
 
                 let actual = &response[offset..offset + expected_bytes.len()];
                 if actual != expected_bytes {
-                    bail!(
-                        "Response doesn't match template at offset {}: expected {:?}, got {:?}",
+                    bail!(RuntimeError::new(RuntimeErrorKind::TemplateMismatch {
                         offset,
-                        expected_bytes,
-                        actual
-                    );
+                        expected: expected_bytes.clone(),
+                        found: actual.to_vec(),
+                    }));
                 }
                 offset += expected_bytes.len();
             }
@@ -672,11 +1182,10 @@ fn parse_response_with_template(
                 length,
             } => {
                 if offset + length > response.len() {
-                    bail!(
-                        "Response too short: expected {} bytes at offset {}",
-                        length,
-                        offset
-                    );
+                    bail!(RuntimeError::new(RuntimeErrorKind::ResponseTooShort {
+                        expected: *length,
+                        offset,
+                    }));
                 }
 
                 let bytes = &response[offset..offset + length];
@@ -704,7 +1213,9 @@ mod tests {
     use parking_lot::RwLock;
 
     use super::*;
+    use crate::runtime::dry_run::{TranscriptEntry, parse_hex};
     use crate::runtime::parser::{Id, parse_rig_file};
+    use crate::runtime::parser_errors::Span;
     use std::collections::BTreeMap;
 
     struct DummyExternalApi {
@@ -761,6 +1272,7 @@ mod tests {
             left: Box::new(Expr::Integer(10)),
             op: BinaryOp::Add,
             right: Box::new(Expr::Integer(5)),
+            span: Span::default(),
         };
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
         assert_eq!(result, Value::Integer(15));
@@ -769,6 +1281,7 @@ mod tests {
             left: Box::new(Expr::Integer(10)),
             op: BinaryOp::Greater,
             right: Box::new(Expr::Integer(5)),
+            span: Span::default(),
         };
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
         assert_eq!(result, Value::Boolean(true));
@@ -780,7 +1293,7 @@ mod tests {
         let interpreter = Interpreter::default();
         let mut env = Env::new();
 
-        let statement = Statement::Assign(Id::new("x"), Expr::Integer(42));
+        let statement = StatementKind::Assign(Id::new("x"), Expr::Integer(42)).into();
         interpreter
             .execute_statement(&statement, &DummyExternalApi::new(), &mut env)
             .await?;
@@ -796,10 +1309,11 @@ mod tests {
         let interpreter = Interpreter::default();
         let mut env = Env::new();
 
-        let statement = Statement::FunctionCall {
+        let statement = StatementKind::FunctionCall {
             name: "write".to_string(),
             args: vec![Expr::Bytes(vec![1, 2, 3, 4])],
-        };
+        }
+        .into();
         let api = DummyExternalApi::new();
         interpreter
             .execute_statement(&statement, &api, &mut env)
@@ -841,6 +1355,7 @@ mod tests {
                 },
                 InterpolationPart::Literal(vec![0xFD]),
             ],
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
@@ -863,21 +1378,29 @@ mod tests {
 
         env.set("x".to_string(), Value::Integer(10));
 
-        let statement = Statement::If {
+        let statement = StatementKind::If {
             condition: Expr::BinaryOp {
                 left: Box::new(Expr::Identifier(Id::new("x"))),
                 op: BinaryOp::Greater,
                 right: Box::new(Expr::Integer(5)),
+                span: Span::default(),
             },
-            then_body: vec![Statement::FunctionCall {
-                name: "write".to_string(),
-                args: vec![Expr::Bytes(vec![1])],
-            }],
-            else_body: Some(vec![Statement::FunctionCall {
-                name: "write".to_string(),
-                args: vec![Expr::Bytes(vec![0])],
-            }]),
-        };
+            then_body: vec![
+                StatementKind::FunctionCall {
+                    name: "write".to_string(),
+                    args: vec![Expr::Bytes(vec![1])],
+                }
+                .into(),
+            ],
+            else_body: Some(vec![
+                StatementKind::FunctionCall {
+                    name: "write".to_string(),
+                    args: vec![Expr::Bytes(vec![0])],
+                }
+                .into(),
+            ]),
+        }
+        .into();
 
         let api = DummyExternalApi::new();
         interpreter
@@ -897,6 +1420,7 @@ mod tests {
         let enum_def = Enum {
             name: "Vfo".to_string(),
             variants: BTreeMap::from([("A".to_string(), 0), ("B".to_string(), 1)]),
+            span: Span::default(),
         };
         env.register_enum(&enum_def);
 
@@ -982,6 +1506,7 @@ mod tests {
             left: Box::new(Expr::Integer(10)),
             op: BinaryOp::Divide,
             right: Box::new(Expr::Integer(0)),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env);
@@ -1002,6 +1527,7 @@ mod tests {
             left: Box::new(Expr::Integer(10)),
             op: BinaryOp::Modulo,
             right: Box::new(Expr::Integer(0)),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env);
@@ -1037,16 +1563,20 @@ mod tests {
                     left: Box::new(Expr::Identifier(Id::new("a"))),
                     op: BinaryOp::Add,
                     right: Box::new(Expr::Identifier(Id::new("b"))),
+                    span: Span::default(),
                 }),
                 op: BinaryOp::Multiply,
                 right: Box::new(Expr::BinaryOp {
                     left: Box::new(Expr::Identifier(Id::new("c"))),
                     op: BinaryOp::Subtract,
                     right: Box::new(Expr::Identifier(Id::new("d"))),
+                    span: Span::default(),
                 }),
+                span: Span::default(),
             }),
             op: BinaryOp::Add,
             right: Box::new(Expr::Integer(5)),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
@@ -1070,7 +1600,9 @@ mod tests {
                 left: Box::new(Expr::Identifier(Id::new("b"))),
                 op: BinaryOp::Multiply,
                 right: Box::new(Expr::Identifier(Id::new("c"))),
+                span: Span::default(),
             }),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
@@ -1087,6 +1619,7 @@ mod tests {
             left: Box::new(Expr::Float(3.5)),
             op: BinaryOp::Add,
             right: Box::new(Expr::Integer(2)),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
@@ -1102,35 +1635,46 @@ mod tests {
         let interpreter = Interpreter::default();
         let mut env = Env::new();
 
-        let nested_if = Statement::If {
+        let nested_if = StatementKind::If {
             condition: Expr::BinaryOp {
                 left: Box::new(Expr::Integer(5)),
                 op: BinaryOp::Greater,
                 right: Box::new(Expr::Integer(3)),
+                span: Span::default(),
             },
             then_body: vec![
-                Statement::FunctionCall {
+                StatementKind::FunctionCall {
                     name: "write".to_string(),
                     args: vec![Expr::Bytes(vec![1])],
-                },
-                Statement::If {
+                }
+                .into(),
+                StatementKind::If {
                     condition: Expr::BinaryOp {
                         left: Box::new(Expr::Integer(1)),
                         op: BinaryOp::Equal,
                         right: Box::new(Expr::Integer(1)),
+                        span: Span::default(),
                     },
-                    then_body: vec![Statement::FunctionCall {
-                        name: "write".to_string(),
-                        args: vec![Expr::Bytes(vec![2])],
-                    }],
+                    then_body: vec![
+                        StatementKind::FunctionCall {
+                            name: "write".to_string(),
+                            args: vec![Expr::Bytes(vec![2])],
+                        }
+                        .into(),
+                    ],
                     else_body: None,
-                },
+                }
+                .into(),
             ],
-            else_body: Some(vec![Statement::FunctionCall {
-                name: "write".to_string(),
-                args: vec![Expr::Bytes(vec![3])],
-            }]),
-        };
+            else_body: Some(vec![
+                StatementKind::FunctionCall {
+                    name: "write".to_string(),
+                    args: vec![Expr::Bytes(vec![3])],
+                }
+                .into(),
+            ]),
+        }
+        .into();
 
         let api = DummyExternalApi::new();
         interpreter
@@ -1154,20 +1698,25 @@ mod tests {
                     left: Box::new(Expr::Integer(1)),
                     op: BinaryOp::Equal,
                     right: Box::new(Expr::Integer(1)),
+                    span: Span::default(),
                 }),
                 op: BinaryOp::And,
                 right: Box::new(Expr::BinaryOp {
                     left: Box::new(Expr::Integer(1)),
                     op: BinaryOp::Equal,
                     right: Box::new(Expr::Integer(2)),
+                    span: Span::default(),
                 }),
+                span: Span::default(),
             }),
             op: BinaryOp::Or,
             right: Box::new(Expr::BinaryOp {
                 left: Box::new(Expr::Integer(3)),
                 op: BinaryOp::Greater,
                 right: Box::new(Expr::Integer(2)),
+                span: Span::default(),
             }),
+            span: Span::default(),
         };
 
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
@@ -1180,14 +1729,18 @@ mod tests {
         let interpreter = Interpreter::default();
         let mut env = Env::new();
 
-        let if_stmt = Statement::If {
+        let if_stmt = StatementKind::If {
             condition: Expr::Integer(42),
-            then_body: vec![Statement::FunctionCall {
-                name: "write".to_string(),
-                args: vec![Expr::String("should not execute".to_string())],
-            }],
+            then_body: vec![
+                StatementKind::FunctionCall {
+                    name: "write".to_string(),
+                    args: vec![Expr::String("should not execute".to_string())],
+                }
+                .into(),
+            ],
             else_body: None,
-        };
+        }
+        .into();
 
         let result = interpreter
             .execute_statement(&if_stmt, &DummyExternalApi::new(), &mut env)
@@ -1257,7 +1810,10 @@ mod tests {
                 InterpolationPart::Literal(vec![0xFD]),
             ];
 
-            let expr = Expr::StringInterpolation { parts };
+            let expr = Expr::StringInterpolation {
+                parts,
+                span: Span::default(),
+            };
             let result = interpreter.evaluate_expression(&expr, &mut env);
 
             match result {
@@ -1289,7 +1845,10 @@ mod tests {
             length: 4,
         }];
 
-        let expr = Expr::StringInterpolation { parts };
+        let expr = Expr::StringInterpolation {
+            parts,
+            span: Span::default(),
+        };
         let result = interpreter.evaluate_expression(&expr, &mut env);
 
         assert!(result.is_err());
@@ -1311,7 +1870,10 @@ mod tests {
             length: 0,
         }];
 
-        let expr = Expr::StringInterpolation { parts };
+        let expr = Expr::StringInterpolation {
+            parts,
+            span: Span::default(),
+        };
         let result = interpreter.evaluate_expression(&expr, &mut env);
 
         assert!(result.is_err());
@@ -1333,7 +1895,10 @@ mod tests {
             length: 4,
         }];
 
-        let expr = Expr::StringInterpolation { parts };
+        let expr = Expr::StringInterpolation {
+            parts,
+            span: Span::default(),
+        };
         let result = interpreter.evaluate_expression(&expr, &mut env)?;
 
         match result {
@@ -1440,4 +2005,448 @@ mod tests {
         );
         Ok(())
     }
+
+    fn test_enums() -> HashMap<String, HashMap<String, u32>> {
+        HashMap::from([(
+            "Vfo".to_string(),
+            HashMap::from([("A".to_string(), 0), ("B".to_string(), 1)]),
+        )])
+    }
+
+    #[test]
+    fn test_coerce_external_values() -> Result<()> {
+        let enums = test_enums();
+
+        assert_eq!(
+            Value::String("0x10".to_string()).coerce(&DataType::Int, &enums)?,
+            Value::Integer(16)
+        );
+        assert_eq!(
+            Value::Float(14074000.0).coerce(&DataType::Int, &enums)?,
+            Value::Integer(14074000)
+        );
+        assert_eq!(
+            Value::Integer(1).coerce(&DataType::Bool, &enums)?,
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            Value::String("B".to_string()).coerce(&DataType::Enum("Vfo".to_string()), &enums)?,
+            Value::EnumVariant {
+                enum_name: "Vfo".to_string(),
+                variant_name: "B".to_string(),
+                value: 1,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_coerce_invalid_values() {
+        let enums = test_enums();
+
+        assert!(matches!(
+            Value::Boolean(true).coerce(&DataType::Int, &enums),
+            Err(ValueError::WrongType { .. })
+        ));
+        assert!(matches!(
+            Value::Integer(i64::from(i32::MAX) + 1).coerce(&DataType::Int, &enums),
+            Err(ValueError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            Value::Float(1.5).coerce(&DataType::Int, &enums),
+            Err(ValueError::WrongType { .. })
+        ));
+        assert!(matches!(
+            Value::String("C".to_string()).coerce(&DataType::Enum("Vfo".to_string()), &enums),
+            Err(ValueError::UnknownEnumVariant { .. })
+        ));
+        assert!(matches!(
+            Value::try_from(&serde_json::json!([1, 2])),
+            Err(ValueError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        assert_eq!(
+            serde_json::Value::from(Value::Bytes(vec![0xFE, 0x0A])),
+            serde_json::json!("FE0A")
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::Unit),
+            serde_json::Value::Null
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_with_typed_params() -> Result<()> {
+        let dsl_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_freq(int freq, Vfo target) {
+                    write("FEFE.{target:1}.{freq:bcd_lu:5}.FD");
+                }
+            }
+        "#;
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let api = DummyExternalApi::new();
+        let params = HashMap::from([
+            ("freq".to_string(), Value::Integer(14074000)),
+            ("target".to_string(), Value::String("B".to_string())),
+        ]);
+        interpreter
+            .execute_command("set_freq", params, &api)
+            .await?;
+
+        assert_eq!(
+            api.output.read()[0],
+            "WRITE: [254, 254, 1, 0, 64, 7, 20, 0, 253]"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_execute_match() -> Result<()> {
+        let dsl_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_vfo(Vfo rx, Vfo tx) {
+                    match (rx, tx) {
+                        (Vfo::A, Vfo::A) => { write("00"); }
+                        (Vfo::A, _) => { write("01"); }
+                        _ => {
+                            match rx {
+                                Vfo::B => { write("02"); }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        "#;
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let api = DummyExternalApi::new();
+        for (rx, tx) in [("A", "A"), ("A", "B"), ("B", "A")] {
+            let params = HashMap::from([
+                ("rx".to_string(), Value::String(rx.to_string())),
+                ("tx".to_string(), Value::String(tx.to_string())),
+            ]);
+            interpreter.execute_command("set_vfo", params, &api).await?;
+        }
+
+        assert_eq!(
+            *api.output.read(),
+            vec!["WRITE: [0]", "WRITE: [1]", "WRITE: [2]"]
+        );
+
+        let dsl_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_vfo(Vfo rx, Vfo tx) {
+                    match (rx, tx) { (Vfo::A, Vfo::B) => { write("00"); }, _ => { write("01"); } }
+                }
+            }
+        "#;
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let api = DummyExternalApi::new();
+        for (rx, tx) in [("A", "B"), ("B", "B")] {
+            let params = HashMap::from([
+                ("rx".to_string(), Value::String(rx.to_string())),
+                ("tx".to_string(), Value::String(tx.to_string())),
+            ]);
+            interpreter.execute_command("set_vfo", params, &api).await?;
+        }
+        assert_eq!(*api.output.read(), vec!["WRITE: [0]", "WRITE: [1]"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_constants() -> Result<()> {
+        let dsl_source = r#"
+            const PREFIX = "FEFE94E0";
+            const TARGET = Vfo::B;
+            const COMMAND = 0x07;
+
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn vfo_swap() {
+                    write(PREFIX + "07B0FD");
+                    write("{COMMAND:1}.{TARGET:1}");
+                }
+            }
+        "#;
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let api = DummyExternalApi::new();
+        interpreter
+            .execute_command("vfo_swap", HashMap::new(), &api)
+            .await?;
+
+        assert_eq!(
+            api.output.read()[0],
+            "WRITE: [254, 254, 148, 224, 7, 176, 253]"
+        );
+        assert_eq!(api.output.read()[1], "WRITE: [7, 1]");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_template_with_constant() -> Result<()> {
+        let dsl_source = r#"
+            const OK = "FEFEE094FBFD";
+
+            impl Transceiver for TestRig {
+                fn set_freq(int freq) {
+                    write("FEFE94E0.05.{freq:bcd_lu:5}.FD");
+                    read("FEFE94E005.{echo:bcd_lu:5}.FD" + OK);
+                    set_var(s"echo", echo);
+                }
+            }
+        "#;
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let params = HashMap::from([("freq".to_string(), Value::Integer(14074000))]);
+        let reply = parse_hex("FEFE94E0050040071400FDFEFEE094FBFD")?;
+        let transcript = interpreter
+            .dry_run("set_freq", params.clone(), vec![reply])
+            .await;
+        assert!(transcript.error.is_none());
+        assert_eq!(
+            transcript.entries.last(),
+            Some(&TranscriptEntry::SetVar {
+                name: "echo".to_string(),
+                value: Value::Integer(14074000),
+            })
+        );
+
+        let nak = parse_hex("FEFE94E0050040071400FDFEFEE094FAFD")?;
+        let transcript = interpreter.dry_run("set_freq", params, vec![nak]).await;
+        assert!(transcript.error.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_constants_in_declaration_order() -> Result<()> {
+        let dsl_source = r#"
+            const OFFSET = 1;
+            const ADDRESS = OFFSET + 1;
+            const B = UNKNOWN;
+
+            impl Transceiver for TestRig {
+                fn vfo_swap() {
+                    write("{ADDRESS:1}");
+                }
+            }
+        "#;
+
+        let err = Interpreter::new(parse_rig_file(dsl_source)?)
+            .create_env()
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("UNKNOWN"), "{message}");
+        assert!(message.contains("line 4, column 13"), "{message}");
+
+        let dsl_source = dsl_source.replace("const B = UNKNOWN;", "");
+        let interpreter = Interpreter::new(parse_rig_file(&dsl_source)?);
+        let api = DummyExternalApi::new();
+        interpreter
+            .execute_command("vfo_swap", HashMap::new(), &api)
+            .await?;
+        assert_eq!(api.output.read()[0], "WRITE: [2]");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tracing() -> Result<()> {
+        let dsl_source = r#"
+            impl Transceiver for TestRig {
+                fn set_freq(int freq) {
+                    write("FEFE{freq:bcd_lu:4}FD");
+                }
+            }
+        "#;
+
+        let tracer = Arc::new(crate::runtime::tracer::RecordingTracer::new());
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?).with_tracer(tracer.clone());
+        let api = DummyExternalApi::new();
+        interpreter
+            .execute_command(
+                "set_freq",
+                HashMap::from([("freq".to_string(), Value::Integer(14074000))]),
+                &api,
+            )
+            .await?;
+
+        let events = tracer.events();
+        assert!(matches!(
+            &events[0],
+            TraceEvent::Statement { statement, bindings, .. }
+                if statement.starts_with("write(")
+                    && bindings.get("freq") == Some(&Value::Integer(14074000))
+        ));
+        assert_eq!(
+            events.last(),
+            Some(&TraceEvent::Write(vec![
+                0xFE, 0xFE, 0x00, 0x40, 0x07, 0x14, 0xFD
+            ]))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runtime_error_position() -> Result<()> {
+        let dsl_source = "impl Transceiver for TestRig {\n    enum Vfo {\n        A = 0,\n    }\n    fn set_vfo(int vfo) {\n        target = vfo as Vfo;\n    }\n}\n";
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let api = DummyExternalApi::new();
+        let err = interpreter
+            .execute_command(
+                "set_vfo",
+                HashMap::from([("vfo".to_string(), Value::Integer(3))]),
+                &api,
+            )
+            .await
+            .unwrap_err();
+
+        let err = err.downcast::<RuntimeError>()?;
+        assert!(matches!(
+            err.kind,
+            RuntimeErrorKind::InvalidEnumValue { value: 3, .. }
+        ));
+        let span = err.span.as_ref().unwrap();
+        assert_eq!((span.start.line, span.start.column), (6, 18));
+
+        let rendered = err.to_string();
+        assert!(rendered.starts_with("Runtime error at line 6, column 18:"));
+        assert!(rendered.contains(" → 6 |         target = vfo as Vfo;"));
+        assert!(rendered.contains("^^^^^^^^^^"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() -> Result<()> {
+        let dsl_source = r#"
+            impl Transceiver for TestRig {
+                fn set_freq(int freq) {
+                    write("FEFE94E0.05.{freq:bcd_lu:5}.FD");
+                    read("FEFEE094FBFD");
+                }
+                status {
+                    write("FEFE94E003FD");
+                    read("FEFEE09403.{freq:bcd_lu:5}.FD");
+                    set_var(s"freq", freq);
+                }
+            }
+        "#;
+
+        let interpreter = Interpreter::new(parse_rig_file(dsl_source)?);
+        let params = HashMap::from([("freq".to_string(), Value::Integer(14074000))]);
+        let transcript = interpreter
+            .dry_run("set_freq", params.clone(), vec![])
+            .await;
+        assert!(transcript.error.is_none());
+        assert_eq!(
+            transcript.entries,
+            vec![
+                TranscriptEntry::Write(vec![
+                    0xFE, 0xFE, 0x94, 0xE0, 0x05, 0x00, 0x40, 0x07, 0x14, 0x00, 0xFD
+                ]),
+                TranscriptEntry::Read {
+                    data: vec![0xFE, 0xFE, 0xE0, 0x94, 0xFB, 0xFD],
+                    synthesized: true,
+                },
+            ]
+        );
+
+        let nak = vec![0xFE, 0xFE, 0xE0, 0x94, 0xFA, 0xFD];
+        let transcript = interpreter.dry_run("set_freq", params, vec![nak]).await;
+        assert_eq!(transcript.entries.len(), 2);
+        assert!(transcript.error.is_some());
+
+        let api = DryRunApi::new(vec![]);
+        interpreter.execute_status(&api).await?;
+        let transcript = api.into_transcript(Ok(()));
+        assert_eq!(
+            transcript.entries.last(),
+            Some(&TranscriptEntry::SetVar {
+                name: "freq".to_string(),
+                value: Value::Integer(0),
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_test() -> Result<()> {
+        let dsl_source = r#"
+            const OK = "FEFEE094FBFD";
+
+            impl Transceiver for TestRig {
+                fn set_freq(int freq) {
+                    write("FEFE94E0.05.{freq:bcd_lu:5}.FD");
+                    read(OK);
+                }
+                status {
+                    write("FEFE94E003FD");
+                    read("FEFEE09403.{freq:bcd_lu:5}.FD");
+                    set_var(s"freq", freq);
+                }
+                test s"set_freq" {
+                    call set_freq(14074000);
+                    expect_write("FEFE94E0.05.0040071400.FD");
+                    reply(OK);
+                    call status();
+                    expect_write("FEFE94E003FD");
+                    reply("FEFEE09403.0040071400.FD");
+                    expect_status(freq = 14074000);
+                }
+                test s"wrong_write" {
+                    call set_freq(7074000);
+                    expect_write("FEFE94E0.05.0040071400.FD");
+                    reply(OK);
+                }
+                test s"missing_reply" {
+                    call set_freq(14074000);
+                    expect_write("FEFE94E0.05.0040071400.FD");
+                }
+                test s"wrong_status" {
+                    call status();
+                    expect_write("FEFE94E003FD");
+                    reply("FEFEE09403.0040070700.FD");
+                    expect_status(freq = 14074000);
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(dsl_source)?;
+        let tests = rig_file.impl_block.tests.clone();
+        let interpreter = Interpreter::new(rig_file);
+
+        interpreter.run_test(&tests[0]).await?;
+
+        let err = interpreter.run_test(&tests[1]).await.unwrap_err();
+        assert!(format!("{err:#}").contains("line 24"));
+        assert!(format!("{err:#}").contains("expected FEFE94E0050040071400FD"));
+
+        let err = interpreter.run_test(&tests[2]).await.unwrap_err();
+        assert!(format!("{err:#}").contains("no reply is scripted"));
+
+        let err = interpreter.run_test(&tests[3]).await.unwrap_err();
+        assert!(format!("{err:#}").contains("Status freq is 7074000, expected 14074000"));
+        Ok(())
+    }
 }
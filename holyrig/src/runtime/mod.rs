@@ -1,14 +1,36 @@
+mod diagnostics;
+mod dry_run;
+mod formatter;
 mod interpreter;
 mod parser;
 mod parser_errors;
+mod rig_test;
+mod runtime_errors;
 mod schema_parser;
 mod semantic_analyzer;
+mod tracer;
 
-pub use interpreter::{Env, ExternalApi, Interpreter, Value};
-pub use parser::RigFile;
-pub use parser::parse_rig_file;
-pub use schema_parser::{SchemaFile, parse_schema};
+pub use diagnostics::{Diagnostic, Severity, diagnostics_to_json, diagnostics_to_sarif};
+pub use dry_run::{DryRunApi, Transcript, TranscriptEntry, parse_hex};
+pub use formatter::{format_rig_file, format_rig_module, format_schema};
+pub use interpreter::{Env, ExternalApi, Interpreter, Value, ValueError};
+pub use parser::{
+    Capabilities, Const, DataType, FrequencyRange, HamlibMode, Import, ImportError, Passbands,
+    RigFile, RigModule, RigStatus, RigTest, TestStep, TestStepKind,
+};
+pub use parser::{
+    Token, module_loader, parse_rig_file, parse_rig_file_with_recovery, parse_rig_module,
+    parse_rig_module_with_recovery, parse_with_level, read_module,
+};
+pub use parser_errors::{ErrorLevel, ParseError, Recovered, SourcePosition, Span};
+pub use runtime_errors::{RuntimeError, RuntimeErrorKind};
+pub use schema_parser::{
+    ArgumentError, SchemaCommand, SchemaFile, SchemaParameter, parse_schema,
+    parse_schema_with_recovery,
+};
 pub use semantic_analyzer::{
-    SemanticAnalyzer, SemanticError, parse_and_validate_with_schema,
+    SemanticAnalyzer, SemanticError, SemanticWarning, filter_cascading_errors,
+    parse_and_check_with_imports, parse_and_validate_with_imports, parse_and_validate_with_schema,
     semantic_errors_to_parse_errors,
 };
+pub use tracer::{ChannelTracer, Debugger, RecordingTracer, TraceEvent, Tracer};
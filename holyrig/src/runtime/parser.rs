@@ -1,14 +1,19 @@
 use std::{
-    collections::{BTreeMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
+    path::{Component, Path},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, bail};
 use logos::Logos;
+use serde::Serialize;
 
+use super::interpreter::{Value, ValueError};
 use super::parser_errors::{
-    ErrorLevel, ParseError, ParseErrorType, SourcePosition, calculate_position,
+    ErrorLevel, ParseError, ParseErrorType, Recovered, SourcePosition, Span, calculate_position,
 };
+use super::schema_parser::{SchemaCommand, SchemaParameter};
 
 #[derive(Logos, Debug, Copy, Clone)]
 #[logos(skip r"[ \t\f]+")]
@@ -43,6 +48,12 @@ pub enum Token<'source> {
     Status,
     #[token("if")]
     If,
+    #[token("match")]
+    Match,
+    #[token("const")]
+    Const,
+    #[token("import")]
+    Import,
     #[token("else")]
     Else,
     #[token("int")]
@@ -53,6 +64,10 @@ pub enum Token<'source> {
     BraceOpen,
     #[token("}")]
     BraceClose,
+    #[token("[")]
+    BracketOpen,
+    #[token("]")]
+    BracketClose,
     #[token("(")]
     ParenOpen,
     #[token(")")]
@@ -61,6 +76,8 @@ pub enum Token<'source> {
     EqualAssign,
     #[token("==")]
     Equal,
+    #[token("=>")]
+    FatArrow,
     #[token("!=")]
     NotEqual,
     #[token("<=")]
@@ -95,8 +112,10 @@ pub enum Token<'source> {
     DoubleColon,
     #[token("\n")]
     NewLine,
-    #[regex(r"//[^\n]*\n")]
-    Comment,
+    #[regex(r"//[^\n]*", |lex| lex.slice())]
+    Comment(&'source str),
+    #[regex(r"///[^\n]*\n", |lex| lex.slice(), priority = 10)]
+    DocComment(&'source str),
     #[token("as")]
     As,
 }
@@ -205,6 +224,63 @@ impl From<Token<'_>> for BinaryOp {
     }
 }
 
+impl BinaryOp {
+    /// Binding strength as in the expression grammar, higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or | BinaryOp::And => 0,
+            BinaryOp::Equal | BinaryOp::NotEqual => 1,
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 2,
+            BinaryOp::Add | BinaryOp::Subtract => 3,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 4,
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl Display for InterpolationPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpolationPart::Literal(bytes) => {
+                for byte in bytes {
+                    write!(f, "{byte:02X}")?;
+                }
+                Ok(())
+            }
+            InterpolationPart::Variable {
+                name,
+                format: Some(format),
+                length,
+            } => write!(f, "{{{name}:{format}:{length}}}"),
+            InterpolationPart::Variable {
+                name,
+                format: None,
+                length,
+            } => write!(f, "{{{name}:{length}}}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Integer(i64),
@@ -217,28 +293,176 @@ pub enum Expr {
         left: Box<Expr>,
         op: BinaryOp,
         right: Box<Expr>,
+        span: Span,
     },
     StringInterpolation {
         parts: Vec<InterpolationPart>,
+        span: Span,
     },
     Cast {
         expr: Box<Expr>,
         target_type: DataType,
+        span: Span,
     },
 }
 
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Integer(integer) => write!(f, "{integer}"),
+            Expr::Float(float) => write!(f, "{float:?}"),
+            Expr::String(string) => write!(f, "s\"{string}\""),
+            Expr::Bytes(bytes) => {
+                write!(f, "\"")?;
+                for byte in bytes {
+                    write!(f, "{byte:02X}")?;
+                }
+                write!(f, "\"")
+            }
+            Expr::Identifier(id) => write!(f, "{id}"),
+            Expr::QualifiedIdentifier(scope, id) => write!(f, "{scope}::{id}"),
+            Expr::BinaryOp {
+                left, op, right, ..
+            } => {
+                // Operators are left associative, a right operand of equal precedence keeps
+                // its parentheses
+                for (index, operand) in [left, right].into_iter().enumerate() {
+                    if index == 1 {
+                        write!(f, " {op} ")?;
+                    }
+                    match &**operand {
+                        Expr::BinaryOp { op: inner, .. }
+                            if inner.precedence() < op.precedence()
+                                || (index == 1 && inner.precedence() == op.precedence()) =>
+                        {
+                            write!(f, "({operand})")?
+                        }
+                        _ => write!(f, "{operand}")?,
+                    }
+                }
+                Ok(())
+            }
+            Expr::StringInterpolation { parts, .. } => {
+                write!(f, "\"")?;
+                for (index, part) in parts.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ".")?;
+                    }
+                    write!(f, "{part}")?;
+                }
+                write!(f, "\"")
+            }
+            Expr::Cast {
+                expr, target_type, ..
+            } if matches!(**expr, Expr::BinaryOp { .. }) => write!(f, "({expr}) as {target_type}"),
+            Expr::Cast {
+                expr, target_type, ..
+            } => write!(f, "{expr} as {target_type}"),
+        }
+    }
+}
+
 impl Expr {
     fn binary_op(a: Expr, op: Token<'_>, b: Expr) -> Self {
         Expr::BinaryOp {
             left: Box::new(a),
             op: op.into(),
             right: Box::new(b),
+            span: Span::default(),
+        }
+    }
+
+    /// Source region of the expression, only known for expressions that can fail at runtime.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Expr::BinaryOp { span, .. }
+            | Expr::StringInterpolation { span, .. }
+            | Expr::Cast { span, .. } => Some(span).filter(|span| span.is_known()),
+            _ => None,
+        }
+    }
+
+    /// Templates of a read, the string interpolations joined to other bytes with `+`. Empty when
+    /// the expression is not a template.
+    pub fn read_templates(&self) -> Vec<&[InterpolationPart]> {
+        match self {
+            Expr::StringInterpolation { parts, .. } => vec![parts],
+            Expr::BinaryOp {
+                left,
+                op: BinaryOp::Add,
+                right,
+                ..
+            } => {
+                let mut templates = left.read_templates();
+                templates.extend(right.read_templates());
+                templates
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn with_span(mut self, new_span: Span) -> Self {
+        if let Expr::BinaryOp { span, .. }
+        | Expr::StringInterpolation { span, .. }
+        | Expr::Cast { span, .. } = &mut self
+        {
+            *span = new_span;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Integer(i64),
+    EnumVariant(Id, Id),
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Integer(integer) => write!(f, "{integer}"),
+            Pattern::EnumVariant(scope, id) => write!(f, "{scope}::{id}"),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub struct MatchArm {
+    /// One pattern per matched expression.
+    pub patterns: Vec<Pattern>,
+    pub body: Vec<Statement>,
+}
+
+impl MatchArm {
+    /// Patterns of the arm for `arity` matched expressions, a lone `_` matching every position.
+    pub fn patterns_for(&self, arity: usize) -> Cow<'_, [Pattern]> {
+        match self.patterns.as_slice() {
+            [Pattern::Wildcard] => Cow::Owned(vec![Pattern::Wildcard; arity]),
+            patterns => Cow::Borrowed(patterns),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Self {
+            kind,
+            span: Span::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
     Assign(Id, Expr),
     FunctionCall {
         name: String,
@@ -249,6 +473,38 @@ pub enum Statement {
         then_body: Vec<Statement>,
         else_body: Option<Vec<Statement>>,
     },
+    Match {
+        exprs: Vec<Expr>,
+        arms: Vec<MatchArm>,
+    },
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Renders the statement on a single line, nested bodies are elided.
+impl Display for StatementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |exprs: &[Expr]| {
+            exprs
+                .iter()
+                .map(|expr| expr.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            StatementKind::Assign(id, expr) => write!(f, "{id} = {expr};"),
+            StatementKind::FunctionCall { name, args } => write!(f, "{name}({});", join(args)),
+            StatementKind::If { condition, .. } => write!(f, "if {condition} {{ ... }}"),
+            StatementKind::Match { exprs, .. } if exprs.len() == 1 => {
+                write!(f, "match {} {{ ... }}", exprs[0])
+            }
+            StatementKind::Match { exprs, .. } => write!(f, "match ({}) {{ ... }}", join(exprs)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -260,6 +516,8 @@ pub struct Init {
 pub struct Enum {
     pub name: String,
     pub variants: BTreeMap<String, u32>,
+    /// Location of the name, unknown for enums imported from a module.
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -303,6 +561,8 @@ pub struct Command {
     pub name: String,
     pub parameters: Vec<Parameter>,
     pub statements: Vec<Statement>,
+    /// Location of the name.
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -310,12 +570,206 @@ pub struct Status {
     pub statements: Vec<Statement>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrequencyRange {
+    pub min: i64,
+    pub max: i64,
+    /// Modes available in this range, empty when every mode of the rig is.
+    pub modes: Vec<String>,
+}
+
+impl FrequencyRange {
+    pub fn contains(&self, freq: i64) -> bool {
+        (self.min..=self.max).contains(&freq)
+    }
+}
+
+/// Name of a mode in hamlib, when it differs from the default mapping of rigctld.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HamlibMode {
+    pub mode: String,
+    pub hamlib: String,
+}
+
+/// Filter widths of a mode in Hz, the first one is the normal passband.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Passbands {
+    pub mode: String,
+    pub widths: Vec<i64>,
+}
+
+/// Last status of a rig, with the schema fields declaring it.
+#[derive(Debug, Clone, Copy)]
+pub struct RigStatus<'a> {
+    pub fields: &'a BTreeMap<String, SchemaParameter>,
+    pub values: &'a HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Capabilities {
+    pub rx_ranges: Vec<FrequencyRange>,
+    pub tx_ranges: Vec<FrequencyRange>,
+    pub modes: Vec<String>,
+    pub tuning_steps: Vec<i64>,
+    /// Model number of the rig in hamlib, reported by rigctld.
+    pub hamlib_model: Option<i64>,
+    pub hamlib_modes: Vec<HamlibMode>,
+    pub passbands: Vec<Passbands>,
+    /// Enums named by the mode references, the semantic analyzer requires `Mode`.
+    #[serde(skip)]
+    pub mode_enums: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn can_receive(&self, freq: i64) -> bool {
+        self.rx_ranges.is_empty() || self.rx_ranges.iter().any(|range| range.contains(freq))
+    }
+
+    /// Whether the rig can transmit on `freq`, in `mode` when it is known.
+    pub fn can_transmit(&self, freq: i64, mode: Option<&str>) -> bool {
+        self.tx_ranges.iter().any(|range| {
+            range.contains(freq)
+                && (range.modes.is_empty()
+                    || mode.is_none_or(|mode| range.modes.iter().any(|m| m == mode)))
+        })
+    }
+
+    pub fn supports_mode(&self, mode: &str) -> bool {
+        self.modes.is_empty() || self.modes.iter().any(|m| m == mode)
+    }
+
+    /// Whether the transmitter can be keyed in the last status of the rig. The status doesn't
+    /// tell which VFO transmits, so keying is allowed while any of its `rf` frequencies lies
+    /// within the transmit ranges in the reported mode.
+    fn can_key(&self, status: RigStatus) -> bool {
+        let mut mode = None;
+        let mut freqs = Vec::new();
+        for (name, field) in status.fields {
+            match (&field.param_type, status.values.get(name)) {
+                (DataType::Int, Some(Value::Integer(freq))) if field.rf => freqs.push(*freq),
+                (DataType::Enum(enum_name), Some(Value::EnumVariant { variant_name, .. }))
+                    if self.mode_enums.contains(enum_name) =>
+                {
+                    mode = Some(variant_name.as_str());
+                }
+                _ => {}
+            }
+        }
+        self.tx_ranges.is_empty()
+            || freqs.is_empty()
+            || freqs.iter().any(|freq| self.can_transmit(*freq, mode))
+    }
+
+    /// Rejects arguments of a schema command the rig cannot handle: `rf` parameters outside of
+    /// the receive ranges, modes the rig does not support and `tx` parameters keying the
+    /// transmitter outside of the transmit ranges of the last `status`, when it is known.
+    pub fn check_args(
+        &self,
+        command: &SchemaCommand,
+        args: &HashMap<String, Value>,
+        status: Option<RigStatus>,
+    ) -> Result<(), BTreeMap<String, ValueError>> {
+        let mut errors = BTreeMap::new();
+        for param in &command.parameters {
+            let Some(value) = args.get(&param.name) else {
+                continue;
+            };
+            let supported = match (&param.param_type, value) {
+                (DataType::Int, Value::Integer(freq)) if param.rf => self.can_receive(*freq),
+                (DataType::Enum(enum_name), Value::EnumVariant { variant_name, .. })
+                    if self.mode_enums.contains(enum_name) =>
+                {
+                    self.supports_mode(variant_name)
+                }
+                (DataType::Bool, Value::Boolean(true)) if param.tx => {
+                    status.is_none_or(|status| self.can_key(status))
+                }
+                _ => true,
+            };
+            if !supported {
+                errors.insert(
+                    param.name.clone(),
+                    ValueError::NotSupported {
+                        value: value.to_string(),
+                    },
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Entry of a capabilities block, the mode lists carry the enum name of each reference.
+enum CapabilityEntry {
+    RxRanges(Vec<(FrequencyRange, Vec<String>)>),
+    TxRanges(Vec<(FrequencyRange, Vec<String>)>),
+    Modes(Vec<(String, String)>),
+    TuningSteps(Vec<i64>),
+    HamlibModel(i64),
+    HamlibModes(Vec<(String, HamlibMode)>),
+    Passbands(Vec<(String, Passbands)>),
+}
+
+#[derive(Debug, Clone)]
+pub enum TestStepKind {
+    /// Runs a command, `init` or `status` with positional arguments.
+    Call {
+        command: String,
+        args: Vec<Expr>,
+    },
+    ExpectWrite(Expr),
+    Reply(Expr),
+    ExpectStatus(Vec<(Id, Expr)>),
+}
+
+impl Display for TestStepKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestStepKind::Call { command, args } => {
+                let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "call {command}({});", args.join(", "))
+            }
+            TestStepKind::ExpectWrite(data) => write!(f, "expect_write({data});"),
+            TestStepKind::Reply(data) => write!(f, "reply({data});"),
+            TestStepKind::ExpectStatus(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{name} = {value}"))
+                    .collect();
+                write!(f, "expect_status({});", fields.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestStep {
+    pub kind: TestStepKind,
+    pub span: Span,
+}
+
+/// Protocol test vector of a rig file. The writes and replies following a call describe the
+/// exchange with the rig during that call, in order.
+#[derive(Debug, Clone)]
+pub struct RigTest {
+    pub name: String,
+    pub steps: Vec<TestStep>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub enum Member {
     Enum(Enum),
     Init(Init),
     Command(Command),
     Status(Status),
+    Capabilities(Capabilities),
+    Test(RigTest),
 }
 
 #[derive(Debug, Clone)]
@@ -326,6 +780,8 @@ pub struct Impl {
     pub status: Option<Status>,
     pub commands: BTreeMap<String, Command>,
     pub enums: Vec<Enum>,
+    pub capabilities: Option<Capabilities>,
+    pub tests: Vec<RigTest>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -335,31 +791,182 @@ pub struct Settings {
 
 #[derive(Debug, Clone)]
 pub struct RigFile {
+    /// Text the rig file was parsed from, used to point runtime errors at their source.
+    pub source: String,
     pub settings: Settings,
+    pub imports: Vec<Import>,
+    /// Constants in declaration order, those imported first, each one may use the previous ones.
+    pub constants: Vec<Const>,
     pub impl_block: Impl,
 }
 
+/// Shared declarations imported by rig files, usually stored in `.rigmod` files.
+#[derive(Debug, Clone, Default)]
+pub struct RigModule {
+    pub imports: Vec<Import>,
+    pub constants: Vec<Const>,
+    pub enums: Vec<Enum>,
+}
+
+/// `import` declaration, of a path relative to the directory of the rig file.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    pub span: Span,
+}
+
+/// Failure to resolve an import of a rig file.
+#[derive(Debug)]
+pub struct ImportError {
+    /// Location of the import of the rig file the failing module was reached from.
+    pub span: Span,
+    pub error: anyhow::Error,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// `const` declaration.
+#[derive(Debug, Clone)]
+pub struct Const {
+    pub id: Id,
+    pub expr: Expr,
+    /// Location of the declaration, unknown for constants imported from a module.
+    pub span: Span,
+}
+
+enum ModuleItem {
+    Import(Import),
+    Const(Const),
+    Enum(Enum),
+}
+
 impl RigFile {
+    /// Status fields set anywhere in the status block, including inside `if` and `match`.
     pub fn get_supported_status_fields(&self) -> HashSet<String> {
+        fn collect(statements: &[Statement], fields: &mut HashSet<String>) {
+            for stmt in statements {
+                match &stmt.kind {
+                    StatementKind::FunctionCall { name, args } if name == "set_var" => {
+                        if let Some(Expr::String(var_name)) = args.first() {
+                            fields.insert(var_name.clone());
+                        }
+                    }
+                    StatementKind::If {
+                        then_body,
+                        else_body,
+                        ..
+                    } => {
+                        collect(then_body, fields);
+                        collect(else_body.as_deref().unwrap_or_default(), fields);
+                    }
+                    StatementKind::Match { arms, .. } => {
+                        for arm in arms {
+                            collect(&arm.body, fields);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         let mut implemented_status = HashSet::new();
         if let Some(status) = &self.impl_block.status {
-            for stmt in &status.statements {
-                if let Statement::FunctionCall { name, args } = stmt
-                    && name == "set_var"
-                    && let Some(Expr::String(var_name)) = args.first()
+            collect(&status.statements, &mut implemented_status);
+        }
+        implemented_status
+    }
+
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.impl_block.capabilities.as_ref()
+    }
+
+    /// Value of a constant.
+    pub fn constant(&self, id: &str) -> Option<&Expr> {
+        self.constants
+            .iter()
+            .find(|constant| constant.id.as_str() == id)
+            .map(|constant| &constant.expr)
+    }
+
+    /// Loads every imported module through `load` and merges its constants and enums into the
+    /// rig file. Declarations of the importing file take precedence over imported ones.
+    /// Imported constants come before those of the file, a module after the ones it imports.
+    pub fn resolve_imports(
+        &mut self,
+        mut load: impl FnMut(&str) -> Result<String>,
+    ) -> Result<(), ImportError> {
+        let mut visited = HashSet::new();
+        let mut modules = Vec::new();
+        for import in &self.imports {
+            load_module(&import.path, &mut load, &mut visited, &mut modules).map_err(|error| {
+                ImportError {
+                    span: import.span.clone(),
+                    error,
+                }
+            })?;
+        }
+
+        let mut constants: Vec<Const> = Vec::new();
+        for module in modules {
+            for constant in module.constants {
+                let declared = |constants: &[Const]| {
+                    constants.iter().any(|existing| existing.id == constant.id)
+                };
+                if !declared(&self.constants) && !declared(&constants) {
+                    // The span points into the module, not into the rig file
+                    constants.push(Const {
+                        span: Span::default(),
+                        ..constant
+                    });
+                }
+            }
+            for enum_def in module.enums {
+                if !self
+                    .impl_block
+                    .enums
+                    .iter()
+                    .any(|existing| existing.name == enum_def.name)
                 {
-                    implemented_status.insert(var_name.clone());
+                    self.impl_block.enums.push(Enum {
+                        span: Span::default(),
+                        ..enum_def
+                    });
                 }
             }
         }
-        implemented_status
+        constants.append(&mut self.constants);
+        self.constants = constants;
+
+        Ok(())
+    }
+
+    pub fn enum_mappings(&self) -> HashMap<String, HashMap<String, u32>> {
+        self.impl_block
+            .enums
+            .iter()
+            .map(|enum_def| {
+                (
+                    enum_def.name.clone(),
+                    enum_def.variants.clone().into_iter().collect(),
+                )
+            })
+            .collect()
     }
 }
 
 impl Default for RigFile {
     fn default() -> Self {
         Self {
+            source: String::new(),
             settings: Default::default(),
+            imports: vec![],
+            constants: Vec::new(),
             impl_block: Impl {
                 schema: String::new(),
                 name: String::new(),
@@ -367,28 +974,69 @@ impl Default for RigFile {
                 status: None,
                 commands: BTreeMap::new(),
                 enums: vec![],
+                capabilities: None,
+                tests: vec![],
             },
         }
     }
 }
 
+/// Module loader for [`RigFile::resolve_imports`], reading the modules imported by a rig file
+/// of `dir` through `read`.
+pub fn module_loader(
+    dir: &Path,
+    mut read: impl FnMut(&Path) -> Result<String>,
+) -> impl FnMut(&str) -> Result<String> {
+    move |import| read(&dir.join(import))
+}
+
+/// Reads a module from the file system, for [`module_loader`].
+pub fn read_module(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Parses a module and, before it, the modules it imports that were not visited yet.
+fn load_module(
+    path: &str,
+    load: &mut impl FnMut(&str) -> Result<String>,
+    visited: &mut HashSet<String>,
+    modules: &mut Vec<RigModule>,
+) -> Result<()> {
+    // Modules are shared by the rigs of a directory and must not reach outside of it
+    let inside = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside {
+        bail!("Import path '{path}' must be relative and stay in the directory of the rig file");
+    }
+    if !visited.insert(path.to_string()) {
+        return Ok(());
+    }
+
+    let source = load(path).with_context(|| format!("Failed to load module '{path}'"))?;
+    let module = parse_rig_module(&source)
+        .map_err(|err| anyhow!("Failed to parse module '{path}':\n{err}"))?;
+    for import in &module.imports {
+        load_module(&import.path, load, visited, modules)?;
+    }
+    modules.push(module);
+    Ok(())
+}
+
 peg::parser! {
-    pub grammar rig<'source>() for [Token<'source>] {
+    pub grammar rig<'source>(spans: &[Span]) for [Token<'source>] {
         rule settings() -> Settings
             = assigns:assign()* {
                 Settings {
-                    settings: assigns
-                        .into_iter()
-                        .map(|statement| {
-                            match statement {
-                                Statement::Assign(id, expr) => (id, expr),
-                                _ => panic!("Expected assign statement in settings"),
-                            }
-                        })
-                        .collect()
+                    settings: assigns.into_iter().collect()
                 }
             }
 
+        rule spanned<T>(r: rule<T>) -> (T, Span)
+            = start:position!() value:r() end:position!() {
+                (value, span_between(spans, start, end))
+            }
+
         rule integer() -> i64
             = [Token::Integer(num)] {?
                 num.parse::<i64>().or(Err("Invalid integer"))
@@ -410,12 +1058,14 @@ peg::parser! {
             }
 
         rule enum_member() -> Member
-            = [Token::Enum] [Token::Id(name)] [Token::BraceOpen]
+            = [Token::Enum] name:spanned(<[Token::Id(name)] { name }>) [Token::BraceOpen]
               variants:(enum_variant() ** [Token::Comma]) [Token::Comma]?
               [Token::BraceClose] {
+                let (name, span) = name;
                 Member::Enum(Enum {
                     name: name.to_string(),
                     variants: variants.into_iter().collect(),
+                    span,
                 })
             }
 
@@ -433,54 +1083,92 @@ peg::parser! {
             }
 
         rule statement() -> Statement
-            = if_statement() / function_call_stmt() / var_assign_statement()
+            = statement:spanned(<
+                if_statement() / match_statement() / function_call_stmt() / var_assign_statement()
+              >) {
+                let (kind, span) = statement;
+                Statement { kind, span }
+            }
 
-        rule function_call_stmt() -> Statement
+        rule function_call_stmt() -> StatementKind
             = [Token::Id(name)] [Token::ParenOpen]
               args:(expr() ** [Token::Comma]) [Token::Comma]?
               [Token::ParenClose] [Token::Semicolon] {
-                Statement::FunctionCall {
+                StatementKind::FunctionCall {
                     name: name.to_string(),
                     args
                 }
             }
 
-        rule var_assign_statement() -> Statement
+        rule var_assign_statement() -> StatementKind
             = [Token::Id(var)] [Token::EqualAssign] expr:expr() [Token::Semicolon] {
-                Statement::Assign(Id(var.to_string()), expr)
+                StatementKind::Assign(Id(var.to_string()), expr)
             }
 
-        rule if_statement() -> Statement
+        rule if_statement() -> StatementKind
             = [Token::If] condition:expr() [Token::BraceOpen]
               then_body:statement()*
               [Token::BraceClose]
               else_body:([Token::Else] else_part:(
-                  nested_if:if_statement() { vec![nested_if] } /
+                  nested_if:spanned(<if_statement()>) {
+                      let (kind, span) = nested_if;
+                      vec![Statement { kind, span }]
+                  } /
                   [Token::BraceOpen] body:statement()* [Token::BraceClose] { body }
               ) { else_part })?
             {
-                Statement::If {
+                StatementKind::If {
                     condition,
                     then_body,
                     else_body,
                 }
             }
 
+        rule pattern() -> Pattern
+            = [Token::Id("_")] { Pattern::Wildcard }
+            / [Token::Id(scope)] [Token::DoubleColon] [Token::Id(id)] {
+                Pattern::EnumVariant(scope.into(), id.into())
+            }
+            / negative:[Token::Minus]? integer:integer() {
+                Pattern::Integer(if negative.is_some() { -integer } else { integer })
+            }
+
+        rule match_arm() -> MatchArm
+            = patterns:(
+                [Token::ParenOpen] patterns:(pattern() ++ [Token::Comma]) [Token::ParenClose] { patterns }
+                / pattern:pattern() { vec![pattern] }
+              )
+              [Token::FatArrow] [Token::BraceOpen] body:statement()* [Token::BraceClose] [Token::Comma]? {
+                MatchArm { patterns, body }
+            }
+
+        rule match_statement() -> StatementKind
+            = [Token::Match]
+              exprs:(
+                  [Token::ParenOpen] exprs:(expr() ++ [Token::Comma]) [Token::ParenClose] { exprs }
+                  / expr:expr() { vec![expr] }
+              )
+              [Token::BraceOpen] arms:match_arm()* [Token::BraceClose] {
+                StatementKind::Match { exprs, arms }
+            }
+
         rule init() -> Member
             = [Token::Init] [Token::BraceOpen] statements:statement()* [Token::BraceClose] {
                 Member::Init(Init { statements })
             }
 
         rule command() -> Member
-            = [Token::Fn] [Token::Id(name)] [Token::ParenOpen]
+            = [Token::Fn] name:spanned(<[Token::Id(name)] { name }>) [Token::ParenOpen]
               params:(parameter() ** [Token::Comma]) [Token::Comma]?
               [Token::ParenClose] [Token::BraceOpen]
               statements:statement()*
               [Token::BraceClose] {
+                let (name, span) = name;
                 Member::Command(Command {
                     name: name.to_string(),
                     parameters: params,
                     statements,
+                    span,
                 })
             }
 
@@ -489,8 +1177,154 @@ peg::parser! {
                 Member::Status(Status { statements })
             }
 
+        rule mode() -> (String, String)
+            = [Token::Id(enum_name)] [Token::DoubleColon] [Token::Id(variant)] {
+                (enum_name.to_string(), variant.to_string())
+            }
+
+        rule mode_list() -> Vec<(String, String)>
+            = [Token::BracketOpen] modes:(mode() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                modes
+            }
+
+        rule frequency_range() -> (FrequencyRange, Vec<String>)
+            = min:integer() [Token::Dot] [Token::Dot] max:integer() modes:([Token::Colon] modes:mode_list() { modes })? {
+                let (enums, modes) = modes.unwrap_or_default().into_iter().unzip();
+                (FrequencyRange { min, max, modes }, enums)
+            }
+
+        rule range_list() -> Vec<(FrequencyRange, Vec<String>)>
+            = [Token::BracketOpen] ranges:(frequency_range() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                ranges
+            }
+
+        rule integer_list() -> Vec<i64>
+            = [Token::BracketOpen] integers:(integer() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                integers
+            }
+
+        rule hamlib_mode() -> (String, HamlibMode)
+            = mode:mode() [Token::Colon] [Token::Id(hamlib)] {
+                let (enum_name, mode) = mode;
+                (enum_name, HamlibMode { mode, hamlib: hamlib.to_string() })
+            }
+
+        rule hamlib_mode_list() -> Vec<(String, HamlibMode)>
+            = [Token::BracketOpen] modes:(hamlib_mode() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                modes
+            }
+
+        rule passbands() -> (String, Passbands)
+            = mode:mode() [Token::Colon] widths:integer_list() {
+                let (enum_name, mode) = mode;
+                (enum_name, Passbands { mode, widths })
+            }
+
+        rule passbands_list() -> Vec<(String, Passbands)>
+            = [Token::BracketOpen] passbands:(passbands() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                passbands
+            }
+
+        rule capability_entry() -> CapabilityEntry
+            = entry:(
+                [Token::Id("rx_ranges")] [Token::EqualAssign] ranges:range_list() { CapabilityEntry::RxRanges(ranges) }
+                / [Token::Id("tx_ranges")] [Token::EqualAssign] ranges:range_list() { CapabilityEntry::TxRanges(ranges) }
+                / [Token::Id("modes")] [Token::EqualAssign] modes:mode_list() { CapabilityEntry::Modes(modes) }
+                / [Token::Id("tuning_steps")] [Token::EqualAssign] steps:integer_list() { CapabilityEntry::TuningSteps(steps) }
+                / [Token::Id("hamlib_model")] [Token::EqualAssign] value:integer() { CapabilityEntry::HamlibModel(value) }
+                / [Token::Id("hamlib_modes")] [Token::EqualAssign] modes:hamlib_mode_list() { CapabilityEntry::HamlibModes(modes) }
+                / [Token::Id("passbands")] [Token::EqualAssign] passbands:passbands_list() { CapabilityEntry::Passbands(passbands) }
+              ) [Token::Semicolon] {
+                entry
+            }
+
+        rule capabilities() -> Member
+            = [Token::Id("capabilities")] [Token::BraceOpen] entries:capability_entry()* [Token::BraceClose] {
+                let mut capabilities = Capabilities::default();
+                let mut mode_enums = Vec::new();
+                for entry in entries {
+                    match entry {
+                        CapabilityEntry::RxRanges(ranges) => {
+                            let (ranges, enums): (_, Vec<Vec<_>>) = ranges.into_iter().unzip();
+                            mode_enums.extend(enums.into_iter().flatten());
+                            capabilities.rx_ranges = ranges;
+                        }
+                        CapabilityEntry::TxRanges(ranges) => {
+                            let (ranges, enums): (_, Vec<Vec<_>>) = ranges.into_iter().unzip();
+                            mode_enums.extend(enums.into_iter().flatten());
+                            capabilities.tx_ranges = ranges;
+                        }
+                        CapabilityEntry::Modes(modes) => {
+                            let (enums, modes): (Vec<_>, _) = modes.into_iter().unzip();
+                            mode_enums.extend(enums);
+                            capabilities.modes = modes;
+                        }
+                        CapabilityEntry::TuningSteps(steps) => capabilities.tuning_steps = steps,
+                        CapabilityEntry::HamlibModel(value) => capabilities.hamlib_model = Some(value),
+                        CapabilityEntry::HamlibModes(modes) => {
+                            let (enums, modes): (Vec<_>, _) = modes.into_iter().unzip();
+                            mode_enums.extend(enums);
+                            capabilities.hamlib_modes = modes;
+                        }
+                        CapabilityEntry::Passbands(passbands) => {
+                            let (enums, passbands): (Vec<_>, _) = passbands.into_iter().unzip();
+                            mode_enums.extend(enums);
+                            capabilities.passbands = passbands;
+                        }
+                    }
+                }
+                capabilities.mode_enums = mode_enums;
+                Member::Capabilities(capabilities)
+            }
+
+        rule test_name() -> String
+            = [Token::Bytes(name)] { name[1..name.len() - 1].to_string() }
+            / [Token::Str(name)] { name[2..name.len() - 1].to_string() }
+
+        rule test_call_target() -> String
+            = [Token::Id(name)] { name.to_string() }
+            / [Token::Init] { "init".to_string() }
+            / [Token::Status] { "status".to_string() }
+
+        rule status_expectation() -> (Id, Expr)
+            = [Token::Id(name)] [Token::EqualAssign] value:expr() { (name.into(), value) }
+
+        rule test_step_kind() -> TestStepKind
+            = [Token::Id("call")] command:test_call_target() [Token::ParenOpen]
+              args:(expr() ** [Token::Comma]) [Token::Comma]?
+              [Token::ParenClose] [Token::Semicolon] {
+                TestStepKind::Call { command, args }
+            }
+            / [Token::Id("expect_write")] [Token::ParenOpen] data:expr() [Token::ParenClose] [Token::Semicolon] {
+                TestStepKind::ExpectWrite(data)
+            }
+            / [Token::Id("reply")] [Token::ParenOpen] data:expr() [Token::ParenClose] [Token::Semicolon] {
+                TestStepKind::Reply(data)
+            }
+            / [Token::Id("expect_status")] [Token::ParenOpen]
+              fields:(status_expectation() ++ [Token::Comma]) [Token::Comma]?
+              [Token::ParenClose] [Token::Semicolon] {
+                TestStepKind::ExpectStatus(fields)
+            }
+
+        rule test_step() -> TestStep
+            = step:spanned(<test_step_kind()>) {
+                let (kind, span) = step;
+                TestStep { kind, span }
+            }
+
+        rule test_block() -> Member
+            = test:spanned(<
+                [Token::Id("test")] name:test_name() [Token::BraceOpen] steps:test_step()* [Token::BraceClose] {
+                    (name, steps)
+                }
+              >) {
+                let ((name, steps), span) = test;
+                Member::Test(RigTest { name, steps, span })
+            }
+
         rule member() -> Member
-            = member:(init() / enum_member() / command() / status()) {
+            = member:(init() / enum_member() / command() / status() / capabilities() / test_block()) {
                 member
             }
 
@@ -508,6 +1342,8 @@ peg::parser! {
                 let mut status = None;
                 let mut commands = BTreeMap::new();
                 let mut enums = Vec::new();
+                let mut capabilities = None;
+                let mut tests = Vec::new();
 
                 for member in members {
                     match member {
@@ -517,6 +1353,8 @@ peg::parser! {
                             commands.insert(command.name.clone(), command);
                         },
                         Member::Enum(e) => enums.push(e),
+                        Member::Capabilities(c) => capabilities = Some(c),
+                        Member::Test(test) => tests.push(test),
                     }
                 }
 
@@ -527,19 +1365,72 @@ peg::parser! {
                     status,
                     commands,
                     enums,
+                    capabilities,
+                    tests,
                 }
             }
+        rule import() -> ModuleItem
+            = import:spanned(<[Token::Import] [Token::Str(path)] [Token::Semicolon] { path }>) {
+                let (path, span) = import;
+                ModuleItem::Import(Import {
+                    path: path[2..path.len() - 1].to_string(),
+                    span,
+                })
+            }
+
+        rule const_decl() -> ModuleItem
+            = declaration:spanned(<[Token::Const] [Token::Id(id)] [Token::EqualAssign] expr:expr() [Token::Semicolon] {
+                (Id(id.into()), expr)
+            }>) {
+                let ((id, expr), span) = declaration;
+                ModuleItem::Const(Const { id, expr, span })
+            }
+
+        rule enum_item() -> ModuleItem
+            = member:enum_member() {
+                match member {
+                    Member::Enum(enum_def) => ModuleItem::Enum(enum_def),
+                    _ => unreachable!("enum_member only produces enums"),
+                }
+            }
+
         pub rule rig_file() -> RigFile
-            = settings:settings() impl_block:impl_block() {
+            = settings:settings() items:(import() / const_decl())* impl_block:impl_block() {
+                let mut imports = Vec::new();
+                let mut constants = Vec::new();
+                for item in items {
+                    match item {
+                        ModuleItem::Import(import) => imports.push(import),
+                        ModuleItem::Const(constant) => constants.push(constant),
+                        ModuleItem::Enum(_) => {}
+                    }
+                }
+
                 RigFile {
+                    source: String::new(),
                     settings,
+                    imports,
+                    constants,
                     impl_block,
                 }
             }
 
-        rule assign() -> Statement
+        pub rule rig_module() -> RigModule
+            = items:(import() / const_decl() / enum_item())* {
+                let mut module = RigModule::default();
+                for item in items {
+                    match item {
+                        ModuleItem::Import(import) => module.imports.push(import),
+                        ModuleItem::Const(constant) => module.constants.push(constant),
+                        ModuleItem::Enum(enum_def) => module.enums.push(enum_def),
+                    }
+                }
+                module
+            }
+
+        rule assign() -> (Id, Expr)
             = [Token::Id(id)] [Token::EqualAssign] expr:expr() [Token::Semicolon] {
-                Statement::Assign(Id(id.into()), expr)
+                (Id(id.into()), expr)
             }
 
         pub rule atomic_expr() -> Expr
@@ -554,7 +1445,10 @@ peg::parser! {
 
                 if content.contains('{') && content.contains('}') {
                     let parts = parse_string_interpolation(content)?;
-                    Ok(Expr::StringInterpolation { parts })
+                    Ok(Expr::StringInterpolation {
+                        parts,
+                        span: Span::default(),
+                    })
                 } else {
                     let bytes: Vec<_> = content
                         .as_bytes()
@@ -584,6 +1478,10 @@ peg::parser! {
             }
 
         rule expr() -> Expr = precedence! {
+            start:position!() expr:@ end:position!() {
+                Expr::with_span(expr, span_between(spans, start, end))
+            }
+            --
             a:(@) op:([Token::Or] / [Token::And]) b:@ {
                 Expr::binary_op(a, op, b)
             }
@@ -613,6 +1511,7 @@ peg::parser! {
                 Expr::Cast {
                     expr: Box::new(a),
                     target_type,
+                    span: Span::default(),
                 }
             }
             --
@@ -715,98 +1614,321 @@ fn parse_string_interpolation(template: &str) -> Result<Vec<InterpolationPart>,
     string_interpolation::parse_interpolation(&tokens).map_err(|_| "Parser failed")
 }
 
+/// Span from the token at `start` up to the end of the token before `end`.
+fn span_between(spans: &[Span], start: usize, end: usize) -> Span {
+    match (spans.get(start), spans.get(end.saturating_sub(1))) {
+        (Some(first), Some(last)) if end > start => {
+            Span::new(first.start.clone(), last.end.clone())
+        }
+        (Some(first), _) => Span::new(first.start.clone(), first.start.clone()),
+        _ => Span::default(),
+    }
+}
+
 pub fn parse_atomic_expr(expr: &str) -> Result<Expr, &str> {
     let tokens: Vec<_> = Token::lexer(expr)
         .collect::<Result<_, _>>()
         .map_err(|_| "Lexer failed")?;
-    rig::atomic_expr(&tokens).map_err(|_| "Parsing atomic expr failed")
+    rig::atomic_expr(&tokens, &[]).map_err(|_| "Parsing atomic expr failed")
 }
 
 pub fn parse_rig_file(source: &str) -> Result<RigFile, ParseError> {
     parse_with_level(source, ErrorLevel::Normal)
 }
 
-pub struct TokenWithPosition<'source> {
-    pub token: Token<'source>,
-    pub position: SourcePosition,
+pub fn parse_with_level(source: &str, level: ErrorLevel) -> Result<RigFile, ParseError> {
+    first_error(parse_rig_file_recovering(source, level, false))
 }
 
-pub fn parse_with_level(source: &str, level: ErrorLevel) -> Result<RigFile, ParseError> {
+/// Parses a rig file and recovers from syntax errors at statement and member boundaries, so
+/// every error is reported and the parts that parsed are kept.
+pub fn parse_rig_file_with_recovery(source: &str) -> Recovered<RigFile> {
+    parse_rig_file_recovering(source, ErrorLevel::Normal, true)
+}
+
+fn parse_rig_file_recovering(source: &str, level: ErrorLevel, recover: bool) -> Recovered<RigFile> {
+    let mut recovered = parse_tokens(
+        source,
+        lex(source, level, recover, |token| {
+            !matches!(
+                token,
+                Token::Comment(_) | Token::DocComment(_) | Token::NewLine
+            )
+        }),
+        "rig file",
+        recover,
+        rig::rig_file,
+    );
+    if let Some(rig_file) = &mut recovered.value {
+        rig_file.source = source.to_string();
+    }
+    recovered
+}
+
+pub fn parse_rig_module(source: &str) -> Result<RigModule, ParseError> {
+    first_error(parse_rig_module_recovering(source, false))
+}
+
+/// Parses a rig module like `parse_rig_file_with_recovery`.
+pub fn parse_rig_module_with_recovery(source: &str) -> Recovered<RigModule> {
+    parse_rig_module_recovering(source, true)
+}
+
+fn parse_rig_module_recovering(source: &str, recover: bool) -> Recovered<RigModule> {
+    parse_tokens(
+        source,
+        lex(source, ErrorLevel::Normal, recover, |token| {
+            !matches!(
+                token,
+                Token::Comment(_) | Token::DocComment(_) | Token::NewLine
+            )
+        }),
+        "rig module",
+        recover,
+        rig::rig_module,
+    )
+}
+
+pub(crate) fn first_error<T>(recovered: Recovered<T>) -> Result<T, ParseError> {
+    match (recovered.value, recovered.errors.into_iter().next()) {
+        (Some(value), None) => Ok(value),
+        (_, Some(err)) => Err(err),
+        (None, None) => unreachable!("parsing failed without an error"),
+    }
+}
+
+/// Tokens the grammar runs on, with their spans and the errors of invalid characters.
+pub(crate) struct Lexed<'source> {
+    tokens: Vec<Token<'source>>,
+    spans: Vec<Span>,
+    errors: Vec<ParseError>,
+    level: ErrorLevel,
+}
+
+/// Lexes `source` into the tokens for which `keep` holds. Without `recover` lexing ends at the
+/// first invalid character, with it a run of invalid characters is reported once and skipped.
+pub(crate) fn lex<'source>(
+    source: &'source str,
+    level: ErrorLevel,
+    recover: bool,
+    keep: impl Fn(&Token) -> bool,
+) -> Lexed<'source> {
     let mut lexer = Token::lexer(source);
-    let mut tokens_with_positions = Vec::new();
+    let mut lexed = Lexed {
+        tokens: Vec::new(),
+        spans: Vec::new(),
+        errors: Vec::new(),
+        level,
+    };
+    let mut previous_error_end = None;
 
     while let Some(token_result) = lexer.next() {
+        let span = lexer.span();
         match token_result {
             Ok(token) => {
-                if !matches!(token, Token::Comment | Token::NewLine) {
-                    let span = lexer.span();
-                    let position = calculate_position(source, span.start);
-                    tokens_with_positions.push(TokenWithPosition { token, position });
+                if keep(&token) {
+                    lexed.spans.push(Span::new(
+                        calculate_position(source, span.start),
+                        calculate_position(source, span.end),
+                    ));
+                    lexed.tokens.push(token);
                 }
             }
             Err(_) => {
-                let span = lexer.span();
-                let position = calculate_position(source, span.start);
-                return Err(ParseError {
-                    position,
-                    error_type: Box::new(ParseErrorType::Tokenization {
-                        message: format!("Unable to tokenize input at position {}", span.start),
-                        context: "Invalid character or token".to_string(),
-                    }),
-                    source: source.to_string(),
-                    level,
-                });
+                if previous_error_end != Some(span.start) {
+                    lexed.errors.push(ParseError {
+                        position: calculate_position(source, span.start),
+                        error_type: Box::new(ParseErrorType::Tokenization {
+                            message: format!("Unable to tokenize input at position {}", span.start),
+                            context: "Invalid character or token".to_string(),
+                        }),
+                        source: source.to_string(),
+                        level,
+                    });
+                }
+                previous_error_end = Some(span.end);
+                if !recover {
+                    break;
+                }
             }
         }
     }
+    lexed
+}
 
-    let tokens: Vec<Token> = tokens_with_positions.iter().map(|t| t.token).collect();
-
-    rig::rig_file(&tokens).map_err(|peg_error| {
-        let error_msg = format!("{peg_error}");
-
-        let position = if peg_error.location < tokens_with_positions.len() {
-            tokens_with_positions[peg_error.location].position.clone()
-        } else if !tokens_with_positions.is_empty() {
-            tokens_with_positions[0].position.clone()
-        } else {
-            SourcePosition::new(1, 1, 0)
+/// Most syntax errors reported for a single file, recovery stops after that.
+const MAX_ERRORS: usize = 100;
+
+/// Runs `parse` on the lexed tokens. With `recover`, the tokens of the statement or member a
+/// syntax error is found in are dropped and parsing is repeated until it succeeds.
+pub(crate) fn parse_tokens<'source, T>(
+    source: &str,
+    lexed: Lexed<'source>,
+    description: &str,
+    recover: bool,
+    parse: impl Fn(&[Token<'source>], &[Span]) -> Result<T, peg::error::ParseError<usize>>,
+) -> Recovered<T> {
+    let Lexed {
+        mut tokens,
+        mut spans,
+        mut errors,
+        level,
+    } = lexed;
+    let mut skipped = Vec::new();
+    if !recover && !errors.is_empty() {
+        return Recovered {
+            value: None,
+            errors,
+            skipped,
         };
+    }
 
-        let found = if peg_error.location < tokens_with_positions.len() {
-            Some(format!(
-                "{:?}",
-                tokens_with_positions[peg_error.location].token
-            ))
-        } else {
-            Some("unexpected token".to_string())
+    let value = loop {
+        let peg_error = match parse(&tokens, &spans) {
+            Ok(value) => break Some(value),
+            Err(peg_error) => peg_error,
         };
+        errors.push(syntax_error(
+            source,
+            &tokens,
+            &spans,
+            &peg_error,
+            description,
+            level,
+        ));
+        if !recover || peg_error.location >= tokens.len() || errors.len() >= MAX_ERRORS {
+            break None;
+        }
 
-        let expected = peg_error
-            .expected
-            .tokens()
-            .map(|token| token.to_string())
-            .collect();
+        let range = recovery_range(&tokens, peg_error.location);
+        skipped.push(affected_span(&tokens, &spans, &range));
+        tokens.drain(range.clone());
+        spans.drain(range);
+    };
+
+    errors.sort_by_key(|err| err.position.offset);
+    Recovered {
+        value,
+        errors,
+        skipped,
+    }
+}
 
-        ParseError {
-            position,
-            error_type: Box::new(ParseErrorType::Syntax {
-                expected,
-                found,
-                context: format!("Failed to parse rig file structure. PEG error: {error_msg}"),
-                peg_error: Some(error_msg),
-                user_friendly_message: None,
-            }),
-            source: source.to_string(),
-            level,
+/// Span from the dropped tokens in `range` to the end of the member of the impl block they are
+/// in, the statements after them may rely on what was dropped.
+fn affected_span(tokens: &[Token], spans: &[Span], range: &std::ops::Range<usize>) -> Span {
+    let brace_depth = |depth: i32, token: &Token| match token {
+        Token::BraceOpen => depth + 1,
+        Token::BraceClose => depth - 1,
+        _ => depth,
+    };
+
+    let start = spans[range.start].start.clone();
+    let mut depth = tokens[..range.start].iter().fold(0, brace_depth);
+    if depth >= 2 {
+        for (token, span) in tokens.iter().zip(spans).skip(range.start) {
+            depth = brace_depth(depth, token);
+            if depth < 2 {
+                return Span::new(start, span.end.clone());
+            }
+        }
+    }
+    Span::new(start, spans[range.end - 1].end.clone())
+}
+
+fn syntax_error(
+    source: &str,
+    tokens: &[Token],
+    spans: &[Span],
+    peg_error: &peg::error::ParseError<usize>,
+    description: &str,
+    level: ErrorLevel,
+) -> ParseError {
+    let error_msg = format!("{peg_error}");
+
+    let position = if peg_error.location < spans.len() {
+        spans[peg_error.location].start.clone()
+    } else if let Some(last) = spans.last() {
+        last.end.clone()
+    } else {
+        SourcePosition::new(1, 1, 0)
+    };
+
+    let found = match tokens.get(peg_error.location) {
+        Some(token) => Some(format!("{token:?}")),
+        None => Some("unexpected token".to_string()),
+    };
+
+    let expected = peg_error
+        .expected
+        .tokens()
+        .map(|token| token.to_string())
+        .collect();
+
+    ParseError {
+        position,
+        error_type: Box::new(ParseErrorType::Syntax {
+            expected,
+            found,
+            context: format!("Failed to parse {description} structure. PEG error: {error_msg}"),
+            peg_error: Some(error_msg),
+            user_friendly_message: None,
+        }),
+        source: source.to_string(),
+        level,
+    }
+}
+
+/// Tokens of the statement or member containing the token at `location`: from the end of the
+/// previous one, a `;`, `{` or `}`, up to its own `;` or the end of its block. The closing brace
+/// of the enclosing block is only dropped if it is the only token left.
+fn recovery_range(tokens: &[Token], location: usize) -> std::ops::Range<usize> {
+    let start = tokens[..location]
+        .iter()
+        .rposition(|token| {
+            matches!(
+                token,
+                Token::Semicolon | Token::BraceOpen | Token::BraceClose
+            )
+        })
+        .map_or(0, |index| index + 1);
+
+    let mut end = location;
+    let mut depth = 0;
+    while end < tokens.len() {
+        match tokens[end] {
+            Token::BraceOpen => depth += 1,
+            Token::BraceClose if depth == 0 => break,
+            Token::BraceClose => {
+                depth -= 1;
+                // A block ends the statement unless an else follows
+                if depth == 0 && !matches!(tokens.get(end + 1), Some(Token::Else)) {
+                    end += 1;
+                    break;
+                }
+            }
+            Token::Semicolon if depth == 0 => {
+                end += 1;
+                break;
+            }
+            _ => {}
         }
-    })
+        end += 1;
+    }
+
+    if start == end {
+        location..location + 1
+    } else {
+        start..end
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::bail;
 
+    use crate::runtime::SchemaParameter;
+
     use super::*;
 
     fn create_semantic_error(
@@ -821,6 +1943,7 @@ mod tests {
             error_type: Box::new(ParseErrorType::Semantic {
                 message: message.to_string(),
                 suggestion: suggestion.map(|s| s.to_string()),
+                code: ParseError::RESOLUTION_CODE,
             }),
             source: source.to_string(),
             level: ErrorLevel::Normal,
@@ -1083,7 +2206,7 @@ mod tests {
     #[test]
     fn test_parse_real_ic7300_file() {
         let ic7300_content =
-            std::fs::read_to_string("rigs/IC-7300.rig").expect("Failed to read IC7300.rig");
+            std::fs::read_to_string("../rigs/IC-7300.rig").expect("Failed to read IC7300.rig");
 
         let result = parse_rig_file(&ic7300_content);
         assert!(result.is_ok());
@@ -1094,9 +2217,11 @@ mod tests {
 
         assert!(rig_file.impl_block.commands.len() > 5);
 
-        assert_eq!(rig_file.impl_block.enums.len(), 2);
+        assert_eq!(rig_file.impl_block.enums.len(), 1);
         assert_eq!(rig_file.impl_block.enums[0].name, "Vfo");
-        assert_eq!(rig_file.impl_block.enums[1].name, "Mode");
+        assert_eq!(rig_file.imports[0].path, "icom_civ.rigmod");
+        assert_eq!(rig_file.imports.len(), 1);
+        assert!(rig_file.capabilities().is_some());
     }
 
     #[test]
@@ -1120,8 +2245,8 @@ mod tests {
         assert_eq!(cmd.name, "test_func");
         assert_eq!(cmd.statements.len(), 3);
 
-        match &cmd.statements[0] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[0].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "write");
                 assert_eq!(args.len(), 1);
                 match &args[0] {
@@ -1132,8 +2257,8 @@ mod tests {
             _ => panic!("Expected function call for write"),
         }
 
-        match &cmd.statements[1] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[1].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "read");
                 assert_eq!(args.len(), 1);
                 match &args[0] {
@@ -1144,8 +2269,8 @@ mod tests {
             _ => panic!("Expected function call for read"),
         }
 
-        match &cmd.statements[2] {
-            Statement::Assign(var, _) => {
+        match &cmd.statements[2].kind {
+            StatementKind::Assign(var, _) => {
                 assert_eq!(var.0, "command");
             }
             _ => panic!("Expected variable assignment"),
@@ -1172,8 +2297,8 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test"];
         assert_eq!(cmd.statements.len(), 4);
 
-        match &cmd.statements[0] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "x");
                 match expr {
                     Expr::Integer(n) => assert_eq!(*n, 42),
@@ -1183,8 +2308,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[1] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[1].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "y");
                 match expr {
                     Expr::String(s) => assert_eq!(s, "hello"),
@@ -1194,8 +2319,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[2] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[2].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "z");
                 match expr {
                     Expr::Identifier(id) => assert_eq!(id.0, "identifier"),
@@ -1205,8 +2330,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[3] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[3].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "w");
                 match expr {
                     Expr::BinaryOp {
@@ -1241,8 +2366,8 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test_numbers"];
         assert_eq!(cmd.statements.len(), 4);
 
-        match &cmd.statements[0] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "int_var");
                 match expr {
                     Expr::Integer(n) => assert_eq!(*n, 42),
@@ -1252,8 +2377,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[1] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[1].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "float_var");
                 match expr {
                     Expr::Float(n) => assert_eq!(*n, 3.5),
@@ -1263,8 +2388,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[2] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[2].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "hex_var");
                 match expr {
                     Expr::Integer(n) => assert_eq!(*n, 255),
@@ -1274,8 +2399,8 @@ mod tests {
             _ => panic!("Expected assignment"),
         }
 
-        match &cmd.statements[3] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[3].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "result");
                 match expr {
                     Expr::BinaryOp {
@@ -1322,8 +2447,8 @@ mod tests {
         assert_eq!(arithmetic_cmd.name, "test_arithmetic");
         assert_eq!(arithmetic_cmd.statements.len(), 4);
 
-        match &arithmetic_cmd.statements[0] {
-            Statement::Assign(var, expr) => {
+        match &arithmetic_cmd.statements[0].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.0, "x");
                 match expr {
                     Expr::BinaryOp {
@@ -1340,8 +2465,8 @@ mod tests {
         assert_eq!(comparison_cmd.name, "test_comparisons");
         assert_eq!(comparison_cmd.statements.len(), 1);
 
-        match &comparison_cmd.statements[0] {
-            Statement::If { condition, .. } => match condition {
+        match &comparison_cmd.statements[0].kind {
+            StatementKind::If { condition, .. } => match condition {
                 Expr::BinaryOp {
                     op: BinaryOp::Equal,
                     ..
@@ -1368,11 +2493,11 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test_interpolation"];
         assert_eq!(cmd.statements.len(), 2);
 
-        match &cmd.statements[0] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.as_str(), "command");
                 match expr {
-                    Expr::StringInterpolation { parts } => {
+                    Expr::StringInterpolation { parts, .. } => {
                         assert_eq!(parts.len(), 4);
 
                         match &parts[0] {
@@ -1444,32 +2569,32 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test_func"];
         assert_eq!(cmd.statements.len(), 5);
 
-        match &cmd.statements[0] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[0].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "write");
                 assert_eq!(args.len(), 1);
             }
             _ => panic!("Expected function call"),
         }
 
-        match &cmd.statements[1] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[1].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "read");
                 assert_eq!(args.len(), 1);
             }
             _ => panic!("Expected function call"),
         }
 
-        match &cmd.statements[2] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[2].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "send_command");
                 assert_eq!(args.len(), 2);
             }
             _ => panic!("Expected function call"),
         }
 
-        match &cmd.statements[3] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[3].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "delay");
                 assert_eq!(args.len(), 1);
                 match &args[0] {
@@ -1480,8 +2605,8 @@ mod tests {
             _ => panic!("Expected function call"),
         }
 
-        match &cmd.statements[4] {
-            Statement::FunctionCall { name, args } => {
+        match &cmd.statements[4].kind {
+            StatementKind::FunctionCall { name, args } => {
                 assert_eq!(name, "custom_func");
                 assert_eq!(args.len(), 0);
             }
@@ -1619,8 +2744,8 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test"];
         assert_eq!(cmd.statements.len(), 2);
 
-        match &cmd.statements[0] {
-            Statement::Assign(var, expr) => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(var, expr) => {
                 assert_eq!(var.as_str(), "x");
                 match expr {
                     Expr::Integer(n) => assert_eq!(*n, 0xABCD),
@@ -1648,8 +2773,8 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test"];
         assert_eq!(cmd.statements.len(), 3);
 
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
                 Expr::Float(f) => assert!((f - 4.14159).abs() < 1e-6),
                 _ => panic!("Expected float"),
             },
@@ -1674,8 +2799,8 @@ mod tests {
 
         let rig_file = parse_rig_file(&dsl_source)?;
         let cmd = &rig_file.impl_block.commands["test"];
-        match &cmd.statements[0] {
-            Statement::Assign(var, _) => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(var, _) => {
                 assert_eq!(var.as_str(), long_id);
             }
             _ => panic!("Expected assignment"),
@@ -1733,9 +2858,9 @@ mod tests {
 
         let rig_file = parse_rig_file(dsl_source)?;
         let cmd = &rig_file.impl_block.commands["test"];
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
-                Expr::StringInterpolation { parts } => match &parts[1] {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
+                Expr::StringInterpolation { parts, .. } => match &parts[1] {
                     InterpolationPart::Variable { length, .. } => {
                         assert_eq!(*length, 1000);
                     }
@@ -1760,9 +2885,9 @@ mod tests {
 
         let rig_file = parse_rig_file(dsl_source)?;
         let cmd = &rig_file.impl_block.commands["test"];
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
-                Expr::StringInterpolation { parts } => {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
+                Expr::StringInterpolation { parts, .. } => {
                     assert_eq!(parts.len(), 5);
                     match &parts[0] {
                         InterpolationPart::Literal(bytes) => {
@@ -1797,8 +2922,8 @@ mod tests {
 
         let rig_file = parse_rig_file(dsl_source)?;
         let cmd = &rig_file.impl_block.commands["test"];
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
                 Expr::BinaryOp {
                     op: BinaryOp::Divide,
                     ..
@@ -1829,8 +2954,8 @@ mod tests {
         let cmd = &rig_file.impl_block.commands["test"];
         assert_eq!(cmd.statements.len(), 2);
 
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
                 Expr::QualifiedIdentifier(scope, id) => {
                     assert_eq!(scope.as_str(), "TestEnum");
                     assert_eq!(id.as_str(), "A");
@@ -1854,8 +2979,8 @@ mod tests {
 
         let rig_file = parse_rig_file(dsl_source)?;
         let cmd = &rig_file.impl_block.commands["test"];
-        match &cmd.statements[0] {
-            Statement::Assign(_, expr) => match expr {
+        match &cmd.statements[0].kind {
+            StatementKind::Assign(_, expr) => match expr {
                 Expr::BinaryOp { .. } => {}
                 _ => panic!("Expected binary operation with mixed types"),
             },
@@ -1863,4 +2988,334 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_parse_capabilities() -> Result<()> {
+        let dsl_source = r#"
+            impl Transceiver for Rig {
+                capabilities {
+                    rx_ranges = [30000..74800000];
+                    tx_ranges = [
+                        1800000..2000000,
+                        5351500..5366500: [Mode::USB, Mode::CWU],
+                    ];
+                    modes = [Mode::USB, Mode::LSB, Mode::CWU];
+                    tuning_steps = [1, 10, 100];
+                    hamlib_model = 3073;
+                    hamlib_modes = [Mode::CWL: CWR];
+                    passbands = [Mode::USB: [2400, 1800, 3000], Mode::CWU: [500]];
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(dsl_source)?;
+        let capabilities = rig_file
+            .capabilities()
+            .expect("Capabilities should be parsed");
+
+        assert_eq!(capabilities.rx_ranges.len(), 1);
+        assert_eq!(capabilities.tx_ranges[1].modes, vec!["USB", "CWU"]);
+        assert_eq!(capabilities.tuning_steps, vec![1, 10, 100]);
+        assert_eq!(capabilities.hamlib_model, Some(3073));
+        assert_eq!(
+            capabilities.hamlib_modes,
+            [HamlibMode {
+                mode: "CWL".to_string(),
+                hamlib: "CWR".to_string(),
+            }]
+        );
+        assert_eq!(capabilities.passbands[0].widths, [2400, 1800, 3000]);
+        assert_eq!(capabilities.passbands[1].mode, "CWU");
+        assert!(capabilities.mode_enums.iter().all(|name| name == "Mode"));
+        assert_eq!(capabilities.mode_enums.len(), 8);
+
+        assert!(capabilities.can_receive(14074000));
+        assert!(!capabilities.can_receive(144000000));
+        assert!(capabilities.can_transmit(1850000, Some("LSB")));
+        assert!(!capabilities.can_transmit(5357000, Some("LSB")));
+        assert!(capabilities.can_transmit(5357000, None));
+        assert!(!capabilities.can_transmit(14074000, None));
+        assert!(!capabilities.supports_mode("FM"));
+
+        let command = SchemaCommand {
+            parameters: vec![
+                SchemaParameter {
+                    rf: true,
+                    ..SchemaParameter::new(DataType::Int, "freq")
+                },
+                SchemaParameter::new(DataType::Int, "pitch"),
+                SchemaParameter::new(DataType::Enum("Mode".to_string()), "mode"),
+            ],
+            doc: None,
+        };
+        let errors = capabilities
+            .check_args(
+                &command,
+                &HashMap::from([
+                    ("freq".to_string(), Value::Integer(144000000)),
+                    ("pitch".to_string(), Value::Integer(600)),
+                    (
+                        "mode".to_string(),
+                        Value::EnumVariant {
+                            enum_name: "Mode".to_string(),
+                            variant_name: "FM".to_string(),
+                            value: 5,
+                        },
+                    ),
+                ]),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(errors.keys().collect::<Vec<_>>(), ["freq", "mode"]);
+
+        let transmit = SchemaCommand {
+            parameters: vec![SchemaParameter {
+                tx: true,
+                ..SchemaParameter::new(DataType::Bool, "tx")
+            }],
+            doc: None,
+        };
+        let fields = BTreeMap::from([
+            (
+                "freq_a".to_string(),
+                SchemaParameter {
+                    rf: true,
+                    ..SchemaParameter::new(DataType::Int, "freq_a")
+                },
+            ),
+            (
+                "mode".to_string(),
+                SchemaParameter::new(DataType::Enum("Mode".to_string()), "mode"),
+            ),
+        ]);
+        let key = |freq: i64, mode: &str, tx: bool| {
+            let status = HashMap::from([
+                ("freq_a".to_string(), Value::Integer(freq)),
+                (
+                    "mode".to_string(),
+                    Value::EnumVariant {
+                        enum_name: "Mode".to_string(),
+                        variant_name: mode.to_string(),
+                        value: 0,
+                    },
+                ),
+            ]);
+            capabilities.check_args(
+                &transmit,
+                &HashMap::from([("tx".to_string(), Value::Boolean(tx))]),
+                Some(RigStatus {
+                    fields: &fields,
+                    values: &status,
+                }),
+            )
+        };
+        assert!(key(1850000, "LSB", true).is_ok());
+        assert!(key(5357000, "USB", true).is_ok());
+        assert!(key(5357000, "LSB", true).is_err());
+        assert!(key(14074000, "USB", true).is_err());
+        assert!(key(14074000, "USB", false).is_ok());
+        assert!(
+            capabilities
+                .check_args(
+                    &transmit,
+                    &HashMap::from([("tx".to_string(), Value::Boolean(true))]),
+                    None,
+                )
+                .is_ok()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_imports() -> Result<()> {
+        let dsl_source = r#"
+            version = 1;
+
+            import s"common.rigmod";
+
+            const OK = "FB";
+
+            impl Transceiver for Rig {
+                enum Vfo {
+                    A = 0,
+                }
+            }
+        "#;
+
+        let modules = HashMap::from([
+            (
+                "common.rigmod",
+                r#"
+                    import s"vendor.rigmod";
+                    const OK = "FA";
+                    const END = "FD";
+                    enum Vfo {
+                        A = 1,
+                    }
+                "#,
+            ),
+            (
+                "vendor.rigmod",
+                r#"
+                    import s"common.rigmod";
+                    enum Mode {
+                        USB = 1,
+                    }
+                "#,
+            ),
+        ]);
+
+        let mut rig_file = parse_rig_file(dsl_source)?;
+        rig_file.resolve_imports(|path| {
+            modules
+                .get(path)
+                .map(|source| source.to_string())
+                .ok_or_else(|| anyhow!("Module not found"))
+        })?;
+
+        assert_eq!(rig_file.constant("OK"), Some(&Expr::Bytes(vec![0xFB])));
+        assert_eq!(rig_file.constant("END"), Some(&Expr::Bytes(vec![0xFD])));
+        assert_eq!(rig_file.impl_block.enums.len(), 2);
+        assert_eq!(rig_file.impl_block.enums[0].variants["A"], 0);
+        assert_eq!(rig_file.impl_block.enums[1].name, "Mode");
+
+        let mut rig_file = parse_rig_file(dsl_source)?;
+        let err = rig_file
+            .resolve_imports(|_| Err(anyhow!("Module not found")))
+            .unwrap_err();
+        assert_eq!(err.span.start.line, 4);
+
+        for path in [
+            "/etc/common.rigmod",
+            "../common.rigmod",
+            "modules/../../x.rigmod",
+        ] {
+            let mut rig_file = parse_rig_file(&dsl_source.replace("common.rigmod", path))?;
+            let err = rig_file.resolve_imports(|_| Ok(String::new())).unwrap_err();
+            assert_eq!((err.span.start.line, err.span.start.column), (4, 13));
+            assert!(err.to_string().contains("must be relative"), "{err}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spans() -> Result<()> {
+        let dsl_source = "impl Test for Rig {\n    fn test() {\n        x = (1 + 2) / 0;\n        if x > 1 {\n            write(\"00\");\n        }\n    }\n}\n";
+
+        let rig_file = parse_rig_file(dsl_source)?;
+        let cmd = &rig_file.impl_block.commands["test"];
+
+        let assign = &cmd.statements[0];
+        assert_eq!((assign.span.start.line, assign.span.start.column), (3, 9));
+        assert_eq!((assign.span.end.line, assign.span.end.column), (3, 25));
+        let StatementKind::Assign(_, expr) = &assign.kind else {
+            bail!("Expected assignment");
+        };
+        let span = expr.span().context("Expected span on binary operation")?;
+        assert_eq!((span.start.column, span.end.column), (13, 24));
+
+        let if_statement = &cmd.statements[1];
+        assert_eq!(if_statement.span.start.line, 4);
+        assert_eq!(if_statement.span.end.line, 6);
+        let StatementKind::If { then_body, .. } = &if_statement.kind else {
+            bail!("Expected if statement");
+        };
+        assert_eq!(then_body[0].span.start.line, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_test_block() -> Result<()> {
+        let dsl_source = r#"
+            impl Test for Rig {
+                fn set_freq(int freq) {
+                    write("05.{freq:bcd_lu:5}");
+                }
+                test s"tune" {
+                    call set_freq(14074000);
+                    expect_write("05.0040071400");
+                    reply("FB");
+                    call status();
+                    expect_status(freq = 14074000);
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(dsl_source)?;
+        let test = &rig_file.impl_block.tests[0];
+        assert_eq!(test.name, "tune");
+        assert_eq!(test.steps.len(), 5);
+        assert!(matches!(
+            &test.steps[0].kind,
+            TestStepKind::Call { command, args } if command == "set_freq" && args.len() == 1
+        ));
+        assert!(matches!(test.steps[1].kind, TestStepKind::ExpectWrite(_)));
+        assert!(matches!(test.steps[2].kind, TestStepKind::Reply(_)));
+        assert!(matches!(
+            &test.steps[3].kind,
+            TestStepKind::Call { command, .. } if command == "status"
+        ));
+        assert!(matches!(
+            &test.steps[4].kind,
+            TestStepKind::ExpectStatus(fields) if fields.len() == 1
+        ));
+        assert_eq!(test.steps[1].span.start.line, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let dsl_source = r#"impl Test for Rig {
+    fn first() {
+        write("00") ;
+        read("00") x;
+        write("01");
+    }
+
+    fn second() {
+        if x == 1 {
+            write("02")
+        }
+        write("03");
+    }
+
+    fn third(int freq) {
+        write("04") ~ ;
+    }
+
+    init {
+        write("05");
+    }
+}
+"#;
+        let recovered = parse_rig_file_with_recovery(dsl_source);
+        let lines: Vec<_> = recovered
+            .errors
+            .iter()
+            .map(|err| err.position.line)
+            .collect();
+        assert_eq!(lines, [4, 11, 16]);
+        assert!(matches!(
+            recovered.errors[2].error_type.as_ref(),
+            ParseErrorType::Tokenization { .. }
+        ));
+
+        let rig_file = recovered.value.expect("No partial rig file");
+        let commands = &rig_file.impl_block.commands;
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands["first"].statements.len(), 2);
+        assert_eq!(commands["second"].statements.len(), 2);
+        assert_eq!(commands["third"].statements.len(), 1);
+        assert!(rig_file.impl_block.init.is_some());
+
+        let error = parse_rig_file(dsl_source).unwrap_err();
+        assert_eq!(error.position.line, 16);
+        assert!(
+            parse_rig_file_with_recovery("impl Test for Rig {")
+                .value
+                .is_none()
+        );
+    }
 }
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct SourcePosition {
     pub line: usize,
     pub column: usize,
@@ -15,6 +15,24 @@ impl SourcePosition {
     }
 }
 
+/// Region of the source a node was parsed from, `end` is exclusive. Nodes built outside of the
+/// parser have the default span, which is line 0.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+impl Span {
+    pub fn new(start: SourcePosition, end: SourcePosition) -> Self {
+        Self { start, end }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.start.line > 0
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ErrorLevel {
     #[default]
@@ -23,6 +41,16 @@ pub enum ErrorLevel {
     Verbose,
 }
 
+/// Outcome of parsing with error recovery: every error found and the parts that did parse,
+/// `None` if parsing could not recover.
+#[derive(Debug)]
+pub struct Recovered<T> {
+    pub value: Option<T>,
+    pub errors: Vec<ParseError>,
+    /// Regions affected by the tokens dropped to recover, see `filter_cascading_errors`.
+    pub skipped: Vec<Span>,
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     pub position: SourcePosition,
@@ -47,39 +75,49 @@ pub enum ParseErrorType {
     Semantic {
         message: String,
         suggestion: Option<String>,
+        /// Code of the semantic error kind, see `SemanticErrorType::code`.
+        code: &'static str,
     },
 }
 
 impl ParseError {
+    pub const TOKENIZATION_CODE: &'static str = "E0001";
+    pub const SYNTAX_CODE: &'static str = "E0002";
+    /// Imports or the schema of a rig file could not be resolved.
+    pub const RESOLUTION_CODE: &'static str = "E0003";
+
     pub fn with_level(mut self, level: ErrorLevel) -> Self {
         self.level = level;
         self
     }
 
-    fn format_context(&self, position: &SourcePosition) -> String {
-        let lines: Vec<&str> = self.source.lines().collect();
-        let line_idx = position.line.saturating_sub(1);
-
-        let start_line = line_idx.saturating_sub(2);
-        let end_line = (line_idx + 3).min(lines.len());
-
-        let mut context = String::new();
-        let line_num_width = (end_line.checked_ilog10().unwrap_or(0) + 1) as usize;
-        for (i, line) in lines[start_line..end_line].iter().enumerate() {
-            let current_line_num = start_line + i + 1;
-            if current_line_num == position.line {
-                context.push_str(&format!(" → {current_line_num:line_num_width$} | {line}\n"));
-                context.push_str(&format!(
-                    "   {} | {}{}\n",
-                    " ".repeat(line_num_width),
-                    " ".repeat(position.column.saturating_sub(1)),
-                    "^"
-                ));
-            } else {
-                context.push_str(&format!("   {current_line_num:line_num_width$} | {line}\n"));
-            }
+    /// Stable identifier of the kind of error, semantic errors have one per kind.
+    pub fn code(&self) -> &'static str {
+        match self.error_type.as_ref() {
+            ParseErrorType::Tokenization { .. } => Self::TOKENIZATION_CODE,
+            ParseErrorType::Syntax { .. } => Self::SYNTAX_CODE,
+            ParseErrorType::Semantic { code, .. } => code,
         }
-        context
+    }
+
+    /// One line description of the error without the position and source context.
+    pub fn message(&self) -> String {
+        match self.error_type.as_ref() {
+            ParseErrorType::Tokenization { .. } => "Invalid character".to_string(),
+            ParseErrorType::Syntax {
+                expected,
+                found,
+                user_friendly_message,
+                ..
+            } => user_friendly_message
+                .clone()
+                .unwrap_or_else(|| self.get_user_friendly_syntax_message(expected, found)),
+            ParseErrorType::Semantic { message, .. } => message.clone(),
+        }
+    }
+
+    fn format_context(&self, position: &SourcePosition) -> String {
+        format_context(&self.source, position, 1)
     }
 
     fn get_user_friendly_syntax_message(
@@ -245,6 +283,7 @@ impl std::fmt::Display for ParseError {
             ParseErrorType::Semantic {
                 message,
                 suggestion,
+                ..
             } => {
                 writeln!(
                     f,
@@ -283,3 +322,33 @@ pub(crate) fn calculate_position(source: &str, offset: usize) -> SourcePosition
 
     SourcePosition::new(line, column, offset)
 }
+
+/// Renders the lines around `position` and marks `width` characters starting at it.
+pub(crate) fn format_context(source: &str, position: &SourcePosition, width: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    if position.line == 0 || position.line > lines.len() {
+        return String::new();
+    }
+    let line_idx = position.line - 1;
+
+    let start_line = line_idx.saturating_sub(2);
+    let end_line = (line_idx + 3).min(lines.len());
+
+    let mut context = String::new();
+    let line_num_width = (end_line.checked_ilog10().unwrap_or(0) + 1) as usize;
+    for (i, line) in lines[start_line..end_line].iter().enumerate() {
+        let current_line_num = start_line + i + 1;
+        if current_line_num == position.line {
+            context.push_str(&format!(" → {current_line_num:line_num_width$} | {line}\n"));
+            context.push_str(&format!(
+                "   {} | {}{}\n",
+                " ".repeat(line_num_width),
+                " ".repeat(position.column.saturating_sub(1)),
+                "^".repeat(width.max(1))
+            ));
+        } else {
+            context.push_str(&format!("   {current_line_num:line_num_width$} | {line}\n"));
+        }
+    }
+    context
+}
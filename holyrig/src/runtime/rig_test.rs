@@ -0,0 +1,70 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Result, bail};
+use parking_lot::Mutex;
+
+use super::interpreter::{ExternalApi, Value};
+use super::tracer::hex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Exchange {
+    Write(Vec<u8>),
+    Reply(Vec<u8>),
+}
+
+/// `ExternalApi` of rig file tests. Every write has to match the next expected write and every
+/// read is answered with the next scripted reply.
+#[derive(Default)]
+pub(crate) struct ScriptedApi {
+    exchanges: Mutex<VecDeque<Exchange>>,
+    status: Mutex<HashMap<String, Value>>,
+}
+
+impl ScriptedApi {
+    pub(crate) fn expect(&self, exchanges: impl IntoIterator<Item = Exchange>) {
+        *self.exchanges.lock() = exchanges.into_iter().collect();
+    }
+
+    /// Fails when the call finished before making every expected exchange.
+    pub(crate) fn finish(&self) -> Result<()> {
+        match self.exchanges.lock().pop_front() {
+            None => Ok(()),
+            Some(Exchange::Write(expected)) => {
+                bail!("Expected write {} was not made", hex(&expected))
+            }
+            Some(Exchange::Reply(reply)) => bail!("Reply {} was not read", hex(&reply)),
+        }
+    }
+
+    pub(crate) fn status(&self, name: &str) -> Option<Value> {
+        self.status.lock().get(name).cloned()
+    }
+}
+
+impl ExternalApi for ScriptedApi {
+    async fn write(&self, data: &[u8]) -> Result<()> {
+        match self.exchanges.lock().pop_front() {
+            Some(Exchange::Write(expected)) if expected == data => Ok(()),
+            Some(Exchange::Write(expected)) => {
+                bail!("Wrote {}, expected {}", hex(data), hex(&expected))
+            }
+            Some(Exchange::Reply(_)) => bail!("Wrote {}, expected a read", hex(data)),
+            None => bail!("Unexpected write {}", hex(data)),
+        }
+    }
+
+    async fn read(&self, size: usize) -> Result<Vec<u8>> {
+        match self.exchanges.lock().pop_front() {
+            Some(Exchange::Reply(reply)) => Ok(reply),
+            Some(Exchange::Write(expected)) => {
+                bail!("Read {size} bytes, expected write {}", hex(&expected))
+            }
+            None => bail!("Read {size} bytes, but no reply is scripted"),
+        }
+    }
+
+    fn set_var(&self, var: &str, value: Value) -> Result<()> {
+        self.status.lock().insert(var.to_string(), value);
+        Ok(())
+    }
+}
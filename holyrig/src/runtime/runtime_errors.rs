@@ -0,0 +1,146 @@
+use std::fmt;
+
+use super::parser::BinaryOp;
+use super::parser_errors::{Span, format_context};
+use super::tracer::hex;
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero {
+        op: BinaryOp,
+    },
+    InvalidEnumValue {
+        enum_name: String,
+        value: i64,
+    },
+    ResponseTooShort {
+        expected: usize,
+        offset: usize,
+    },
+    TemplateMismatch {
+        offset: usize,
+        expected: Vec<u8>,
+        found: Vec<u8>,
+    },
+    UnexpectedResponse {
+        expected: Vec<u8>,
+        found: Vec<u8>,
+    },
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero { op } => write!(f, "Right side of '{op}' is zero"),
+            RuntimeErrorKind::InvalidEnumValue { enum_name, value } => {
+                write!(f, "Invalid enum value: {value} for enum {enum_name}")
+            }
+            RuntimeErrorKind::ResponseTooShort { expected, offset } => {
+                write!(
+                    f,
+                    "Response too short: expected {expected} bytes at offset {offset}"
+                )
+            }
+            RuntimeErrorKind::TemplateMismatch {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Response doesn't match template at offset {offset}: expected {}, got {}",
+                hex(expected),
+                hex(found)
+            ),
+            RuntimeErrorKind::UnexpectedResponse { expected, found } => {
+                write!(
+                    f,
+                    "Got invalid response: expected {}, got {}",
+                    hex(expected),
+                    hex(found)
+                )
+            }
+            RuntimeErrorKind::Other(err) => write!(f, "{err:#}"),
+        }
+    }
+}
+
+/// Failure while executing a rig file. Rendered with the source line it happened on once the
+/// interpreter attached the span and source.
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Option<Span>,
+    pub source: Option<String>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind) -> Self {
+        Self {
+            kind,
+            span: None,
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(span) = &self.span else {
+            return write!(f, "{}", self.kind);
+        };
+
+        writeln!(
+            f,
+            "Runtime error at line {}, column {}:",
+            span.start.line, span.start.column
+        )?;
+        write!(f, "{}", self.kind)?;
+        if let Some(source) = &self.source {
+            let width = if span.start.line == span.end.line {
+                span.end.column.saturating_sub(span.start.column)
+            } else {
+                1
+            };
+            write!(f, "\n{}", format_context(source, &span.start, width))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Attaches `span` to the error unless it already points at a more specific location.
+pub(crate) fn locate(error: anyhow::Error, span: &Span) -> anyhow::Error {
+    if !span.is_known() {
+        return error;
+    }
+
+    match error.downcast::<RuntimeError>() {
+        Ok(mut error) => {
+            if error.span.is_none() {
+                error.span = Some(span.clone());
+            }
+            error.into()
+        }
+        Err(error) => RuntimeError {
+            kind: RuntimeErrorKind::Other(error),
+            span: Some(span.clone()),
+            source: None,
+        }
+        .into(),
+    }
+}
+
+/// Attaches the source text so the error renders the offending line.
+pub(crate) fn with_source(error: anyhow::Error, source: &str) -> anyhow::Error {
+    match error.downcast::<RuntimeError>() {
+        Ok(mut error) => {
+            if error.source.is_none() && !source.is_empty() {
+                error.source = Some(source.to_string());
+            }
+            error.into()
+        }
+        Err(error) => error,
+    }
+}
@@ -1,23 +1,179 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::RangeInclusive;
 
 use anyhow::Result;
-use logos::Logos;
 
-use super::parser::{DataType, Id, Token};
-use super::parser_errors::{
-    ErrorLevel, ParseError, ParseErrorType, SourcePosition, calculate_position,
-};
+use super::interpreter::{Value, ValueError};
+use super::parser::{DataType, Id, Token, first_error, lex, parse_tokens};
+use super::parser_errors::{ErrorLevel, ParseError, Recovered};
 
 #[derive(Debug, Clone)]
 pub struct SchemaParameter {
     pub param_type: DataType,
     pub name: String,
+    pub range: Option<RangeInclusive<i64>>,
+    pub unit: Option<String>,
+    pub step: Option<i64>,
+    pub default: Option<String>,
+    /// Radio frequency of the rig, checked against the receive ranges of its capabilities.
+    pub rf: bool,
+    /// Keys the transmitter when true, checked against the transmit ranges of its capabilities.
+    pub tx: bool,
+    pub doc: Option<String>,
+}
+
+impl SchemaParameter {
+    pub fn new(param_type: DataType, name: &str) -> Self {
+        Self {
+            param_type,
+            name: name.to_string(),
+            range: None,
+            unit: None,
+            step: None,
+            default: None,
+            rf: false,
+            tx: false,
+            doc: None,
+        }
+    }
+
+    /// Checks the declared range and step of an already coerced value.
+    pub fn check(&self, value: &Value) -> Result<(), ValueError> {
+        let Value::Integer(integer) = value else {
+            return Ok(());
+        };
+
+        if let Some(range) = &self.range
+            && !range.contains(integer)
+        {
+            return Err(ValueError::OutOfRange {
+                value: integer.to_string(),
+                min: *range.start(),
+                max: *range.end(),
+            });
+        }
+
+        if let Some(step) = self.step {
+            let base = self.range.as_ref().map(|range| *range.start()).unwrap_or(0);
+            if step > 0 && (integer - base) % step != 0 {
+                return Err(ValueError::InvalidStep {
+                    value: *integer,
+                    step,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemaParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.param_type)?;
+        if let Some(range) = &self.range {
+            write!(f, "<{}..{}>", range.start(), range.end())?;
+        }
+        write!(f, " {}", self.name)?;
+
+        let mut metadata = Vec::new();
+        if let Some(unit) = &self.unit {
+            metadata.push(format!("unit = s\"{unit}\""));
+        }
+        if let Some(step) = self.step {
+            metadata.push(format!("step = {step}"));
+        }
+        match (&self.param_type, &self.default) {
+            (DataType::Enum(enum_name), Some(default)) => {
+                metadata.push(format!("default = {enum_name}::{default}"))
+            }
+            (_, Some(default)) => metadata.push(format!("default = {default}")),
+            (_, None) => {}
+        }
+        if self.rf {
+            metadata.push("rf".to_string());
+        }
+        if self.tx {
+            metadata.push("tx".to_string());
+        }
+        if !metadata.is_empty() {
+            write!(f, " [{}]", metadata.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaCommand {
+    pub parameters: Vec<SchemaParameter>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentError {
+    Missing,
+    Unknown,
+    Invalid(ValueError),
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgumentError::Missing => write!(f, "Missing parameter"),
+            ArgumentError::Unknown => write!(f, "Unknown parameter"),
+            ArgumentError::Invalid(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl SchemaCommand {
+    /// Fills in declared defaults, coerces every argument to its declared type and enforces the
+    /// declared constraints. All failures are reported together, keyed by parameter name.
+    pub fn prepare_args(
+        &self,
+        mut args: HashMap<String, Value>,
+        enums: &HashMap<String, HashMap<String, u32>>,
+    ) -> Result<HashMap<String, Value>, BTreeMap<String, ArgumentError>> {
+        let mut prepared = HashMap::new();
+        let mut errors = BTreeMap::new();
+
+        for param in &self.parameters {
+            let value = match (args.remove(&param.name), &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => Value::String(default.clone()),
+                (None, None) => {
+                    errors.insert(param.name.clone(), ArgumentError::Missing);
+                    continue;
+                }
+            };
+
+            match value
+                .coerce(&param.param_type, enums)
+                .and_then(|value| param.check(&value).map(|_| value))
+            {
+                Ok(value) => {
+                    prepared.insert(param.name.clone(), value);
+                }
+                Err(err) => {
+                    errors.insert(param.name.clone(), ArgumentError::Invalid(err));
+                }
+            }
+        }
+
+        errors.extend(args.into_keys().map(|name| (name, ArgumentError::Unknown)));
+
+        if errors.is_empty() {
+            Ok(prepared)
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum SchemaMember {
     Enum(String, Vec<String>),
-    Command(String, Vec<SchemaParameter>),
+    Command(String, SchemaCommand),
     Status(Vec<SchemaParameter>),
 }
 
@@ -25,8 +181,8 @@ pub enum SchemaMember {
 pub struct SchemaBlock {
     pub name: String,
     pub enums: BTreeMap<String, Vec<String>>,
-    pub commands: BTreeMap<String, Vec<SchemaParameter>>,
-    pub status: BTreeMap<String, DataType>,
+    pub commands: BTreeMap<String, SchemaCommand>,
+    pub status: BTreeMap<String, SchemaParameter>,
 }
 
 #[derive(Debug, Clone)]
@@ -34,8 +190,16 @@ pub struct SchemaFile {
     pub version: u32,
     pub name: String,
     pub enums: BTreeMap<String, Vec<String>>,
-    pub commands: BTreeMap<String, Vec<SchemaParameter>>,
-    pub status: BTreeMap<String, DataType>,
+    pub commands: BTreeMap<String, SchemaCommand>,
+    pub status: BTreeMap<String, SchemaParameter>,
+}
+
+enum ParameterAttribute {
+    Unit(String),
+    Step(i64),
+    Default(String),
+    Rf,
+    Tx,
 }
 
 peg::parser! {
@@ -50,53 +214,117 @@ peg::parser! {
                 Id::new(id)
             }
 
-        rule data_type() -> DataType
-            = [Token::Int] { DataType::Int }
-            / [Token::Bool] { DataType::Bool }
-            / id:identifier() { DataType::Enum(id.as_str().to_string()) }
+        rule doc() -> Option<String>
+            = lines:([Token::DocComment(line)] { line })* {
+                if lines.is_empty() {
+                    None
+                } else {
+                    Some(lines.iter().map(|line| doc_comment_text(line)).collect::<Vec<_>>().join("\n"))
+                }
+            }
+
+        rule signed_integer() -> i64
+            = negative:[Token::Minus]? [Token::Integer(num)] {?
+                let num = num.parse::<i64>().or(Err("Invalid integer"))?;
+                Ok(if negative.is_some() { -num } else { num })
+            }
+
+        rule range() -> RangeInclusive<i64>
+            = [Token::Less] min:signed_integer() [Token::Dot] [Token::Dot] max:signed_integer() [Token::Greater] {?
+                if min <= max {
+                    Ok(min..=max)
+                } else {
+                    Err("Range minimum must not exceed its maximum")
+                }
+            }
+
+        rule data_type() -> (DataType, Option<RangeInclusive<i64>>)
+            = [Token::Int] range:range()? { (DataType::Int, range) }
+            / [Token::Bool] { (DataType::Bool, None) }
+            / id:identifier() { (DataType::Enum(id.as_str().to_string()), None) }
+
+        rule default_value() -> String
+            = value:signed_integer() { value.to_string() }
+            / [Token::Id(_)] [Token::DoubleColon] [Token::Id(variant)] { variant.to_string() }
+            / [Token::Id(value)] { value.to_string() }
+
+        rule attribute() -> ParameterAttribute
+            = [Token::Id("unit")] [Token::EqualAssign] [Token::Str(unit)] {
+                ParameterAttribute::Unit(unit[2..unit.len() - 1].to_string())
+            }
+            / [Token::Id("step")] [Token::EqualAssign] step:signed_integer() {?
+                if step > 0 {
+                    Ok(ParameterAttribute::Step(step))
+                } else {
+                    Err("Step must be positive")
+                }
+            }
+            / [Token::Id("default")] [Token::EqualAssign] value:default_value() {
+                ParameterAttribute::Default(value)
+            }
+            / [Token::Id("rf")] { ParameterAttribute::Rf }
+            / [Token::Id("tx")] { ParameterAttribute::Tx }
+
+        rule attributes() -> Vec<ParameterAttribute>
+            = [Token::BracketOpen] attributes:(attribute() ** [Token::Comma]) [Token::Comma]? [Token::BracketClose] {
+                attributes
+            }
 
         rule parameter() -> SchemaParameter
-            = param_type:data_type() name:identifier() {
-                SchemaParameter {
-                    param_type,
-                    name: name.as_str().to_string(),
+            = doc:doc() param_type:data_type() name:identifier() attributes:attributes()? {
+                let (param_type, range) = param_type;
+                let mut parameter = SchemaParameter::new(param_type, name.as_str());
+                parameter.range = range;
+                parameter.doc = doc;
+                for attribute in attributes.unwrap_or_default() {
+                    match attribute {
+                        ParameterAttribute::Unit(unit) => parameter.unit = Some(unit),
+                        ParameterAttribute::Step(step) => parameter.step = Some(step),
+                        ParameterAttribute::Default(value) => parameter.default = Some(value),
+                        ParameterAttribute::Rf => parameter.rf = true,
+                        ParameterAttribute::Tx => parameter.tx = true,
+                    }
                 }
+                parameter
             }
 
         rule parameter_list() -> Vec<SchemaParameter>
-            = [Token::ParenOpen] params:(parameter() ** [Token::Comma]) [Token::ParenClose] {
+            = [Token::ParenOpen] params:(parameter() ** [Token::Comma]) [Token::Comma]? doc() [Token::ParenClose] {
                 params
             }
 
-        rule command_declaration() -> (String, Vec<SchemaParameter>)
-            = [Token::Fn] name:identifier() params:parameter_list()? [Token::Semicolon] {
-                (name.as_str().to_string(), params.unwrap_or_default())
+        rule command_declaration() -> (String, SchemaCommand)
+            = doc:doc() [Token::Fn] name:identifier() params:parameter_list()? [Token::Semicolon] {
+                (
+                    name.as_str().to_string(),
+                    SchemaCommand {
+                        parameters: params.unwrap_or_default(),
+                        doc,
+                    },
+                )
             }
 
         rule enum_variant() -> String
-            = id:identifier() [Token::Comma]? {
+            = doc() id:identifier() [Token::Comma]? {
                 id.as_str().to_string()
             }
 
         rule enum_declaration() -> SchemaMember
-            = [Token::Enum] name:identifier() [Token::BraceOpen]
+            = doc() [Token::Enum] name:identifier() [Token::BraceOpen]
               variants:enum_variant()*
-              [Token::BraceClose] {
+              doc() [Token::BraceClose] {
                 SchemaMember::Enum(name.as_str().to_string(), variants)
             }
 
         rule status_field() -> SchemaParameter
-            = param_type:data_type() name:identifier() [Token::Semicolon] {
-                SchemaParameter {
-                    param_type,
-                    name: name.as_str().to_string(),
-                }
+            = parameter:parameter() [Token::Semicolon] {
+                parameter
             }
 
         rule status_declaration() -> Vec<SchemaParameter>
-            = [Token::Status] [Token::BraceOpen]
+            = doc() [Token::Status] [Token::BraceOpen]
               fields:status_field()*
-              [Token::BraceClose] {
+              doc() [Token::BraceClose] {
                 fields
             }
 
@@ -107,7 +335,7 @@ peg::parser! {
 
         rule schema_block() -> SchemaBlock
             = [Token::Schema] name:identifier() [Token::BraceOpen]
-              members:schema_member()* [Token::BraceClose] {?
+              members:schema_member()* doc() [Token::BraceClose] {?
 
                 let mut enums = BTreeMap::new();
                 let mut commands = BTreeMap::new();
@@ -117,14 +345,14 @@ peg::parser! {
                         SchemaMember::Enum(name, variants) => {
                             enums.insert(name, variants);
                         },
-                        SchemaMember::Command(name, params) => {
-                            commands.insert(name, params);
+                        SchemaMember::Command(name, command) => {
+                            commands.insert(name, command);
                         },
                         SchemaMember::Status(schema_status) => {
                             status.extend(
                                 schema_status
                                     .into_iter()
-                                    .map(|param| (param.name, param.param_type))
+                                    .map(|param| (param.name.clone(), param))
                             )
                         },
                     }
@@ -148,7 +376,7 @@ peg::parser! {
             }
 
         pub rule schema_file() -> SchemaFile
-            = version:version_setting() block:schema_block() {
+            = doc() version:version_setting() doc() block:schema_block() {
                 SchemaFile {
                     version,
                     name: block.name,
@@ -160,81 +388,38 @@ peg::parser! {
     }
 }
 
+fn doc_comment_text(line: &str) -> &str {
+    line.trim_start_matches('/').trim()
+}
+
 pub fn parse_schema(source: &str) -> Result<SchemaFile, ParseError> {
     parse_schema_with_level(source, ErrorLevel::Normal)
 }
 
 pub fn parse_schema_with_level(source: &str, level: ErrorLevel) -> Result<SchemaFile, ParseError> {
-    let mut lexer = Token::lexer(source);
-    let mut tokens_with_positions = Vec::new();
-
-    while let Some(token_result) = lexer.next() {
-        match token_result {
-            Ok(token) => {
-                if !matches!(token, Token::Comment | Token::NewLine) {
-                    let span = lexer.span();
-                    let position = calculate_position(source, span.start);
-                    tokens_with_positions
-                        .push(super::parser::TokenWithPosition { token, position });
-                }
-            }
-            Err(_) => {
-                let span = lexer.span();
-                let position = calculate_position(source, span.start);
-                return Err(ParseError {
-                    position,
-                    error_type: Box::new(ParseErrorType::Tokenization {
-                        message: format!("Unable to tokenize input at position {}", span.start),
-                        context: "Invalid character or token".to_string(),
-                    }),
-                    source: source.to_string(),
-                    level,
-                });
-            }
-        }
-    }
-
-    let tokens: Vec<Token> = tokens_with_positions.iter().map(|t| t.token).collect();
-
-    schema_parser::schema_file(&tokens).map_err(|peg_error| {
-        let error_msg = format!("{peg_error}");
-
-        let position = if peg_error.location < tokens_with_positions.len() {
-            tokens_with_positions[peg_error.location].position.clone()
-        } else if !tokens_with_positions.is_empty() {
-            tokens_with_positions[0].position.clone()
-        } else {
-            SourcePosition::new(1, 1, 0)
-        };
-
-        let found = if peg_error.location < tokens_with_positions.len() {
-            Some(format!(
-                "{:?}",
-                tokens_with_positions[peg_error.location].token
-            ))
-        } else {
-            Some("unexpected token".to_string())
-        };
+    first_error(parse_schema_recovering(source, level, false))
+}
 
-        let expected = peg_error
-            .expected
-            .tokens()
-            .map(|token| token.to_string())
-            .collect();
+/// Parses a schema and recovers from syntax errors at member boundaries, so every error is
+/// reported and the members that parsed are kept.
+pub fn parse_schema_with_recovery(source: &str) -> Recovered<SchemaFile> {
+    parse_schema_recovering(source, ErrorLevel::Normal, true)
+}
 
-        ParseError {
-            position,
-            error_type: Box::new(ParseErrorType::Syntax {
-                expected,
-                found,
-                context: format!("Failed to parse schema file structure. PEG error: {error_msg}"),
-                peg_error: Some(error_msg),
-                user_friendly_message: None,
-            }),
-            source: source.to_string(),
-            level,
-        }
-    })
+fn parse_schema_recovering(
+    source: &str,
+    level: ErrorLevel,
+    recover: bool,
+) -> Recovered<SchemaFile> {
+    parse_tokens(
+        source,
+        lex(source, level, recover, |token| {
+            !matches!(token, Token::Comment(_) | Token::NewLine)
+        }),
+        "schema file",
+        recover,
+        |tokens, _| schema_parser::schema_file(tokens),
+    )
 }
 
 #[cfg(test)]
@@ -277,13 +462,13 @@ mod tests {
         );
 
         assert!(schema.commands.contains_key("set_freq"));
-        let set_freq_cmd = &schema.commands["set_freq"];
+        let set_freq_cmd = &schema.commands["set_freq"].parameters;
         assert_eq!(set_freq_cmd.len(), 2);
         assert_eq!(set_freq_cmd[0].name, "freq");
         assert!(matches!(set_freq_cmd[0].param_type, DataType::Int));
 
         assert!(schema.status.contains_key("freq_a"));
-        assert!(matches!(schema.status["freq_a"], DataType::Int));
+        assert!(matches!(schema.status["freq_a"].param_type, DataType::Int));
 
         Ok(())
     }
@@ -324,6 +509,175 @@ mod tests {
 
         let schema = result.unwrap();
         assert!(schema.commands.contains_key("simple_command"));
-        assert!(schema.commands["simple_command"].is_empty());
+        assert!(schema.commands["simple_command"].parameters.is_empty());
+    }
+
+    #[test]
+    fn test_parse_parameter_metadata() -> Result<()> {
+        let schema_source = r#"
+        version = 1;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                B,
+            }
+
+            /// Tune a VFO.
+            /// Frequencies are absolute.
+            fn set_freq(
+                /// Target frequency.
+                int<30000..470000000> freq [unit = s"Hz", step = 10, rf],
+                Vfo target [default = Vfo::A],
+            );
+
+            fn set_offset(int<-9999..9999> offset);
+            fn transmit(bool tx [tx]);
+
+            status {
+                /// Frequency of VFO A.
+                int freq_a [unit = s"Hz"];
+            }
+        }
+        "#;
+
+        let schema = parse_schema(schema_source)?;
+
+        let set_freq = &schema.commands["set_freq"];
+        assert_eq!(
+            set_freq.doc.as_deref(),
+            Some("Tune a VFO.\nFrequencies are absolute.")
+        );
+        let freq = &set_freq.parameters[0];
+        assert_eq!(freq.range, Some(30000..=470000000));
+        assert_eq!(freq.unit.as_deref(), Some("Hz"));
+        assert_eq!(freq.step, Some(10));
+        assert!(freq.rf);
+        assert_eq!(freq.doc.as_deref(), Some("Target frequency."));
+        assert_eq!(set_freq.parameters[1].default.as_deref(), Some("A"));
+
+        let offset = &schema.commands["set_offset"].parameters[0];
+        assert_eq!(offset.range, Some(-9999..=9999));
+        assert!(!offset.rf);
+        assert!(schema.commands["transmit"].parameters[0].tx);
+
+        let freq_a = &schema.status["freq_a"];
+        assert_eq!(freq_a.unit.as_deref(), Some("Hz"));
+        assert_eq!(freq_a.doc.as_deref(), Some("Frequency of VFO A."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid_range() {
+        let schema_source = r#"
+        version = 1;
+
+        schema Test {
+            fn set_freq(int<100..10> freq);
+        }
+        "#;
+
+        assert!(parse_schema(schema_source).is_err());
+    }
+
+    #[test]
+    fn test_prepare_args() -> Result<()> {
+        let schema_source = r#"
+        version = 1;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                B,
+            }
+
+            fn set_freq(int<30000..470000000> freq [step = 10], Vfo target [default = Vfo::A]);
+        }
+        "#;
+
+        let schema = parse_schema(schema_source)?;
+        let command = &schema.commands["set_freq"];
+        let enums = HashMap::from([(
+            "Vfo".to_string(),
+            HashMap::from([("A".to_string(), 0), ("B".to_string(), 1)]),
+        )]);
+
+        let args = command
+            .prepare_args(
+                HashMap::from([("freq".to_string(), Value::Integer(14074000))]),
+                &enums,
+            )
+            .unwrap();
+        assert_eq!(args["freq"], Value::Integer(14074000));
+        assert!(matches!(
+            &args["target"],
+            Value::EnumVariant { variant_name, value: 0, .. } if variant_name == "A"
+        ));
+
+        let errors = command
+            .prepare_args(
+                HashMap::from([
+                    ("freq".to_string(), Value::Integer(1000)),
+                    ("target".to_string(), Value::String("C".to_string())),
+                    ("power".to_string(), Value::Integer(100)),
+                ]),
+                &enums,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            errors["freq"],
+            ArgumentError::Invalid(ValueError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            errors["target"],
+            ArgumentError::Invalid(ValueError::UnknownEnumVariant { .. })
+        ));
+        assert_eq!(errors["power"], ArgumentError::Unknown);
+
+        let errors = command
+            .prepare_args(
+                HashMap::from([("freq".to_string(), Value::Integer(14074005))]),
+                &enums,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            errors["freq"],
+            ArgumentError::Invalid(ValueError::InvalidStep { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_error_recovery() {
+        let schema_source = r#"
+        version = 1;
+
+        schema Transceiver {
+            fn set_freq(int freq Vfo target);
+            fn clear_rit();
+
+            status {
+                int freq_a
+                bool transmit;
+            }
+
+            fn transmit(bool tx);
+        }
+        "#;
+
+        let recovered = parse_schema_with_recovery(schema_source);
+        let lines: Vec<_> = recovered
+            .errors
+            .iter()
+            .map(|err| err.position.line)
+            .collect();
+        assert_eq!(lines, [5, 10]);
+
+        let schema = recovered.value.expect("No partial schema");
+        assert!(schema.commands.contains_key("clear_rit"));
+        assert!(schema.commands.contains_key("transmit"));
+        assert!(!schema.commands.contains_key("set_freq"));
     }
 }
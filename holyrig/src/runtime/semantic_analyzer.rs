@@ -1,9 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
 use super::SchemaFile;
-use super::parser::{BinaryOp, DataType, Expr, InterpolationPart, RigFile, Statement};
-use super::parser_errors::{ErrorLevel, ParseError, ParseErrorType, SourcePosition};
+use super::parser::{
+    BinaryOp, DataType, Expr, InterpolationPart, MatchArm, Pattern, RigFile, Statement,
+    StatementKind, TestStepKind, parse_rig_file_with_recovery,
+};
+use super::parser_errors::{
+    ErrorLevel, ParseError, ParseErrorType, Recovered, SourcePosition, Span,
+};
+use crate::data_format::DataFormat;
 
 #[derive(Debug, Clone)]
 pub struct SemanticError {
@@ -108,6 +114,73 @@ pub enum SemanticErrorType {
         from_type: DataType,
         to_type: DataType,
     },
+    InvalidCapabilityRange {
+        name: String,
+        min: i64,
+        max: i64,
+    },
+    MatchArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    NonExhaustiveMatch {
+        missing: Vec<String>,
+    },
+    TemplateFieldOverflow {
+        name: String,
+        format: String,
+        length: usize,
+        min: i64,
+        max: i64,
+    },
+    NegativeIntoUnsigned {
+        name: String,
+        format: String,
+        min: i64,
+        max: i64,
+    },
+    UnmappedEnumValue {
+        enum_name: String,
+        min: i64,
+        max: i64,
+    },
+}
+
+impl SemanticErrorType {
+    /// Stable identifier of the error kind, reported by the machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticErrorType::UndefinedVariable { .. } => "E0101",
+            SemanticErrorType::UndefinedFunction { .. } => "E0102",
+            SemanticErrorType::UndefinedEnumVariant { .. } => "E0103",
+            SemanticErrorType::UndefinedEnum { .. } => "E0104",
+            SemanticErrorType::TypeMismatch { .. } => "E0105",
+            SemanticErrorType::InvalidFunctionArguments { .. } => "E0106",
+            SemanticErrorType::InvalidFunctionArgumentType { .. } => "E0107",
+            SemanticErrorType::CommandNotInSchema { .. } => "E0108",
+            SemanticErrorType::ParameterTypeMismatch { .. } => "E0109",
+            SemanticErrorType::MissingRequiredParameter { .. } => "E0110",
+            SemanticErrorType::UnknownParameter { .. } => "E0111",
+            SemanticErrorType::DuplicateEnumVariant { .. } => "E0112",
+            SemanticErrorType::InvalidBinaryOperation { .. } => "E0113",
+            SemanticErrorType::SchemaVersionMismatch { .. } => "E0114",
+            SemanticErrorType::SchemaTypeMismatch { .. } => "E0115",
+            SemanticErrorType::InvalidInterpolationVariable { .. } => "E0116",
+            SemanticErrorType::EmptyCommand { .. } => "E0117",
+            SemanticErrorType::InvalidEnumVariantValue { .. } => "E0118",
+            SemanticErrorType::DivisionByZero { .. } => "E0119",
+            SemanticErrorType::InvalidDataFormat { .. } => "E0120",
+            SemanticErrorType::InvalidStatusVariable { .. } => "E0121",
+            SemanticErrorType::StatusVariableTypeMismatch { .. } => "E0122",
+            SemanticErrorType::InvalidCast { .. } => "E0123",
+            SemanticErrorType::InvalidCapabilityRange { .. } => "E0124",
+            SemanticErrorType::MatchArityMismatch { .. } => "E0125",
+            SemanticErrorType::NonExhaustiveMatch { .. } => "E0126",
+            SemanticErrorType::TemplateFieldOverflow { .. } => "E0127",
+            SemanticErrorType::NegativeIntoUnsigned { .. } => "E0128",
+            SemanticErrorType::UnmappedEnumValue { .. } => "E0129",
+        }
+    }
 }
 
 impl fmt::Display for SemanticError {
@@ -275,12 +348,214 @@ impl fmt::Display for SemanticError {
             SemanticErrorType::InvalidCast { from_type, to_type } => {
                 write!(f, "Invalid cast from {from_type:?} to {to_type:?}")
             }
+            SemanticErrorType::InvalidCapabilityRange { name, min, max } => {
+                write!(f, "Invalid range {min}..{max} in capability '{name}'")
+            }
+            SemanticErrorType::MatchArityMismatch { expected, found } => {
+                write!(f, "Match arm has {found} patterns, expected {expected}")
+            }
+            SemanticErrorType::NonExhaustiveMatch { missing } => {
+                write!(f, "Non-exhaustive match, missing {}", missing.join(", "))
+            }
+            SemanticErrorType::TemplateFieldOverflow {
+                name,
+                format,
+                length,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Value {} of '{name}' does not fit in {format}:{length}",
+                    format_range(*min, *max)
+                )
+            }
+            SemanticErrorType::NegativeIntoUnsigned {
+                name,
+                format,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Negative value {} of '{name}' written as unsigned {format}",
+                    format_range(*min, *max)
+                )
+            }
+            SemanticErrorType::UnmappedEnumValue {
+                enum_name,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "No variant of enum '{enum_name}' has a value in {}",
+                    format_range(*min, *max)
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for SemanticError {}
 
+fn format_range(min: i64, max: i64) -> String {
+    if min == max {
+        min.to_string()
+    } else {
+        format!("{min}..{max}")
+    }
+}
+
+/// Finding that does not prevent the rig from running but likely points at a mistake or a gap
+/// in the schema coverage, see `SemanticAnalyzer::warnings`.
+#[derive(Debug, Clone)]
+pub struct SemanticWarning {
+    pub position: Option<SourcePosition>,
+    pub warning_type: SemanticWarningType,
+}
+
+#[derive(Debug, Clone)]
+pub enum SemanticWarningType {
+    UnimplementedCommand {
+        command_name: String,
+    },
+    UnsetStatusField {
+        name: String,
+    },
+    UnmappedEnumVariant {
+        enum_name: String,
+        variant_name: String,
+    },
+    WriteWithoutRead,
+    UnusedVariable {
+        name: String,
+    },
+    UnreachableBranch {
+        branch: String,
+    },
+    PossibleTemplateFieldOverflow {
+        name: String,
+        format: String,
+        length: usize,
+        min: i64,
+        max: i64,
+    },
+    PossibleNegativeIntoUnsigned {
+        name: String,
+        format: String,
+        min: i64,
+        max: i64,
+    },
+    PossibleUnmappedEnumValue {
+        enum_name: String,
+        min: i64,
+        max: i64,
+    },
+    UnresolvableDefault {
+        command_name: String,
+        parameter: String,
+        enum_name: String,
+        variant_name: String,
+    },
+}
+
+impl SemanticWarningType {
+    /// Stable identifier of the warning kind, reported by the machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticWarningType::UnimplementedCommand { .. } => "W0101",
+            SemanticWarningType::UnsetStatusField { .. } => "W0102",
+            SemanticWarningType::UnmappedEnumVariant { .. } => "W0103",
+            SemanticWarningType::WriteWithoutRead => "W0104",
+            SemanticWarningType::UnusedVariable { .. } => "W0105",
+            SemanticWarningType::UnreachableBranch { .. } => "W0106",
+            SemanticWarningType::PossibleTemplateFieldOverflow { .. } => "W0107",
+            SemanticWarningType::PossibleNegativeIntoUnsigned { .. } => "W0108",
+            SemanticWarningType::PossibleUnmappedEnumValue { .. } => "W0109",
+            SemanticWarningType::UnresolvableDefault { .. } => "W0110",
+        }
+    }
+}
+
+impl fmt::Display for SemanticWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.warning_type {
+            SemanticWarningType::UnimplementedCommand { command_name } => {
+                write!(f, "Schema command '{command_name}' is not implemented")
+            }
+            SemanticWarningType::UnsetStatusField { name } => {
+                write!(f, "Status field '{name}' is never set")
+            }
+            SemanticWarningType::UnmappedEnumVariant {
+                enum_name,
+                variant_name,
+            } => {
+                write!(
+                    f,
+                    "Variant '{enum_name}::{variant_name}' has no value in the rig"
+                )
+            }
+            SemanticWarningType::WriteWithoutRead => {
+                write!(f, "Write is not followed by a read of the reply")
+            }
+            SemanticWarningType::UnusedVariable { name } => {
+                write!(f, "Variable '{name}' is never used")
+            }
+            SemanticWarningType::UnreachableBranch { branch } => {
+                write!(f, "Unreachable {branch}")
+            }
+            SemanticWarningType::PossibleTemplateFieldOverflow {
+                name,
+                format,
+                length,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Value {} of '{name}' may not fit in {format}:{length}",
+                    format_range(*min, *max)
+                )
+            }
+            SemanticWarningType::PossibleNegativeIntoUnsigned {
+                name,
+                format,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Value {} of '{name}' may be negative but is written as unsigned {format}",
+                    format_range(*min, *max)
+                )
+            }
+            SemanticWarningType::PossibleUnmappedEnumValue {
+                enum_name,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Cast of {} to enum '{enum_name}' may receive values without a variant",
+                    format_range(*min, *max)
+                )
+            }
+            SemanticWarningType::UnresolvableDefault {
+                command_name,
+                parameter,
+                enum_name,
+                variant_name,
+            } => {
+                write!(
+                    f,
+                    "Default '{enum_name}::{variant_name}' of '{command_name}' parameter '{parameter}' has no value in the rig"
+                )
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SemanticAnalyzer {
     schema: SchemaFile,
@@ -302,6 +577,16 @@ impl SemanticAnalyzer {
     }
 
     pub fn analyze(&self, rig_file: &RigFile) -> Result<(), Vec<SemanticError>> {
+        let (errors, _) = self.check(rig_file);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Validates the rig file, returns the errors and the warnings found along the way.
+    fn check(&self, rig_file: &RigFile) -> (Vec<SemanticError>, Vec<SemanticWarning>) {
         let mut errors = Vec::new();
         let mut context = AnalysisContext::new(&self.schema, rig_file);
 
@@ -310,11 +595,182 @@ impl SemanticAnalyzer {
         self.validate_settings(rig_file, &mut errors, &mut context);
         self.validate_impl_block(rig_file, &mut errors, &mut context);
         self.validate_status_block(rig_file, &mut errors, &mut context);
+        self.validate_capabilities(rig_file, &mut errors);
+        self.validate_tests(rig_file, &mut errors);
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
+        (errors, context.warnings)
+    }
+
+    /// Collects the warnings for a rig file, meant to be reported next to the errors of
+    /// `analyze`: schema items the rig does not cover, writes without a read, unused variables,
+    /// unreachable branches and values that may not fit their template field or enum.
+    pub fn warnings(&self, rig_file: &RigFile) -> Vec<SemanticWarning> {
+        let (_, warnings) = self.diagnose(rig_file);
+        warnings
+    }
+
+    /// Collects both the errors of `analyze` and the warnings of `warnings` in one pass.
+    pub fn diagnose(&self, rig_file: &RigFile) -> (Vec<SemanticError>, Vec<SemanticWarning>) {
+        let (errors, mut warnings) = self.check(rig_file);
+        let impl_block = &rig_file.impl_block;
+
+        for command_name in self.schema.commands.keys() {
+            if !impl_block.commands.contains_key(command_name) {
+                warnings.push(SemanticWarning {
+                    position: None,
+                    warning_type: SemanticWarningType::UnimplementedCommand {
+                        command_name: command_name.clone(),
+                    },
+                });
+            }
+        }
+
+        let status_fields = rig_file.get_supported_status_fields();
+        for name in self.schema.status.keys() {
+            if !status_fields.contains(name) {
+                warnings.push(SemanticWarning {
+                    position: None,
+                    warning_type: SemanticWarningType::UnsetStatusField { name: name.clone() },
+                });
+            }
+        }
+
+        self.check_enum_mappings(rig_file, &status_fields, &mut warnings);
+        self.check_parameter_defaults(rig_file, &mut warnings);
+
+        let enums: HashMap<&str, Vec<&String>> = impl_block
+            .enums
+            .iter()
+            .map(|enum_def| (enum_def.name.as_str(), enum_def.variants.keys().collect()))
+            .collect();
+        let mut constants = HashMap::new();
+        for constant in &rig_file.constants {
+            if let Some(value) = constant_value(&constant.expr, &constants) {
+                constants.insert(constant.id.as_str(), value);
+            }
+        }
+        let bodies = impl_block
+            .init
+            .iter()
+            .map(|init| (&init.statements, Vec::new()))
+            .chain(
+                impl_block
+                    .status
+                    .iter()
+                    .map(|status| (&status.statements, Vec::new())),
+            )
+            .chain(impl_block.commands.values().map(|command| {
+                let parameters = command
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.name.as_str())
+                    .collect();
+                (&command.statements, parameters)
+            }));
+        for (statements, parameters) in bodies {
+            check_unused_variables(statements, &mut warnings);
+            for span in check_reads(statements, Vec::new(), &mut warnings) {
+                push_unread_write(span, &mut warnings);
+            }
+
+            // Constants shadowed by a parameter or a variable of the body aren't constant there
+            let mut defined = Vec::new();
+            collect_variables(statements, &mut defined, &mut HashSet::new());
+            let mut constants = constants.clone();
+            for name in parameters
+                .into_iter()
+                .chain(defined.into_iter().map(|(name, _)| name))
+            {
+                constants.remove(name);
+            }
+            check_unreachable_branches(statements, &enums, &constants, &mut warnings);
+        }
+
+        warnings.sort_by_key(|warning| warning.position.as_ref().map(|position| position.offset));
+        (errors, warnings)
+    }
+
+    /// Warns about the variants of the schema enums the rig uses that it gives no value.
+    fn check_enum_mappings(
+        &self,
+        rig_file: &RigFile,
+        status_fields: &HashSet<String>,
+        warnings: &mut Vec<SemanticWarning>,
+    ) {
+        let impl_block = &rig_file.impl_block;
+        let parameter_types = impl_block
+            .commands
+            .values()
+            .flat_map(|command| command.parameters.iter().map(|param| &param.param_type));
+        let status_types = status_fields
+            .iter()
+            .filter_map(|name| self.schema.status.get(name))
+            .map(|field| &field.param_type);
+        let mut used_enums: HashSet<&str> = parameter_types
+            .chain(status_types)
+            .filter_map(|data_type| match data_type {
+                DataType::Enum(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        used_enums.extend(
+            impl_block
+                .enums
+                .iter()
+                .map(|enum_def| enum_def.name.as_str()),
+        );
+
+        for (enum_name, variants) in &self.schema.enums {
+            if !used_enums.contains(enum_name.as_str()) {
+                continue;
+            }
+            let rig_enum = impl_block
+                .enums
+                .iter()
+                .find(|enum_def| &enum_def.name == enum_name);
+            for variant_name in variants {
+                if !rig_enum.is_some_and(|rig_enum| rig_enum.variants.contains_key(variant_name)) {
+                    warnings.push(SemanticWarning {
+                        position: rig_enum.and_then(|rig_enum| known_position(&rig_enum.span)),
+                        warning_type: SemanticWarningType::UnmappedEnumVariant {
+                            enum_name: enum_name.clone(),
+                            variant_name: variant_name.clone(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    /// Warns about the enum defaults of the implemented commands that the rig gives no value,
+    /// calls relying on them would be rejected.
+    fn check_parameter_defaults(&self, rig_file: &RigFile, warnings: &mut Vec<SemanticWarning>) {
+        let enums = rig_file.enum_mappings();
+        for (command_name, command) in &self.schema.commands {
+            let Some(rig_command) = rig_file.impl_block.commands.get(command_name) else {
+                continue;
+            };
+            for parameter in &command.parameters {
+                let (DataType::Enum(enum_name), Some(default)) =
+                    (&parameter.param_type, &parameter.default)
+                else {
+                    continue;
+                };
+                if !enums
+                    .get(enum_name)
+                    .is_some_and(|variants| variants.contains_key(default))
+                {
+                    warnings.push(SemanticWarning {
+                        position: known_position(&rig_command.span),
+                        warning_type: SemanticWarningType::UnresolvableDefault {
+                            command_name: command_name.clone(),
+                            parameter: parameter.name.clone(),
+                            enum_name: enum_name.clone(),
+                            variant_name: default.clone(),
+                        },
+                    });
+                }
+            }
         }
     }
 
@@ -330,6 +786,81 @@ impl SemanticAnalyzer {
         }
     }
 
+    fn validate_capabilities(&self, rig_file: &RigFile, errors: &mut Vec<SemanticError>) {
+        let Some(capabilities) = rig_file.capabilities() else {
+            return;
+        };
+
+        let named_ranges = [
+            ("rx_ranges", &capabilities.rx_ranges),
+            ("tx_ranges", &capabilities.tx_ranges),
+        ];
+        let mut modes: Vec<&String> = capabilities.modes.iter().collect();
+        for (name, ranges) in named_ranges {
+            for range in ranges {
+                if range.min < 0 || range.min > range.max {
+                    errors.push(SemanticError {
+                        position: None,
+                        error_type: SemanticErrorType::InvalidCapabilityRange {
+                            name: name.to_string(),
+                            min: range.min,
+                            max: range.max,
+                        },
+                    });
+                }
+                modes.extend(&range.modes);
+            }
+        }
+        modes.extend(capabilities.hamlib_modes.iter().map(|mode| &mode.mode));
+        modes.extend(
+            capabilities
+                .passbands
+                .iter()
+                .map(|passbands| &passbands.mode),
+        );
+
+        if modes.is_empty() {
+            return;
+        }
+
+        let mut reported = HashSet::new();
+        for enum_name in &capabilities.mode_enums {
+            if enum_name != "Mode" && reported.insert(enum_name) {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::TypeMismatch {
+                        expected: DataType::Enum("Mode".to_string()),
+                        found: DataType::Enum(enum_name.clone()),
+                        context: "capability modes".to_string(),
+                    },
+                });
+            }
+        }
+
+        let Some(schema_modes) = self.schema.enums.get("Mode") else {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::UndefinedEnum {
+                    name: "Mode".to_string(),
+                },
+            });
+            return;
+        };
+
+        let mut reported = HashSet::new();
+        for mode in modes {
+            if !schema_modes.contains(mode) && reported.insert(mode) {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::UndefinedEnumVariant {
+                        enum_name: "Mode".to_string(),
+                        variant_name: mode.clone(),
+                    },
+                });
+            }
+        }
+    }
+
     fn validate_enums(
         &self,
         rig_file: &RigFile,
@@ -337,6 +868,7 @@ impl SemanticAnalyzer {
         context: &mut AnalysisContext,
     ) {
         for enum_def in &rig_file.impl_block.enums {
+            let first_error = errors.len();
             let mut seen_variants = HashSet::new();
 
             for variant_name in enum_def.variants.keys() {
@@ -359,6 +891,7 @@ impl SemanticAnalyzer {
                     },
                 });
             }
+            locate_errors(&mut errors[first_error..], &enum_def.span);
 
             context.register_enum(
                 &enum_def.name,
@@ -373,13 +906,28 @@ impl SemanticAnalyzer {
         errors: &mut Vec<SemanticError>,
         context: &mut AnalysisContext,
     ) {
-        for (id, expr) in &rig_file.settings.settings {
-            match self.infer_expression_type(expr, context) {
-                Ok(expr_type) => {
+        let settings = rig_file
+            .settings
+            .settings
+            .iter()
+            .map(|(id, expr)| (id, expr, None));
+        let constants = rig_file
+            .constants
+            .iter()
+            .map(|constant| (&constant.id, &constant.expr, known_position(&constant.span)));
+        for (id, expr, position) in settings.chain(constants) {
+            match self.infer_expression(expr, context) {
+                Ok((expr_type, range)) => {
                     context.register_variable(id.as_str(), expr_type);
+                    context.set_range(id.as_str(), range);
                 }
                 Err(expr_errors) => {
-                    errors.extend(expr_errors);
+                    errors.extend(expr_errors.into_iter().map(|mut error| {
+                        if error.position.is_none() {
+                            error.position = position.clone();
+                        }
+                        error
+                    }));
                 }
             }
         }
@@ -420,10 +968,10 @@ impl SemanticAnalyzer {
         context: &mut AnalysisContext,
     ) {
         let schema_params = match self.schema.commands.get(command_name) {
-            Some(cmd) => cmd,
+            Some(cmd) => &cmd.parameters,
             None => {
                 errors.push(SemanticError {
-                    position: None,
+                    position: known_position(&command.span),
                     error_type: SemanticErrorType::CommandNotInSchema {
                         command_name: command_name.to_string(),
                     },
@@ -433,6 +981,7 @@ impl SemanticAnalyzer {
         };
 
         let mut local_context = context.clone();
+        let first_error = errors.len();
 
         for rig_param in &command.parameters {
             let schema_param_type = schema_params
@@ -466,6 +1015,18 @@ impl SemanticAnalyzer {
             }
 
             local_context.register_variable(&rig_param.name, rig_param.param_type.clone());
+            let range = match &rig_param.param_type {
+                DataType::Bool => Some(ValueRange::new(0.0, 1.0)),
+                DataType::Enum(enum_name) => context
+                    .get_enum_values(enum_name)
+                    .and_then(|values| ValueRange::hull(values.values().copied())),
+                _ => schema_params
+                    .iter()
+                    .find(|schema_param| schema_param.name == rig_param.name)
+                    .and_then(|schema_param| schema_param.range.as_ref())
+                    .map(|range| ValueRange::new(*range.start() as f64, *range.end() as f64)),
+            };
+            local_context.set_range(&rig_param.name, range);
         }
 
         for schema_param in schema_params {
@@ -492,12 +1053,14 @@ impl SemanticAnalyzer {
                 },
             });
         }
+        locate_errors(&mut errors[first_error..], &command.span);
 
         for statement in &command.statements {
             if let Err(stmt_errors) = self.validate_statement(statement, &mut local_context) {
                 errors.extend(stmt_errors);
             }
         }
+        context.warnings = local_context.warnings;
     }
 
     fn validate_statement(
@@ -506,23 +1069,27 @@ impl SemanticAnalyzer {
         context: &mut AnalysisContext,
     ) -> Result<(), Vec<SemanticError>> {
         let mut errors = Vec::new();
+        let first_warning = context.warnings.len();
+        self.check_value_ranges(statement, context, &mut errors);
 
-        match statement {
-            Statement::Assign(_id, expr) => {
-                match self.infer_expression_type(expr, context) {
-                    Ok(_expr_type) => {
+        match &statement.kind {
+            StatementKind::Assign(id, expr) => {
+                match self.infer_expression(expr, context) {
+                    Ok((_expr_type, range)) => {
                         // Assignment is valid - in a real implementation we might want to
-                        // track the variable type for future use
+                        // track the variable type for future use. The range is tracked for
+                        // the template fields and casts using the variable.
+                        context.set_range(id.as_str(), range);
                     }
                     Err(expr_errors) => {
                         errors.extend(expr_errors);
                     }
                 }
             }
-            Statement::FunctionCall { name, args } => {
+            StatementKind::FunctionCall { name, args } => {
                 self.validate_function_call(name, args, context, &mut errors);
             }
-            Statement::If {
+            StatementKind::If {
                 condition,
                 then_body,
                 else_body,
@@ -559,6 +1126,19 @@ impl SemanticAnalyzer {
                     }
                 }
             }
+            StatementKind::Match { exprs, arms } => {
+                self.validate_match(exprs, arms, context, &mut errors);
+            }
+        }
+
+        locate_errors(&mut errors, &statement.span);
+        if statement.span.is_known() {
+            for warning in context.warnings[first_warning..]
+                .iter_mut()
+                .filter(|warning| warning.position.is_none())
+            {
+                warning.position = Some(statement.span.start.clone());
+            }
         }
 
         if errors.is_empty() {
@@ -568,40 +1148,324 @@ impl SemanticAnalyzer {
         }
     }
 
-    fn validate_function_call(
+    /// Checks that the casts to enums in the expressions of `statement` only receive values
+    /// with a variant and that the fields of a written template can hold their values.
+    fn check_value_ranges(
         &self,
-        name: &str,
-        args: &[Expr],
+        statement: &Statement,
         context: &mut AnalysisContext,
         errors: &mut Vec<SemanticError>,
     ) {
-        if !self.builtin_functions.contains(name) {
-            errors.push(SemanticError {
-                position: None,
-                error_type: SemanticErrorType::UndefinedFunction {
-                    name: name.to_string(),
-                },
-            });
-            return;
-        }
+        let exprs: &[Expr] = match &statement.kind {
+            StatementKind::Assign(_, expr) => std::slice::from_ref(expr),
+            StatementKind::FunctionCall { args, .. } => args,
+            StatementKind::If { condition, .. } => std::slice::from_ref(condition),
+            StatementKind::Match { exprs, .. } => exprs,
+        };
 
-        match name {
-            "write" => {
-                if args.len() != 1 {
-                    errors.push(SemanticError {
-                        position: None,
-                        error_type: SemanticErrorType::InvalidFunctionArguments {
-                            function_name: name.to_string(),
-                            expected: 1,
-                            found: args.len(),
-                        },
-                    });
-                    return;
+        let mut casts = Vec::new();
+        let mut pending: Vec<&Expr> = exprs.iter().collect();
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expr::Cast {
+                    expr,
+                    target_type: DataType::Enum(enum_name),
+                    ..
+                } => {
+                    casts.push((enum_name, expr.as_ref()));
+                    pending.push(expr);
                 }
+                Expr::Cast { expr, .. } => pending.push(expr),
+                Expr::BinaryOp { left, right, .. } => pending.extend([left.as_ref(), right]),
+                _ => {}
+            }
+        }
 
-                match self.infer_expression_type(&args[0], context) {
-                    Ok(_) => {
-                        if let Expr::StringInterpolation { parts } = &args[0] {
+        for (enum_name, expr) in casts {
+            let (Ok((_, Some(range))), Some(values)) = (
+                self.infer_expression(expr, context),
+                context.get_enum_values(enum_name),
+            ) else {
+                continue;
+            };
+            let (min, max) = range.bounds();
+            let mapped = values
+                .values()
+                .filter(|value| (min..=max).contains(&i64::from(**value)))
+                .count() as i64;
+            if mapped == 0 {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::UnmappedEnumValue {
+                        enum_name: enum_name.clone(),
+                        min,
+                        max,
+                    },
+                });
+            } else if mapped < max - min + 1 {
+                context.warnings.push(SemanticWarning {
+                    position: None,
+                    warning_type: SemanticWarningType::PossibleUnmappedEnumValue {
+                        enum_name: enum_name.clone(),
+                        min,
+                        max,
+                    },
+                });
+            }
+        }
+
+        let StatementKind::FunctionCall { name, args } = &statement.kind else {
+            return;
+        };
+        if name != "write" {
+            return;
+        }
+        let [Expr::StringInterpolation { parts, .. }] = args.as_slice() else {
+            return;
+        };
+        for part in parts {
+            let InterpolationPart::Variable {
+                name,
+                format,
+                length,
+            } = part
+            else {
+                continue;
+            };
+            let (Some(range), Some(data_format)) =
+                (context.get_range(name), template_format(format.as_deref()))
+            else {
+                continue;
+            };
+            let (min, max) = range.bounds();
+            let (capacity_min, capacity_max) = data_format.capacity(*length);
+            let format = data_format.to_string();
+
+            if min < 0 && capacity_min == 0 {
+                if max < 0 {
+                    errors.push(SemanticError {
+                        position: None,
+                        error_type: SemanticErrorType::NegativeIntoUnsigned {
+                            name: name.clone(),
+                            format,
+                            min,
+                            max,
+                        },
+                    });
+                    continue;
+                }
+                context.warnings.push(SemanticWarning {
+                    position: None,
+                    warning_type: SemanticWarningType::PossibleNegativeIntoUnsigned {
+                        name: name.clone(),
+                        format: format.clone(),
+                        min,
+                        max,
+                    },
+                });
+            }
+
+            if min > capacity_max || max < capacity_min {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::TemplateFieldOverflow {
+                        name: name.clone(),
+                        format,
+                        length: *length,
+                        min,
+                        max,
+                    },
+                });
+            } else if max > capacity_max || (min < capacity_min && capacity_min < 0) {
+                context.warnings.push(SemanticWarning {
+                    position: None,
+                    warning_type: SemanticWarningType::PossibleTemplateFieldOverflow {
+                        name: name.clone(),
+                        format,
+                        length: *length,
+                        min,
+                        max,
+                    },
+                });
+            }
+        }
+    }
+
+    fn validate_match(
+        &self,
+        exprs: &[Expr],
+        arms: &[MatchArm],
+        context: &mut AnalysisContext,
+        errors: &mut Vec<SemanticError>,
+    ) {
+        let mut expr_types = Vec::new();
+        for expr in exprs {
+            match self.infer_expression_type(expr, context) {
+                Ok(expr_type) => expr_types.push(Some(expr_type)),
+                Err(expr_errors) => {
+                    errors.extend(expr_errors);
+                    expr_types.push(None);
+                }
+            }
+        }
+
+        for arm in arms {
+            let patterns = arm.patterns_for(exprs.len());
+            if patterns.len() != exprs.len() {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::MatchArityMismatch {
+                        expected: exprs.len(),
+                        found: patterns.len(),
+                    },
+                });
+            } else {
+                for (pattern, expected) in patterns.iter().zip(&expr_types) {
+                    let pattern_type = match pattern {
+                        Pattern::Wildcard => continue,
+                        Pattern::Integer(_) => DataType::Int,
+                        Pattern::EnumVariant(scope, id) => {
+                            let expr = Expr::QualifiedIdentifier(scope.clone(), id.clone());
+                            match self.infer_expression_type(&expr, context) {
+                                Ok(pattern_type) => pattern_type,
+                                Err(pattern_errors) => {
+                                    errors.extend(pattern_errors);
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+
+                    if let Some(expected) = expected
+                        && *expected != pattern_type
+                    {
+                        errors.push(SemanticError {
+                            position: None,
+                            error_type: SemanticErrorType::TypeMismatch {
+                                expected: expected.clone(),
+                                found: pattern_type,
+                                context: "match pattern".to_string(),
+                            },
+                        });
+                    }
+                }
+            }
+
+            for stmt in &arm.body {
+                if let Err(stmt_errors) = self.validate_statement(stmt, context) {
+                    errors.extend(stmt_errors);
+                }
+            }
+        }
+
+        let Some(expr_types) = expr_types.into_iter().collect::<Option<Vec<_>>>() else {
+            return;
+        };
+
+        // Values that are not enums can only be covered by a wildcard, represented by `None`.
+        let domains: Vec<Vec<Option<String>>> = expr_types
+            .iter()
+            .map(|expr_type| match expr_type {
+                DataType::Enum(enum_name) => {
+                    let mut variants: Vec<_> = context
+                        .get_enum_variants(enum_name)
+                        .into_iter()
+                        .flatten()
+                        .map(|variant| Some(format!("{enum_name}::{variant}")))
+                        .collect();
+                    variants.sort();
+                    variants
+                }
+                _ => vec![None],
+            })
+            .collect();
+
+        let mut combinations: Vec<Vec<Option<String>>> = vec![vec![]];
+        for domain in &domains {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|prefix| {
+                    domain.iter().map(move |value| {
+                        let mut combination = prefix.clone();
+                        combination.push(value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        let missing: Vec<String> = combinations
+            .into_iter()
+            .filter(|combination| {
+                !arms.iter().any(|arm| {
+                    let patterns = arm.patterns_for(combination.len());
+                    patterns.len() == combination.len()
+                        && patterns.iter().zip(combination).all(|(pattern, value)| {
+                            match (pattern, value) {
+                                (Pattern::Wildcard, _) => true,
+                                (pattern @ Pattern::EnumVariant(..), Some(variant)) => {
+                                    pattern.to_string() == *variant
+                                }
+                                _ => false,
+                            }
+                        })
+                })
+            })
+            .map(|combination| {
+                let values: Vec<_> = combination
+                    .into_iter()
+                    .map(|value| value.unwrap_or_else(|| "_".to_string()))
+                    .collect();
+                if values.len() == 1 {
+                    values[0].clone()
+                } else {
+                    format!("({})", values.join(", "))
+                }
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::NonExhaustiveMatch { missing },
+            });
+        }
+    }
+
+    fn validate_function_call(
+        &self,
+        name: &str,
+        args: &[Expr],
+        context: &mut AnalysisContext,
+        errors: &mut Vec<SemanticError>,
+    ) {
+        if !self.builtin_functions.contains(name) {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::UndefinedFunction {
+                    name: name.to_string(),
+                },
+            });
+            return;
+        }
+
+        match name {
+            "write" => {
+                if args.len() != 1 {
+                    errors.push(SemanticError {
+                        position: None,
+                        error_type: SemanticErrorType::InvalidFunctionArguments {
+                            function_name: name.to_string(),
+                            expected: 1,
+                            found: args.len(),
+                        },
+                    });
+                    return;
+                }
+
+                match self.infer_expression_type(&args[0], context) {
+                    Ok(_) => {
+                        if let Expr::StringInterpolation { parts, .. } = &args[0] {
                             self.validate_string_interpolation(parts, context, errors);
                         }
                     }
@@ -625,24 +1489,39 @@ impl SemanticAnalyzer {
 
                 match &args[0] {
                     Expr::Bytes(_) => {}
-                    Expr::StringInterpolation { parts } => {
-                        for part in parts {
-                            if let InterpolationPart::Variable { name, .. } = part {
+                    expr if !expr.read_templates().is_empty() => {
+                        for part in expr.read_templates().into_iter().flatten() {
+                            if let InterpolationPart::Variable {
+                                name,
+                                format,
+                                length,
+                            } = part
+                                && name != "_"
+                            {
                                 context.register_variable(name, DataType::Int);
+                                let range = template_format(format.as_deref()).map(|format| {
+                                    let (min, max) = format.capacity(*length);
+                                    ValueRange::new(min as f64, max as f64)
+                                });
+                                context.set_range(name, range);
                             }
                         }
                     }
-                    _ => {
-                        errors.push(SemanticError {
-                            position: None,
-                            error_type: SemanticErrorType::InvalidFunctionArgumentType {
-                                function_name: "read".into(),
-                                arg_index: 0,
-                                expected: "Bytes or StringInterpolation".into(),
-                                found: format!("{:?}", args[0]),
-                            },
-                        });
-                    }
+                    expr => match self.infer_expression_type(expr, context) {
+                        Ok(DataType::Bytes) => {}
+                        Ok(found_type) => {
+                            errors.push(SemanticError {
+                                position: None,
+                                error_type: SemanticErrorType::InvalidFunctionArgumentType {
+                                    function_name: "read".into(),
+                                    arg_index: 0,
+                                    expected: "Bytes or StringInterpolation".into(),
+                                    found: found_type.to_string(),
+                                },
+                            });
+                        }
+                        Err(expr_errors) => errors.extend(expr_errors),
+                    },
                 }
             }
             "set_var" => {
@@ -720,16 +1599,27 @@ impl SemanticAnalyzer {
         expr: &Expr,
         context: &AnalysisContext,
     ) -> Result<DataType, Vec<SemanticError>> {
+        self.infer_expression(expr, context)
+            .map(|(expr_type, _)| expr_type)
+    }
+
+    /// Infers the type of an expression and, for numbers, the range of values it can take
+    /// given the ranges of the variables it uses.
+    fn infer_expression(
+        &self,
+        expr: &Expr,
+        context: &AnalysisContext,
+    ) -> Result<(DataType, Option<ValueRange>), Vec<SemanticError>> {
         let mut errors = Vec::new();
 
-        let expr_type = match expr {
-            Expr::Integer(_) => DataType::Int,
-            Expr::Float(_) => DataType::Float,
-            Expr::String(_) => DataType::String,
-            Expr::Bytes(_) => DataType::Bytes,
+        let (expr_type, range) = match expr {
+            Expr::Integer(value) => (DataType::Int, Some(ValueRange::exact(*value as f64))),
+            Expr::Float(value) => (DataType::Float, Some(ValueRange::exact(*value))),
+            Expr::String(_) => (DataType::String, None),
+            Expr::Bytes(_) => (DataType::Bytes, None),
             Expr::Identifier(id) => {
                 match context.get_variable_type(id.as_str()) {
-                    Some(var_type) => var_type,
+                    Some(var_type) => (var_type, context.get_range(id.as_str())),
                     None => {
                         errors.push(SemanticError {
                             position: None,
@@ -738,7 +1628,7 @@ impl SemanticAnalyzer {
                             },
                         });
                         // Default fallback
-                        DataType::Int
+                        (DataType::Int, None)
                     }
                 }
             }
@@ -753,7 +1643,10 @@ impl SemanticAnalyzer {
                             },
                         });
                     }
-                    DataType::Enum(enum_name.as_str().to_string())
+                    let range = context
+                        .get_enum_value(enum_name.as_str(), variant_name.as_str())
+                        .map(|value| ValueRange::exact(value.into()));
+                    (DataType::Enum(enum_name.as_str().to_string()), range)
                 } else {
                     errors.push(SemanticError {
                         position: None,
@@ -762,25 +1655,27 @@ impl SemanticAnalyzer {
                         },
                     });
                     // Default fallback
-                    DataType::Int
+                    (DataType::Int, None)
                 }
             }
-            Expr::BinaryOp { left, op, right } => {
-                let left_type = match self.infer_expression_type(left, context) {
-                    Ok(t) => t,
+            Expr::BinaryOp {
+                left, op, right, ..
+            } => {
+                let (left_type, left_range) = match self.infer_expression(left, context) {
+                    Ok(inferred) => inferred,
                     Err(expr_errors) => {
                         errors.extend(expr_errors);
                         // Default fallback
-                        DataType::Int
+                        (DataType::Int, None)
                     }
                 };
 
-                let right_type = match self.infer_expression_type(right, context) {
-                    Ok(t) => t,
+                let (right_type, right_range) = match self.infer_expression(right, context) {
+                    Ok(inferred) => inferred,
                     Err(expr_errors) => {
                         errors.extend(expr_errors);
                         // Default fallback
-                        DataType::Int
+                        (DataType::Int, None)
                     }
                 };
 
@@ -796,19 +1691,27 @@ impl SemanticAnalyzer {
                 }
 
                 match self.validate_binary_op(&left_type, op, &right_type) {
-                    Ok(result_type) => result_type,
+                    Ok(DataType::Bool) => (DataType::Bool, Some(ValueRange::new(0.0, 1.0))),
+                    Ok(result_type) => {
+                        let range = left_range
+                            .zip(right_range)
+                            .and_then(|(left, right)| left.apply(op, right));
+                        (result_type, range)
+                    }
                     Err(error) => {
                         errors.push(*error);
-                        DataType::Bool
+                        (DataType::Bool, None)
                     }
                 }
             }
-            Expr::StringInterpolation { parts } => {
+            Expr::StringInterpolation { parts, .. } => {
                 self.validate_string_interpolation(parts, context, &mut errors);
-                DataType::Bytes
+                (DataType::Bytes, None)
             }
-            Expr::Cast { expr, target_type } => {
-                let expr_type = self.infer_expression_type(expr, context)?;
+            Expr::Cast {
+                expr, target_type, ..
+            } => {
+                let (expr_type, range) = self.infer_expression(expr, context)?;
                 if !self.validate_cast(&expr_type, target_type) {
                     errors.push(SemanticError {
                         position: None,
@@ -818,12 +1721,26 @@ impl SemanticAnalyzer {
                         },
                     });
                 }
-                target_type.clone()
+                let range = match target_type {
+                    DataType::Int => range.map(ValueRange::truncate),
+                    DataType::Bool => Some(ValueRange::new(0.0, 1.0)),
+                    // Values without a variant fail the cast.
+                    DataType::Enum(enum_name) => range.and_then(|range| {
+                        context
+                            .get_enum_values(enum_name)
+                            .and_then(|values| range.restrict(values.values().copied()))
+                    }),
+                    _ => range,
+                };
+                (target_type.clone(), range)
             }
         };
 
+        if let Some(span) = expr.span() {
+            locate_errors(&mut errors, span);
+        }
         if errors.is_empty() {
-            Ok(expr_type)
+            Ok((expr_type, range))
         } else {
             Err(errors)
         }
@@ -843,6 +1760,11 @@ impl SemanticAnalyzer {
             | BinaryOp::Modulo => {
                 if left_type.is_numeric() && right_type.is_numeric() {
                     Ok(DataType::Int)
+                } else if *op == BinaryOp::Add
+                    && *left_type == DataType::Bytes
+                    && *right_type == DataType::Bytes
+                {
+                    Ok(DataType::Bytes)
                 } else {
                     Err(Box::new(SemanticError {
                         position: None,
@@ -941,60 +1863,131 @@ impl SemanticAnalyzer {
     ) {
         if let Some(status) = &rig_file.impl_block.status {
             for statement in &status.statements {
-                if let Statement::FunctionCall { name, args } = statement
-                    && name == "set_var"
-                {
-                    if args.len() != 2 {
+                let first_error = errors.len();
+                self.validate_status_statement(statement, errors, context);
+                locate_errors(&mut errors[first_error..], &statement.span);
+            }
+        }
+    }
+
+    /// Checks that a `set_var` of the status block sets a status field with a value of its type.
+    fn validate_status_statement(
+        &self,
+        statement: &Statement,
+        errors: &mut Vec<SemanticError>,
+        context: &mut AnalysisContext,
+    ) {
+        let StatementKind::FunctionCall { name, args } = &statement.kind else {
+            return;
+        };
+        if name != "set_var" {
+            return;
+        }
+
+        if args.len() != 2 {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::InvalidFunctionArguments {
+                    function_name: "set_var".to_string(),
+                    expected: 2,
+                    found: args.len(),
+                },
+            });
+            return;
+        }
+
+        let Expr::String(var_name) = &args[0] else {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::InvalidFunctionArgumentType {
+                    function_name: "set_var".to_string(),
+                    arg_index: 0,
+                    expected: "string literal".to_string(),
+                    found: "non-string expression".to_string(),
+                },
+            });
+            return;
+        };
+
+        if let Some(expected_type) = self
+            .schema
+            .status
+            .get(var_name)
+            .map(|field| &field.param_type)
+        {
+            if let Ok(found_type) = self.infer_expression_type(&args[1], context)
+                && &found_type != expected_type
+            {
+                errors.push(SemanticError {
+                    position: None,
+                    error_type: SemanticErrorType::StatusVariableTypeMismatch {
+                        name: var_name.clone(),
+                        expected: expected_type.clone(),
+                        found: found_type,
+                    },
+                });
+            }
+        } else {
+            errors.push(SemanticError {
+                position: None,
+                error_type: SemanticErrorType::InvalidStatusVariable {
+                    name: var_name.clone(),
+                    context: "status block".to_string(),
+                },
+            });
+        }
+    }
+
+    fn validate_tests(&self, rig_file: &RigFile, errors: &mut Vec<SemanticError>) {
+        for step in rig_file
+            .impl_block
+            .tests
+            .iter()
+            .flat_map(|test| &test.steps)
+        {
+            let position = Some(step.span.start.clone());
+            match &step.kind {
+                TestStepKind::Call { command, args } => {
+                    let expected = match command.as_str() {
+                        "init" | "status" => 0,
+                        _ => match rig_file.impl_block.commands.get(command) {
+                            Some(command) => command.parameters.len(),
+                            None => {
+                                errors.push(SemanticError {
+                                    position,
+                                    error_type: SemanticErrorType::UndefinedFunction {
+                                        name: command.clone(),
+                                    },
+                                });
+                                continue;
+                            }
+                        },
+                    };
+                    if args.len() != expected {
                         errors.push(SemanticError {
-                            position: None,
+                            position,
                             error_type: SemanticErrorType::InvalidFunctionArguments {
-                                function_name: "set_var".to_string(),
-                                expected: 2,
+                                function_name: command.clone(),
+                                expected,
                                 found: args.len(),
                             },
                         });
-                        continue;
                     }
-
-                    let var_name = match &args[0] {
-                        Expr::String(name) => name,
-                        _ => {
-                            errors.push(SemanticError {
-                                position: None,
-                                error_type: SemanticErrorType::InvalidFunctionArgumentType {
-                                    function_name: "set_var".to_string(),
-                                    arg_index: 0,
-                                    expected: "string literal".to_string(),
-                                    found: "non-string expression".to_string(),
-                                },
-                            });
-                            continue;
-                        }
-                    };
-
-                    if let Some(expected_type) = self.schema.status.get(var_name) {
-                        if let Ok(found_type) = self.infer_expression_type(&args[1], context)
-                            && &found_type != expected_type
-                        {
+                }
+                TestStepKind::ExpectStatus(fields) => {
+                    for (name, _) in fields {
+                        if !self.schema.status.contains_key(name.as_str()) {
                             errors.push(SemanticError {
-                                position: None,
-                                error_type: SemanticErrorType::StatusVariableTypeMismatch {
-                                    name: var_name.clone(),
-                                    expected: expected_type.clone(),
-                                    found: found_type,
+                                position: position.clone(),
+                                error_type: SemanticErrorType::InvalidStatusVariable {
+                                    name: name.to_string(),
+                                    context: "test".to_string(),
                                 },
                             });
                         }
-                    } else {
-                        errors.push(SemanticError {
-                            position: None,
-                            error_type: SemanticErrorType::InvalidStatusVariable {
-                                name: var_name.clone(),
-                                context: "status block".to_string(),
-                            },
-                        });
                     }
                 }
+                TestStepKind::ExpectWrite(_) | TestStepKind::Reply(_) => {}
             }
         }
     }
@@ -1012,17 +2005,114 @@ impl SemanticAnalyzer {
     }
 }
 
+/// Interval of the values a number can take, inferred along with the expression types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ValueRange {
+    min: f64,
+    max: f64,
+}
+
+impl ValueRange {
+    fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    fn exact(value: f64) -> Self {
+        Self::new(value, value)
+    }
+
+    /// Smallest and largest integers the range can hold.
+    fn bounds(self) -> (i64, i64) {
+        (self.min.floor() as i64, self.max.ceil() as i64)
+    }
+
+    fn truncate(self) -> Self {
+        Self::new(self.min.trunc(), self.max.trunc())
+    }
+
+    /// Range of the result of an arithmetic operation, `None` when it cannot be bounded.
+    fn apply(self, op: &BinaryOp, other: Self) -> Option<Self> {
+        let products = |values: [f64; 4]| {
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            Self::new(min, max)
+        };
+        let range = match op {
+            BinaryOp::Add => Self::new(self.min + other.min, self.max + other.max),
+            BinaryOp::Subtract => Self::new(self.min - other.max, self.max - other.min),
+            BinaryOp::Multiply => products([
+                self.min * other.min,
+                self.min * other.max,
+                self.max * other.min,
+                self.max * other.max,
+            ]),
+            BinaryOp::Divide if other.min > 0.0 || other.max < 0.0 => {
+                let quotients = products([
+                    self.min / other.min,
+                    self.min / other.max,
+                    self.max / other.min,
+                    self.max / other.max,
+                ]);
+                // Integer division truncates towards zero.
+                let truncated = quotients.truncate();
+                Self::new(
+                    quotients.min.min(truncated.min),
+                    quotients.max.max(truncated.max),
+                )
+            }
+            BinaryOp::Modulo if other.min > 0.0 || other.max < 0.0 => {
+                // The remainder is smaller than the divisor and has the sign of the dividend.
+                let divisor = other.min.abs().max(other.max.abs());
+                Self::new(
+                    self.min.max(-divisor).min(0.0),
+                    self.max.min(divisor).max(0.0),
+                )
+            }
+            _ => return None,
+        };
+        (range.min.is_finite() && range.max.is_finite()).then_some(range)
+    }
+
+    /// Smallest range holding all the values, `None` without values.
+    fn hull(values: impl IntoIterator<Item = u32>) -> Option<Self> {
+        values
+            .into_iter()
+            .map(f64::from)
+            .fold(None, |range, value| {
+                Some(range.map_or(Self::exact(value), |range: Self| {
+                    Self::new(range.min.min(value), range.max.max(value))
+                }))
+            })
+    }
+
+    /// Narrows the range to the given values, `None` when it holds none of them.
+    fn restrict(self, values: impl IntoIterator<Item = u32>) -> Option<Self> {
+        Self::hull(
+            values
+                .into_iter()
+                .filter(|value| (self.min..=self.max).contains(&f64::from(*value))),
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 struct AnalysisContext {
     variables: HashMap<String, DataType>,
+    ranges: HashMap<String, ValueRange>,
     enums: HashMap<String, HashSet<String>>,
+    enum_values: HashMap<String, BTreeMap<String, u32>>,
+    /// Warnings found while validating statements, see `SemanticAnalyzer::warnings`.
+    warnings: Vec<SemanticWarning>,
 }
 
 impl AnalysisContext {
     fn new(_schema: &SchemaFile, rig_file: &RigFile) -> Self {
         let mut context = Self {
             variables: HashMap::new(),
+            ranges: HashMap::new(),
             enums: HashMap::new(),
+            enum_values: HashMap::new(),
+            warnings: Vec::new(),
         };
 
         // for (enum_name, enum_def) in &schema.enums {
@@ -1033,6 +2123,9 @@ impl AnalysisContext {
         for enum_def in &rig_file.impl_block.enums {
             let variants = enum_def.variants.keys().cloned().collect();
             context.enums.insert(enum_def.name.clone(), variants);
+            context
+                .enum_values
+                .insert(enum_def.name.clone(), enum_def.variants.clone());
         }
 
         context
@@ -1058,60 +2151,497 @@ impl AnalysisContext {
     fn get_enum_variants(&self, enum_name: &str) -> Option<&HashSet<String>> {
         self.enums.get(enum_name)
     }
-}
 
-pub fn semantic_errors_to_parse_errors(
-    errors: Vec<SemanticError>,
-    source: &str,
-) -> Vec<ParseError> {
-    errors
-        .into_iter()
-        .map(|error| ParseError {
-            position: error
-                .position
-                .clone()
-                .unwrap_or_else(|| SourcePosition::new(1, 1, 0)),
-            error_type: Box::new(ParseErrorType::Semantic {
-                message: error.to_string(),
-                suggestion: None,
-            }),
-            source: source.to_string(),
-            level: ErrorLevel::Normal,
-        })
-        .collect()
-}
+    /// Sets the range of a variable, `None` when its values are unknown.
+    fn set_range(&mut self, name: &str, range: Option<ValueRange>) {
+        match range {
+            Some(range) => self.ranges.insert(name.to_string(), range),
+            None => self.ranges.remove(name),
+        };
+    }
 
-pub fn parse_and_validate_with_schema(
-    rig_source: &str,
-    schemas: &HashMap<String, SchemaFile>,
-) -> Result<RigFile, Vec<ParseError>> {
-    let rig_file = super::parser::parse_rig_file(rig_source).map_err(|x| vec![x])?;
-    let schema_name = &rig_file.impl_block.schema;
-    let schema = schemas.get(schema_name).ok_or_else(|| {
-        vec![ParseError {
-            position: SourcePosition::new(1, 1, 0),
+    fn get_range(&self, name: &str) -> Option<ValueRange> {
+        self.ranges.get(name).copied()
+    }
+
+    fn get_enum_values(&self, enum_name: &str) -> Option<&BTreeMap<String, u32>> {
+        self.enum_values.get(enum_name)
+    }
+
+    fn get_enum_value(&self, enum_name: &str, variant_name: &str) -> Option<u32> {
+        self.enum_values.get(enum_name)?.get(variant_name).copied()
+    }
+}
+
+/// Format of a template field, fields without one are little endian unsigned integers.
+fn template_format(format: Option<&str>) -> Option<DataFormat> {
+    DataFormat::try_from(format.unwrap_or("int_lu")).ok()
+}
+
+fn known_position(span: &Span) -> Option<SourcePosition> {
+    span.is_known().then(|| span.start.clone())
+}
+
+/// Points the errors without a position at the start of `span`, when it is known.
+fn locate_errors(errors: &mut [SemanticError], span: &Span) {
+    for error in errors.iter_mut().filter(|error| error.position.is_none()) {
+        error.position = known_position(span);
+    }
+}
+
+fn push_unread_write(span: &Span, warnings: &mut Vec<SemanticWarning>) {
+    let position = known_position(span);
+    if position.is_some()
+        && warnings.iter().any(|warning| {
+            matches!(warning.warning_type, SemanticWarningType::WriteWithoutRead)
+                && warning.position == position
+        })
+    {
+        return;
+    }
+    warnings.push(SemanticWarning {
+        position,
+        warning_type: SemanticWarningType::WriteWithoutRead,
+    });
+}
+
+/// Follows the writes in `statements` through every branch, warns about the writes followed by
+/// another write and returns the ones still waiting for a read at the end of the statements.
+fn check_reads<'a>(
+    statements: &'a [Statement],
+    mut pending: Vec<&'a Span>,
+    warnings: &mut Vec<SemanticWarning>,
+) -> Vec<&'a Span> {
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::FunctionCall { name, .. } if name == "write" => {
+                for span in pending.drain(..) {
+                    push_unread_write(span, warnings);
+                }
+                pending.push(&statement.span);
+            }
+            // A failing command ends the exchange, nothing is left to read.
+            StatementKind::FunctionCall { name, .. } if name == "read" || name == "error" => {
+                pending.clear();
+            }
+            StatementKind::FunctionCall { .. } | StatementKind::Assign(..) => {}
+            StatementKind::If {
+                then_body,
+                else_body,
+                ..
+            } => {
+                let mut branches = check_reads(then_body, pending.clone(), warnings);
+                match else_body {
+                    Some(else_body) => branches.extend(check_reads(else_body, pending, warnings)),
+                    None => branches.extend(pending),
+                }
+                pending = branches;
+            }
+            StatementKind::Match { arms, .. } => {
+                let mut branches = Vec::new();
+                for arm in arms {
+                    branches.extend(check_reads(&arm.body, pending.clone(), warnings));
+                }
+                pending = branches;
+            }
+        }
+        pending.sort_by_key(|span| span.start.offset);
+        pending.dedup_by_key(|span| span.start.offset);
+    }
+    pending
+}
+
+fn expr_variables<'a>(expr: &'a Expr, used: &mut HashSet<&'a str>) {
+    match expr {
+        Expr::Identifier(id) => {
+            used.insert(id.as_str());
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            expr_variables(left, used);
+            expr_variables(right, used);
+        }
+        Expr::StringInterpolation { parts, .. } => {
+            for part in parts {
+                if let InterpolationPart::Variable { name, .. } = part {
+                    used.insert(name);
+                }
+            }
+        }
+        Expr::Cast { expr, .. } => expr_variables(expr, used),
+        Expr::Integer(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Bytes(_)
+        | Expr::QualifiedIdentifier(..) => {}
+    }
+}
+
+/// Variables assigned or captured by a `read` template in `statements`, with the statement
+/// defining them, and the variables the statements use.
+fn collect_variables<'a>(
+    statements: &'a [Statement],
+    defined: &mut Vec<(&'a str, &'a Span)>,
+    used: &mut HashSet<&'a str>,
+) {
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Assign(id, expr) => {
+                expr_variables(expr, used);
+                defined.push((id.as_str(), &statement.span));
+            }
+            StatementKind::FunctionCall { name, args } => match args.first() {
+                Some(expr) if name == "read" && !expr.read_templates().is_empty() => {
+                    for part in expr.read_templates().into_iter().flatten() {
+                        if let InterpolationPart::Variable { name, .. } = part {
+                            defined.push((name, &statement.span));
+                        }
+                    }
+                }
+                _ => {
+                    for arg in args {
+                        expr_variables(arg, used);
+                    }
+                }
+            },
+            StatementKind::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                expr_variables(condition, used);
+                collect_variables(then_body, defined, used);
+                collect_variables(else_body.as_deref().unwrap_or_default(), defined, used);
+            }
+            StatementKind::Match { exprs, arms } => {
+                for expr in exprs {
+                    expr_variables(expr, used);
+                }
+                for arm in arms {
+                    collect_variables(&arm.body, defined, used);
+                }
+            }
+        }
+    }
+}
+
+fn check_unused_variables(statements: &[Statement], warnings: &mut Vec<SemanticWarning>) {
+    let mut defined = Vec::new();
+    let mut used = HashSet::new();
+    collect_variables(statements, &mut defined, &mut used);
+
+    let mut reported = HashSet::new();
+    for (name, span) in defined {
+        if !name.starts_with('_') && !used.contains(name) && reported.insert(name) {
+            warnings.push(SemanticWarning {
+                position: known_position(span),
+                warning_type: SemanticWarningType::UnusedVariable {
+                    name: name.to_string(),
+                },
+            });
+        }
+    }
+}
+
+/// Warns about the branches of `if` statements with a constant condition that are never taken
+/// and the match arms only matching values the arms before them already match.
+fn check_unreachable_branches(
+    statements: &[Statement],
+    enums: &HashMap<&str, Vec<&String>>,
+    constants: &HashMap<&str, i64>,
+    warnings: &mut Vec<SemanticWarning>,
+) {
+    for statement in statements {
+        let mut unreachable = |branch: String| {
+            warnings.push(SemanticWarning {
+                position: known_position(&statement.span),
+                warning_type: SemanticWarningType::UnreachableBranch { branch },
+            });
+        };
+        match &statement.kind {
+            StatementKind::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                if let Some(value) = constant_value(condition, constants) {
+                    if value == 0 {
+                        unreachable("if branch".to_string());
+                    } else if else_body.is_some() {
+                        unreachable("else branch".to_string());
+                    }
+                }
+                check_unreachable_branches(then_body, enums, constants, warnings);
+                if let Some(else_body) = else_body {
+                    check_unreachable_branches(else_body, enums, constants, warnings);
+                }
+            }
+            StatementKind::Match { exprs, arms } => {
+                for (index, arm) in arms.iter().enumerate() {
+                    if arm_is_unreachable(exprs.len(), &arms[..index], arm, enums) {
+                        let patterns: Vec<_> = arm
+                            .patterns
+                            .iter()
+                            .map(|pattern| pattern.to_string())
+                            .collect();
+                        let patterns = match patterns.as_slice() {
+                            [pattern] => pattern.clone(),
+                            _ => format!("({})", patterns.join(", ")),
+                        };
+                        unreachable(format!("match arm '{patterns}'"));
+                    }
+                }
+                for arm in arms {
+                    check_unreachable_branches(&arm.body, enums, constants, warnings);
+                }
+            }
+            StatementKind::Assign(..) | StatementKind::FunctionCall { .. } => {}
+        }
+    }
+}
+
+/// Value of an integer or boolean expression only made of literals and `constants`, booleans
+/// being 0 or 1.
+fn constant_value(expr: &Expr, constants: &HashMap<&str, i64>) -> Option<i64> {
+    match expr {
+        Expr::Integer(value) => Some(*value),
+        Expr::Identifier(id) => constants.get(id.as_str()).copied(),
+        Expr::Cast {
+            expr,
+            target_type: DataType::Int,
+            ..
+        } => constant_value(expr, constants),
+        Expr::Cast {
+            expr,
+            target_type: DataType::Bool,
+            ..
+        } => constant_value(expr, constants).map(|value| i64::from(value != 0)),
+        Expr::BinaryOp {
+            left, op, right, ..
+        } => {
+            let left = constant_value(left, constants)?;
+            let right = constant_value(right, constants)?;
+            match op {
+                BinaryOp::Add => left.checked_add(right),
+                BinaryOp::Subtract => left.checked_sub(right),
+                BinaryOp::Multiply => left.checked_mul(right),
+                BinaryOp::Divide => left.checked_div(right),
+                BinaryOp::Modulo => left.checked_rem(right),
+                BinaryOp::Equal => Some(i64::from(left == right)),
+                BinaryOp::NotEqual => Some(i64::from(left != right)),
+                BinaryOp::Less => Some(i64::from(left < right)),
+                BinaryOp::LessEqual => Some(i64::from(left <= right)),
+                BinaryOp::Greater => Some(i64::from(left > right)),
+                BinaryOp::GreaterEqual => Some(i64::from(left >= right)),
+                BinaryOp::And => Some(i64::from(left != 0 && right != 0)),
+                BinaryOp::Or => Some(i64::from(left != 0 || right != 0)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether every value `arm` matches is matched by one of the `previous` arms. Wildcards over
+/// rig enums stand for each of their variants, other wildcards only match another wildcard.
+fn arm_is_unreachable(
+    arity: usize,
+    previous: &[MatchArm],
+    arm: &MatchArm,
+    enums: &HashMap<&str, Vec<&String>>,
+) -> bool {
+    let previous: Vec<_> = previous.iter().map(|arm| arm.patterns_for(arity)).collect();
+    let arm = arm.patterns_for(arity);
+    if arm.len() != arity || previous.iter().any(|patterns| patterns.len() != arity) {
+        return false;
+    }
+
+    let domain = |index: usize| {
+        previous
+            .iter()
+            .chain([&arm])
+            .find_map(|patterns| match &patterns[index] {
+                Pattern::EnumVariant(scope, _) => enums.get(scope.as_str()).map(|variants| {
+                    variants
+                        .iter()
+                        .map(|variant| format!("{scope}::{variant}"))
+                        .collect::<Vec<_>>()
+                }),
+                _ => None,
+            })
+    };
+
+    let mut values: Vec<Vec<String>> = vec![vec![]];
+    for (index, pattern) in arm.iter().enumerate() {
+        let options = match (pattern, domain(index)) {
+            (Pattern::Wildcard, Some(variants)) => variants,
+            (pattern, _) => vec![pattern.to_string()],
+        };
+        values = values
+            .into_iter()
+            .flat_map(|prefix| {
+                options.iter().map(move |option| {
+                    let mut value = prefix.clone();
+                    value.push(option.clone());
+                    value
+                })
+            })
+            .collect();
+    }
+
+    !values.is_empty()
+        && values.iter().all(|value| {
+            previous.iter().any(|patterns| {
+                patterns.iter().zip(value).all(|(pattern, value)| {
+                    matches!(pattern, Pattern::Wildcard) || pattern.to_string() == *value
+                })
+            })
+        })
+}
+
+/// Drops the errors likely caused by the statements syntax error recovery dropped: undefined
+/// variables in the regions affected by it and, as they have no position, empty commands.
+pub fn filter_cascading_errors(errors: Vec<SemanticError>, skipped: &[Span]) -> Vec<SemanticError> {
+    if skipped.is_empty() {
+        return errors;
+    }
+    errors
+        .into_iter()
+        .filter(|error| match error.error_type {
+            SemanticErrorType::EmptyCommand { .. } => false,
+            SemanticErrorType::UndefinedVariable { .. } => {
+                !error.position.as_ref().is_some_and(|position| {
+                    skipped
+                        .iter()
+                        .any(|span| (span.start.offset..span.end.offset).contains(&position.offset))
+                })
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+pub fn semantic_errors_to_parse_errors(
+    errors: Vec<SemanticError>,
+    source: &str,
+) -> Vec<ParseError> {
+    errors
+        .into_iter()
+        .map(|error| ParseError {
+            position: error
+                .position
+                .clone()
+                .unwrap_or_else(|| SourcePosition::new(1, 1, 0)),
             error_type: Box::new(ParseErrorType::Semantic {
-                message: format!("Unknown schema: {schema_name}"),
+                message: error.to_string(),
                 suggestion: None,
+                code: error.error_type.code(),
             }),
-            source: rig_source.to_string(),
+            source: source.to_string(),
             level: ErrorLevel::Normal,
-        }]
-    })?;
+        })
+        .collect()
+}
+
+pub fn parse_and_validate_with_schema(
+    rig_source: &str,
+    schemas: &HashMap<String, SchemaFile>,
+) -> Result<RigFile, Vec<ParseError>> {
+    parse_and_validate_with_imports(rig_source, schemas, |path| {
+        Err(anyhow::anyhow!(
+            "Cannot import '{path}' without a rigs directory"
+        ))
+    })
+}
+
+/// Parses a rig file, merges the modules it imports using `load` and validates the result
+/// against its schema. Parsing recovers from syntax errors, the parts that parsed are still
+/// validated and all errors are returned together.
+pub fn parse_and_validate_with_imports(
+    rig_source: &str,
+    schemas: &HashMap<String, SchemaFile>,
+    load: impl FnMut(&str) -> anyhow::Result<String>,
+) -> Result<RigFile, Vec<ParseError>> {
+    let (result, _) = check_rig_source(rig_source, schemas, load, false);
+    result
+}
+
+/// Like `parse_and_validate_with_imports`, also returning the warnings of the rig file, whether
+/// or not it has errors. Warnings are left out when the syntax error recovery dropped statements,
+/// they would show up as unused variables and unread writes.
+pub fn parse_and_check_with_imports(
+    rig_source: &str,
+    schemas: &HashMap<String, SchemaFile>,
+    load: impl FnMut(&str) -> anyhow::Result<String>,
+) -> (Result<RigFile, Vec<ParseError>>, Vec<SemanticWarning>) {
+    check_rig_source(rig_source, schemas, load, true)
+}
+
+fn check_rig_source(
+    rig_source: &str,
+    schemas: &HashMap<String, SchemaFile>,
+    load: impl FnMut(&str) -> anyhow::Result<String>,
+    lint: bool,
+) -> (Result<RigFile, Vec<ParseError>>, Vec<SemanticWarning>) {
+    let semantic_error = |position: SourcePosition, message: String| ParseError {
+        position,
+        error_type: Box::new(ParseErrorType::Semantic {
+            message,
+            suggestion: None,
+            code: ParseError::RESOLUTION_CODE,
+        }),
+        source: rig_source.to_string(),
+        level: ErrorLevel::Normal,
+    };
+
+    let Recovered {
+        value,
+        mut errors,
+        skipped,
+    } = parse_rig_file_with_recovery(rig_source);
+    let Some(mut rig_file) = value else {
+        return (Err(errors), Vec::new());
+    };
+    if let Err(err) = rig_file.resolve_imports(load) {
+        errors.push(semantic_error(err.span.start.clone(), err.to_string()));
+        return (Err(errors), Vec::new());
+    }
+
+    let schema_name = &rig_file.impl_block.schema;
+    let Some(schema) = schemas.get(schema_name) else {
+        errors.push(semantic_error(
+            SourcePosition::new(1, 1, 0),
+            format!("Unknown schema: {schema_name}"),
+        ));
+        return (Err(errors), Vec::new());
+    };
 
     let analyzer = SemanticAnalyzer::new(schema.clone());
-    analyzer
-        .analyze(&rig_file)
-        .map(|_| rig_file)
-        .map_err(|err| semantic_errors_to_parse_errors(err, rig_source))
+    let (semantic_errors, warnings) = if lint && skipped.is_empty() {
+        analyzer.diagnose(&rig_file)
+    } else {
+        (
+            analyzer.analyze(&rig_file).err().unwrap_or_default(),
+            Vec::new(),
+        )
+    };
+    if !semantic_errors.is_empty() {
+        let semantic_errors = filter_cascading_errors(semantic_errors, &skipped);
+        errors.extend(semantic_errors_to_parse_errors(semantic_errors, rig_source));
+        errors.sort_by_key(|err| err.position.offset);
+    }
+    if errors.is_empty() {
+        (Ok(rig_file), warnings)
+    } else {
+        (Err(errors), warnings)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
     use super::*;
-    use crate::runtime::{parser::parse_rig_file, schema_parser::SchemaParameter};
+    use crate::runtime::{
+        parser::parse_rig_file,
+        schema_parser::{SchemaCommand, SchemaParameter},
+    };
 
     fn create_test_schema() -> SchemaFile {
         let mut schema = SchemaFile {
@@ -1128,16 +2658,13 @@ mod tests {
 
         schema.commands.insert(
             "set_freq".to_string(),
-            vec![
-                SchemaParameter {
-                    param_type: DataType::Int,
-                    name: "freq".to_string(),
-                },
-                SchemaParameter {
-                    param_type: DataType::Enum("Vfo".to_string()),
-                    name: "target".to_string(),
-                },
-            ],
+            SchemaCommand {
+                parameters: vec![
+                    SchemaParameter::new(DataType::Int, "freq"),
+                    SchemaParameter::new(DataType::Enum("Vfo".to_string()), "target"),
+                ],
+                doc: None,
+            },
         );
 
         schema
@@ -1167,6 +2694,29 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_constants_in_declaration_order() {
+        let analyzer = SemanticAnalyzer::new(create_test_schema());
+
+        let rig_file_source = r#"
+            const OFFSET = 1;
+            const ADDRESS = OFFSET + 1;
+            const B = UNKNOWN;
+
+            impl Transceiver for TestRig {}
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let errors = analyzer.analyze(&rig_file).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0].error_type,
+            SemanticErrorType::UndefinedVariable { name } if name == "UNKNOWN"
+        ));
+        let position = errors[0].position.as_ref().unwrap();
+        assert_eq!((position.line, position.column), (4, 13));
+    }
+
     #[test]
     fn test_undefined_function() {
         let schema = create_test_schema();
@@ -1297,4 +2847,647 @@ mod tests {
                 .all(|e| matches!(&e.error_type, SemanticErrorType::InvalidCast { .. }))
         );
     }
+
+    #[test]
+    fn test_capabilities_validation() {
+        let mut schema = create_test_schema();
+        schema.enums.insert(
+            "Mode".to_string(),
+            vec!["USB".to_string(), "LSB".to_string()],
+        );
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                capabilities {
+                    rx_ranges = [30000..60000000];
+                    tx_ranges = [7200000..7000000: [Mode::USB, Mode::FM]];
+                    modes = [Mode::USB, Mode::LSB];
+                    passbands = [Mode::USB: [2400], Mode::AM: [6000]];
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let errors = analyzer.analyze(&rig_file).unwrap_err();
+
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::InvalidCapabilityRange { name, .. } if name == "tx_ranges"
+        )));
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::UndefinedEnumVariant { variant_name, .. } if variant_name == "FM"
+        )));
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::UndefinedEnumVariant { variant_name, .. } if variant_name == "AM"
+        )));
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn test_capability_modes_of_other_enum() {
+        let mut schema = create_test_schema();
+        schema
+            .enums
+            .insert("Mode".to_string(), vec!["USB".to_string()]);
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                capabilities {
+                    modes = [Vfo::A, Mode::USB];
+                    passbands = [Vfo::USB: [2400]];
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let errors = analyzer.analyze(&rig_file).unwrap_err();
+
+        let mismatches: Vec<_> = errors
+            .iter()
+            .filter_map(|e| match &e.error_type {
+                SemanticErrorType::TypeMismatch {
+                    expected, found, ..
+                } => Some((expected.clone(), found.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            mismatches,
+            [(
+                DataType::Enum("Mode".to_string()),
+                DataType::Enum("Vfo".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_match_exhaustiveness() {
+        let mut schema = create_test_schema();
+        schema.commands.insert(
+            "set_vfo".to_string(),
+            SchemaCommand {
+                parameters: vec![
+                    SchemaParameter::new(DataType::Enum("Vfo".to_string()), "rx"),
+                    SchemaParameter::new(DataType::Enum("Vfo".to_string()), "tx"),
+                ],
+                doc: None,
+            },
+        );
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_vfo(Vfo rx, Vfo tx) {
+                    match (rx, tx) {
+                        (Vfo::A, _) => { write("00"); }
+                        (Vfo::B, Vfo::B) => { write("01"); }
+                    }
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let errors = analyzer.analyze(&rig_file).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0].error_type,
+            SemanticErrorType::NonExhaustiveMatch { missing } if missing == &["(Vfo::B, Vfo::A)"]
+        ));
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_vfo(Vfo rx, Vfo tx) {
+                    match (rx, tx) {
+                        (Vfo::A, _) => { write("00"); }
+                        (Vfo::B, 1) => { write("01"); }
+                        (Vfo::B) => { write("01"); }
+                        _ => {}
+                    }
+                    match rx {
+                        Vfo::A => { write("02"); }
+                    }
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let errors = analyzer.analyze(&rig_file).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::TypeMismatch { context, .. } if context == "match pattern"
+        )));
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::MatchArityMismatch {
+                expected: 2,
+                found: 1
+            }
+        )));
+        assert!(errors.iter().any(|e| matches!(
+            &e.error_type,
+            SemanticErrorType::NonExhaustiveMatch { missing } if missing == &["Vfo::B"]
+        )));
+    }
+
+    #[test]
+    fn test_match_catch_all() {
+        let mut schema = create_test_schema();
+        schema.commands.insert(
+            "set_vfo".to_string(),
+            SchemaCommand {
+                parameters: vec![
+                    SchemaParameter::new(DataType::Enum("Vfo".to_string()), "rx"),
+                    SchemaParameter::new(DataType::Enum("Vfo".to_string()), "tx"),
+                ],
+                doc: None,
+            },
+        );
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_vfo(Vfo rx, Vfo tx) {
+                    match (rx, tx) { (Vfo::A, Vfo::B) => { write("00"); }, _ => { write("01"); } }
+                    match (rx, tx) {
+                        _ => { write("02"); }
+                        (Vfo::B, Vfo::A) => { write("03"); }
+                    }
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        assert!(analyzer.analyze(&rig_file).is_ok());
+        let unreachable: Vec<_> = analyzer
+            .warnings(&rig_file)
+            .into_iter()
+            .filter(|warning| warning.warning_type.code() == "W0106")
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(unreachable.len(), 1);
+        assert!(unreachable[0].contains("(Vfo::B, Vfo::A)"));
+    }
+
+    #[test]
+    fn test_unresolvable_default() {
+        let mut schema = create_test_schema();
+        schema
+            .enums
+            .get_mut("Vfo")
+            .unwrap()
+            .push("Current".to_string());
+        let set_freq = schema.commands.get_mut("set_freq").unwrap();
+        set_freq.parameters[1].default = Some("Current".to_string());
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"
+            impl Transceiver for TestRig {
+                enum Vfo {
+                    A = 0,
+                    B = 1,
+                }
+                fn set_freq(int freq, Vfo target) {
+                    write("00.{target:1}");
+                    read("00");
+                }
+            }
+        "#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let warnings: Vec<_> = analyzer
+            .warnings(&rig_file)
+            .into_iter()
+            .filter(|warning| warning.warning_type.code() == "W0110")
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Default 'Vfo::Current' of 'set_freq' parameter 'target' has no value in the rig"
+        );
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut schema = create_test_schema();
+        schema.commands.insert(
+            "vfo_swap".to_string(),
+            SchemaCommand {
+                parameters: vec![],
+                doc: None,
+            },
+        );
+        schema.status.insert(
+            "freq".to_string(),
+            SchemaParameter::new(DataType::Int, "freq"),
+        );
+        schema.status.insert(
+            "vfo".to_string(),
+            SchemaParameter::new(DataType::Enum("Vfo".to_string()), "vfo"),
+        );
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    enum Vfo {
+        A = 0,
+    }
+
+    fn set_freq(int freq, Vfo target) {
+        unused = freq + 1;
+        write("00.{freq:bcd_lu:5}");
+        match target {
+            _ => { write("01"); }
+            Vfo::A => { read("01"); }
+        }
+        if 1 as bool { read("02"); } else { read("03"); }
+    }
+
+    status {
+        write("02");
+        read("02.{freq:bcd_lu:5}.{_:1}");
+        set_var(s"freq", freq);
+    }
+}
+"#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let warnings = analyzer.warnings(&rig_file);
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|warning| {
+                let line = warning.position.as_ref().map(|position| position.line);
+                (warning.warning_type.code(), line)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("W0101", None),
+                ("W0102", None),
+                ("W0103", Some(2)),
+                ("W0105", Some(7)),
+                ("W0104", Some(8)),
+                ("W0106", Some(9)),
+                ("W0106", Some(13)),
+            ]
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "Variant 'Vfo::B' has no value in the rig"
+        );
+        assert_eq!(warnings[5].to_string(), "Unreachable match arm 'Vfo::A'");
+        assert_eq!(warnings[6].to_string(), "Unreachable else branch");
+    }
+
+    /// Warnings of the lint `code` for a rig file, with their line.
+    fn lint(schema: SchemaFile, rig_file_source: &str, code: &str) -> Vec<(String, Option<usize>)> {
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        SemanticAnalyzer::new(schema)
+            .warnings(&rig_file)
+            .into_iter()
+            .filter(|warning| warning.warning_type.code() == code)
+            .map(|warning| {
+                let line = warning.position.as_ref().map(|position| position.line);
+                (warning.to_string(), line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unimplemented_command_lint() {
+        let mut schema = create_test_schema();
+        schema.commands.insert(
+            "vfo_swap".to_string(),
+            SchemaCommand {
+                parameters: vec![],
+                doc: None,
+            },
+        );
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) {
+        write("00");
+        read("00");
+    }
+}
+"#;
+
+        assert_eq!(
+            lint(schema.clone(), rig_file_source, "W0101"),
+            [(
+                "Schema command 'vfo_swap' is not implemented".to_string(),
+                None
+            )]
+        );
+        let rig_file_source = rig_file_source.replace(
+            "\n}\n",
+            "\n    fn vfo_swap() {\n        write(\"01\");\n        read(\"01\");\n    }\n}\n",
+        );
+        assert!(lint(schema, &rig_file_source, "W0101").is_empty());
+    }
+
+    #[test]
+    fn test_unset_status_field_lint() {
+        let mut schema = create_test_schema();
+        for name in ["freq", "transmit"] {
+            schema
+                .status
+                .insert(name.to_string(), SchemaParameter::new(DataType::Int, name));
+        }
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) {
+        write("00");
+        read("00");
+    }
+
+    status {
+        write("03");
+        read("03.{freq:4}");
+        if freq > 0 {
+            set_var(s"freq", freq);
+        }
+    }
+}
+"#;
+
+        assert_eq!(
+            lint(schema, rig_file_source, "W0102"),
+            [("Status field 'transmit' is never set".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn test_unmapped_enum_variant_lint() {
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    enum Vfo {
+        A = 0,
+    }
+
+    fn set_freq(int freq, Vfo target) {
+        write("00.{target:1}");
+        read("00");
+    }
+}
+"#;
+
+        assert_eq!(
+            lint(create_test_schema(), rig_file_source, "W0103"),
+            [(
+                "Variant 'Vfo::B' has no value in the rig".to_string(),
+                Some(2)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_write_without_read_lint() {
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) {
+        write("00");
+        write("01");
+        read("01");
+        if freq > 0 {
+            write("02");
+        }
+        read("02");
+        write("03");
+    }
+}
+"#;
+
+        let warnings = lint(create_test_schema(), rig_file_source, "W0104");
+        let lines: Vec<_> = warnings.into_iter().map(|(_, line)| line).collect();
+        assert_eq!(lines, [Some(3), Some(10)]);
+    }
+
+    #[test]
+    fn test_unused_variable_lint() {
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) {
+        unused = freq + 1;
+        _ignored = 2;
+        used = 3;
+        write("00.{used:1}");
+        read("00");
+    }
+}
+"#;
+
+        assert_eq!(
+            lint(create_test_schema(), rig_file_source, "W0105"),
+            [("Variable 'unused' is never used".to_string(), Some(3))]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_branch_lint() {
+        let mut schema = create_test_schema();
+        schema.commands.insert(
+            "vfo_swap".to_string(),
+            SchemaCommand {
+                parameters: vec![],
+                doc: None,
+            },
+        );
+        let rig_file_source = r#"const DEBUG = 0;
+const LEVEL = DEBUG + 2;
+
+impl Transceiver for TestRig {
+    enum Vfo {
+        A = 0,
+        B = 1,
+    }
+
+    fn set_freq(int freq, Vfo target) {
+        if 2 > 3 { write("00"); read("00"); }
+        if DEBUG as bool { write("01"); read("01"); } else { write("02"); read("02"); }
+        if LEVEL * 2 == 4 { write("03"); read("03"); } else { write("04"); read("04"); }
+        if freq > LEVEL { write("05"); read("05"); } else { write("06"); read("06"); }
+        match target {
+            Vfo::A => { write("07"); read("07"); }
+            Vfo::B => { write("08"); read("08"); }
+            _ => { write("09"); read("09"); }
+        }
+    }
+
+    fn vfo_swap() {
+        DEBUG = 1;
+        if DEBUG as bool { write("0A"); read("0A"); }
+    }
+}
+"#;
+
+        assert_eq!(
+            lint(schema, rig_file_source, "W0106"),
+            [
+                ("Unreachable if branch".to_string(), Some(11)),
+                ("Unreachable if branch".to_string(), Some(12)),
+                ("Unreachable else branch".to_string(), Some(13)),
+                ("Unreachable match arm '_'".to_string(), Some(15)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_value_ranges() {
+        let mut schema = create_test_schema();
+        schema.enums.insert(
+            "Mode".to_string(),
+            vec!["USB".to_string(), "LSB".to_string()],
+        );
+        for (command, param, range) in [
+            ("cw_pitch", "pitch", 300..=900),
+            ("set_level", "level", 0..=100),
+        ] {
+            schema.commands.insert(
+                command.to_string(),
+                SchemaCommand {
+                    parameters: vec![SchemaParameter {
+                        range: Some(range),
+                        ..SchemaParameter::new(DataType::Int, param)
+                    }],
+                    doc: None,
+                },
+            );
+        }
+        schema.status.insert(
+            "mode".to_string(),
+            SchemaParameter::new(DataType::Enum("Mode".to_string()), "mode"),
+        );
+        let analyzer = SemanticAnalyzer::new(schema);
+
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    enum Mode {
+        USB = 1,
+        LSB = 2,
+    }
+
+    fn cw_pitch(int pitch) {
+        pitch = (pitch - 100) * 30 / 2;
+        write("14.09.{pitch:bcd_bu:2}");
+        write("14.09.{pitch:1}");
+    }
+
+    fn set_level(int level) {
+        level = level - 200;
+        write("{level:int_lu:1}");
+    }
+
+    status {
+        read("{mode:1}");
+        set_var(s"mode", mode as Mode);
+        set_var(s"mode", 3 as Mode);
+        read("{_:8}{_:int_bs:16}");
+    }
+}
+"#;
+
+        let rig_file = parse_rig_file(rig_file_source).unwrap();
+        let mut errors = analyzer.analyze(&rig_file).unwrap_err();
+        errors.sort_by_key(|error| error.position.as_ref().map(|position| position.line));
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| {
+                (
+                    error.error_type.code(),
+                    error.position.as_ref().unwrap().line,
+                )
+            })
+            .collect();
+        assert_eq!(found, [("E0127", 10), ("E0128", 15), ("E0129", 21)]);
+        assert_eq!(
+            errors[0].to_string(),
+            "Value 3000..12000 of 'pitch' does not fit in int_lu:1"
+        );
+
+        let warnings: Vec<_> = analyzer
+            .warnings(&rig_file)
+            .into_iter()
+            .filter(|warning| {
+                matches!(
+                    warning.warning_type,
+                    SemanticWarningType::PossibleTemplateFieldOverflow { .. }
+                        | SemanticWarningType::PossibleNegativeIntoUnsigned { .. }
+                        | SemanticWarningType::PossibleUnmappedEnumValue { .. }
+                )
+            })
+            .collect();
+        let found: Vec<_> = warnings.iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "Value 3000..12000 of 'pitch' may not fit in bcd_bu:2",
+                "Cast of 0..255 to enum 'Mode' may receive values without a variant",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validation_after_recovery() {
+        let schemas = HashMap::from([("Transceiver".to_string(), create_test_schema())]);
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    fn set_freq(int freq, Vfo target) {
+        data = "00" +;
+        write(data);
+        write(missing);
+    }
+
+    fn clear_rit() {
+        write("00");
+    }
+}
+"#;
+
+        let errors = parse_and_validate_with_schema(rig_file_source, &schemas).unwrap_err();
+        let codes: Vec<_> = errors.iter().map(|err| err.code()).collect();
+        // Variables used after the broken statement aren't reported, `clear_rit` missing from
+        // the schema is
+        assert_eq!(codes, [ParseError::SYNTAX_CODE, "E0108"]);
+        assert_eq!(errors[0].position.line, 3);
+        assert_eq!((errors[1].position.line, errors[1].position.column), (8, 8));
+    }
+
+    #[test]
+    fn test_warnings_next_to_errors() {
+        let schemas = HashMap::from([("Transceiver".to_string(), create_test_schema())]);
+        let rig_file_source = r#"impl Transceiver for TestRig {
+    enum Vfo {
+        A = 0,
+        B = 1,
+    }
+
+    fn set_freq(int freq, Vfo target) {
+        unused = 1;
+        write(missing);
+    }
+}
+"#;
+
+        let (result, warnings) = parse_and_check_with_imports(rig_file_source, &schemas, |path| {
+            Err(anyhow::anyhow!("Unexpected import {path}"))
+        });
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position.line, 9);
+        let codes: Vec<_> = warnings
+            .iter()
+            .map(|warning| warning.warning_type.code())
+            .collect();
+        assert!(codes.contains(&"W0104"));
+        assert!(codes.contains(&"W0105"));
+    }
 }
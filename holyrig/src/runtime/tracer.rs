@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::sync::{Notify, mpsc};
+
+use super::interpreter::Value;
+use super::parser_errors::SourcePosition;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    /// A statement is about to be executed.
+    Statement {
+        statement: String,
+        position: Option<SourcePosition>,
+        bindings: BTreeMap<String, Value>,
+    },
+    Evaluate {
+        expr: String,
+        value: Value,
+    },
+    Write(Vec<u8>),
+    Read(Vec<u8>),
+    /// Execution is suspended until the debugger resumes it.
+    Paused {
+        position: Option<SourcePosition>,
+    },
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Statement {
+                statement,
+                position,
+                ..
+            } => match position {
+                Some(position) => write!(f, "{}:{}: {statement}", position.line, position.column),
+                None => write!(f, "{statement}"),
+            },
+            TraceEvent::Evaluate { expr, value } => write!(f, "  {expr} => {value}"),
+            TraceEvent::Write(bytes) => write!(f, "  >> {}", hex(bytes)),
+            TraceEvent::Read(bytes) => write!(f, "  << {}", hex(bytes)),
+            TraceEvent::Paused { .. } => write!(f, "  paused"),
+        }
+    }
+}
+
+impl From<&TraceEvent> for serde_json::Value {
+    fn from(event: &TraceEvent) -> Self {
+        let position = |position: &Option<SourcePosition>| {
+            position
+                .as_ref()
+                .map(|position| json!({"line": position.line, "column": position.column}))
+        };
+
+        match event {
+            TraceEvent::Statement {
+                statement,
+                position: statement_position,
+                bindings,
+            } => json!({
+                "event": "statement",
+                "statement": statement,
+                "position": position(statement_position),
+                "bindings": bindings
+                    .iter()
+                    .map(|(name, value)| (name.clone(), serde_json::Value::from(value)))
+                    .collect::<serde_json::Map<_, _>>(),
+            }),
+            TraceEvent::Evaluate { expr, value } => json!({
+                "event": "evaluate",
+                "expr": expr,
+                "value": serde_json::Value::from(value),
+            }),
+            TraceEvent::Write(bytes) => json!({"event": "write", "data": hex(bytes)}),
+            TraceEvent::Read(bytes) => json!({"event": "read", "data": hex(bytes)}),
+            TraceEvent::Paused {
+                position: paused_position,
+            } => json!({"event": "paused", "position": position(paused_position)}),
+        }
+    }
+}
+
+/// Receives the events of an interpreter. Execution waits for `trace` to return, which lets
+/// debuggers suspend it.
+#[async_trait]
+pub trait Tracer: Send + Sync {
+    /// Events are only built and delivered while this returns true.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    async fn trace(&self, event: TraceEvent);
+}
+
+/// Keeps every event in memory.
+#[derive(Default)]
+pub struct RecordingTracer {
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl RecordingTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().clone()
+    }
+}
+
+#[async_trait]
+impl Tracer for RecordingTracer {
+    async fn trace(&self, event: TraceEvent) {
+        self.events.lock().push(event);
+    }
+}
+
+/// Sends every event to a channel, e.g. to display them while the interpreter runs.
+pub struct ChannelTracer {
+    sender: mpsc::UnboundedSender<TraceEvent>,
+}
+
+impl ChannelTracer {
+    pub fn new(sender: mpsc::UnboundedSender<TraceEvent>) -> Self {
+        Self { sender }
+    }
+}
+
+#[async_trait]
+impl Tracer for ChannelTracer {
+    fn is_enabled(&self) -> bool {
+        !self.sender.is_closed()
+    }
+
+    async fn trace(&self, event: TraceEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Forwards events to another tracer and suspends execution before statements while stepping
+/// or when a breakpoint line is reached.
+pub struct Debugger<T: Tracer> {
+    inner: T,
+    stepping: AtomicBool,
+    breakpoints: Mutex<HashSet<usize>>,
+    resume: Notify,
+}
+
+impl<T: Tracer> Debugger<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            stepping: AtomicBool::new(false),
+            breakpoints: Mutex::new(HashSet::new()),
+            resume: Notify::new(),
+        }
+    }
+
+    pub fn set_stepping(&self, stepping: bool) {
+        self.stepping.store(stepping, Ordering::SeqCst);
+    }
+
+    pub fn add_breakpoint(&self, line: usize) {
+        self.breakpoints.lock().insert(line);
+    }
+
+    pub fn remove_breakpoint(&self, line: usize) {
+        self.breakpoints.lock().remove(&line);
+    }
+
+    /// Lets the paused executions continue to their next pause point, does nothing when none is
+    /// paused.
+    pub fn resume(&self) {
+        self.resume.notify_waiters();
+    }
+
+    /// Stops stepping, removes every breakpoint and resumes the paused executions.
+    pub fn clear(&self) {
+        self.set_stepping(false);
+        self.breakpoints.lock().clear();
+        self.resume();
+    }
+
+    fn should_pause(&self, position: &Option<SourcePosition>) -> bool {
+        self.stepping.load(Ordering::SeqCst)
+            || position
+                .as_ref()
+                .is_some_and(|position| self.breakpoints.lock().contains(&position.line))
+    }
+}
+
+#[async_trait]
+impl<T: Tracer> Tracer for Debugger<T> {
+    fn is_enabled(&self) -> bool {
+        self.inner.is_enabled()
+    }
+
+    async fn trace(&self, event: TraceEvent) {
+        let pause_at = match &event {
+            TraceEvent::Statement { position, .. } if self.should_pause(position) => {
+                Some(position.clone())
+            }
+            _ => None,
+        };
+
+        self.inner.trace(event).await;
+
+        if let Some(position) = pause_at {
+            // Wait before reporting the pause, so that a resume right after it is not missed
+            let resumed = self.resume.notified();
+            tokio::pin!(resumed);
+            resumed.as_mut().enable();
+            self.inner.trace(TraceEvent::Paused { position }).await;
+            resumed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn statement(line: usize) -> TraceEvent {
+        TraceEvent::Statement {
+            statement: "write(\"00\");".to_string(),
+            position: Some(SourcePosition::new(line, 1, 0)),
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_debugger_breakpoint() {
+        let debugger = Arc::new(Debugger::new(RecordingTracer::new()));
+        debugger.add_breakpoint(2);
+
+        debugger.trace(statement(1)).await;
+        assert_eq!(debugger.inner.events().len(), 1);
+
+        let paused = tokio::spawn({
+            let debugger = debugger.clone();
+            async move { debugger.trace(statement(2)).await }
+        });
+        tokio::task::yield_now().await;
+        debugger.resume();
+        paused.await.unwrap();
+
+        let events = debugger.inner.events();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], TraceEvent::Paused { .. }));
+
+        // Resuming while nothing is paused must not skip the next breakpoint
+        debugger.resume();
+        let paused = tokio::spawn({
+            let debugger = debugger.clone();
+            async move { debugger.trace(statement(2)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!paused.is_finished());
+        debugger.clear();
+        paused.await.unwrap();
+        debugger.trace(statement(2)).await;
+        assert_eq!(debugger.inner.events().len(), 6);
+    }
+}
@@ -0,0 +1,710 @@
+//! Comparison of two versions of a schema, classifying each change by whether the clients and
+//! rig files written against the old version keep working.
+
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+use crate::runtime::{DataType, RigFile, SchemaFile, SchemaParameter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compatibility {
+    Compatible,
+    Breaking,
+}
+
+impl Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compatibility::Compatible => write!(f, "compatible"),
+            Compatibility::Breaking => write!(f, "breaking"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    SchemaRenamed {
+        old: String,
+        new: String,
+    },
+    VersionChanged {
+        old: u32,
+        new: u32,
+    },
+    EnumAdded(String),
+    EnumRemoved(String),
+    VariantAdded {
+        enum_name: String,
+        variant: String,
+    },
+    VariantRemoved {
+        enum_name: String,
+        variant: String,
+    },
+    /// The default of a parameter of the new schema names a variant it removed.
+    DefaultVariantRemoved {
+        command: String,
+        parameter: String,
+        enum_name: String,
+        variant: String,
+    },
+    CommandAdded(String),
+    CommandRemoved(String),
+    ParameterAdded {
+        command: String,
+        parameter: String,
+    },
+    ParameterRemoved {
+        command: String,
+        parameter: String,
+    },
+    ParameterTypeChanged {
+        command: String,
+        parameter: String,
+        old: DataType,
+        new: DataType,
+    },
+    ParameterRangeChanged {
+        command: String,
+        parameter: String,
+        old: Option<RangeInclusive<i64>>,
+        new: Option<RangeInclusive<i64>>,
+    },
+    ParameterUnitChanged {
+        command: String,
+        parameter: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ParameterDefaultChanged {
+        command: String,
+        parameter: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    StatusAdded(String),
+    StatusRemoved(String),
+    StatusTypeChanged {
+        field: String,
+        old: DataType,
+        new: DataType,
+    },
+}
+
+impl SchemaChange {
+    pub fn compatibility(&self) -> Compatibility {
+        let breaking = match self {
+            SchemaChange::SchemaRenamed { .. }
+            | SchemaChange::EnumRemoved(_)
+            | SchemaChange::VariantRemoved { .. }
+            | SchemaChange::DefaultVariantRemoved { .. }
+            | SchemaChange::CommandRemoved(_)
+            | SchemaChange::ParameterAdded { .. }
+            | SchemaChange::ParameterRemoved { .. }
+            | SchemaChange::ParameterTypeChanged { .. }
+            | SchemaChange::StatusRemoved(_)
+            | SchemaChange::StatusTypeChanged { .. } => true,
+            // Rigs encode the parameter assuming the old range, a wider one may overflow them,
+            // and a narrower one rejects values clients used to send
+            SchemaChange::ParameterRangeChanged { .. } => true,
+            // Clients relying on the default have to pass the parameter explicitly
+            SchemaChange::ParameterDefaultChanged { new, .. } => new.is_none(),
+            // Units only document the values, rigs and clients exchange the same numbers
+            SchemaChange::ParameterUnitChanged { .. } => false,
+            SchemaChange::VersionChanged { .. }
+            | SchemaChange::EnumAdded(_)
+            | SchemaChange::VariantAdded { .. }
+            | SchemaChange::CommandAdded(_)
+            | SchemaChange::StatusAdded(_) => false,
+        };
+        if breaking {
+            Compatibility::Breaking
+        } else {
+            Compatibility::Compatible
+        }
+    }
+
+    /// Whether the rig file uses the part of the schema a breaking change touches.
+    pub fn breaks(&self, rig: &RigFile) -> bool {
+        if self.compatibility() == Compatibility::Compatible {
+            return false;
+        }
+        let implements = |command: &str| rig.impl_block.commands.contains_key(command);
+        match self {
+            SchemaChange::SchemaRenamed { .. } => true,
+            SchemaChange::EnumRemoved(enum_name) => rig
+                .impl_block
+                .enums
+                .iter()
+                .any(|enum_def| &enum_def.name == enum_name),
+            SchemaChange::VariantRemoved { enum_name, variant } => {
+                rig.impl_block.enums.iter().any(|enum_def| {
+                    &enum_def.name == enum_name && enum_def.variants.contains_key(variant)
+                })
+            }
+            SchemaChange::CommandRemoved(command)
+            | SchemaChange::ParameterAdded { command, .. }
+            | SchemaChange::ParameterRemoved { command, .. }
+            | SchemaChange::ParameterTypeChanged { command, .. }
+            | SchemaChange::DefaultVariantRemoved { command, .. } => implements(command),
+            // Only the clients are affected by a narrower range
+            SchemaChange::ParameterRangeChanged {
+                command, old, new, ..
+            } => implements(command) && widens(old, new),
+            SchemaChange::StatusRemoved(field) | SchemaChange::StatusTypeChanged { field, .. } => {
+                rig.get_supported_status_fields().contains(field)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `new` accepts values outside of `old`, unbounded ranges accepting everything.
+fn widens(old: &Option<RangeInclusive<i64>>, new: &Option<RangeInclusive<i64>>) -> bool {
+    match (old, new) {
+        (_, None) => old.is_some(),
+        (None, Some(_)) => false,
+        (Some(old), Some(new)) => new.start() < old.start() || new.end() > old.end(),
+    }
+}
+
+fn optional<T: Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or("none".to_string(), |value| value.to_string())
+}
+
+fn range(range: &Option<RangeInclusive<i64>>) -> String {
+    range.as_ref().map_or("unbounded".to_string(), |range| {
+        format!("{}..{}", range.start(), range.end())
+    })
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::SchemaRenamed { old, new } => {
+                write!(f, "Schema renamed from '{old}' to '{new}'")
+            }
+            SchemaChange::VersionChanged { old, new } => {
+                write!(f, "Version changed from {old} to {new}")
+            }
+            SchemaChange::EnumAdded(name) => write!(f, "Added enum '{name}'"),
+            SchemaChange::EnumRemoved(name) => write!(f, "Removed enum '{name}'"),
+            SchemaChange::VariantAdded { enum_name, variant } => {
+                write!(f, "Added variant '{enum_name}::{variant}'")
+            }
+            SchemaChange::VariantRemoved { enum_name, variant } => {
+                write!(f, "Removed variant '{enum_name}::{variant}'")
+            }
+            SchemaChange::DefaultVariantRemoved {
+                command,
+                parameter,
+                enum_name,
+                variant,
+            } => write!(
+                f,
+                "Default of parameter '{parameter}' of command '{command}' names removed variant '{enum_name}::{variant}'"
+            ),
+            SchemaChange::CommandAdded(name) => write!(f, "Added command '{name}'"),
+            SchemaChange::CommandRemoved(name) => write!(f, "Removed command '{name}'"),
+            SchemaChange::ParameterAdded { command, parameter } => {
+                write!(f, "Added parameter '{parameter}' to command '{command}'")
+            }
+            SchemaChange::ParameterRemoved { command, parameter } => {
+                write!(
+                    f,
+                    "Removed parameter '{parameter}' from command '{command}'"
+                )
+            }
+            SchemaChange::ParameterTypeChanged {
+                command,
+                parameter,
+                old,
+                new,
+            } => write!(
+                f,
+                "Type of parameter '{parameter}' of command '{command}' changed from {old} to {new}"
+            ),
+            SchemaChange::ParameterRangeChanged {
+                command,
+                parameter,
+                old,
+                new,
+            } => write!(
+                f,
+                "Range of parameter '{parameter}' of command '{command}' changed from {} to {}",
+                range(old),
+                range(new)
+            ),
+            SchemaChange::ParameterUnitChanged {
+                command,
+                parameter,
+                old,
+                new,
+            } => write!(
+                f,
+                "Unit of parameter '{parameter}' of command '{command}' changed from {} to {}",
+                optional(old),
+                optional(new)
+            ),
+            SchemaChange::ParameterDefaultChanged {
+                command,
+                parameter,
+                old,
+                new,
+            } => write!(
+                f,
+                "Default of parameter '{parameter}' of command '{command}' changed from {} to {}",
+                optional(old),
+                optional(new)
+            ),
+            SchemaChange::StatusAdded(field) => write!(f, "Added status field '{field}'"),
+            SchemaChange::StatusRemoved(field) => write!(f, "Removed status field '{field}'"),
+            SchemaChange::StatusTypeChanged { field, old, new } => {
+                write!(
+                    f,
+                    "Type of status field '{field}' changed from {old} to {new}"
+                )
+            }
+        }
+    }
+}
+
+/// Changes from `old` to `new`, in schema order: header, enums, commands, then status fields.
+pub fn diff(old: &SchemaFile, new: &SchemaFile) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    if old.name != new.name {
+        changes.push(SchemaChange::SchemaRenamed {
+            old: old.name.clone(),
+            new: new.name.clone(),
+        });
+    }
+    if old.version != new.version {
+        changes.push(SchemaChange::VersionChanged {
+            old: old.version,
+            new: new.version,
+        });
+    }
+
+    for (name, old_variants) in &old.enums {
+        let Some(new_variants) = new.enums.get(name) else {
+            changes.push(SchemaChange::EnumRemoved(name.clone()));
+            continue;
+        };
+        for variant in old_variants {
+            if !new_variants.contains(variant) {
+                changes.push(SchemaChange::VariantRemoved {
+                    enum_name: name.clone(),
+                    variant: variant.clone(),
+                });
+                removed_default_variants(new, name, variant, &mut changes);
+            }
+        }
+        for variant in new_variants {
+            if !old_variants.contains(variant) {
+                changes.push(SchemaChange::VariantAdded {
+                    enum_name: name.clone(),
+                    variant: variant.clone(),
+                });
+            }
+        }
+    }
+    for name in new.enums.keys() {
+        if !old.enums.contains_key(name) {
+            changes.push(SchemaChange::EnumAdded(name.clone()));
+        }
+    }
+
+    for (name, old_command) in &old.commands {
+        let Some(new_command) = new.commands.get(name) else {
+            changes.push(SchemaChange::CommandRemoved(name.clone()));
+            continue;
+        };
+        for old_param in &old_command.parameters {
+            match new_command
+                .parameters
+                .iter()
+                .find(|param| param.name == old_param.name)
+            {
+                Some(new_param) => diff_parameter(name, old_param, new_param, &mut changes),
+                None => changes.push(SchemaChange::ParameterRemoved {
+                    command: name.clone(),
+                    parameter: old_param.name.clone(),
+                }),
+            }
+        }
+        for new_param in &new_command.parameters {
+            if !old_command
+                .parameters
+                .iter()
+                .any(|param| param.name == new_param.name)
+            {
+                changes.push(SchemaChange::ParameterAdded {
+                    command: name.clone(),
+                    parameter: new_param.name.clone(),
+                });
+            }
+        }
+    }
+    for name in new.commands.keys() {
+        if !old.commands.contains_key(name) {
+            changes.push(SchemaChange::CommandAdded(name.clone()));
+        }
+    }
+
+    for (name, old_field) in &old.status {
+        match new.status.get(name) {
+            Some(new_field) if new_field.param_type != old_field.param_type => {
+                changes.push(SchemaChange::StatusTypeChanged {
+                    field: name.clone(),
+                    old: old_field.param_type.clone(),
+                    new: new_field.param_type.clone(),
+                });
+            }
+            Some(_) => {}
+            None => changes.push(SchemaChange::StatusRemoved(name.clone())),
+        }
+    }
+    for name in new.status.keys() {
+        if !old.status.contains_key(name) {
+            changes.push(SchemaChange::StatusAdded(name.clone()));
+        }
+    }
+
+    changes
+}
+
+/// Reports the parameters of `schema` whose default is the removed `enum_name::variant`.
+fn removed_default_variants(
+    schema: &SchemaFile,
+    enum_name: &str,
+    variant: &str,
+    changes: &mut Vec<SchemaChange>,
+) {
+    for (command, schema_command) in &schema.commands {
+        for parameter in &schema_command.parameters {
+            if matches!(&parameter.param_type, DataType::Enum(name) if name == enum_name)
+                && parameter.default.as_deref() == Some(variant)
+            {
+                changes.push(SchemaChange::DefaultVariantRemoved {
+                    command: command.clone(),
+                    parameter: parameter.name.clone(),
+                    enum_name: enum_name.to_string(),
+                    variant: variant.to_string(),
+                });
+            }
+        }
+    }
+}
+
+fn diff_parameter(
+    command: &str,
+    old: &SchemaParameter,
+    new: &SchemaParameter,
+    changes: &mut Vec<SchemaChange>,
+) {
+    let command = command.to_string();
+    let parameter = old.name.clone();
+    if old.param_type != new.param_type {
+        changes.push(SchemaChange::ParameterTypeChanged {
+            command,
+            parameter,
+            old: old.param_type.clone(),
+            new: new.param_type.clone(),
+        });
+        return;
+    }
+    if old.range != new.range {
+        changes.push(SchemaChange::ParameterRangeChanged {
+            command: command.clone(),
+            parameter: parameter.clone(),
+            old: old.range.clone(),
+            new: new.range.clone(),
+        });
+    }
+    if old.unit != new.unit {
+        changes.push(SchemaChange::ParameterUnitChanged {
+            command: command.clone(),
+            parameter: parameter.clone(),
+            old: old.unit.clone(),
+            new: new.unit.clone(),
+        });
+    }
+    if old.default != new.default {
+        changes.push(SchemaChange::ParameterDefaultChanged {
+            command,
+            parameter,
+            old: old.default.clone(),
+            new: new.default.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{parse_and_validate_with_schema, parse_rig_file, parse_schema};
+    use std::collections::HashMap;
+
+    const OLD_SCHEMA: &str = r#"
+        version = 1;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                B,
+            }
+
+            fn set_freq(int<30000..60000000> freq [unit = s"Hz"], Vfo target [default = Vfo::A]);
+            fn set_split(bool split);
+            fn transmit(bool tx);
+
+            status {
+                int freq_a;
+                bool transmit;
+            }
+        }
+    "#;
+
+    const NEW_SCHEMA: &str = r#"
+        version = 2;
+
+        schema Transceiver {
+            enum Vfo {
+                A,
+                Current,
+            }
+
+            enum Mode {
+                USB,
+                LSB,
+            }
+
+            fn set_freq(int<30000..470000000> freq [unit = s"Hz"], Vfo target);
+            fn set_split(bool split);
+            fn set_mode(Mode mode);
+            fn transmit(int tx);
+
+            status {
+                int freq_a;
+                int transmit;
+                Mode mode;
+            }
+        }
+    "#;
+
+    const RIG: &str = r#"
+        impl Transceiver for Test {
+            enum Vfo {
+                A = 0,
+                B = 1,
+            }
+
+            fn set_split(bool split) {
+                write("01.{split:1}");
+                read("01");
+            }
+
+            fn transmit(bool tx) {
+                write("02.{tx:1}");
+                read("02");
+            }
+
+            status {
+                write("03");
+                read("03.{freq_a:bcd_lu:4}");
+                set_var(s"freq_a", freq_a);
+            }
+        }
+    "#;
+
+    #[test]
+    fn test_diff() {
+        let old = parse_schema(OLD_SCHEMA).unwrap();
+        let new = parse_schema(NEW_SCHEMA).unwrap();
+        let changes: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .map(|change| (change.compatibility(), change.to_string()))
+            .collect();
+
+        use Compatibility::*;
+        let expected = [
+            (Compatible, "Version changed from 1 to 2"),
+            (Breaking, "Removed variant 'Vfo::B'"),
+            (Compatible, "Added variant 'Vfo::Current'"),
+            (Compatible, "Added enum 'Mode'"),
+            (
+                Breaking,
+                "Range of parameter 'freq' of command 'set_freq' changed from 30000..60000000 to 30000..470000000",
+            ),
+            (
+                Breaking,
+                "Default of parameter 'target' of command 'set_freq' changed from A to none",
+            ),
+            (
+                Breaking,
+                "Type of parameter 'tx' of command 'transmit' changed from bool to int",
+            ),
+            (Compatible, "Added command 'set_mode'"),
+            (
+                Breaking,
+                "Type of status field 'transmit' changed from bool to int",
+            ),
+            (Compatible, "Added status field 'mode'"),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(compatibility, change)| (compatibility, change.to_string()))
+            .collect();
+        assert_eq!(changes, expected);
+
+        // Swapping the versions turns additions into removals
+        let reverse = diff(&new, &old);
+        assert!(reverse.contains(&SchemaChange::CommandRemoved("set_mode".to_string())));
+        assert!(reverse.contains(&SchemaChange::EnumRemoved("Mode".to_string())));
+        assert!(reverse.contains(&SchemaChange::StatusRemoved("mode".to_string())));
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_breaks() {
+        let old = parse_schema(OLD_SCHEMA).unwrap();
+        let new = parse_schema(NEW_SCHEMA).unwrap();
+        let schemas = HashMap::from([(old.name.clone(), old.clone())]);
+        let rig = parse_and_validate_with_schema(RIG, &schemas).unwrap();
+
+        let broken: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .filter(|change| change.breaks(&rig))
+            .map(|change| change.to_string())
+            .collect();
+        assert_eq!(
+            broken,
+            [
+                "Removed variant 'Vfo::B'",
+                "Type of parameter 'tx' of command 'transmit' changed from bool to int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_narrowed_range_and_removed_default() {
+        let old = parse_schema(
+            r#"
+            version = 1;
+
+            schema Transceiver {
+                enum Vfo {
+                    A,
+                    B,
+                }
+
+                fn set_freq(int<30000..60000000> freq, Vfo target [default = Vfo::B]);
+            }
+        "#,
+        )
+        .unwrap();
+        let new = parse_schema(
+            r#"
+            version = 1;
+
+            schema Transceiver {
+                enum Vfo {
+                    A,
+                }
+
+                fn set_freq(int<1000000..60000000> freq, Vfo target [default = Vfo::B]);
+            }
+        "#,
+        )
+        .unwrap();
+        let changes = diff(&old, &new);
+        let found: Vec<_> = changes
+            .iter()
+            .map(|change| (change.compatibility(), change.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    Compatibility::Breaking,
+                    "Removed variant 'Vfo::B'".to_string()
+                ),
+                (
+                    Compatibility::Breaking,
+                    "Default of parameter 'target' of command 'set_freq' names removed variant 'Vfo::B'"
+                        .to_string()
+                ),
+                (
+                    Compatibility::Breaking,
+                    "Range of parameter 'freq' of command 'set_freq' changed from 30000..60000000 to 1000000..60000000"
+                        .to_string()
+                ),
+            ]
+        );
+
+        // Rigs keep working with a narrower range, not when the default can't be resolved
+        let rig = parse_rig_file(
+            r#"
+            impl Transceiver for Test {
+                enum Vfo {
+                    A = 0,
+                }
+
+                fn set_freq(int freq, Vfo target) {
+                    write("00.{freq:bcd_lu:4}");
+                    read("00");
+                }
+            }
+        "#,
+        )
+        .unwrap();
+        let broken: Vec<_> = changes
+            .iter()
+            .filter(|change| change.breaks(&rig))
+            .map(|change| change.to_string())
+            .collect();
+        assert_eq!(
+            broken,
+            ["Default of parameter 'target' of command 'set_freq' names removed variant 'Vfo::B'"]
+        );
+    }
+
+    #[test]
+    fn test_unit_changed() {
+        let old = parse_schema(
+            r#"
+            version = 1;
+
+            schema Transceiver {
+                fn cw_pitch(int pitch);
+            }
+        "#,
+        )
+        .unwrap();
+        let new = parse_schema(
+            r#"
+            version = 1;
+
+            schema Transceiver {
+                fn cw_pitch(int pitch [unit = s"Hz"]);
+            }
+        "#,
+        )
+        .unwrap();
+        let changes = diff(&old, &new);
+        let found: Vec<_> = changes
+            .iter()
+            .map(|change| (change.compatibility(), change.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            [(
+                Compatibility::Compatible,
+                "Unit of parameter 'pitch' of command 'cw_pitch' changed from none to Hz"
+                    .to_string()
+            )]
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::{Context, Result};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc;
-use tokio::time::{Duration, sleep};
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio::time::{Duration, sleep, timeout};
+use tokio_serial::{ClearBuffer, SerialPort, SerialPortBuilderExt, SerialStream};
 
 use crate::rig_settings::{DataBits, RigSettings, StopBits};
 
@@ -18,6 +18,20 @@ pub enum DeviceCommand {
     Shutdown,
 }
 
+/// The rig did not send the expected bytes within the timeout of the device.
+#[derive(Debug)]
+pub struct ReadTimeout {
+    pub timeout: u16,
+}
+
+impl std::fmt::Display for ReadTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rig did not answer within {}ms", self.timeout)
+    }
+}
+
+impl std::error::Error for ReadTimeout {}
+
 #[derive(Debug)]
 pub enum DeviceMessage {
     Error { device_id: usize, error: String },
@@ -31,6 +45,8 @@ pub struct SerialDevice {
     settings: RigSettings,
     command_tx: mpsc::Sender<DeviceCommand>,
     device_tx: mpsc::Sender<DeviceMessage>,
+    /// A read timed out, the rest of its reply may still arrive before the next command
+    late_reply: bool,
 }
 
 impl SerialDevice {
@@ -49,6 +65,7 @@ impl SerialDevice {
                 settings,
                 command_tx,
                 device_tx,
+                late_reply: false,
             },
             command_rx,
         ))
@@ -99,16 +116,35 @@ impl SerialDevice {
     }
 
     async fn write_only(&mut self, data: &[u8]) -> Result<()> {
+        if std::mem::take(&mut self.late_reply) {
+            self.clear_input();
+        }
         self.port.write_all(data).await?;
         Ok(())
     }
 
     async fn read_exact(&mut self, length: usize) -> Result<Vec<u8>> {
         let mut buf = vec![0u8; length];
-        self.port.read_exact(&mut buf).await?;
+        let duration = Duration::from_millis(self.settings.timeout.into());
+        let Ok(result) = timeout(duration, self.port.read_exact(&mut buf)).await else {
+            // The bytes read so far are lost, later reads would start in the middle of the reply
+            self.clear_input();
+            self.late_reply = true;
+            return Err(ReadTimeout {
+                timeout: self.settings.timeout,
+            }
+            .into());
+        };
+        result?;
         Ok(buf)
     }
 
+    fn clear_input(&self) {
+        if let Err(err) = self.port.clear(ClearBuffer::Input) {
+            eprintln!("Failed to clear the input of device {}: {err}", self.id);
+        }
+    }
+
     pub async fn run(mut self, mut command_rx: mpsc::Receiver<DeviceCommand>) -> Result<()> {
         while let Some(cmd) = command_rx.recv().await {
             match cmd {
@@ -123,7 +159,8 @@ impl SerialDevice {
                     response_tx,
                 } => {
                     let result = self.read_exact(length).await;
-                    if result.is_err() {
+                    // A silent rig is not a broken port
+                    if result.as_ref().is_err_and(|err| !err.is::<ReadTimeout>()) {
                         self.handle_error().await;
                     }
                     response_tx.send(result).await.ok();
@@ -151,3 +188,41 @@ impl SerialDevice {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_timeout_drops_late_reply() -> Result<()> {
+        let (mut rig, port) = SerialStream::pair()?;
+        let (command_tx, _) = mpsc::channel(1);
+        let (device_tx, _) = mpsc::channel(1);
+        let mut device = SerialDevice {
+            id: 1,
+            port,
+            settings: RigSettings {
+                timeout: 50,
+                ..RigSettings::default()
+            },
+            command_tx,
+            device_tx,
+            late_reply: false,
+        };
+
+        // The rig answers after the timeout
+        rig.write_all(&[0xFE, 0xFE]).await?;
+        let err = device.read_exact(4).await.unwrap_err();
+        assert!(err.is::<ReadTimeout>());
+        rig.write_all(&[0xFB, 0xFD]).await?;
+        sleep(Duration::from_millis(50)).await;
+
+        // The next exchange only sees its own reply
+        device.write_only(&[0x03]).await?;
+        let mut request = [0u8; 1];
+        rig.read_exact(&mut request).await?;
+        rig.write_all(&[0x01, 0x02, 0x03, 0x04]).await?;
+        assert_eq!(device.read_exact(4).await?, [0x01, 0x02, 0x03, 0x04]);
+        Ok(())
+    }
+}
@@ -1,24 +1,85 @@
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use parking_lot::RwLock;
 use serde_json::json;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::time::{Duration, sleep};
 
 use crate::gui::GuiMessage;
 use crate::resources::Resources;
-use crate::rig_settings::{RigSettings, Settings};
+use crate::rig_settings::{DeviceClass, RigSettings, Settings};
 use crate::runtime::ExternalApi;
-use crate::runtime::{Interpreter, Value};
-use crate::serial::device::{DeviceCommand, DeviceMessage, SerialDevice};
+use crate::runtime::{
+    Debugger, Interpreter, RigStatus, RuntimeError, RuntimeErrorKind, TraceEvent, Tracer, Value,
+};
+use crate::serial::device::{DeviceCommand, DeviceMessage, ReadTimeout, SerialDevice};
 
 const RIGS_FILE: &str = "rigs.toml";
 
+/// Reason a command failed, reported by the interfaces with their own error codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandErrorKind {
+    /// No device with the requested id
+    DeviceNotFound,
+    /// The rig file does not implement the command
+    UnknownCommand,
+    /// Parameters rejected by the schema or by the capabilities of the rig
+    InvalidParams,
+    /// The rig did not answer in time
+    Timeout,
+    /// The rig answered something the rig file doesn't expect
+    Protocol,
+    /// Failure of the rig file itself
+    Internal,
+    /// Communication failure with the device
+    Io,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandError {
+    pub kind: CommandErrorKind,
+    pub message: String,
+}
+
+impl CommandError {
+    fn new(kind: CommandErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Classifies an error raised while running the rig file.
+    fn from_runtime(err: anyhow::Error) -> Self {
+        fn kind(err: &anyhow::Error) -> CommandErrorKind {
+            if err.is::<ReadTimeout>() {
+                return CommandErrorKind::Timeout;
+            }
+            match err.downcast_ref::<RuntimeError>().map(|err| &err.kind) {
+                Some(RuntimeErrorKind::Other(err)) => kind(err),
+                Some(RuntimeErrorKind::DivisionByZero { .. }) => CommandErrorKind::Internal,
+                Some(_) => CommandErrorKind::Protocol,
+                None => CommandErrorKind::Io,
+            }
+        }
+        Self::new(kind(&err), err.to_string())
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum CommandResponse {
     Success(HashMap<String, Value>),
-    Error(String),
+    Error(CommandError),
 }
 
 impl From<CommandResponse> for serde_json::Value {
@@ -29,7 +90,7 @@ impl From<CommandResponse> for serde_json::Value {
                 .map(|(key, value)| (key, serde_json::Value::from(value)))
                 .collect(),
             CommandResponse::Error(err) => {
-                json!({"error": err})
+                json!({"error": err.message})
             }
         }
     }
@@ -43,12 +104,21 @@ pub enum ManagerCommand {
     ExecuteCommand {
         device_id: usize,
         command_name: String,
-        params: HashMap<String, String>,
+        params: HashMap<String, Value>,
         response_channel: Option<oneshot::Sender<CommandResponse>>,
     },
     RemoveDevice {
         device_id: usize,
     },
+    SetTracing {
+        device_id: usize,
+        enabled: bool,
+    },
+    /// Settings of every device and whether it is connected, for interfaces that missed
+    /// manager messages.
+    GetDevices {
+        response_channel: oneshot::Sender<Vec<(RigSettings, bool)>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -57,9 +127,17 @@ pub enum ManagerMessage {
         // DeviceId, RigFile name
         rigs: HashMap<usize, String>,
     },
+    /// Device created or updated, also sent for the devices loaded at startup.
+    DeviceAdded {
+        settings: RigSettings,
+    },
+    DeviceRemoved {
+        device_id: usize,
+    },
     DeviceConnected {
         device_id: usize,
         rig_model: String,
+        device_class: DeviceClass,
     },
     DeviceDisconnected {
         device_id: usize,
@@ -68,11 +146,20 @@ pub enum ManagerMessage {
         device_id: usize,
         values: HashMap<String, Value>,
     },
+    Trace {
+        device_id: usize,
+        event: TraceEvent,
+    },
 }
 
+/// Debuggers of the devices by id, shared with the interfaces so that they can resume a command
+/// while the manager waits for it to finish.
+pub type Debuggers = Arc<RwLock<HashMap<usize, Arc<Debugger<DeviceTracer>>>>>;
+
 pub struct DeviceManager {
     resources: Arc<Resources>,
     devices: HashMap<usize, Device>,
+    debuggers: Debuggers,
     settings: Settings,
     data_dir: PathBuf,
 
@@ -92,8 +179,42 @@ pub struct DeviceManager {
 struct Device {
     // Manager to devices channel
     command_tx: mpsc::Sender<DeviceCommand>,
+    // Manager to the worker running the commands of the device
+    job_tx: mpsc::UnboundedSender<DeviceJob>,
     rig_wrapper: Interpreter,
     settings: RigSettings,
+    tracing: Arc<AtomicBool>,
+    connected: bool,
+    /// Last polled status, the transmit commands are checked against
+    status: Arc<RwLock<HashMap<String, Value>>>,
+}
+
+/// Schema command queued on the worker of a device.
+struct DeviceJob {
+    command_name: String,
+    params: HashMap<String, Value>,
+    response_channel: Option<oneshot::Sender<CommandResponse>>,
+}
+
+/// Publishes the interpreter events of a device while tracing is enabled for it.
+pub struct DeviceTracer {
+    device_id: usize,
+    enabled: Arc<AtomicBool>,
+    manager_tx: broadcast::Sender<ManagerMessage>,
+}
+
+#[async_trait]
+impl Tracer for DeviceTracer {
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    async fn trace(&self, event: TraceEvent) {
+        let _ = self.manager_tx.send(ManagerMessage::Trace {
+            device_id: self.device_id,
+            event,
+        });
+    }
 }
 
 struct DeviceExternalApi {
@@ -159,7 +280,9 @@ impl DeviceManager {
         let (manager_command_tx, manager_command_rx) = mpsc::channel(10);
         let (device_tx, device_rx) = mpsc::channel(10);
 
-        let (manager_message_tx, _) = broadcast::channel(10);
+        // Trace events are sent for every statement, a small buffer makes the interfaces lag
+        // behind and drop status updates along with them
+        let (manager_message_tx, _) = broadcast::channel(256);
 
         let data_dir = dirs::data_dir()
             .unwrap_or_else(|| PathBuf::from("."))
@@ -173,6 +296,7 @@ impl DeviceManager {
         Self {
             resources,
             devices: HashMap::new(),
+            debuggers: Default::default(),
             settings: Default::default(),
             data_dir,
             manager_message_tx,
@@ -191,19 +315,46 @@ impl DeviceManager {
         self.manager_command_tx.clone()
     }
 
+    pub fn debuggers(&self) -> Debuggers {
+        self.debuggers.clone()
+    }
+
+    /// Forgets the debugger of a device, letting its paused commands finish.
+    fn remove_debugger(&self, device_id: usize) {
+        if let Some(debugger) = self.debuggers.write().remove(&device_id) {
+            debugger.clear();
+        }
+    }
+
     pub async fn load_rigs(&mut self, gui_sender: &mpsc::Sender<GuiMessage>) -> Result<()> {
         let settings_path = self.data_dir.join(RIGS_FILE);
-        let settings = if !settings_path.exists() {
+        let mut settings = if !settings_path.exists() {
             Settings::default()
         } else {
             let content = std::fs::read_to_string(&settings_path)?;
             toml::from_str(&content)?
         };
+        // Rigs saved before the hamlib listeners existed all get the default addresses
+        for index in 1..settings.rigs.len() {
+            let (rigs, rest) = settings.rigs.split_at_mut(index);
+            let moved = rest[0].clone().without_conflicts(rigs);
+            if moved.hamlib_address() != rest[0].hamlib_address() {
+                eprintln!(
+                    "Rig {} moved to {} as its hamlib address is already used",
+                    moved.id,
+                    moved.hamlib_address().unwrap_or_default()
+                );
+                rest[0] = moved;
+            }
+        }
 
-        for (rig_id, settings) in settings.rigs.iter().enumerate() {
-            if let Err(err) = self.add_device(rig_id, settings.clone()).await {
-                eprintln!("Failed to load rig {rig_id}: {err}");
+        for settings in &settings.rigs {
+            if let Err(err) = self.add_device(settings.id, settings.clone()).await {
+                eprintln!("Failed to load rig {}: {err}", settings.id);
             }
+            let _ = self.manager_message_tx.send(ManagerMessage::DeviceAdded {
+                settings: settings.clone(),
+            });
         }
 
         self.manager_message_tx.send(ManagerMessage::InitialState {
@@ -227,13 +378,18 @@ impl DeviceManager {
             DeviceMessage::Connected { device_id } => {
                 let init_result = self.initialize_device(device_id).await;
 
-                let rig_model = self.devices[&device_id].settings.rig_type.clone();
+                let device = self.devices.get_mut(&device_id).unwrap();
+                device.connected = true;
+                let settings = &device.settings;
+                let rig_model = settings.rig_type.clone();
+                let device_class = settings.device_class;
 
                 let _ = self
                     .manager_message_tx
                     .send(ManagerMessage::DeviceConnected {
                         device_id,
                         rig_model,
+                        device_class,
                     });
 
                 if init_result.is_ok() {
@@ -243,6 +399,9 @@ impl DeviceManager {
                 }
             }
             DeviceMessage::Disconnected { device_id } => {
+                if let Some(device) = self.devices.get_mut(&device_id) {
+                    device.connected = false;
+                }
                 let _ = self
                     .manager_message_tx
                     .send(ManagerMessage::DeviceDisconnected { device_id });
@@ -261,6 +420,7 @@ impl DeviceManager {
         match manager_command {
             ManagerCommand::CreateOrUpdateDevice { settings } => {
                 self.devices.remove(&settings.id);
+                self.remove_debugger(settings.id);
 
                 let changed_settings = self
                     .settings
@@ -276,9 +436,12 @@ impl DeviceManager {
                 let content = toml::to_string(&self.settings)?;
                 std::fs::write(path, content)?;
 
-                if let Err(err) = self.add_device(settings.id, settings).await {
+                if let Err(err) = self.add_device(settings.id, settings.clone()).await {
                     eprintln!("Failed to add device: {err}");
                 }
+                let _ = self
+                    .manager_message_tx
+                    .send(ManagerMessage::DeviceAdded { settings });
             }
             ManagerCommand::ExecuteCommand {
                 device_id,
@@ -286,23 +449,34 @@ impl DeviceManager {
                 params,
                 response_channel,
             } => {
-                let result = self.execute_command(device_id, &command_name, params).await;
-
-                let response = match result {
-                    Ok(response) => CommandResponse::Success(response),
-                    Err(err) => {
-                        eprintln!("Command {command_name} of device {device_id} failed: {err}");
-                        CommandResponse::Error(err.to_string())
-                    }
+                let job = DeviceJob {
+                    command_name,
+                    params,
+                    response_channel,
                 };
-                if let Some(response_channel) = response_channel {
-                    response_channel.send(response).unwrap();
-                }
+                // Commands run on the worker of their device, a paused command only holds the
+                // commands of its own device
+                let job = match self.devices.get(&device_id) {
+                    Some(device) => match device.job_tx.send(job) {
+                        Ok(()) => return Ok(()),
+                        Err(err) => err.0,
+                    },
+                    None => job,
+                };
+                let err = CommandError::new(
+                    CommandErrorKind::DeviceNotFound,
+                    format!("Device not found: {device_id}"),
+                );
+                respond(device_id, &job.command_name, job.response_channel, Err(err));
             }
             ManagerCommand::RemoveDevice { device_id } => {
                 if let Some(device) = self.devices.remove(&device_id) {
                     let _ = device.command_tx.send(DeviceCommand::Shutdown).await;
                 }
+                self.remove_debugger(device_id);
+                let _ = self
+                    .manager_message_tx
+                    .send(ManagerMessage::DeviceRemoved { device_id });
 
                 if let Some(pos) = self
                     .settings
@@ -316,6 +490,27 @@ impl DeviceManager {
                     std::fs::write(path, content)?;
                 }
             }
+            ManagerCommand::SetTracing { device_id, enabled } => {
+                match self.devices.get(&device_id) {
+                    Some(device) => device.tracing.store(enabled, Ordering::Relaxed),
+                    None => eprintln!("Can't trace device {device_id}: device not found"),
+                }
+            }
+            ManagerCommand::GetDevices { response_channel } => {
+                let devices = self
+                    .settings
+                    .rigs
+                    .iter()
+                    .map(|settings| {
+                        let connected = self
+                            .devices
+                            .get(&settings.id)
+                            .is_some_and(|device| device.connected);
+                        (settings.clone(), connected)
+                    })
+                    .collect();
+                let _ = response_channel.send(devices);
+            }
         }
         Ok(())
     }
@@ -357,7 +552,13 @@ impl DeviceManager {
             loop {
                 sleep(Duration::from_millis(poll_interval as u64)).await;
 
-                let values = Self::execute_status_commands(&device_clone).await.unwrap();
+                let values = match Self::execute_status_commands(&device_clone).await {
+                    Ok(values) => values,
+                    Err(err) => {
+                        eprintln!("Status of device {device_id} failed: {err}");
+                        continue;
+                    }
+                };
                 let changed_values: HashMap<String, Value> = values
                     .iter()
                     .filter(|(name, value)| {
@@ -369,6 +570,7 @@ impl DeviceManager {
                     .map(|(name, value)| (name.clone(), value.clone()))
                     .collect();
 
+                *device_clone.status.write() = values.clone();
                 if !changed_values.is_empty() {
                     let _ = manager_tx.send(ManagerMessage::StatusUpdate {
                         device_id,
@@ -383,24 +585,68 @@ impl DeviceManager {
     }
 
     pub async fn add_device(&mut self, device_id: usize, settings: RigSettings) -> Result<()> {
+        let rig_class = self.resources.rig_class(&settings.rig_type);
+        if rig_class.is_some_and(|class| class != settings.device_class) {
+            bail!(
+                "Rig type {} is not a {}",
+                settings.rig_type,
+                settings.device_class
+            );
+        }
+
+        let tracing = Arc::new(AtomicBool::new(false));
+        let debugger = Arc::new(Debugger::new(DeviceTracer {
+            device_id,
+            enabled: tracing.clone(),
+            manager_tx: self.manager_message_tx.clone(),
+        }));
         let rig_wrapper = self
             .resources
             .rigs
             .get(&settings.rig_type)
             .context("Unknown rig type")?
-            .clone();
+            .clone()
+            .with_tracer(debugger.clone());
         let (serial_device, command_rx) =
             SerialDevice::new(device_id, settings.clone(), self.device_tx.clone()).await?;
 
         let id = settings.id;
 
+        let (job_tx, mut job_rx) = mpsc::unbounded_channel::<DeviceJob>();
+        let command_tx = serial_device.command_sender();
+        let resources = self.resources.clone();
+        let interpreter = rig_wrapper.clone();
+        let serial_tx = command_tx.clone();
+        let status = Arc::new(RwLock::new(HashMap::new()));
+        let worker_status = status.clone();
+        tokio::spawn(async move {
+            while let Some(job) = job_rx.recv().await {
+                let status = worker_status.read().clone();
+                let result = Self::execute_command(
+                    &resources,
+                    &interpreter,
+                    &serial_tx,
+                    &job.command_name,
+                    job.params,
+                    &status,
+                )
+                .await;
+                respond(device_id, &job.command_name, job.response_channel, result);
+            }
+        });
+
         let device = Device {
-            command_tx: serial_device.command_sender(),
+            command_tx,
+            job_tx,
             rig_wrapper,
             settings,
+            tracing,
+            connected: false,
+            status,
         };
 
         self.devices.insert(device_id, device);
+        self.debuggers.write().insert(device_id, debugger);
 
         let device_tx = self.device_tx.clone();
         tokio::spawn(async move {
@@ -436,21 +682,72 @@ impl DeviceManager {
     }
 
     async fn execute_command(
-        &self,
-        device_id: usize,
+        resources: &Resources,
+        rig_wrapper: &Interpreter,
+        command_tx: &mpsc::Sender<DeviceCommand>,
         command_name: &str,
-        params: HashMap<String, String>,
-    ) -> Result<HashMap<String, Value>> {
-        let device = self
-            .devices
-            .get(&device_id)
-            .ok_or_else(|| anyhow!("Device not found: {}", device_id))?;
+        params: HashMap<String, Value>,
+        status: &HashMap<String, Value>,
+    ) -> Result<HashMap<String, Value>, CommandError> {
+        let rig_file = rig_wrapper.rig_file();
+        if !rig_file.impl_block.commands.contains_key(command_name) {
+            return Err(CommandError::new(
+                CommandErrorKind::UnknownCommand,
+                format!(
+                    "{} does not implement {command_name}",
+                    rig_file.impl_block.name
+                ),
+            ));
+        }
+        let schema = resources.schemas.get(&rig_file.impl_block.schema);
+        let schema_command = schema.and_then(|schema| schema.commands.get(command_name));
+        let params = match schema_command {
+            Some(command) => command
+                .prepare_args(params, &rig_file.enum_mappings())
+                .map_err(|errors| {
+                    let errors = errors
+                        .iter()
+                        .map(|(name, err)| format!("{name}: {err}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    CommandError::new(
+                        CommandErrorKind::InvalidParams,
+                        format!("Invalid params for command {command_name}: {errors}"),
+                    )
+                })?,
+            None => params,
+        };
 
-        let external_api = DeviceExternalApi::new(device.command_tx.clone());
-        device
-            .rig_wrapper
+        if let Some(command) = schema_command
+            && let Some(capabilities) = rig_file.capabilities()
+        {
+            capabilities
+                .check_args(
+                    command,
+                    &params,
+                    schema.map(|schema| RigStatus {
+                        fields: &schema.status,
+                        values: status,
+                    }),
+                )
+                .map_err(|errors| {
+                    let errors = errors
+                        .iter()
+                        .map(|(name, err)| format!("{name}: {err}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    CommandError::new(
+                        CommandErrorKind::InvalidParams,
+                        format!("Command {command_name} rejected: {errors}"),
+                    )
+                })?;
+        }
+
+        let external_api = DeviceExternalApi::new(command_tx.clone());
+        rig_wrapper
             .execute_command(command_name, params, &external_api)
             .await
+            .map_err(CommandError::from_runtime)
     }
 
     pub async fn initialize_device(&self, device_id: usize) -> Result<()> {
@@ -463,3 +760,118 @@ impl DeviceManager {
         device.rig_wrapper.execute_init(&external_api).await
     }
 }
+
+/// Answers the requester of a command with its outcome.
+fn respond(
+    device_id: usize,
+    command_name: &str,
+    response_channel: Option<oneshot::Sender<CommandResponse>>,
+    result: Result<HashMap<String, Value>, CommandError>,
+) {
+    let response = match result {
+        Ok(response) => CommandResponse::Success(response),
+        Err(err) => {
+            eprintln!("Command {command_name} of device {device_id} failed: {err}");
+            CommandResponse::Error(err)
+        }
+    };
+    if let Some(response_channel) = response_channel {
+        // The requester may be gone, e.g. a stopped rigctld listener
+        let _ = response_channel.send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Span;
+
+    #[test]
+    fn test_command_error_kind() {
+        let kind = |err: anyhow::Error| CommandError::from_runtime(err).kind;
+
+        assert_eq!(
+            kind(ReadTimeout { timeout: 500 }.into()),
+            CommandErrorKind::Timeout
+        );
+        // Errors raised by the external API are wrapped once located in the rig file
+        let located = RuntimeError {
+            kind: RuntimeErrorKind::Other(ReadTimeout { timeout: 500 }.into()),
+            span: Some(Span::default()),
+            source: None,
+        };
+        assert_eq!(kind(located.into()), CommandErrorKind::Timeout);
+        assert_eq!(
+            kind(
+                RuntimeError::new(RuntimeErrorKind::UnexpectedResponse {
+                    expected: vec![0xFB],
+                    found: vec![0xFA],
+                })
+                .into()
+            ),
+            CommandErrorKind::Protocol
+        );
+        assert_eq!(kind(anyhow!("Device disconnected")), CommandErrorKind::Io);
+    }
+
+    #[tokio::test]
+    async fn test_load_legacy_rigs() -> Result<()> {
+        let data_dir = std::env::temp_dir().join(format!("holyrig-legacy-{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir)?;
+        // Written before the rigctld section existed
+        let rigs = r#"
+[[rigs]]
+id = 0
+rig_type = "IC7300"
+port = ""
+baud_rate = "Baud9600"
+data_bits = "Bits8"
+parity = false
+stop_bits = "Bits1"
+rts = false
+dtr = false
+
+[[rigs]]
+id = 1
+rig_type = "IC7300"
+port = ""
+baud_rate = "Baud9600"
+data_bits = "Bits8"
+parity = false
+stop_bits = "Bits1"
+rts = false
+dtr = false
+"#;
+        std::fs::write(data_dir.join(RIGS_FILE), rigs)?;
+
+        let mut manager = DeviceManager::new(Resources::load()?);
+        manager.data_dir = data_dir.clone();
+        let _receiver = manager.receiver();
+        let (gui_sender, _gui_receiver) = mpsc::channel(8);
+        let result = manager.load_rigs(&gui_sender).await;
+        std::fs::remove_dir_all(&data_dir)?;
+        result?;
+
+        let addresses: Vec<_> = manager
+            .settings
+            .rigs
+            .iter()
+            .map(|rig| rig.rigctld.address.as_str())
+            .collect();
+        assert_eq!(addresses, ["127.0.0.1:4532", "127.0.0.1:4534"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_trace_missing_device() -> Result<()> {
+        let mut manager = DeviceManager::new(Resources::load()?);
+        // A rig whose port failed to open has settings but no device
+        manager
+            .handle_manager_command(ManagerCommand::SetTracing {
+                device_id: 7,
+                enabled: true,
+            })
+            .await?;
+        Ok(())
+    }
+}